@@ -0,0 +1,83 @@
+//! wasm-bindgen bindings of the move analysis engine, for web playgrounds.
+//! Build with `wasm-pack build crates/move-language-server-wasm`.
+//!
+//! Files only live in memory, they are identified by the paths given to `setFile`.
+
+use move_language_server::{analysis::Analysis, config::ProjectConfig};
+use std::path::{Path, PathBuf};
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct MoveAnalysis {
+    analysis: Analysis,
+    stdlib_files: Vec<PathBuf>,
+    module_files: Vec<PathBuf>,
+}
+
+#[wasm_bindgen]
+impl MoveAnalysis {
+    /// `config` takes the same keys as the language server config, only `sender_address` is used.
+    #[wasm_bindgen(constructor)]
+    pub fn new(config: JsValue) -> Result<MoveAnalysis, JsValue> {
+        let config: ProjectConfig = if config.is_undefined() || config.is_null() {
+            ProjectConfig::default()
+        } else {
+            config
+                .into_serde()
+                .map_err(|e| JsValue::from_str(&format!("invalid config, {}", e)))?
+        };
+        Ok(MoveAnalysis {
+            analysis: Analysis::new(config),
+            stdlib_files: vec![],
+            module_files: vec![],
+        })
+    }
+
+    /// Add or update a stdlib or module file.
+    #[wasm_bindgen(js_name = setFile)]
+    pub fn set_file(&mut self, path: &str, text: &str, is_stdlib: bool) {
+        let path = PathBuf::from(path);
+        self.analysis.set_file_content(&path, text);
+        let files = if is_stdlib {
+            &mut self.stdlib_files
+        } else {
+            &mut self.module_files
+        };
+        if !files.contains(&path) {
+            files.push(path);
+            self.update_project_files();
+        }
+    }
+
+    #[wasm_bindgen(js_name = removeFile)]
+    pub fn remove_file(&mut self, path: &str) {
+        let path = PathBuf::from(path);
+        self.stdlib_files.retain(|f| f != &path);
+        self.module_files.retain(|f| f != &path);
+        self.analysis.remove_file_content(&path);
+        self.update_project_files();
+    }
+
+    /// Syntax errors of a single file.
+    pub fn parse(&self, path: &str) -> JsValue {
+        to_js(&self.analysis.syntax_errors(Path::new(path)))
+    }
+
+    /// Errors of checking `path` with all the other files.
+    pub fn diagnostics(&self, path: &str) -> JsValue {
+        to_js(&self.analysis.check_file(Path::new(path)))
+    }
+
+    pub fn symbols(&self, path: &str) -> JsValue {
+        to_js(&self.analysis.symbols(Path::new(path)))
+    }
+
+    fn update_project_files(&mut self) {
+        self.analysis
+            .set_project_files(self.stdlib_files.clone(), self.module_files.clone());
+    }
+}
+
+fn to_js<T: serde::Serialize>(value: &T) -> JsValue {
+    JsValue::from_serde(value).unwrap_or(JsValue::NULL)
+}
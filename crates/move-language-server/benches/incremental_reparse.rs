@@ -0,0 +1,102 @@
+use criterion::{criterion_group, criterion_main, BatchSize, BenchmarkId, Criterion};
+use move_language_server::{
+    move_document::MoveDocument,
+    salsa::{config_query::Config, move_ast_query::Ast, RootDatabase},
+};
+use std::path::PathBuf;
+use tower_lsp::lsp_types::{Position, Range};
+use xi_rope::Rope;
+
+const SIZES: [usize; 3] = [100, 1_000, 5_000];
+
+/// A module with `functions` functions, each takes 5 lines.
+fn generate_module(functions: usize) -> String {
+    let mut source = String::from("address 0x1 {\nmodule Bench {\n");
+    for i in 0..functions {
+        source.push_str(&format!(
+            "    public fun f{}(a: u64, b: u64): u64 {{\n        let c = a + b;\n        c * {}\n    }}\n\n",
+            i, i
+        ));
+    }
+    source.push_str("}\n}\n");
+    source
+}
+
+/// Insert a statement at the start of the body of the function in the middle of the module.
+fn middle_edit(functions: usize) -> (Range, &'static str) {
+    let line = 2 + (functions / 2) * 5 + 1;
+    let pos = Position::new(line as u64, 8);
+    (Range::new(pos, pos), "let d = 1;\n        ")
+}
+
+fn bench_document_edit(c: &mut Criterion) {
+    let mut group = c.benchmark_group("document_edit");
+    for size in SIZES.iter() {
+        let source = generate_module(*size);
+        let (range, text) = middle_edit(*size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, s| {
+            b.iter_batched(
+                || MoveDocument::new(1, s),
+                |mut doc| doc.edit(range, text),
+                BatchSize::LargeInput,
+            )
+        });
+    }
+    group.finish();
+}
+
+fn bench_full_parse(c: &mut Criterion) {
+    let mut group = c.benchmark_group("full_parse");
+    for size in SIZES.iter() {
+        let source = generate_module(*size);
+        group.bench_with_input(BenchmarkId::from_parameter(size), &source, |b, s| {
+            b.iter(|| MoveDocument::new(1, s))
+        });
+    }
+    group.finish();
+}
+
+fn bench_salsa_invalidation(c: &mut Criterion) {
+    let mut group = c.benchmark_group("salsa_invalidation");
+    group.sample_size(20);
+    for size in SIZES.iter() {
+        let path = PathBuf::from("/bench.move");
+        let mut db = RootDatabase::default();
+        db.set_stdlib_files(vec![]);
+        db.set_module_files(vec![path.clone()]);
+        db.set_sender(None);
+
+        let mut doc = MoveDocument::new(1, generate_module(*size));
+        db.update_source(path.clone(), doc.doc().rope().clone());
+        let _ = db.ast(path.clone());
+
+        let (range, text) = middle_edit(*size);
+        doc.edit(range, text).unwrap();
+        let edited: Rope = doc.doc().rope().clone();
+        let original = Rope::from(generate_module(*size));
+
+        let mut flip = false;
+        group.bench_function(BenchmarkId::from_parameter(size), |b| {
+            b.iter(|| {
+                // alternate contents so that every iteration really invalidates.
+                flip = !flip;
+                let rope = if flip {
+                    edited.clone()
+                } else {
+                    original.clone()
+                };
+                db.update_source(path.clone(), rope);
+                db.ast(path.clone()).is_ok()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(
+    benches,
+    bench_document_edit,
+    bench_full_parse,
+    bench_salsa_invalidation
+);
+criterion_main!(benches);
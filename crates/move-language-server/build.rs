@@ -1,12 +1,78 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+
+/// Environment variable overriding the `src` folder of the grammar.
+const GRAMMAR_DIR_VAR: &str = "TREE_SITTER_MOVE_DIR";
+
+/// `src` folder of the tree-sitter-move grammar: `$TREE_SITTER_MOVE_DIR`, the submodule of
+/// the repository, or the copy vendored in the crate, which is what `cargo install` builds.
+fn grammar_dir(manifest_dir: &Path) -> PathBuf {
+    println!("cargo:rerun-if-env-changed={}", GRAMMAR_DIR_VAR);
+    if let Some(dir) = std::env::var_os(GRAMMAR_DIR_VAR) {
+        let dir = PathBuf::from(dir);
+        // the folder of the grammar itself is accepted as well.
+        return if dir.join("src").join("parser.c").is_file() {
+            dir.join("src")
+        } else {
+            dir
+        };
+    }
+    let candidates = [
+        manifest_dir.join("../../tree-sitter-move/src"),
+        manifest_dir.join("vendor/tree-sitter-move/src"),
+    ];
+    match candidates.iter().find(|d| d.join("parser.c").is_file()) {
+        Some(dir) => dir.clone(),
+        None => panic!(
+            "tree-sitter-move grammar not found in {:?}, \
+             run `git submodule update --init` or set {}",
+            candidates, GRAMMAR_DIR_VAR
+        ),
+    }
+}
+
+/// Compile the external scanner of the grammar, in C or C++, if it has one.
+/// Its symbols are referred to by the language `parser.c` defines.
+fn compile_scanner(dir: &Path) {
+    let c = dir.join("scanner.c");
+    let cc = dir.join("scanner.cc");
+    println!("cargo:rerun-if-changed={}", c.display());
+    println!("cargo:rerun-if-changed={}", cc.display());
+    if c.is_file() {
+        cc::Build::new()
+            .include(dir)
+            .file(c)
+            .compile("tree-sitter-move-scanner");
+    } else if cc.is_file() {
+        cc::Build::new()
+            .cpp(true)
+            .include(dir)
+            .file(cc)
+            .compile("tree-sitter-move-scanner");
+    } else {
+        return;
+    }
+    println!("cargo:rustc-cfg=external_scanner");
+}
 
 fn main() {
-    let dir: PathBuf = ["..", "..", "tree-sitter-move", "src"].iter().collect();
+    // the grammar is only used by the language server, and doesn't compile to wasm.
+    if std::env::var_os("CARGO_FEATURE_LSP").is_some() {
+        let manifest_dir = PathBuf::from(std::env::var_os("CARGO_MANIFEST_DIR").unwrap());
+        let dir = grammar_dir(&manifest_dir);
+        println!("cargo:rerun-if-changed={}", dir.join("parser.c").display());
+        println!(
+            "cargo:rerun-if-changed={}",
+            dir.join("node-types.json").display()
+        );
+        // read by `tree_sitter_move::NODE_TYPES`.
+        println!("cargo:rustc-env=TREE_SITTER_MOVE_SRC={}", dir.display());
 
-    cc::Build::new()
-        .include(&dir)
-        .file(dir.join("parser.c"))
-        .compile("tree-sitter-move");
+        cc::Build::new()
+            .include(&dir)
+            .file(dir.join("parser.c"))
+            .compile("tree-sitter-move");
+        compile_scanner(&dir);
+    }
 
     built::write_built_file().expect("Failed to acquire build-time information");
 }
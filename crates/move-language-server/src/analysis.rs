@@ -0,0 +1,489 @@
+//! Check and compile move projects without speaking lsp.
+//! This is the incremental engine behind the language server, for embedding in other tools.
+
+use crate::{
+    config::ProjectConfig,
+    dialect::Dialect,
+    salsa::{
+        config_query::Config, move_ast_query::Ast, text_source_query::SourceReader, RootDatabase,
+    },
+    utils::{canonicalize, check_move_file, find_bytecode_file, find_move_file, SkipReason},
+};
+use anyhow::Result;
+use move_ir_types::location::Loc;
+use move_lang::{
+    compiled_unit::CompiledUnit,
+    errors::Errors,
+    parser::ast::{Definition, FunctionVisibility, ModuleDefinition, ModuleMember},
+};
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    collections::hash_map::DefaultHasher,
+    hash::{Hash, Hasher},
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use xi_rope::Rope;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Label {
+    pub file: PathBuf,
+    /// byte offsets in `file`.
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Diagnostic {
+    pub primary: Label,
+    pub secondary: Vec<Label>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SymbolKind {
+    Module,
+    Struct,
+    Function,
+    Constant,
+    Script,
+}
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// byte offsets of the name.
+    pub span: Range<usize>,
+    /// byte offsets of the whole definition.
+    pub definition_span: Range<usize>,
+    /// the module defining the symbol.
+    pub container: Option<String>,
+    /// source of the definition up to its body, on one line, `None` for modules.
+    pub signature: Option<String>,
+    /// whether the symbol is a public function.
+    pub public: bool,
+}
+
+pub struct Analysis {
+    db: RootDatabase,
+    config: ProjectConfig,
+}
+
+impl Analysis {
+    pub fn new(config: ProjectConfig) -> Self {
+        let mut db = RootDatabase::default();
+        apply_config(&mut db, &config);
+        Self { db, config }
+    }
+
+    /// Analysis of sources written in `dialect`.
+    pub fn with_dialect(config: ProjectConfig, dialect: Arc<dyn Dialect>) -> Self {
+        let mut db = RootDatabase::with_dialect(dialect);
+        apply_config(&mut db, &config);
+        Self { db, config }
+    }
+
+    /// Analysis of the project at `folder`, configured by its `Move.toml`.
+    pub fn load(folder: &Path) -> Result<Self> {
+        let config = ProjectConfig::load(Some(folder), Value::Null)?;
+        Ok(Self::new(config))
+    }
+
+    pub fn config(&self) -> &ProjectConfig {
+        &self.config
+    }
+
+    pub fn set_config(&mut self, config: ProjectConfig) {
+        apply_config(&mut self.db, &config);
+        self.config = config;
+    }
+
+    /// Set the project files directly, for embedders without a file system.
+    pub fn set_project_files(&mut self, stdlib_files: Vec<PathBuf>, module_files: Vec<PathBuf>) {
+        self.db.set_project_files(stdlib_files, module_files);
+    }
+
+    /// Use `text` as the content of `file` instead of what's on disk, e.g. an unsaved buffer.
+    pub fn set_file_content(&mut self, file: &Path, text: &str) {
+        self.db.update_source(file.to_path_buf(), Rope::from(text));
+    }
+
+    /// Read `file` from disk again.
+    pub fn remove_file_content(&mut self, file: &Path) {
+        let file = canonicalize(file);
+        self.db.close_source(file.clone());
+        self.db.did_change(file.as_path());
+    }
+
+    /// `file` is changed on disk.
+    pub fn file_changed(&mut self, file: &Path) {
+        self.db.did_change(canonicalize(file).as_path());
+    }
+
+    /// Check all stdlib and module files.
+    pub fn check_all(&self) -> Vec<Diagnostic> {
+        let (_, result) = self.db.check_all(None);
+        to_diagnostics(result.err().unwrap_or_default())
+    }
+
+    /// Check `file` together with the project files, `file` doesn't have to be one of them.
+    pub fn check_file(&self, file: &Path) -> Vec<Diagnostic> {
+        let (_, result) = self.db.check_file(None, file.to_path_buf());
+        to_diagnostics(result.err().unwrap_or_default())
+    }
+
+    pub fn compile_file(&self, file: &Path) -> Result<Vec<CompiledUnit>, Vec<Diagnostic>> {
+        let (_, result) = self.db.compile_file(None, file.to_path_buf());
+        result.map_err(to_diagnostics)
+    }
+
+    /// Parse errors of `file` alone.
+    pub fn syntax_errors(&self, file: &Path) -> Vec<Diagnostic> {
+        self.db
+            .ast(canonicalize(file))
+            .err()
+            .map(to_diagnostics)
+            .unwrap_or_default()
+    }
+
+    /// Modules, scripts and module members defined in `file`, empty if it doesn't parse.
+    pub fn symbols(&self, file: &Path) -> Vec<Symbol> {
+        self.db.symbols(canonicalize(file)).to_vec()
+    }
+}
+
+/// Modules, scripts and module members of definitions parsed from `source`.
+pub fn file_symbols(defs: &[Definition], source: &str) -> Vec<Symbol> {
+    let mut symbols = vec![];
+    for def in defs.iter() {
+        match def {
+            Definition::Module(m) => module_symbols(m, source, &mut symbols),
+            Definition::Address(_, _, modules) => {
+                for m in modules {
+                    module_symbols(m, source, &mut symbols);
+                }
+            }
+            Definition::Script(s) => symbols.push(Symbol {
+                name: s.function.name.0.value.clone(),
+                kind: SymbolKind::Script,
+                span: span(&s.function.name.0.loc),
+                definition_span: span(&s.loc),
+                container: None,
+                signature: signature(source, &s.function.loc),
+                public: false,
+            }),
+        }
+    }
+    symbols
+}
+
+/// Tests among the `symbols` of `file`, whose source is `source`: the script of a `test*` file
+/// or of a file in a `tests` folder, script functions named `test*`, and module functions
+/// named `test_*` or marked with a `#[test]` attribute, in dialects which know attributes.
+pub fn test_symbols(file: &Path, source: &str, symbols: &[Symbol]) -> Vec<Symbol> {
+    let test_file = file
+        .file_stem()
+        .map_or(false, |s| s.to_string_lossy().starts_with("test"))
+        || file
+            .parent()
+            .map_or(false, |p| p.components().any(|c| c.as_os_str() == "tests"));
+    symbols
+        .iter()
+        .cloned()
+        .filter(|s| match s.kind {
+            SymbolKind::Script => test_file || s.name.starts_with("test"),
+            SymbolKind::Function => {
+                s.name.starts_with("test_") || has_test_attribute(source, s.span.start)
+            }
+            _ => false,
+        })
+        .collect()
+}
+
+/// Whether the attributes, on the lines above the definition whose name is at `offset`,
+/// include `#[test]`, or `#[test(..)]`.
+fn has_test_attribute(source: &str, offset: usize) -> bool {
+    let line_start = source
+        .get(..offset)
+        .and_then(|s| s.rfind('\n'))
+        .map_or(0, |i| i + 1);
+    source[..line_start]
+        .lines()
+        .rev()
+        .map(str::trim)
+        .take_while(|line| line.starts_with("#[") || line.starts_with("///"))
+        .filter_map(|line| line.strip_prefix("#["))
+        .any(|attribute| {
+            attribute
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .next()
+                == Some("test")
+        })
+}
+
+fn module_symbols(module: &ModuleDefinition, source: &str, symbols: &mut Vec<Symbol>) {
+    let module_name = &module.name.0;
+    symbols.push(Symbol {
+        name: module_name.value.clone(),
+        kind: SymbolKind::Module,
+        span: span(&module_name.loc),
+        definition_span: span(&module.loc),
+        container: None,
+        signature: None,
+        public: false,
+    });
+    for member in module.members.iter() {
+        let (name, kind, loc, public) = match member {
+            ModuleMember::Function(f) => (
+                &f.name.0,
+                SymbolKind::Function,
+                &f.loc,
+                matches!(f.visibility, FunctionVisibility::Public(_)),
+            ),
+            ModuleMember::Struct(s) => (&s.name.0, SymbolKind::Struct, &s.loc, false),
+            ModuleMember::Constant(c) => (&c.name.0, SymbolKind::Constant, &c.loc, false),
+            _ => continue,
+        };
+        symbols.push(Symbol {
+            name: name.value.clone(),
+            kind,
+            span: span(&name.loc),
+            definition_span: span(loc),
+            container: Some(module_name.value.clone()),
+            signature: signature(source, loc),
+            public,
+        });
+    }
+}
+
+/// Source of the definition at `loc` up to its body, on one line.
+fn signature(source: &str, loc: &Loc) -> Option<String> {
+    source.get(span(loc)).map(definition_signature)
+}
+
+/// The source of a definition up to its body, on one line.
+pub fn definition_signature(definition: &str) -> String {
+    let head = definition
+        .split(|c| c == '{' || c == ';')
+        .next()
+        .unwrap_or_default();
+    head.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+pub(crate) fn span(loc: &Loc) -> Range<usize> {
+    loc.span().start().to_usize()..loc.span().end().to_usize()
+}
+
+/// Collect the files of the stdlib and modules folders of `config` into `db`.
+/// Files which are too large or not utf-8 are left out, and returned with the reason.
+pub fn apply_config(db: &mut RootDatabase, config: &ProjectConfig) -> Vec<(PathBuf, SkipReason)> {
+    let respect_gitignore = config.respect_gitignore;
+    let mut skipped = vec![];
+    let mut accept = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+        files
+            .into_iter()
+            .filter(|f| match check_move_file(f, config.max_file_size) {
+                Ok(()) => true,
+                Err(reason) => {
+                    warn!("{}: {}", f.display(), reason);
+                    skipped.push((canonicalize(f), reason));
+                    false
+                }
+            })
+            .collect()
+    };
+    let stdlib_files = config
+        .stdlib_folder
+        .as_ref()
+        .map(|f| find_move_file(f, respect_gitignore))
+        .unwrap_or_default();
+    let stdlib_files = accept(stdlib_files);
+    let module_files: Vec<_> = config
+        .modules_folders
+        .iter()
+        .flat_map(|f| find_move_file(f, respect_gitignore))
+        .collect();
+    let module_files = accept(module_files);
+    let script_files: Vec<_> = config
+        .scripts_folders
+        .iter()
+        .flat_map(|f| find_move_file(f, respect_gitignore))
+        .collect();
+    let script_files = accept(script_files);
+    // interfaces of compiled dependencies are checked against like stdlib files.
+    let interfaces = bytecode_interfaces(&config.dependency_bytecode_folders);
+    let folder_stdlibs = config
+        .folder_stdlibs
+        .iter()
+        .map(|(folder, stdlib)| {
+            let files = accept(find_move_file(stdlib, respect_gitignore));
+            let files = files
+                .into_iter()
+                .chain(interfaces.iter().cloned())
+                .collect();
+            (folder.clone(), files)
+        })
+        .collect();
+    let stdlib_files = stdlib_files
+        .into_iter()
+        .chain(interfaces.iter().cloned())
+        .collect();
+
+    db.set_project_files(stdlib_files, module_files);
+    db.set_interfaces(interfaces);
+    db.set_script_files(script_files);
+    db.set_folder_stdlibs(folder_stdlibs);
+    db.set_sender_with_durability(config.sender_address, salsa::Durability::HIGH);
+    db.set_compiler_flags_with_durability(config.compiler_flags.clone(), salsa::Durability::HIGH);
+    skipped
+}
+
+/// Source files declaring the public interfaces of the compiled modules under `folders`.
+/// They are generated into a temporary folder unique to `folders`.
+fn bytecode_interfaces(folders: &[PathBuf]) -> Vec<PathBuf> {
+    if folders.is_empty() {
+        return vec![];
+    }
+    let bytecode_files: Vec<String> = folders
+        .iter()
+        .flat_map(|f| find_bytecode_file(f))
+        .map(|f| f.to_string_lossy().to_string())
+        .collect();
+    let mut hasher = DefaultHasher::new();
+    folders.hash(&mut hasher);
+    let out_dir = std::env::temp_dir()
+        .join("move-ls-interfaces")
+        .join(format!("{:016x}", hasher.finish()));
+    match move_lang::generate_interface_files(
+        bytecode_files.as_slice(),
+        Some(out_dir.to_string_lossy().to_string()),
+        false,
+    ) {
+        Ok(files) => files.into_iter().map(PathBuf::from).collect(),
+        Err(e) => {
+            error!("fail to generate interfaces of dependency bytecode, {}", e);
+            vec![]
+        }
+    }
+}
+
+pub(crate) fn to_diagnostics(errors: Errors) -> Vec<Diagnostic> {
+    errors
+        .into_iter()
+        .filter_map(|error| {
+            let mut labels = error.into_iter().map(|(loc, message)| Label {
+                file: PathBuf::from(loc.file()),
+                span: span(&loc),
+                message,
+            });
+            Some(Diagnostic {
+                primary: labels.next()?,
+                secondary: labels.collect(),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = canonicalize(tmp.path());
+        std::fs::create_dir_all(dir.join("modules")).unwrap();
+        let file = dir.join("modules").join("M.move");
+        std::fs::write(
+            &file,
+            "address 0x1 {\nmodule M {\n    fun f(): u64 { 0 }\n}\n}\n",
+        )
+        .unwrap();
+
+        let mut analysis = Analysis::new(ProjectConfig {
+            modules_folders: vec![dir.join("modules")],
+            ..Default::default()
+        });
+        assert!(analysis.check_all().is_empty());
+        assert!(analysis.compile_file(&file).is_ok());
+
+        analysis.set_file_content(
+            &file,
+            "address 0x1 {\nmodule M {\n    fun f(): u64 { true }\n}\n}\n",
+        );
+        let diags = analysis.check_file(&file);
+        assert_eq!(diags.len(), 1);
+        assert_eq!(diags[0].primary.file, file);
+
+        analysis.remove_file_content(&file);
+        assert!(analysis.check_all().is_empty());
+
+        let symbols: Vec<_> = analysis
+            .symbols(&file)
+            .into_iter()
+            .map(|s| (s.name, s.kind))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![
+                ("M".to_string(), SymbolKind::Module),
+                ("f".to_string(), SymbolKind::Function)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_test_symbols() {
+        let source = "module M {\n    public fun test_f() {}\n    fun tested() {}\n}\nscript {\n    fun main() {}\n}\n";
+        let (defs, _) =
+            move_lang::parser::syntax::parse_file_string("test_m.move", source, Default::default())
+                .unwrap();
+        let names = |file: &str| -> Vec<_> {
+            test_symbols(Path::new(file), source, &file_symbols(&defs, source))
+                .into_iter()
+                .map(|s| (s.name, s.kind))
+                .collect()
+        };
+        assert_eq!(
+            names("scripts/test_m.move"),
+            vec![
+                ("test_f".to_string(), SymbolKind::Function),
+                ("main".to_string(), SymbolKind::Script)
+            ]
+        );
+        assert_eq!(
+            names("scripts/m.move"),
+            vec![("test_f".to_string(), SymbolKind::Function)]
+        );
+        assert_eq!(names("tests/m.move").len(), 2);
+    }
+
+    #[test]
+    fn test_test_attribute() {
+        let source = "module M {\n    #[test]\n    fun f() {}\n    #[test_only]\n    fun g() {}\n    /// Checks h.\n    #[test(account = 0x1)]\n    fun h() {}\n}\n";
+        // attributes are blanked out for the compiler, like dialects knowing them do,
+        // and so are comments.
+        let blanked = source
+            .lines()
+            .map(|l| {
+                if l.trim_start().starts_with("#[") || l.trim_start().starts_with("///") {
+                    " ".repeat(l.len())
+                } else {
+                    l.to_string()
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+        let (defs, _) =
+            move_lang::parser::syntax::parse_file_string("m.move", &blanked, Default::default())
+                .unwrap();
+        let names: Vec<_> = test_symbols(Path::new("m.move"), source, &file_symbols(&defs, source))
+            .into_iter()
+            .map(|s| s.name)
+            .collect();
+        assert_eq!(names, vec!["f".to_string(), "h".to_string()]);
+    }
+}
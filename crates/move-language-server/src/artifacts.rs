@@ -0,0 +1,159 @@
+//! Output directory layout of compiled units.
+//!
+//! Modules are written to `modules/<address>/<name>.mv` and scripts to `scripts/<name>.mv`,
+//! each with a source map at the same place under `source_maps`, with the `.mvsm` extension.
+
+use anyhow::Result;
+use move_lang::compiled_unit::CompiledUnit;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+const MODULES_DIR: &str = "modules";
+const SCRIPTS_DIR: &str = "scripts";
+const SOURCE_MAPS_DIR: &str = "source_maps";
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ArtifactKind {
+    Module,
+    Script,
+}
+
+/// A compiled unit written to the output directory.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Artifact {
+    pub kind: ArtifactKind,
+    /// `0x1::M` for modules, the function name for scripts.
+    pub id: String,
+    pub bytecode: PathBuf,
+    pub source_map: PathBuf,
+}
+
+/// Artifacts of a compilation, sent back to the client.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompilationManifest {
+    pub out_dir: PathBuf,
+    pub artifacts: Vec<Artifact>,
+}
+
+/// Write `units` and their source maps under `out_dir`.
+pub fn write_units(units: Vec<CompiledUnit>, out_dir: &Path) -> Result<CompilationManifest> {
+    let mut artifacts = vec![];
+    for unit in units {
+        let (kind, id, relative) = match &unit {
+            CompiledUnit::Module { ident, .. } => {
+                let ident = &ident.0.value;
+                (
+                    ArtifactKind::Module,
+                    format!("{}::{}", ident.address, ident.name),
+                    Path::new(MODULES_DIR)
+                        .join(ident.address.to_string())
+                        .join(&ident.name),
+                )
+            }
+            CompiledUnit::Script { .. } => {
+                let name = unit.name();
+                (
+                    ArtifactKind::Script,
+                    name.clone(),
+                    Path::new(SCRIPTS_DIR).join(name),
+                )
+            }
+        };
+        let bytecode = out_dir.join(&relative).with_extension("mv");
+        let source_map = out_dir
+            .join(SOURCE_MAPS_DIR)
+            .join(&relative)
+            .with_extension("mvsm");
+        write(&source_map, unit.serialize_source_map())?;
+        write(&bytecode, unit.serialize())?;
+        artifacts.push(Artifact {
+            kind,
+            id,
+            bytecode,
+            source_map,
+        });
+    }
+    Ok(CompilationManifest {
+        out_dir: out_dir.to_path_buf(),
+        artifacts,
+    })
+}
+
+fn write(path: &Path, content: Vec<u8>) -> Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{salsa::RootDatabase, utils::canonicalize};
+    use xi_rope::Rope;
+
+    #[test]
+    fn test_write_units() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = canonicalize(tmp.path());
+        let file = dir.join("M.move");
+        let mut db = RootDatabase::default();
+        db.update_source(
+            file.clone(),
+            Rope::from("address 0x1 {\nmodule M {\n    public fun f(): u64 { 0 }\n}\n}\n"),
+        );
+        let (_, units) = db.compile_file(None, file);
+
+        let manifest = write_units(units.unwrap(), &dir.join("target")).unwrap();
+        assert_eq!(manifest.artifacts.len(), 1);
+        let artifact = &manifest.artifacts[0];
+        assert_eq!(artifact.kind, ArtifactKind::Module);
+        assert!(artifact.id.ends_with("::M"));
+        assert!(artifact
+            .bytecode
+            .starts_with(dir.join("target").join(MODULES_DIR)));
+        assert!(artifact.bytecode.ends_with("M.mv"));
+        assert!(artifact.bytecode.is_file());
+        assert!(artifact.source_map.is_file());
+    }
+
+    #[test]
+    fn test_interfaces_are_not_written() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = canonicalize(tmp.path());
+        let lib = dir.join("L.move");
+        let stub = dir.join("S.move");
+        let file = dir.join("M.move");
+        let mut db = RootDatabase::default();
+        db.set_project_files(vec![lib.clone(), stub.clone()], vec![]);
+        db.set_interfaces(vec![stub.clone()]);
+        db.update_source(
+            lib,
+            Rope::from("address 0x1 {\nmodule L {\n    public fun f(): u64 { 0 }\n}\n}\n"),
+        );
+        // the interface generated from the bytecode of `S`.
+        db.update_source(
+            stub,
+            Rope::from("address 0x1 {\nmodule S {\n    native public fun g(): u64;\n}\n}\n"),
+        );
+        db.update_source(
+            file.clone(),
+            Rope::from(
+                "address 0x1 {\nmodule M {\n    use 0x1::L;\n    use 0x1::S;\n    \
+                 public fun h(): u64 { L::f() + S::g() }\n}\n}\n",
+            ),
+        );
+        let (_, units) = db.compile_file_with_deps(None, file);
+
+        let manifest = write_units(units.unwrap(), &dir.join("target")).unwrap();
+        let mut ids: Vec<_> = manifest.artifacts.iter().map(|a| a.id.as_str()).collect();
+        ids.sort();
+        assert_eq!(ids.len(), 2);
+        assert!(ids[0].ends_with("::L"));
+        assert!(ids[1].ends_with("::M"));
+    }
+}
@@ -1,18 +1,515 @@
+use crate::utils::{canonicalize, find_move_file};
+use anyhow::Result;
 use move_core_types::account_address::AccountAddress;
 use move_lang::shared::Address;
 use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
-use std::{convert::TryFrom, path::PathBuf};
+use serde_json::{json, Map, Value};
+use std::{
+    collections::BTreeMap,
+    convert::TryFrom,
+    fmt,
+    path::{Path, PathBuf},
+};
 
-#[derive(Default, Debug, Clone, Serialize, Deserialize)]
+/// Section of the client settings holding the server config.
+pub const CONFIG_SECTION: &str = "move";
+/// Project manifest at the root of a workspace folder, with the same keys as `ProjectConfig`.
+pub const MANIFEST_FILE: &str = "Move.toml";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ProjectConfig {
     #[serde(default)]
     pub stdlib_folder: Option<PathBuf>,
+    /// modules or scripts folders checked against another stdlib version than `stdlib_folder`,
+    /// with the stdlib folder of each.
+    #[serde(default)]
+    pub folder_stdlibs: BTreeMap<PathBuf, PathBuf>,
     #[serde(default)]
     pub modules_folders: Vec<PathBuf>,
+    /// folders of scripts, which are checked with the project but never used as dependencies.
+    #[serde(default)]
+    pub scripts_folders: Vec<PathBuf>,
+    /// folders of compiled `.mv` modules the project depends on, without their sources.
+    #[serde(default)]
+    pub dependency_bytecode_folders: Vec<PathBuf>,
     #[serde(deserialize_with = "deserialize_address")]
     #[serde(serialize_with = "serialize_address")]
     #[serde(default)]
     pub sender_address: Option<Address>,
+    /// named addresses, new modules can be placed at one of them.
+    #[serde(default)]
+    pub addresses: BTreeMap<String, String>,
+    /// skip files ignored by git when scanning stdlib and module folders.
+    #[serde(default = "default_true")]
+    pub respect_gitignore: bool,
+    /// max level of server logs forwarded to the client, `warn` if not set.
+    #[serde(default)]
+    pub log_level: Option<String>,
+    /// refer to stdlib files outside the workspace with `move-readonly:` uris,
+    /// for clients which can't open files outside the workspace.
+    #[serde(default)]
+    pub readonly_stdlib: bool,
+    /// attach secondary labels of compiler errors as related information.
+    #[serde(default = "default_true")]
+    pub related_information: bool,
+    /// report errors in stdlib files.
+    #[serde(default = "default_true")]
+    pub stdlib_diagnostics: bool,
+    /// report at most this many diagnostics for a file, all of them if not set.
+    #[serde(default)]
+    pub max_diagnostics_per_file: Option<usize>,
+    /// sort `use` declarations when the document is saved.
+    #[serde(default)]
+    pub organize_uses_on_save: bool,
+    /// remove whitespace at the end of lines when the document is saved.
+    #[serde(default)]
+    pub trim_trailing_whitespace_on_save: bool,
+    /// wait this long after a save before checking, so a burst of saves is checked once.
+    #[serde(default)]
+    pub diagnostic_delay_ms: u64,
+    /// skip move files larger than this many bytes, 0 for no limit.
+    #[serde(default = "default_max_file_size")]
+    pub max_file_size: u64,
+    /// compiler options, to match the settings of command line builds.
+    #[serde(default)]
+    pub compiler_flags: CompilerFlags,
+    /// compile module and script files into the output directory when they are saved.
+    #[serde(default)]
+    pub compile_on_save: bool,
+    /// output directory of compilations, `target` under the workspace root if not set.
+    #[serde(default)]
+    pub out_dir: Option<PathBuf>,
+    /// modules or scripts folders compiled to another output directory than `out_dir`,
+    /// with the output directory of each.
+    #[serde(default)]
+    pub folder_out_dirs: BTreeMap<PathBuf, PathBuf>,
+}
+
+/// Options of checks and compilations.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
+pub struct CompilerFlags {
+    /// run the bytecode verifier on compiled units.
+    #[serde(default)]
+    pub verify: bool,
+    /// errors whose message contains one of these are not reported, like `Unused`.
+    /// They still fail compilations.
+    #[serde(default)]
+    pub silence: Vec<String>,
+}
+
+impl Default for ProjectConfig {
+    fn default() -> Self {
+        Self {
+            stdlib_folder: None,
+            folder_stdlibs: BTreeMap::new(),
+            modules_folders: vec![],
+            scripts_folders: vec![],
+            dependency_bytecode_folders: vec![],
+            sender_address: None,
+            addresses: BTreeMap::new(),
+            respect_gitignore: true,
+            log_level: None,
+            readonly_stdlib: false,
+            related_information: true,
+            stdlib_diagnostics: true,
+            max_diagnostics_per_file: None,
+            organize_uses_on_save: false,
+            trim_trailing_whitespace_on_save: false,
+            diagnostic_delay_ms: 0,
+            max_file_size: default_max_file_size(),
+            compiler_flags: CompilerFlags::default(),
+            compile_on_save: false,
+            out_dir: None,
+            folder_out_dirs: BTreeMap::new(),
+        }
+    }
+}
+
+impl ProjectConfig {
+    /// Config of a workspace folder, or of the whole workspace if there is no folder.
+    /// Settings are applied in order, later ones override earlier ones:
+    /// defaults, the folder's `Move.toml`, client settings.
+    /// Relative paths are resolved against the folder.
+    pub fn load(folder: Option<&Path>, client_settings: Value) -> Result<Self> {
+        let manifest = match folder {
+            Some(f) => read_manifest(f)?.unwrap_or_default(),
+            None => Map::new(),
+        };
+        Self::from_settings(folder, manifest, client_settings)
+    }
+
+    /// Like `load`, but a manifest which can't be read or doesn't match the settings is left
+    /// out and its error returned along the config, so that a broken `Move.toml` of one folder
+    /// doesn't leave the whole workspace without a config.
+    /// Errors of the client settings are still errors.
+    pub fn load_lenient(
+        folder: Option<&Path>,
+        client_settings: Value,
+    ) -> Result<(Self, Option<anyhow::Error>)> {
+        match Self::load(folder, client_settings.clone()) {
+            Ok(config) => Ok((config, None)),
+            Err(e) => {
+                let config = Self::from_settings(folder, Map::new(), client_settings)?;
+                Ok((config, Some(e)))
+            }
+        }
+    }
+
+    fn from_settings(
+        folder: Option<&Path>,
+        mut settings: Map<String, Value>,
+        client_settings: Value,
+    ) -> Result<Self> {
+        if let Value::Object(client_settings) = client_settings {
+            settings.extend(client_settings.into_iter().filter(|(_, v)| !v.is_null()));
+        }
+        let mut config: ProjectConfig = serde_json::from_value(Value::Object(settings))?;
+        if let Some(folder) = folder {
+            config.resolve_paths(folder);
+        }
+        Ok(config)
+    }
+
+    /// Combine the configs of several workspace folders into one project.
+    ///
+    /// Modules, scripts and bytecode folders of all of them are checked.
+    /// `stdlib_folder` and `sender_address` are the ones of the first folder which sets them,
+    /// and a named address the first definition of it.
+    /// Folders of a workspace folder with another stdlib are checked against it, and compiled
+    /// to its output directory if it has another one.
+    /// Every other setting, like `log_level` or the compiler flags, is the one of the first
+    /// folder, defaults included: those apply to the whole server, not to a folder.
+    pub fn combine(configs: Vec<ProjectConfig>) -> ProjectConfig {
+        let mut configs = configs.into_iter();
+        let mut combined = configs.next().unwrap_or_default();
+        for config in configs {
+            match (&combined.stdlib_folder, &config.stdlib_folder) {
+                (None, _) => combined.stdlib_folder = config.stdlib_folder.clone(),
+                (Some(stdlib), Some(other)) if stdlib != other => {
+                    let folders = config.modules_folders.iter().chain(&config.scripts_folders);
+                    for f in folders {
+                        combined
+                            .folder_stdlibs
+                            .entry(f.clone())
+                            .or_insert_with(|| other.clone());
+                    }
+                }
+                _ => {}
+            }
+            for (folder, stdlib) in config.folder_stdlibs {
+                combined.folder_stdlibs.entry(folder).or_insert(stdlib);
+            }
+            if let Some(out_dir) = config.out_dir.as_ref() {
+                if combined.out_dir.as_ref() != Some(out_dir) {
+                    let folders = config.modules_folders.iter().chain(&config.scripts_folders);
+                    for f in folders {
+                        combined
+                            .folder_out_dirs
+                            .entry(f.clone())
+                            .or_insert_with(|| out_dir.clone());
+                    }
+                }
+            }
+            for (folder, out_dir) in config.folder_out_dirs {
+                combined.folder_out_dirs.entry(folder).or_insert(out_dir);
+            }
+            if combined.sender_address.is_none() {
+                combined.sender_address = config.sender_address;
+            }
+            for (name, address) in config.addresses {
+                combined.addresses.entry(name).or_insert(address);
+            }
+            for f in config.modules_folders {
+                if !combined.modules_folders.contains(&f) {
+                    combined.modules_folders.push(f);
+                }
+            }
+            for f in config.scripts_folders {
+                if !combined.scripts_folders.contains(&f) {
+                    combined.scripts_folders.push(f);
+                }
+            }
+            for f in config.dependency_bytecode_folders {
+                if !combined.dependency_bytecode_folders.contains(&f) {
+                    combined.dependency_bytecode_folders.push(f);
+                }
+            }
+        }
+        combined
+    }
+
+    fn resolve_paths(&mut self, base: &Path) {
+        let resolve = |p: &mut PathBuf| {
+            if !p.as_os_str().is_empty() && p.is_relative() {
+                *p = base.join(p.as_path());
+            }
+        };
+        self.stdlib_folder.iter_mut().for_each(resolve);
+        self.folder_stdlibs = std::mem::take(&mut self.folder_stdlibs)
+            .into_iter()
+            .map(|(mut folder, mut stdlib)| {
+                resolve(&mut folder);
+                resolve(&mut stdlib);
+                (folder, stdlib)
+            })
+            .collect();
+        self.out_dir.iter_mut().for_each(resolve);
+        self.folder_out_dirs = std::mem::take(&mut self.folder_out_dirs)
+            .into_iter()
+            .map(|(mut folder, mut out_dir)| {
+                resolve(&mut folder);
+                resolve(&mut out_dir);
+                (folder, out_dir)
+            })
+            .collect();
+        self.modules_folders.iter_mut().for_each(resolve);
+        self.scripts_folders.iter_mut().for_each(resolve);
+        self.dependency_bytecode_folders
+            .iter_mut()
+            .for_each(resolve);
+    }
+
+    /// Output directory of compilations of `file`: the one of the innermost folder with its
+    /// own, `out_dir` otherwise.
+    pub fn out_dir_of(&self, file: &Path) -> Option<&Path> {
+        self.folder_out_dirs
+            .iter()
+            .filter(|(folder, _)| file.starts_with(canonicalize(folder)))
+            .max_by_key(|(folder, _)| folder.components().count())
+            .map(|(_, out_dir)| out_dir.as_path())
+            .or_else(|| self.out_dir.as_deref())
+    }
+
+    /// Problems of the stdlib and modules folders which would leave the project without files.
+    pub fn problems(&self) -> Vec<ConfigProblem> {
+        let folders: Vec<(&str, &PathBuf)> = self
+            .stdlib_folder
+            .iter()
+            .map(|f| ("stdlib_folder", f))
+            .chain(self.modules_folders.iter().map(|f| ("modules_folders", f)))
+            .collect();
+        let mut problems = vec![];
+        for (setting, folder) in folders.iter() {
+            let problem = if !folder.exists() {
+                ConfigProblem::Missing {
+                    setting: setting.to_string(),
+                    folder: folder.to_path_buf(),
+                }
+            } else if find_move_file(folder, self.respect_gitignore).is_empty() {
+                ConfigProblem::NoMoveFiles {
+                    setting: setting.to_string(),
+                    folder: folder.to_path_buf(),
+                }
+            } else {
+                continue;
+            };
+            problems.push(problem);
+        }
+        for (i, (_, first)) in folders.iter().enumerate() {
+            for (_, second) in &folders[i + 1..] {
+                if first.starts_with(second) || second.starts_with(first) {
+                    problems.push(ConfigProblem::Overlap {
+                        first: first.to_path_buf(),
+                        second: second.to_path_buf(),
+                    });
+                }
+            }
+        }
+        problems
+    }
+}
+
+/// A config under which files are silently left out of the project, or checked twice.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum ConfigProblem {
+    Missing {
+        setting: String,
+        folder: PathBuf,
+    },
+    NoMoveFiles {
+        setting: String,
+        folder: PathBuf,
+    },
+    /// one folder contains the other, so its files are in the project twice.
+    Overlap {
+        first: PathBuf,
+        second: PathBuf,
+    },
+}
+
+impl fmt::Display for ConfigProblem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigProblem::Missing { setting, folder } => {
+                write!(f, "{} {} doesn't exist", setting, folder.display())
+            }
+            ConfigProblem::NoMoveFiles { setting, folder } => {
+                write!(f, "{} {} has no .move files", setting, folder.display())
+            }
+            ConfigProblem::Overlap { first, second } => write!(
+                f,
+                "folders {} and {} overlap",
+                first.display(),
+                second.display()
+            ),
+        }
+    }
+}
+
+/// JSON Schema of the settings of `ProjectConfig`, for editors to build their settings UI
+/// from and to validate user settings with. Defaults are the ones of `ProjectConfig::default`.
+pub fn config_schema() -> Value {
+    let path = json!({ "type": "string" });
+    let optional_path = json!({ "type": ["string", "null"] });
+    let paths = json!({ "type": "array", "items": path });
+    let path_map = json!({ "type": "object", "additionalProperties": path });
+    let mut properties = json!({
+        "stdlib_folder": describe(&optional_path, "Folder of the stdlib modules."),
+        "folder_stdlibs": describe(
+            &path_map,
+            "Modules or scripts folders checked against another stdlib version than `stdlib_folder`, with the stdlib folder of each.",
+        ),
+        "modules_folders": describe(&paths, "Folders of the modules of the project."),
+        "scripts_folders": describe(
+            &paths,
+            "Folders of scripts, which are checked with the project but never used as dependencies.",
+        ),
+        "dependency_bytecode_folders": describe(
+            &paths,
+            "Folders of compiled `.mv` modules the project depends on, without their sources.",
+        ),
+        "sender_address": {
+            "type": ["string", "null"],
+            "pattern": "^(0x[0-9a-fA-F]+)?$",
+            "description": "Address of the modules outside of address blocks.",
+        },
+        "addresses": {
+            "type": "object",
+            "additionalProperties": { "type": "string" },
+            "description": "Named addresses, new modules can be placed at one of them.",
+        },
+        "respect_gitignore": describe(
+            &json!({ "type": "boolean" }),
+            "Skip files ignored by git when scanning stdlib and module folders.",
+        ),
+        "log_level": {
+            "type": ["string", "null"],
+            "examples": ["off", "error", "warn", "info", "debug", "trace"],
+            "description": "Max level of server logs forwarded to the client, `warn` if not set.",
+        },
+        "readonly_stdlib": describe(
+            &json!({ "type": "boolean" }),
+            "Refer to stdlib files outside the workspace with `move-readonly:` uris, for clients which can't open files outside the workspace.",
+        ),
+        "related_information": describe(
+            &json!({ "type": "boolean" }),
+            "Attach secondary labels of compiler errors as related information.",
+        ),
+        "stdlib_diagnostics": describe(&json!({ "type": "boolean" }), "Report errors in stdlib files."),
+        "max_diagnostics_per_file": {
+            "type": ["integer", "null"],
+            "minimum": 0,
+            "description": "Report at most this many diagnostics for a file, all of them if not set.",
+        },
+        "organize_uses_on_save": describe(
+            &json!({ "type": "boolean" }),
+            "Sort `use` declarations when the document is saved.",
+        ),
+        "trim_trailing_whitespace_on_save": describe(
+            &json!({ "type": "boolean" }),
+            "Remove whitespace at the end of lines when the document is saved.",
+        ),
+        "diagnostic_delay_ms": {
+            "type": "integer",
+            "minimum": 0,
+            "description": "Wait this long after a save before checking, so a burst of saves is checked once.",
+        },
+        "max_file_size": {
+            "type": "integer",
+            "minimum": 0,
+            "description": "Skip move files larger than this many bytes, 0 for no limit.",
+        },
+        "compiler_flags": {
+            "type": "object",
+            "properties": {
+                "verify": {
+                    "type": "boolean",
+                    "default": false,
+                    "description": "Run the bytecode verifier on compiled units.",
+                },
+                "silence": {
+                    "type": "array",
+                    "items": { "type": "string" },
+                    "default": [],
+                    "description": "Errors whose message contains one of these are not reported, like `Unused`. They still fail compilations.",
+                },
+            },
+            "description": "Compiler options, to match the settings of command line builds.",
+        },
+        "compile_on_save": describe(
+            &json!({ "type": "boolean" }),
+            "Compile module and script files into the output directory when they are saved.",
+        ),
+        "out_dir": describe(
+            &optional_path,
+            "Output directory of compilations, `target` under the workspace root if not set.",
+        ),
+        "folder_out_dirs": describe(
+            &path_map,
+            "Modules or scripts folders compiled to another output directory than `out_dir`, with the output directory of each.",
+        ),
+    });
+    let defaults = serde_json::to_value(ProjectConfig::default()).unwrap_or_default();
+    if let (Value::Object(properties), Value::Object(defaults)) = (&mut properties, defaults) {
+        for (name, default) in defaults {
+            if let Some(Value::Object(property)) = properties.get_mut(&name) {
+                property.insert("default".to_string(), default);
+            }
+        }
+    }
+    json!({
+        "$schema": "http://json-schema.org/draft-07/schema#",
+        "title": "Move language server settings",
+        "description": format!("Settings of the `{}` section of the client, or of `{}`.", CONFIG_SECTION, MANIFEST_FILE),
+        "type": "object",
+        "properties": properties,
+    })
+}
+
+/// `schema` with a description.
+fn describe(schema: &Value, description: &str) -> Value {
+    let mut schema = schema.clone();
+    schema["description"] = json!(description);
+    schema
+}
+
+#[cfg(feature = "fs")]
+fn read_manifest(folder: &Path) -> Result<Option<Map<String, Value>>> {
+    let path = folder.join(MANIFEST_FILE);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let content = std::fs::read_to_string(&path)?;
+    match toml::from_str::<Value>(&content) {
+        Ok(Value::Object(settings)) => Ok(Some(settings)),
+        Ok(_) => anyhow::bail!("{} is not a table", path.display()),
+        Err(e) => anyhow::bail!("invalid {}, {}", path.display(), e),
+    }
+}
+
+/// Manifests are only read with the `fs` feature.
+#[cfg(not(feature = "fs"))]
+fn read_manifest(_folder: &Path) -> Result<Option<Map<String, Value>>> {
+    Ok(None)
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_max_file_size() -> u64 {
+    1 << 20
 }
 
 fn deserialize_address<'de, D>(d: D) -> Result<Option<Address>, D::Error>
@@ -41,7 +538,10 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::config::ProjectConfig;
+    use crate::config::{config_schema, ProjectConfig, MANIFEST_FILE};
+    use move_lang::shared::Address;
+    use serde_json::{json, Value};
+    use std::path::{Path, PathBuf};
 
     #[test]
     fn test_config_parse() {
@@ -85,5 +585,229 @@ mod tests {
 
         let config: ProjectConfig = serde_json::from_str(source).unwrap();
         assert!(config.stdlib_folder.is_some());
+        assert!(config.respect_gitignore);
+
+        let source = r#"
+    {
+        "modules_folders": [],
+        "respect_gitignore": false
+    }
+        "#;
+        let config: ProjectConfig = serde_json::from_str(source).unwrap();
+        assert!(!config.respect_gitignore);
+        assert!(config.related_information);
+        assert!(config.stdlib_diagnostics);
+        assert!(config.max_diagnostics_per_file.is_none());
+        assert!(!config.organize_uses_on_save);
+        assert!(!config.trim_trailing_whitespace_on_save);
+        assert_eq!(config.diagnostic_delay_ms, 0);
+        assert_eq!(config.max_file_size, 1 << 20);
+        assert_eq!(config.compiler_flags, CompilerFlags::default());
+
+        let source = r#"
+    {
+        "related_information": false,
+        "stdlib_diagnostics": false,
+        "max_diagnostics_per_file": 10,
+        "organize_uses_on_save": true,
+        "trim_trailing_whitespace_on_save": true,
+        "diagnostic_delay_ms": 200,
+        "compiler_flags": { "verify": true, "silence": ["Unused"] }
+    }
+        "#;
+        let config: ProjectConfig = serde_json::from_str(source).unwrap();
+        assert!(config.organize_uses_on_save);
+        assert!(config.trim_trailing_whitespace_on_save);
+        assert!(!config.related_information);
+        assert!(!config.stdlib_diagnostics);
+        assert_eq!(config.max_diagnostics_per_file, Some(10));
+        assert_eq!(config.diagnostic_delay_ms, 200);
+        assert!(config.compiler_flags.verify);
+        assert_eq!(config.compiler_flags.silence, vec!["Unused".to_string()]);
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(
+            dir.join(MANIFEST_FILE),
+            "stdlib_folder = \"stdlib\"\nmodules_folders = [\"modules\"]\nsender_address = \"0x1\"\n\
+             scripts_folders = [\"scripts\"]\n\
+             dependency_bytecode_folders = [\"deps\"]\n",
+        )
+        .unwrap();
+
+        let config = ProjectConfig::load(Some(&dir), Value::Null).unwrap();
+        assert_eq!(config.stdlib_folder, Some(dir.join("stdlib")));
+        assert_eq!(config.modules_folders, vec![dir.join("modules")]);
+        assert_eq!(config.scripts_folders, vec![dir.join("scripts")]);
+        assert_eq!(config.dependency_bytecode_folders, vec![dir.join("deps")]);
+        assert_eq!(config.sender_address, Address::parse_str("0x1").ok());
+
+        // client settings override the manifest, null values are ignored.
+        let settings = json!({ "sender_address": "0x2", "stdlib_folder": null });
+        let config = ProjectConfig::load(Some(&dir), settings).unwrap();
+        assert_eq!(config.stdlib_folder, Some(dir.join("stdlib")));
+        assert_eq!(config.sender_address, Address::parse_str("0x2").ok());
+    }
+
+    #[test]
+    fn test_config_problems() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::create_dir_all(dir.join("stdlib")).unwrap();
+        std::fs::create_dir_all(dir.join("empty")).unwrap();
+        std::fs::write(dir.join("stdlib").join("Signer.move"), "").unwrap();
+
+        let config = ProjectConfig {
+            stdlib_folder: Some(dir.join("stdlib")),
+            modules_folders: vec![dir.join("missing"), dir.join("empty"), dir.clone()],
+            ..Default::default()
+        };
+        assert_eq!(
+            config.problems(),
+            vec![
+                ConfigProblem::Missing {
+                    setting: "modules_folders".to_string(),
+                    folder: dir.join("missing"),
+                },
+                ConfigProblem::NoMoveFiles {
+                    setting: "modules_folders".to_string(),
+                    folder: dir.join("empty"),
+                },
+                ConfigProblem::Overlap {
+                    first: dir.join("stdlib"),
+                    second: dir.clone(),
+                },
+                ConfigProblem::Overlap {
+                    first: dir.join("missing"),
+                    second: dir.clone(),
+                },
+                ConfigProblem::Overlap {
+                    first: dir.join("empty"),
+                    second: dir.clone(),
+                },
+            ]
+        );
+
+        let config = ProjectConfig {
+            stdlib_folder: Some(dir.join("stdlib")),
+            ..Default::default()
+        };
+        assert!(config.problems().is_empty());
+    }
+
+    #[test]
+    fn test_combine_configs() {
+        let first = ProjectConfig {
+            modules_folders: vec![PathBuf::from("/a/modules")],
+            addresses: vec![("Std".to_string(), "0x1".to_string())]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        let second = ProjectConfig {
+            stdlib_folder: Some(PathBuf::from("/b/stdlib")),
+            modules_folders: vec![PathBuf::from("/b/modules"), PathBuf::from("/a/modules")],
+            addresses: vec![
+                ("Std".to_string(), "0x2".to_string()),
+                ("Swap".to_string(), "0x3".to_string()),
+            ]
+            .into_iter()
+            .collect(),
+            ..Default::default()
+        };
+        let config = ProjectConfig::combine(vec![first, second]);
+        assert_eq!(config.stdlib_folder, Some(PathBuf::from("/b/stdlib")));
+        assert_eq!(
+            config.modules_folders,
+            vec![PathBuf::from("/a/modules"), PathBuf::from("/b/modules")]
+        );
+        assert_eq!(config.addresses.get("Std").map(String::as_str), Some("0x1"));
+        assert_eq!(
+            config.addresses.get("Swap").map(String::as_str),
+            Some("0x3")
+        );
+        assert!(config.folder_stdlibs.is_empty());
+
+        // a folder with an older stdlib keeps it.
+        let third = ProjectConfig {
+            stdlib_folder: Some(PathBuf::from("/c/stdlib")),
+            modules_folders: vec![PathBuf::from("/c/modules")],
+            ..Default::default()
+        };
+        let config = ProjectConfig::combine(vec![config, third]);
+        assert_eq!(config.stdlib_folder, Some(PathBuf::from("/b/stdlib")));
+        assert_eq!(
+            config.folder_stdlibs.get(&PathBuf::from("/c/modules")),
+            Some(&PathBuf::from("/c/stdlib"))
+        );
+
+        // server wide settings are the ones of the first folder, even when they are defaults.
+        let fourth = ProjectConfig {
+            log_level: Some("debug".to_string()),
+            compile_on_save: true,
+            ..Default::default()
+        };
+        let config = ProjectConfig::combine(vec![config, fourth]);
+        assert_eq!(config.log_level, None);
+        assert!(!config.compile_on_save);
+    }
+
+    #[test]
+    fn test_broken_manifest() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        std::fs::write(dir.join(MANIFEST_FILE), "modules_folders = [\"modules\"\n").unwrap();
+
+        assert!(ProjectConfig::load(Some(&dir), Value::Null).is_err());
+        let settings = json!({ "modules_folders": ["src"] });
+        let (config, error) = ProjectConfig::load_lenient(Some(&dir), settings).unwrap();
+        assert!(error.unwrap().to_string().contains(MANIFEST_FILE));
+        assert_eq!(config.modules_folders, vec![dir.join("src")]);
+
+        // client settings are not skipped.
+        let settings = json!({ "modules_folders": "src" });
+        assert!(ProjectConfig::load_lenient(Some(&dir), settings).is_err());
+    }
+
+    #[test]
+    fn test_config_schema() {
+        let schema = config_schema();
+        let properties = schema["properties"].as_object().unwrap();
+        let defaults = serde_json::to_value(ProjectConfig::default()).unwrap();
+        let settings: Vec<_> = defaults.as_object().unwrap().keys().collect();
+        assert_eq!(properties.keys().collect::<Vec<_>>(), settings);
+        assert_eq!(properties["respect_gitignore"]["default"], json!(true));
+        assert_eq!(properties["max_file_size"]["default"], json!(1 << 20));
+        assert_eq!(properties["stdlib_folder"]["default"], Value::Null);
+    }
+
+    #[test]
+    fn test_folder_out_dirs() {
+        let first = ProjectConfig {
+            modules_folders: vec![PathBuf::from("/a/modules")],
+            out_dir: Some(PathBuf::from("/a/build")),
+            ..Default::default()
+        };
+        let second = ProjectConfig {
+            modules_folders: vec![PathBuf::from("/b/modules")],
+            out_dir: Some(PathBuf::from("/b/build")),
+            ..Default::default()
+        };
+        let config = ProjectConfig::combine(vec![first, second]);
+        assert_eq!(
+            config.out_dir_of(Path::new("/a/modules/M.move")),
+            Some(Path::new("/a/build"))
+        );
+        assert_eq!(
+            config.out_dir_of(Path::new("/b/modules/M.move")),
+            Some(Path::new("/b/build"))
+        );
+        assert_eq!(
+            ProjectConfig::default().out_dir_of(Path::new("/a/modules/M.move")),
+            None
+        );
     }
 }
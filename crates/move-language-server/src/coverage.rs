@@ -0,0 +1,166 @@
+//! Code coverage from an execution trace of the vm, as written with `MOVE_VM_TRACE`:
+//! a `<address>::<module>::<function>,<pc>` line per executed instruction.
+//!
+//! Instructions are mapped back to source ranges with the source maps of the units
+//! compiled from the current sources, so the trace must come from the same sources.
+
+use move_lang::{compiled_unit::CompiledUnit, shared::Address};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    ops::Range,
+};
+use vm::{access::ModuleAccess, file_format::FunctionDefinitionIndex};
+
+/// Trace file read from the output directory if none is given.
+pub const TRACE_FILE: &str = "trace";
+
+/// Executed instructions of every function, keyed by `<address>::<module>::<function>`.
+pub type Trace = HashMap<String, BTreeSet<u16>>;
+
+/// Source ranges of a file, in byte offsets.
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct FileCoverage {
+    /// ranges of which some instruction was executed.
+    pub covered: Vec<Range<usize>>,
+    pub uncovered: Vec<Range<usize>>,
+}
+
+/// Parse the content of a trace file, malformed lines are skipped.
+pub fn parse_trace(content: &str) -> Trace {
+    let mut trace = Trace::new();
+    for line in content.lines() {
+        let comma = match line.rfind(',') {
+            Some(i) => i,
+            None => continue,
+        };
+        let (function, pc) = (&line[..comma], &line[comma + 1..]);
+        let (function, pc) = match (function_key(function.trim()), pc.trim().parse::<u16>()) {
+            (Some(f), Ok(pc)) => (f, pc),
+            _ => continue,
+        };
+        trace.entry(function).or_default().insert(pc);
+    }
+    trace
+}
+
+/// `0x00..01::M::f` or `00..01::M::f` => `0x1::M::f`, as keys are built from compiled units.
+fn function_key(function: &str) -> Option<String> {
+    let segments: Vec<_> = function.split("::").collect();
+    match segments.as_slice() {
+        [address, module, name] => {
+            let address = if address.starts_with("0x") {
+                address.to_string()
+            } else {
+                format!("0x{}", address)
+            };
+            let address = Address::parse_str(&address).ok()?;
+            Some(format!("{}::{}::{}", address, module, name))
+        }
+        _ => None,
+    }
+}
+
+/// Coverage of the functions of the compiled modules in `units`, by source file.
+/// A source range is covered if any of its instructions was executed.
+pub fn coverage(units: &[CompiledUnit], trace: &Trace) -> BTreeMap<&'static str, FileCoverage> {
+    let mut ranges: BTreeMap<&'static str, BTreeMap<(usize, usize), bool>> = BTreeMap::new();
+    for unit in units {
+        let (ident, module, source_map) = match unit {
+            CompiledUnit::Module {
+                ident,
+                module,
+                source_map,
+            } => (&ident.0.value, module, source_map),
+            CompiledUnit::Script { .. } => continue,
+        };
+        for (idx, def) in module.function_defs().iter().enumerate() {
+            let code = match &def.code {
+                Some(code) => code,
+                None => continue,
+            };
+            let handle = module.function_handle_at(def.function);
+            let key = format!(
+                "{}::{}::{}",
+                ident.address,
+                ident.name,
+                module.identifier_at(handle.name)
+            );
+            let executed = trace.get(&key);
+            let function_map =
+                match source_map.get_function_source_map(FunctionDefinitionIndex(idx as u16)) {
+                    Ok(m) => m,
+                    Err(_) => continue,
+                };
+            for pc in 0..code.code.len() as u16 {
+                let loc = match function_map.get_code_location(pc) {
+                    Some(loc) => loc,
+                    None => continue,
+                };
+                let span = loc.span();
+                let covered = executed.map_or(false, |e| e.contains(&pc));
+                *ranges
+                    .entry(loc.file())
+                    .or_default()
+                    .entry((span.start().to_usize(), span.end().to_usize()))
+                    .or_default() |= covered;
+            }
+        }
+    }
+
+    ranges
+        .into_iter()
+        .map(|(file, spans)| {
+            let mut coverage = FileCoverage::default();
+            for ((start, end), covered) in spans {
+                if covered {
+                    coverage.covered.push(start..end);
+                } else {
+                    coverage.uncovered.push(start..end);
+                }
+            }
+            (file, coverage)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::RootDatabase;
+    use std::path::PathBuf;
+    use xi_rope::Rope;
+
+    #[test]
+    fn test_parse_trace() {
+        let trace = parse_trace(
+            "00000000000000000000000000000001::M::f,0\n0x1::M::f,2\nnot a trace line\n0x1::M::g,x\n",
+        );
+        assert_eq!(trace.len(), 1);
+        let key = format!("{}::M::f", Address::parse_str("0x1").unwrap());
+        assert_eq!(trace[&key], vec![0, 2].into_iter().collect());
+    }
+
+    #[test]
+    fn test_coverage() {
+        let file = PathBuf::from("/m.move");
+        let mut db = RootDatabase::default();
+        db.update_source(
+            file.clone(),
+            Rope::from(
+                "address 0x1 {\nmodule M {\n    public fun f(): u64 { 0 }\n    public fun g(): u64 { 1 }\n}\n}\n",
+            ),
+        );
+        let (_, units) = db.compile_file(None, file);
+        let units = units.unwrap();
+
+        let none = coverage(&units, &Trace::new());
+        let file_coverage = none.values().next().unwrap();
+        assert!(file_coverage.covered.is_empty());
+        assert!(!file_coverage.uncovered.is_empty());
+
+        let trace = parse_trace("0x1::M::f,0\n0x1::M::f,1\n");
+        let file_coverage = coverage(&units, &trace).into_iter().next().unwrap().1;
+        assert!(!file_coverage.covered.is_empty());
+        assert!(!file_coverage.uncovered.is_empty());
+    }
+}
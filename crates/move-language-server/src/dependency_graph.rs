@@ -0,0 +1,268 @@
+//! Module dependency graph, built from the ASTs of the module files.
+
+use crate::salsa::{
+    module_index_query::{ModuleIndex, ModuleKey},
+    FileId,
+};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fmt::Write,
+};
+use tower_lsp::lsp_types::Range;
+
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+pub struct Edge {
+    pub from: ModuleKey,
+    pub to: ModuleKey,
+    /// file and range of the `use` declaration.
+    pub file: FileId,
+    pub range: Range,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DependencyGraph {
+    /// modules defined in the project, with the file defining them.
+    pub modules: BTreeMap<ModuleKey, FileId>,
+    pub edges: Vec<Edge>,
+}
+
+impl DependencyGraph {
+    /// Graph of modules, given with the file defining them and the modules they use.
+    /// A module used more than once, like with `use 0x1::M::{Self, T}`, makes one edge,
+    /// at its first use.
+    pub fn build(
+        modules: impl IntoIterator<Item = (FileId, ModuleKey, Vec<(ModuleKey, Range)>)>,
+    ) -> Self {
+        let mut graph = Self::default();
+        for (file, key, uses) in modules {
+            let mut used = HashSet::new();
+            for (to, range) in uses {
+                if used.insert(to.clone()) {
+                    graph.edges.push(Edge {
+                        from: key.clone(),
+                        to,
+                        file: file.clone(),
+                        range,
+                    });
+                }
+            }
+            graph.modules.insert(key, file);
+        }
+        graph
+    }
+
+    /// Graph of the modules defined in `files`, from the `module_dependencies` query.
+    pub fn of_files(db: &dyn ModuleIndex, files: impl IntoIterator<Item = FileId>) -> Self {
+        Self::build(files.into_iter().flat_map(|file| {
+            db.module_dependencies(file.clone())
+                .iter()
+                .map(|(key, uses)| (file.clone(), key.clone(), uses.clone()))
+                .collect::<Vec<_>>()
+        }))
+    }
+
+    pub fn dependencies<'a>(&'a self, module: &'a ModuleKey) -> impl Iterator<Item = &'a Edge> {
+        self.edges.iter().filter(move |e| &e.from == module)
+    }
+
+    /// Edges participating in dependency cycles, grouped by strongly connected component.
+    pub fn cycles(&self) -> Vec<Vec<&Edge>> {
+        let components = self.strongly_connected_components();
+        let mut component_of = HashMap::new();
+        for (idx, c) in components.iter().enumerate() {
+            for m in c {
+                component_of.insert(*m, idx);
+            }
+        }
+
+        let mut cycles: BTreeMap<usize, Vec<&Edge>> = BTreeMap::new();
+        for e in &self.edges {
+            let (from, to) = (component_of.get(&e.from), component_of.get(&e.to));
+            if let (Some(f), Some(t)) = (from, to) {
+                let is_cycle = f == t && (components[*f].len() > 1 || e.from == e.to);
+                if is_cycle {
+                    cycles.entry(*f).or_default().push(e);
+                }
+            }
+        }
+        cycles.into_iter().map(|(_, edges)| edges).collect()
+    }
+
+    /// Tarjan's algorithm.
+    fn strongly_connected_components(&self) -> Vec<Vec<&ModuleKey>> {
+        struct State<'a> {
+            index: usize,
+            indices: HashMap<&'a ModuleKey, usize>,
+            low_links: HashMap<&'a ModuleKey, usize>,
+            stack: Vec<&'a ModuleKey>,
+            on_stack: HashSet<&'a ModuleKey>,
+            components: Vec<Vec<&'a ModuleKey>>,
+        }
+
+        fn connect<'a>(graph: &'a DependencyGraph, state: &mut State<'a>, node: &'a ModuleKey) {
+            state.indices.insert(node, state.index);
+            state.low_links.insert(node, state.index);
+            state.index += 1;
+            state.stack.push(node);
+            state.on_stack.insert(node);
+
+            for e in graph.dependencies(node) {
+                let to = &e.to;
+                if !state.indices.contains_key(to) {
+                    connect(graph, state, to);
+                    let low = state.low_links[node].min(state.low_links[to]);
+                    state.low_links.insert(node, low);
+                } else if state.on_stack.contains(to) {
+                    let low = state.low_links[node].min(state.indices[to]);
+                    state.low_links.insert(node, low);
+                }
+            }
+
+            if state.low_links[node] == state.indices[node] {
+                let mut component = vec![];
+                while let Some(m) = state.stack.pop() {
+                    state.on_stack.remove(m);
+                    component.push(m);
+                    if m == node {
+                        break;
+                    }
+                }
+                state.components.push(component);
+            }
+        }
+
+        let mut state = State {
+            index: 0,
+            indices: HashMap::new(),
+            low_links: HashMap::new(),
+            stack: vec![],
+            on_stack: HashSet::new(),
+            components: vec![],
+        };
+        for node in self.modules.keys() {
+            if !state.indices.contains_key(node) {
+                connect(self, &mut state, node);
+            }
+        }
+        state.components
+    }
+
+    pub fn to_json(&self) -> Value {
+        let modules: Vec<_> = self
+            .modules
+            .iter()
+            .map(|(m, f)| json!({ "module": m, "file": f }))
+            .collect();
+        json!({ "modules": modules, "edges": self.edges })
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        let nodes: BTreeSet<_> = self
+            .modules
+            .keys()
+            .chain(self.edges.iter().map(|e| &e.to))
+            .collect();
+        for n in nodes {
+            let _ = writeln!(dot, "    \"{}\";", n);
+        }
+        let edges: BTreeSet<_> = self.edges.iter().map(|e| (&e.from, &e.to)).collect();
+        for (from, to) in edges {
+            let _ = writeln!(dot, "    \"{}\" -> \"{}\";", from, to);
+        }
+        dot.push('}');
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::RootDatabase;
+    use move_lang::shared::Address;
+    use std::path::PathBuf;
+    use xi_rope::Rope;
+
+    fn module(name: &str, uses: &[&str]) -> (FileId, ModuleKey, Vec<(ModuleKey, Range)>) {
+        (
+            PathBuf::from(format!("/{}.move", name)),
+            ModuleKey::new("0x1", name),
+            uses.iter()
+                .map(|u| (ModuleKey::new("0x1", u), Range::default()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn test_cycles() {
+        let modules = vec![
+            module("A", &["B"]),
+            module("B", &["C"]),
+            module("C", &["A", "D"]),
+            module("D", &[]),
+            module("E", &["E"]),
+        ];
+        let graph = DependencyGraph::build(modules);
+        let cycles = graph.cycles();
+        assert_eq!(cycles.len(), 2);
+        let mut cycle_edges: Vec<_> = cycles
+            .iter()
+            .map(|c| {
+                c.iter()
+                    .map(|e| format!("{}->{}", e.from.name, e.to.name))
+                    .collect::<Vec<_>>()
+            })
+            .collect();
+        cycle_edges.sort();
+        assert_eq!(
+            cycle_edges,
+            vec![vec!["A->B", "B->C", "C->A"], vec!["E->E"]]
+        );
+    }
+
+    #[test]
+    fn test_to_dot() {
+        let modules = vec![module("A", &["B"]), module("B", &[])];
+        let graph = DependencyGraph::build(modules);
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(
+            graph.to_dot(),
+            "digraph dependencies {\n    \"0x1::A\";\n    \"0x1::B\";\n    \"0x1::A\" -> \"0x1::B\";\n}"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_uses() {
+        let graph = DependencyGraph::build(vec![module("A", &["B", "B", "C", "B"])]);
+        let edges: Vec<_> = graph.edges.iter().map(|e| e.to.name.as_str()).collect();
+        assert_eq!(edges, vec!["B", "C"]);
+    }
+
+    #[test]
+    fn test_of_files() {
+        let a = PathBuf::from("/a.move");
+        let b = PathBuf::from("/b.move");
+        let mut db = RootDatabase::default();
+        db.set_sender(Address::parse_str("0x2").ok());
+        db.update_source(
+            a.clone(),
+            Rope::from("address 0x1 {\nmodule A {\n    use 0x2::B::{Self, T};\n    use 0x2::B as C;\n}\n}\n"),
+        );
+        db.update_source(b.clone(), Rope::from("module B {\n    struct T {}\n}\n"));
+
+        let graph = DependencyGraph::of_files(&db, vec![a.clone(), b.clone()]);
+        assert_eq!(
+            graph.modules.into_iter().collect::<Vec<_>>(),
+            vec![
+                (ModuleKey::new("0x1", "A"), a.clone()),
+                (ModuleKey::new("0x2", "B"), b)
+            ]
+        );
+        assert_eq!(graph.edges.len(), 1);
+        assert_eq!(graph.edges[0].to, ModuleKey::new("0x2", "B"));
+        assert_eq!(graph.edges[0].file, a);
+        assert_eq!(graph.edges[0].range.start.line, 2);
+    }
+}
@@ -0,0 +1,96 @@
+//! Behaviors which differ between dialects of Move, like those of forks of the language.
+//! A fork implements `Dialect` and hands it to the database, instead of patching the checks.
+//!
+//! The dialect of a database is fixed when it's created, queries never need to be invalidated
+//! because of it.
+
+use move_lang::shared::Address;
+use std::{borrow::Cow, fmt::Debug};
+
+pub trait Dialect: Debug + Send + Sync {
+    fn name(&self) -> &str;
+
+    /// Source given to the move compiler, like with attributes it doesn't know blanked out.
+    /// Byte offsets must stay the same, so that errors point at the original text.
+    fn preprocess<'a>(&self, source: &'a str) -> Cow<'a, str> {
+        Cow::Borrowed(source)
+    }
+
+    /// Address written in a source annotation, like `sender: 0x1`.
+    fn parse_address(&self, address: &str) -> Option<Address> {
+        Address::parse_str(address).ok()
+    }
+
+    /// Signature and description of a native function the dialect adds, shown on hover.
+    fn builtin_function(&self, _name: &str) -> Option<(&'static str, &'static str)> {
+        None
+    }
+}
+
+/// Move as the compiler knows it.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CoreDialect;
+
+impl Dialect for CoreDialect {
+    fn name(&self) -> &str {
+        "move"
+    }
+}
+
+/// Access to the dialect from salsa queries.
+pub trait HasDialect {
+    fn dialect(&self) -> &dyn Dialect;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::{move_ast_query::Ast, RootDatabase};
+    use std::{path::PathBuf, sync::Arc};
+    use xi_rope::Rope;
+
+    /// Knows `#[attribute]` lines, and `@0x..` addresses.
+    #[derive(Debug)]
+    struct AttributeDialect;
+
+    impl Dialect for AttributeDialect {
+        fn name(&self) -> &str {
+            "attributes"
+        }
+
+        fn preprocess<'a>(&self, source: &'a str) -> Cow<'a, str> {
+            let lines: Vec<_> = source
+                .split('\n')
+                .map(|line| {
+                    if line.trim_start().starts_with("#[") {
+                        Cow::Owned(" ".repeat(line.len()))
+                    } else {
+                        Cow::Borrowed(line)
+                    }
+                })
+                .collect();
+            Cow::Owned(lines.join("\n"))
+        }
+
+        fn parse_address(&self, address: &str) -> Option<Address> {
+            Address::parse_str(address.strip_prefix('@')?).ok()
+        }
+    }
+
+    #[test]
+    fn test_dialect() {
+        let source = "//! sender: @0x2\nmodule M {\n    #[test]\n    fun f() {}\n}\n";
+        let file = PathBuf::from("/m.move");
+
+        let mut db = RootDatabase::default();
+        db.update_source(file.clone(), Rope::from(source));
+        assert_eq!(db.dialect().name(), "move");
+        assert!(db.ast(file.clone()).is_err());
+        assert_eq!(db.sender_annotation(file.clone()), None);
+
+        let mut db = RootDatabase::with_dialect(Arc::new(AttributeDialect));
+        db.update_source(file.clone(), Rope::from(source));
+        assert!(db.ast(file.clone()).is_ok());
+        assert_eq!(db.sender_annotation(file), Address::parse_str("0x2").ok());
+    }
+}
@@ -0,0 +1,53 @@
+//! Human readable disassembly of compiled modules and scripts.
+
+use anyhow::{format_err, Result};
+use bytecode_source_map::mapping::SourceMapping;
+use disassembler::disassembler::{Disassembler, DisassemblerOptions};
+use move_lang::compiled_unit::CompiledUnit;
+use vm::file_format::{CompiledModule, CompiledScript};
+
+fn options() -> DisassemblerOptions {
+    let mut options = DisassemblerOptions::new();
+    options.print_code = true;
+    options
+}
+
+pub fn disassemble_module(module: CompiledModule) -> Result<String> {
+    let mapping = SourceMapping::new_from_module(module, ())?;
+    Disassembler::new(mapping, options()).disassemble()
+}
+
+pub fn disassemble_script(script: CompiledScript) -> Result<String> {
+    let mapping = SourceMapping::new_from_script(script, ())?;
+    Disassembler::new(mapping, options()).disassemble()
+}
+
+/// Disassemble the content of a `.mv` file, which is either a module or a script.
+pub fn disassemble_bytes(bytes: &[u8]) -> Result<String> {
+    match CompiledModule::deserialize(bytes) {
+        Ok(module) => disassemble_module(module),
+        Err(_) => {
+            let script = CompiledScript::deserialize(bytes)
+                .map_err(|e| format_err!("not a compiled module or script, {:?}", e))?;
+            disassemble_script(script)
+        }
+    }
+}
+
+/// Disassemble compiled units whose source is `file`.
+pub fn disassemble_units(units: Vec<CompiledUnit>, file: &str) -> Result<String> {
+    let mut outputs = vec![];
+    for unit in units {
+        let output = match unit {
+            CompiledUnit::Module { ident, module, .. } if ident.loc().file() == file => {
+                disassemble_module(module)?
+            }
+            CompiledUnit::Script { loc, script, .. } if loc.file() == file => {
+                disassemble_script(script)?
+            }
+            _ => continue,
+        };
+        outputs.push(output);
+    }
+    Ok(outputs.join("\n"))
+}
@@ -0,0 +1,263 @@
+//! Markdown documentation of modules, generated by the `move/generateDocs` command
+//! from their doc comments, one file per module and an index of them.
+//! Modules referred to in backquotes, like `` `0x1::Signer` ``, link to their own page.
+
+use crate::{
+    analysis::definition_signature,
+    salsa::module_index_query::{ModuleDecl, ModuleKey},
+};
+use move_ir_types::location::Loc;
+use move_lang::{
+    parser::ast::{FunctionVisibility, ModuleDefinition, ModuleMember},
+    MatchedFileCommentMap,
+};
+use std::{collections::BTreeSet, fmt::Write};
+
+/// Folder of the workspace docs are written to by default.
+pub const DOCS_DIR: &str = "docs";
+pub const INDEX_FILE: &str = "index.md";
+
+/// `0x1::Signer` => `0x1_Signer.md`.
+pub fn doc_file_name(key: &ModuleKey) -> String {
+    format!("{}_{}.md", key.address, key.name)
+}
+
+/// A module to document, with the text and doc comments of its file.
+pub struct ModuleSource<'a> {
+    pub decl: &'a ModuleDecl,
+    pub module: &'a ModuleDefinition,
+    pub source: &'a str,
+    pub doc_comments: &'a MatchedFileCommentMap,
+}
+
+impl ModuleSource<'_> {
+    /// Doc comment of the item whose name is at `name`.
+    /// Comments are matched to the token after them, which may be a modifier like `public`.
+    fn doc(&self, name: Loc) -> Option<String> {
+        let name_start = name.span().start();
+        let (start, comment) = self.doc_comments.range(..=name_start).next_back()?;
+        let between = self.source.get(start.to_usize()..name_start.to_usize())?;
+        if between.contains(|c| c == ';' || c == '{' || c == '}') {
+            return None;
+        }
+        let lines: Vec<_> = comment.lines().map(|l| l.trim()).collect();
+        Some(lines.join("\n").trim().to_string()).filter(|d| !d.is_empty())
+    }
+
+    /// Source of `loc` up to its body, on one line.
+    fn signature(&self, loc: Loc) -> String {
+        let span = loc.span();
+        self.source
+            .get(span.start().to_usize()..span.end().to_usize())
+            .map(definition_signature)
+            .unwrap_or_default()
+    }
+
+    /// Link `` `M` `` and `` `0x1::M` `` to the pages of the documented modules they refer to.
+    fn link_modules(&self, text: &str, documented: &BTreeSet<ModuleKey>) -> String {
+        let mut result = String::new();
+        let parts: Vec<_> = text.split('`').collect();
+        for (i, part) in parts.iter().enumerate() {
+            if i % 2 == 0 {
+                result.push_str(part);
+                continue;
+            }
+            // after an unmatched backquote.
+            if i + 1 == parts.len() {
+                result.push('`');
+                result.push_str(part);
+                continue;
+            }
+            match self.resolve(part).filter(|k| documented.contains(k)) {
+                Some(key) => {
+                    let _ = write!(result, "[`{}`]({})", part, doc_file_name(&key));
+                }
+                None => {
+                    let _ = write!(result, "`{}`", part);
+                }
+            }
+        }
+        result
+    }
+
+    fn resolve(&self, name: &str) -> Option<ModuleKey> {
+        let segments: Vec<_> = name.split("::").collect();
+        match segments.as_slice() {
+            [address, module] if address.starts_with("0x") => Some(ModuleKey::new(address, module)),
+            [module] if *module == self.decl.key.name => Some(self.decl.key.clone()),
+            [module] => self
+                .decl
+                .uses
+                .iter()
+                .find(|u| u.member.is_none() && u.local_name() == *module)
+                .map(|u| u.target.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// Page of `module`, links only go to `documented` modules.
+pub fn module_markdown(module: &ModuleSource, documented: &BTreeSet<ModuleKey>) -> String {
+    let key = &module.decl.key;
+    let def = module.module;
+    let mut md = format!("# Module `{}`\n\n", key);
+    if let Some(doc) = module.doc(def.name.0.loc) {
+        let _ = write!(md, "{}\n\n", module.link_modules(&doc, documented));
+    }
+
+    let dependencies: BTreeSet<_> = module.decl.uses.iter().map(|u| &u.target).collect();
+    if !dependencies.is_empty() {
+        md.push_str("## Dependencies\n\n");
+        for dep in dependencies {
+            if documented.contains(dep) {
+                let _ = writeln!(md, "- [`{}`]({})", dep, doc_file_name(dep));
+            } else {
+                let _ = writeln!(md, "- `{}`", dep);
+            }
+        }
+        md.push('\n');
+    }
+
+    let mut section = |title: &str, items: Vec<(String, String, Option<String>)>| {
+        if items.is_empty() {
+            return;
+        }
+        let _ = write!(md, "## {}\n\n", title);
+        for (name, signature, doc) in items {
+            let _ = write!(md, "### `{}`\n\n```move\n{}\n```\n\n", name, signature);
+            if let Some(doc) = doc {
+                let _ = write!(md, "{}\n\n", module.link_modules(&doc, documented));
+            }
+        }
+    };
+    let members = &def.members;
+    section(
+        "Structs",
+        members
+            .iter()
+            .filter_map(|m| match m {
+                ModuleMember::Struct(s) => Some((
+                    s.name.0.value.clone(),
+                    module.signature(s.loc),
+                    module.doc(s.name.0.loc),
+                )),
+                _ => None,
+            })
+            .collect(),
+    );
+    section(
+        "Constants",
+        members
+            .iter()
+            .filter_map(|m| match m {
+                ModuleMember::Constant(c) => Some((
+                    c.name.0.value.clone(),
+                    module.signature(c.loc),
+                    module.doc(c.name.0.loc),
+                )),
+                _ => None,
+            })
+            .collect(),
+    );
+    section(
+        "Functions",
+        members
+            .iter()
+            .filter_map(|m| match m {
+                ModuleMember::Function(f)
+                    if matches!(f.visibility, FunctionVisibility::Public(_)) =>
+                {
+                    Some((
+                        f.name.0.value.clone(),
+                        module.signature(f.loc),
+                        module.doc(f.name.0.loc),
+                    ))
+                }
+                _ => None,
+            })
+            .collect(),
+    );
+    md.trim_end().to_string() + "\n"
+}
+
+/// Index of the documented modules, with the first line of their doc comments.
+pub fn index_markdown(modules: &[ModuleSource]) -> String {
+    let mut md = "# Modules\n\n".to_string();
+    let mut modules: Vec<_> = modules.iter().collect();
+    modules.sort_by(|a, b| a.decl.key.cmp(&b.decl.key));
+    for m in modules {
+        let key = &m.decl.key;
+        let _ = write!(md, "- [`{}`]({})", key, doc_file_name(key));
+        if let Some(summary) = m
+            .doc(m.module.name.0.loc)
+            .and_then(|d| d.lines().next().map(|l| l.to_string()))
+        {
+            let _ = write!(md, ": {}", summary);
+        }
+        md.push('\n');
+    }
+    md
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ide::goto_definition::find_module, salsa::module_index_query::index_modules};
+    use move_lang::{parser::syntax, strip_comments_and_verify};
+
+    const SOURCE: &str = r"address 0x1 {
+/// Coins of the account.
+/// Uses `Signer` and `0x1::Event`.
+module Coin {
+    use 0x1::Signer;
+
+    /// A coin.
+    struct Coin { value: u64 }
+
+    /// Max supply.
+    const MAX: u64 = 100;
+
+    /// Value of `coin`.
+    public fun value(coin: &Coin): u64 {
+        coin.value
+    }
+
+    fun internal() {}
+}
+}
+";
+
+    #[test]
+    fn test_module_markdown() {
+        let (buffer, comment_map, _) = strip_comments_and_verify("coin.move", SOURCE).unwrap();
+        let (defs, doc_comments) =
+            syntax::parse_file_string("coin.move", &buffer, comment_map).unwrap();
+        let decls = index_modules(SOURCE, None);
+        let source = ModuleSource {
+            decl: &decls[0],
+            module: find_module(&defs, "Coin").unwrap(),
+            source: SOURCE,
+            doc_comments: &doc_comments,
+        };
+        let documented = vec![decls[0].key.clone(), ModuleKey::new("0x1", "Signer")]
+            .into_iter()
+            .collect();
+
+        let md = module_markdown(&source, &documented);
+        assert!(md.starts_with(
+            "# Module `0x1::Coin`\n\nCoins of the account.\nUses [`Signer`](0x1_Signer.md) and `0x1::Event`.\n"
+        ));
+        assert!(md.contains("## Dependencies\n\n- [`0x1::Signer`](0x1_Signer.md)\n"));
+        assert!(md.contains("### `Coin`\n\n```move\nstruct Coin\n```\n\nA coin.\n"));
+        assert!(md.contains("### `MAX`\n\n```move\nconst MAX: u64 = 100\n```\n\nMax supply.\n"));
+        assert!(md.contains(
+            "### `value`\n\n```move\npublic fun value(coin: &Coin): u64\n```\n\nValue of `coin`.\n"
+        ));
+        assert!(!md.contains("internal"));
+
+        assert_eq!(
+            index_markdown(&[source]),
+            "# Modules\n\n- [`0x1::Coin`](0x1_Coin.md): Coins of the account.\n"
+        );
+    }
+}
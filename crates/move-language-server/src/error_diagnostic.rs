@@ -1,16 +1,19 @@
 use codespan::{FileId, Files};
+use lsp_types::Range;
 use move_ir_types::location::Loc;
 use move_lang::errors::{Error, ErrorSlice, Errors, FilesSourceText, HashableError};
+use serde::Serialize;
 use std::collections::{HashMap, HashSet};
-use tower_lsp::{lsp_types, lsp_types::Range};
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct DiagnosticInfo {
     pub primary_label: Label,
     pub secondary_labels: Vec<Label>,
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct Label {
     pub file: &'static str,
     pub range: Range,
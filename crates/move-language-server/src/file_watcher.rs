@@ -0,0 +1,110 @@
+//! Server side watching of the project folders, for clients that can't register
+//! `workspace/didChangeWatchedFiles` dynamically.
+
+use notify::{DebouncedEvent, RecommendedWatcher, RecursiveMode, Watcher};
+use std::{path::PathBuf, sync::mpsc, thread, time::Duration};
+use tokio::sync::mpsc::UnboundedSender;
+use tower_lsp::lsp_types::{FileChangeType, FileEvent, Url};
+
+const DEBOUNCE: Duration = Duration::from_millis(200);
+
+pub struct FileWatcher {
+    watcher: RecommendedWatcher,
+    folders: Vec<PathBuf>,
+}
+
+impl FileWatcher {
+    /// Changes of move files and folders under the watched folders are sent to `sender`,
+    /// in the shape the client would have sent them.
+    pub fn new(sender: UnboundedSender<Vec<FileEvent>>) -> notify::Result<Self> {
+        let (tx, rx) = mpsc::channel();
+        let watcher = notify::watcher(tx, DEBOUNCE)?;
+        // ends when the watcher is dropped, or the server stops listening.
+        thread::spawn(move || {
+            for event in rx {
+                let events = file_events(event);
+                if !events.is_empty() && sender.send(events).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            watcher,
+            folders: vec![],
+        })
+    }
+
+    /// Watch `folders` recursively instead of the previous ones.
+    pub fn watch(&mut self, folders: Vec<PathBuf>) {
+        for folder in self.folders.drain(..) {
+            if let Err(e) = self.watcher.unwatch(&folder) {
+                debug!("fail to unwatch {}, {}", folder.display(), e);
+            }
+        }
+        for folder in folders {
+            match self.watcher.watch(&folder, RecursiveMode::Recursive) {
+                Ok(()) => self.folders.push(folder),
+                Err(e) => error!("fail to watch {}, {}", folder.display(), e),
+            }
+        }
+    }
+}
+
+fn file_events(event: DebouncedEvent) -> Vec<FileEvent> {
+    let changes = match event {
+        DebouncedEvent::Create(p) => vec![(p, FileChangeType::Created)],
+        DebouncedEvent::Write(p) => vec![(p, FileChangeType::Changed)],
+        DebouncedEvent::Remove(p) => vec![(p, FileChangeType::Deleted)],
+        DebouncedEvent::Rename(from, to) => vec![
+            (from, FileChangeType::Deleted),
+            (to, FileChangeType::Created),
+        ],
+        _ => vec![],
+    };
+    changes
+        .into_iter()
+        // a deleted folder can't be told from a deleted file without extension.
+        .filter(|(p, typ)| {
+            p.extension().map_or(false, |e| e == "move")
+                || (*typ == FileChangeType::Deleted && p.extension().is_none())
+                || p.is_dir()
+        })
+        .filter_map(|(p, typ)| {
+            Some(FileEvent {
+                uri: Url::from_file_path(p).ok()?,
+                typ,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_file_events() {
+        let dir = std::env::temp_dir();
+        let events = file_events(DebouncedEvent::Rename(
+            dir.join("A.move"),
+            dir.join("B.move"),
+        ));
+        let types: Vec<_> = events.iter().map(|e| e.typ).collect();
+        assert_eq!(
+            types,
+            vec![FileChangeType::Deleted, FileChangeType::Created]
+        );
+        assert!(events[1].uri.path().ends_with("B.move"));
+        assert!(file_events(DebouncedEvent::Write(dir.join("README.md"))).is_empty());
+
+        let events = file_events(DebouncedEvent::Rename(
+            dir.join("move-ls-no-such-folder"),
+            dir.clone(),
+        ));
+        let types: Vec<_> = events.iter().map(|e| e.typ).collect();
+        assert_eq!(
+            types,
+            vec![FileChangeType::Deleted, FileChangeType::Created]
+        );
+    }
+}
@@ -0,0 +1,111 @@
+//! Fingerprints of the inputs of compiled files, kept next to the artifacts in the output
+//! directory, so that compiling unchanged sources again can reuse the existing `.mv` files.
+
+use crate::artifacts::Artifact;
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    hash::{Hash, Hasher},
+    path::{Path, PathBuf},
+};
+
+pub const FINGERPRINTS_FILE: &str = ".fingerprints.json";
+
+#[derive(Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize)]
+pub struct Fingerprints {
+    files: BTreeMap<PathBuf, Compilation>,
+}
+
+/// The last compilation of a source file.
+#[derive(Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
+struct Compilation {
+    /// fingerprint of the inputs.
+    fingerprint: String,
+    artifacts: Vec<Artifact>,
+}
+
+impl Fingerprints {
+    /// Fingerprints stored in `out_dir`, empty if there are none or they can't be read.
+    pub fn load(out_dir: &Path) -> Self {
+        std::fs::read(out_dir.join(FINGERPRINTS_FILE))
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn save(&self, out_dir: &Path) -> Result<()> {
+        std::fs::create_dir_all(out_dir)?;
+        std::fs::write(
+            out_dir.join(FINGERPRINTS_FILE),
+            serde_json::to_vec_pretty(self)?,
+        )?;
+        Ok(())
+    }
+
+    /// Artifacts of `file` if it was compiled from the inputs of `fingerprint`,
+    /// and they are all still there.
+    pub fn fresh_artifacts(&self, file: &Path, fingerprint: &str) -> Option<&[Artifact]> {
+        self.files
+            .get(file)
+            .filter(|c| c.fingerprint == fingerprint)
+            .filter(|c| {
+                c.artifacts
+                    .iter()
+                    .all(|a| a.bytecode.is_file() && a.source_map.is_file())
+            })
+            .map(|c| c.artifacts.as_slice())
+    }
+
+    pub fn insert(&mut self, file: PathBuf, fingerprint: String, artifacts: Vec<Artifact>) {
+        self.files.insert(
+            file,
+            Compilation {
+                fingerprint,
+                artifacts,
+            },
+        );
+    }
+}
+
+/// Fingerprint of a compilation: the sender, and the path and content of every source.
+pub fn fingerprint<'a>(
+    sender: Option<&str>,
+    sources: impl IntoIterator<Item = (&'a Path, &'a str)>,
+) -> String {
+    let mut hasher = DefaultHasher::new();
+    sender.hash(&mut hasher);
+    for (path, content) in sources {
+        path.hash(&mut hasher);
+        content.hash(&mut hasher);
+    }
+    format!("{:016x}", hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fingerprints() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        let file = PathBuf::from("M.move");
+        let a = fingerprint(None, vec![(file.as_path(), "module M {}")]);
+        let b = fingerprint(None, vec![(file.as_path(), "module M { }")]);
+        assert_ne!(a, b);
+        assert_ne!(
+            a,
+            fingerprint(Some("0x1"), vec![(file.as_path(), "module M {}")])
+        );
+
+        assert_eq!(Fingerprints::load(&dir), Fingerprints::default());
+        let mut fingerprints = Fingerprints::default();
+        fingerprints.insert(file.clone(), a.clone(), vec![]);
+        fingerprints.save(&dir).unwrap();
+
+        let loaded = Fingerprints::load(&dir);
+        assert!(loaded.fresh_artifacts(&file, &a).is_some());
+        assert!(loaded.fresh_artifacts(&file, &b).is_none());
+    }
+}
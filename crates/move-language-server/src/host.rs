@@ -0,0 +1,434 @@
+//! The analyses behind the language server as a typed api, for embedders and tests
+//! which don't speak lsp.
+//!
+//! Changes are applied to a `MoveAnalysisHost`, queries run on `AnalysisSnapshot`s of it.
+//! A snapshot keeps seeing the project as it was when taken. Applying a change waits for
+//! the outstanding snapshots to be dropped, so don't keep them across changes.
+//!
+//! The language server answers its requests from snapshots too, its handlers only convert
+//! between lsp types and these.
+
+use crate::{
+    analysis::{self, apply_config, Symbol, SymbolKind},
+    config::ProjectConfig,
+    dialect::HasDialect,
+    ide::{completion, goto_definition},
+    lsif::file_occurrences,
+    move_document::{MoveDocument, RopeDoc},
+    salsa::{
+        config_query::Config,
+        module_index_query::{ModuleDecl, ModuleIndex, ModuleKey},
+        move_ast_query::Ast,
+        text_source_query::{SourceReader, TextSource},
+        FileId, RootDatabase,
+    },
+    utils::canonicalize,
+};
+use salsa::{ParallelDatabase, Snapshot};
+use std::{
+    collections::BTreeSet,
+    path::{Path, PathBuf},
+};
+use tower_lsp::lsp_types::{CompletionItem, Position, Range};
+use xi_rope::Rope;
+
+/// A batch of changes, applied at once by `MoveAnalysisHost::apply_change`.
+#[derive(Clone, Debug, Default)]
+pub struct Change {
+    config: Option<ProjectConfig>,
+    /// new contents, `None` to read the file from disk again.
+    contents: Vec<(PathBuf, Option<String>)>,
+    /// files changed on disk.
+    changed_files: Vec<PathBuf>,
+}
+
+impl Change {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Configure the project again, its files are collected from the new folders.
+    pub fn set_config(mut self, config: ProjectConfig) -> Self {
+        self.config = Some(config);
+        self
+    }
+
+    /// Use `text` as the content of `file` instead of what's on disk, e.g. an unsaved buffer.
+    pub fn set_content(mut self, file: impl Into<PathBuf>, text: impl Into<String>) -> Self {
+        self.contents.push((file.into(), Some(text.into())));
+        self
+    }
+
+    /// Read `file` from disk again.
+    pub fn remove_content(mut self, file: impl Into<PathBuf>) -> Self {
+        self.contents.push((file.into(), None));
+        self
+    }
+
+    /// `file` is changed on disk.
+    pub fn file_changed(mut self, file: impl Into<PathBuf>) -> Self {
+        self.changed_files.push(file.into());
+        self
+    }
+}
+
+pub struct MoveAnalysisHost {
+    db: RootDatabase,
+    config: ProjectConfig,
+}
+
+impl MoveAnalysisHost {
+    pub fn new(config: ProjectConfig) -> Self {
+        let mut db = RootDatabase::default();
+        apply_config(&mut db, &config);
+        Self { db, config }
+    }
+
+    pub fn config(&self) -> &ProjectConfig {
+        &self.config
+    }
+
+    pub fn apply_change(&mut self, change: Change) {
+        let Change {
+            config,
+            contents,
+            changed_files,
+        } = change;
+        if let Some(config) = config {
+            apply_config(&mut self.db, &config);
+            self.config = config;
+        }
+        for (file, content) in contents {
+            match content {
+                Some(text) => self.db.update_source(file, Rope::from(text)),
+                None => {
+                    let file = canonicalize(&file);
+                    self.db.close_source(file.clone());
+                    self.db.did_change(file.as_path());
+                }
+            }
+        }
+        for file in changed_files {
+            self.db.did_change(canonicalize(&file).as_path());
+        }
+    }
+
+    pub fn snapshot(&self) -> AnalysisSnapshot {
+        AnalysisSnapshot::new(&self.db)
+    }
+}
+
+/// Read-only view of a host, which can be sent to another thread.
+pub struct AnalysisSnapshot {
+    db: Snapshot<RootDatabase>,
+}
+
+impl AnalysisSnapshot {
+    /// Snapshot of `db`, which the language server owns instead of a host.
+    pub(crate) fn new(db: &RootDatabase) -> Self {
+        Self { db: db.snapshot() }
+    }
+
+    /// Errors of checking `file` together with the project files.
+    pub fn diagnostics(&self, file: &Path) -> Vec<analysis::Diagnostic> {
+        let (_, result) = self.db.check_file(None, file.to_path_buf());
+        analysis::to_diagnostics(result.err().unwrap_or_default())
+    }
+
+    /// Definition of the module named at `pos` of `file`: the defining file and the range of its name.
+    pub fn definition(&self, file: &Path, pos: Position) -> Option<(FileId, Range)> {
+        let file = canonicalize(file);
+        let doc = self.db.syntax_document(file.clone());
+        goto_definition::module_definition(
+            &doc,
+            pos,
+            self.db.file_modules(file.clone()).as_slice(),
+            self.db.visible_modules(file).as_slice(),
+        )
+    }
+
+    /// Definition of the function called at `pos` of `file`: the defining file and its range.
+    pub fn implementation(&self, file: &Path, pos: Position) -> Option<(FileId, Range)> {
+        let file = canonicalize(file);
+        let doc = self.db.syntax_document(file.clone());
+        let file_modules = self.db.file_modules(file.clone());
+        let (key, name) = goto_definition::called_function(&doc, pos, file_modules.as_slice())?;
+
+        let defining_file = self.module_file(&key, file, file_modules.as_slice())?;
+        let symbols = self.db.symbols(defining_file.clone());
+        let span = goto_definition::function_span(&symbols, key.name.as_str(), name.as_str())?;
+        let source = RopeDoc::new(0, self.db.source_text(defining_file.clone()).as_str());
+        let range = Range::new(
+            source.to_position(span.start)?,
+            source.to_position(span.end)?,
+        );
+        Some((defining_file, range))
+    }
+
+    /// Completions at `pos` of `file`: abilities, functions of the module accessed at `pos`,
+    /// or field names of the struct packed or unpacked at `pos`.
+    pub fn completions(&self, file: &Path, pos: Position) -> Option<Vec<CompletionItem>> {
+        let file = canonicalize(file);
+        let doc = self.db.syntax_document(file.clone());
+        if let Some(items) = completion::ability_items(&doc, pos) {
+            return Some(items);
+        }
+        if let Some(access) = completion::module_access_at(&doc, pos) {
+            return self.function_completions(&doc, file, pos, access);
+        }
+        let access = completion::struct_at(&doc, pos)?;
+        let file_modules = self.db.file_modules(file.clone());
+        let (key, name) = completion::resolve_member(access, file_modules.as_slice(), pos)?;
+
+        let defining_file = self.module_file(&key, file, file_modules.as_slice())?;
+        let ast = self.db.ast(defining_file.clone()).ok()?;
+        let source = self.db.source_text(defining_file);
+        Some(completion::field_items(
+            ast.defs.as_slice(),
+            source.as_str(),
+            key.name.as_str(),
+            name.as_str(),
+        ))
+    }
+
+    /// Functions of the module accessed at `pos`, only public ones outside of that module,
+    /// as scripts and other modules can't call the others.
+    fn function_completions(
+        &self,
+        doc: &MoveDocument,
+        file: FileId,
+        pos: Position,
+        access: completion::MemberAccess,
+    ) -> Option<Vec<CompletionItem>> {
+        let file_modules = self.db.file_modules(file.clone());
+        let key = match completion::resolve_member(access.clone(), file_modules.as_slice(), pos) {
+            Some((key, _)) => key,
+            None => completion::script_used_module(doc, pos, access.module.as_deref()?)?,
+        };
+        let public_only = goto_definition::enclosing_module(file_modules.as_slice(), pos)
+            .map_or(true, |m| m.key != key);
+
+        let defining_file = self.module_file(&key, file, file_modules.as_slice())?;
+        Some(completion::function_items(
+            self.db.symbols(defining_file).as_slice(),
+            key.name.as_str(),
+            public_only,
+        ))
+    }
+
+    /// The file defining module `key`, `file` itself if it's not part of the project.
+    fn module_file(
+        &self,
+        key: &ModuleKey,
+        file: FileId,
+        file_modules: &[ModuleDecl],
+    ) -> Option<FileId> {
+        match self
+            .db
+            .visible_modules(file.clone())
+            .iter()
+            .find(|(_, m)| &m.key == key)
+        {
+            Some((f, _)) => Some(f.clone()),
+            None if file_modules.iter().any(|m| &m.key == key) => Some(file),
+            None => None,
+        }
+    }
+
+    /// Tests of `file`, with the range of their names.
+    pub fn tests(&self, file: &Path) -> Vec<(Symbol, Range)> {
+        let file = canonicalize(file);
+        let text = self.db.source_text(file.clone());
+        let source = RopeDoc::new(0, text.as_str());
+        let symbols = self.db.symbols(file.clone());
+        analysis::test_symbols(&file, text.as_str(), symbols.as_slice())
+            .into_iter()
+            .filter_map(|symbol| {
+                let range = Range::new(
+                    source.to_position(symbol.span.start)?,
+                    source.to_position(symbol.span.end)?,
+                );
+                Some((symbol, range))
+            })
+            .collect()
+    }
+
+    /// Modules, scripts and module members defined in `file`, empty if it doesn't parse.
+    pub fn file_symbols(&self, file: &Path) -> Vec<Symbol> {
+        self.db.symbols(canonicalize(file)).to_vec()
+    }
+
+    /// Symbols of the project whose names contain `query`, case insensitively.
+    pub fn symbols(&self, query: &str, limit: usize) -> Vec<(FileId, Symbol)> {
+        workspace_symbols(&self.db, query, limit)
+    }
+
+    /// Public functions of module files which nothing refers to.
+    pub fn unused_public_functions(&self) -> Vec<(FileId, Symbol)> {
+        unused_public_functions(&self.db)
+    }
+}
+
+/// Symbols of stdlib, module and script files whose names contain `query`, case insensitively.
+/// At most `limit` of them.
+fn workspace_symbols(db: &RootDatabase, query: &str, limit: usize) -> Vec<(FileId, Symbol)> {
+    let query = query.to_lowercase();
+    let files = db
+        .stdlib_files()
+        .into_iter()
+        .chain(db.module_files())
+        .chain(db.script_files());
+    let mut symbols = vec![];
+    for file in files {
+        for symbol in db.symbols(file.clone()).iter() {
+            if !symbol.name.to_lowercase().contains(&query) {
+                continue;
+            }
+            symbols.push((file.clone(), symbol.clone()));
+            if symbols.len() >= limit {
+                return symbols;
+            }
+        }
+    }
+    symbols
+}
+
+/// Public functions of module files referred to nowhere in stdlib, module and script files,
+/// like entry points no script calls anymore. Uses in the defining module count as references.
+fn unused_public_functions(db: &RootDatabase) -> Vec<(FileId, Symbol)> {
+    let dialect = db.dialect().name();
+    let module_files: BTreeSet<_> = db.module_files().into_iter().collect();
+    let files = db
+        .stdlib_files()
+        .into_iter()
+        .chain(db.module_files())
+        .chain(db.script_files());
+    let mut referenced = BTreeSet::new();
+    // (file, symbol, moniker identifier) of the public functions of module files.
+    let mut public = vec![];
+    for file in files {
+        let doc = db.syntax_document(file.clone());
+        let occurrences = file_occurrences(&doc, db.file_modules(file.clone()).as_slice(), dialect);
+        if module_files.contains(&file) {
+            let symbols = db.symbols(file.clone());
+            let public_functions = symbols
+                .iter()
+                .filter(|s| s.kind == SymbolKind::Function && s.public);
+            for symbol in public_functions {
+                let start = doc.doc().to_position(symbol.span.start);
+                let identifier = occurrences
+                    .iter()
+                    .find(|o| o.definition && Some(o.range.start) == start)
+                    .map(|o| o.identifier.clone());
+                if let Some(identifier) = identifier {
+                    public.push((file.clone(), symbol.clone(), identifier));
+                }
+            }
+        }
+        referenced.extend(
+            occurrences
+                .into_iter()
+                .filter(|o| !o.definition)
+                .map(|o| o.identifier),
+        );
+    }
+    public
+        .into_iter()
+        .filter(|(_, _, identifier)| !referenced.contains(identifier))
+        .map(|(file, symbol, _)| (file, symbol))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_analysis_host() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = canonicalize(tmp.path());
+        std::fs::create_dir_all(dir.join("modules")).unwrap();
+        let m = dir.join("modules").join("M.move");
+        let n = dir.join("modules").join("N.move");
+        std::fs::write(
+            &m,
+            "address 0x1 {\nmodule M {\n    public fun f(): u64 { 0 }\n}\n}\n",
+        )
+        .unwrap();
+        std::fs::write(
+            &n,
+            "address 0x1 {\nmodule N {\n    use 0x1::M;\n    fun g(): u64 { M::f() }\n}\n}\n",
+        )
+        .unwrap();
+
+        let mut host = MoveAnalysisHost::new(ProjectConfig {
+            modules_folders: vec![dir.join("modules")],
+            ..Default::default()
+        });
+        let snapshot = host.snapshot();
+        assert!(snapshot.diagnostics(&n).is_empty());
+        let (file, range) = snapshot.definition(&n, Position::new(2, 13)).unwrap();
+        assert_eq!(file, m);
+        assert_eq!(range.start, Position::new(1, 7));
+        // `f` in `M::f()`.
+        let (file, _) = snapshot.implementation(&n, Position::new(3, 22)).unwrap();
+        assert_eq!(file, m);
+        assert!(snapshot.tests(&n).is_empty());
+        let symbols: Vec<_> = snapshot
+            .symbols("f", 10)
+            .into_iter()
+            .map(|(f, s)| (f, s.name, s.kind))
+            .collect();
+        assert_eq!(
+            symbols,
+            vec![(m.clone(), "f".to_string(), SymbolKind::Function)]
+        );
+        drop(snapshot);
+
+        host.apply_change(Change::new().set_content(
+            &n,
+            "address 0x1 {\nmodule N {\n    use 0x1::M;\n    fun g(): bool { M::f() }\n}\n}\n",
+        ));
+        let snapshot = host.snapshot();
+        assert_eq!(snapshot.diagnostics(&n).len(), 1);
+        drop(snapshot);
+
+        host.apply_change(Change::new().remove_content(&n));
+        assert!(host.snapshot().diagnostics(&n).is_empty());
+    }
+
+    #[test]
+    fn test_unused_public_functions() {
+        let m = PathBuf::from("/modules/M.move");
+        let n = PathBuf::from("/modules/N.move");
+        let script = PathBuf::from("/scripts/main.move");
+        let mut db = RootDatabase::default();
+        db.set_project_files(vec![], vec![m.clone(), n.clone()]);
+        db.set_script_files(vec![script.clone()]);
+        db.update_source(
+            m.clone(),
+            Rope::from(
+                "address 0x1 {\nmodule M {\n    public fun used(): u64 { 0 }\n    public fun unused(): u64 { 1 }\n    fun private(): u64 { 2 }\n}\n}\n",
+            ),
+        );
+        db.update_source(
+            n.clone(),
+            Rope::from(
+                "address 0x1 {\nmodule N {\n    use 0x1::M;\n    public fun g(): u64 { M::used() }\n}\n}\n",
+            ),
+        );
+        db.update_source(
+            script,
+            Rope::from("script {\n    use 0x1::N;\n    fun main() { N::g(); }\n}\n"),
+        );
+
+        let unused: Vec<_> = unused_public_functions(&db)
+            .into_iter()
+            .map(|(f, s)| (f, s.name, s.container))
+            .collect();
+        assert_eq!(
+            unused,
+            vec![(m, "unused".to_string(), Some("M".to_string()))]
+        );
+    }
+}
@@ -0,0 +1,317 @@
+//! `acquires` lists checked against the global storage operations of function bodies,
+//! before the compiler gets to them: resources a function acquires without listing them,
+//! and listed resources it never acquires.
+//! A function acquires the resources it borrows or moves from global storage,
+//! and the ones of the functions of its module it calls.
+
+use crate::{
+    lsp_ext::InlayHint,
+    move_document::{rope_text, MoveDocument},
+    tree_sitter_move::kinds,
+};
+use std::collections::{BTreeMap, BTreeSet};
+use tower_lsp::lsp_types::{Diagnostic, DiagnosticSeverity, Range};
+use tree_sitter::Node;
+
+/// Builtins which need the resource they operate on to be acquired.
+const STORAGE_OPERATIONS: &[&str] = &["borrow_global", "borrow_global_mut", "move_from"];
+
+/// A function of a module and the resources it acquires.
+struct Function<'a> {
+    name: String,
+    name_range: Range,
+    /// resources of the `acquires` clause, with their ranges.
+    listed: Vec<(String, Range)>,
+    /// resources acquired by the body, with the ones of the called functions.
+    acquired: BTreeSet<String>,
+    /// functions of the same module called by the body.
+    calls: Vec<(String, Node<'a>)>,
+}
+
+/// Warnings on functions which acquire resources missing from their `acquires` clause,
+/// and on listed resources which are never acquired.
+pub fn acquires_diagnostics(doc: &MoveDocument) -> Vec<Diagnostic> {
+    let mut diagnostics = vec![];
+    for function in functions(doc) {
+        let listed: BTreeSet<_> = function.listed.iter().map(|(r, _)| r).collect();
+        let missing: Vec<_> = function
+            .acquired
+            .iter()
+            .filter(|r| !listed.contains(r))
+            .map(|r| format!("`{}`", r))
+            .collect();
+        if !missing.is_empty() {
+            diagnostics.push(warning(
+                function.name_range,
+                format!(
+                    "`{}` acquires {}, which its `acquires` list is missing",
+                    function.name,
+                    missing.join(", ")
+                ),
+            ));
+        }
+        for (resource, range) in function.listed.iter() {
+            if !function.acquired.contains(resource) {
+                diagnostics.push(warning(
+                    *range,
+                    format!(
+                        "`{}` is in the `acquires` list of `{}`, which never acquires it",
+                        resource, function.name
+                    ),
+                ));
+            }
+        }
+    }
+    diagnostics
+}
+
+/// Resources acquired by the calls to functions of the same module, after the calls in `range`.
+pub fn acquires_hints(doc: &MoveDocument, range: Option<Range>) -> Vec<InlayHint> {
+    let functions = functions(doc);
+    let acquired: BTreeMap<_, _> = functions
+        .iter()
+        .map(|f| (f.name.as_str(), &f.acquired))
+        .collect();
+    let mut hints = vec![];
+    for (callee, call) in functions.iter().flat_map(|f| f.calls.iter()) {
+        let resources = match acquired.get(callee.as_str()) {
+            Some(r) if !r.is_empty() => r,
+            _ => continue,
+        };
+        let position = match doc.node_range(call) {
+            Some(r) => r.end,
+            None => continue,
+        };
+        if let Some(range) = range {
+            if position < range.start || range.end < position {
+                continue;
+            }
+        }
+        hints.push(InlayHint {
+            position,
+            label: format!(
+                "acquires {}",
+                resources.iter().cloned().collect::<Vec<_>>().join(", ")
+            ),
+            padding_left: true,
+        });
+    }
+    hints
+}
+
+fn warning(range: Range, message: String) -> Diagnostic {
+    Diagnostic {
+        range,
+        severity: Some(DiagnosticSeverity::Warning),
+        message,
+        ..Default::default()
+    }
+}
+
+/// Functions of the modules of `doc`, with what they acquire through the calls to each other.
+fn functions(doc: &MoveDocument) -> Vec<Function> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let mut modules = vec![];
+    find_nodes(&tree.root_node(), kinds::MODULE_DEFINITION, &mut modules);
+    let mut result = vec![];
+    for module in modules {
+        let mut definitions = vec![];
+        find_nodes(&module, kinds::FUNCTION_DEFINITION, &mut definitions);
+        let mut functions: Vec<_> = definitions
+            .iter()
+            .filter_map(|d| function(doc, d))
+            .collect();
+        // acquired resources flow from callees to callers until nothing changes.
+        loop {
+            let acquired: BTreeMap<_, _> = functions
+                .iter()
+                .map(|f| (f.name.clone(), f.acquired.clone()))
+                .collect();
+            let mut changed = false;
+            for f in functions.iter_mut() {
+                for (callee, _) in f.calls.iter() {
+                    for resource in acquired.get(callee).into_iter().flatten() {
+                        changed |= f.acquired.insert(resource.clone());
+                    }
+                }
+            }
+            if !changed {
+                break;
+            }
+        }
+        result.extend(functions);
+    }
+    result
+}
+
+fn function<'a>(doc: &MoveDocument, definition: &Node<'a>) -> Option<Function<'a>> {
+    let name = definition.child_by_field_name("name")?;
+    let mut cursor = definition.walk();
+    let children: Vec<_> = definition.children(&mut cursor).collect();
+    // native functions have no body, and acquire nothing.
+    let body = children.iter().rev().find(|c| c.kind() == kinds::BLOCK)?;
+    let listed = match children.iter().find(|c| c.kind() == "acquires") {
+        Some(keyword) => listed_resources(doc, keyword.end_byte(), body.start_byte()),
+        None => vec![],
+    };
+
+    let mut call_nodes = vec![];
+    find_nodes(body, kinds::CALL_EXPRESSION, &mut call_nodes);
+    let mut acquired = BTreeSet::new();
+    let mut calls = vec![];
+    for call in call_nodes {
+        let text = doc.node_text(&call);
+        let callee_end = text.find(|c| c == '<' || c == '(').unwrap_or(text.len());
+        let callee = text[..callee_end].trim();
+        if STORAGE_OPERATIONS.contains(&callee) {
+            let resource = type_argument(&text[callee_end..]).and_then(|t| local_type(&t));
+            acquired.extend(resource);
+        } else if let Some(callee) = local_function(callee) {
+            calls.push((callee.to_string(), call));
+        }
+    }
+    Some(Function {
+        name: doc.node_text(&name).into_owned(),
+        name_range: doc.node_range(&name)?,
+        listed,
+        acquired,
+        calls,
+    })
+}
+
+/// Comma separated resources between byte offsets `start` and `end`.
+fn listed_resources(doc: &MoveDocument, start: usize, end: usize) -> Vec<(String, Range)> {
+    let text = rope_text(doc.doc().rope(), start..end);
+    let mut resources = vec![];
+    let mut offset = start;
+    for part in text.split(',') {
+        let trimmed = part.trim();
+        let part_start = offset + part.find(trimmed).unwrap_or(0);
+        offset += part.len() + 1;
+        let resource = match local_type(trimmed) {
+            Some(r) => r,
+            None => continue,
+        };
+        let range = match (
+            doc.doc().to_position(part_start),
+            doc.doc().to_position(part_start + trimmed.len()),
+        ) {
+            (Some(start), Some(end)) => Range::new(start, end),
+            _ => continue,
+        };
+        resources.push((resource, range));
+    }
+    resources
+}
+
+/// `<T>(addr)` => `T`, `<Coin<X>>(addr)` => `Coin<X>`.
+fn type_argument(text: &str) -> Option<String> {
+    let text = text.trim_start().strip_prefix('<')?;
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '<' => depth += 1,
+            '>' if depth == 0 => return Some(text[..i].trim().to_string()),
+            '>' => depth -= 1,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// `Coin<X>` or `Self::Coin` => `Coin`, `None` for types of other modules.
+fn local_type(ty: &str) -> Option<String> {
+    let name = ty.split('<').next()?.trim();
+    let name = name.strip_prefix("Self::").unwrap_or(name);
+    Some(name.to_string()).filter(|n| !n.is_empty() && !n.contains("::"))
+}
+
+/// `f` or `Self::f` => `f`, `None` for functions of other modules.
+fn local_function(callee: &str) -> Option<&str> {
+    let name = callee.strip_prefix("Self::").unwrap_or(callee);
+    Some(name).filter(|n| !n.is_empty() && !n.contains("::"))
+}
+
+fn find_nodes<'a>(node: &Node<'a>, kind: &str, found: &mut Vec<Node<'a>>) {
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == kind {
+            found.push(child);
+        }
+        find_nodes(&child, kind, found);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r"address 0x1 {
+module M {
+    struct T has key { v: u64 }
+    struct U has key { v: u64 }
+    fun get(a: address): u64 acquires T { borrow_global<T>(a).v }
+    fun take(a: address): U acquires T, U { move_from<U>(a) }
+    fun both(a: address): u64 { get(a) + Self::get(a) }
+    native fun n();
+}
+}
+";
+
+    #[test]
+    fn test_acquires_diagnostics() {
+        let doc = MoveDocument::new(1, SOURCE);
+        let diagnostics: Vec<_> = acquires_diagnostics(&doc)
+            .into_iter()
+            .map(|d| (d.range.start.line, d.range.start.character, d.message))
+            .collect();
+        assert_eq!(
+            diagnostics,
+            vec![
+                (
+                    5,
+                    37,
+                    "`T` is in the `acquires` list of `take`, which never acquires it".to_string()
+                ),
+                (
+                    6,
+                    8,
+                    "`both` acquires `T`, which its `acquires` list is missing".to_string()
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_acquires_hints() {
+        let doc = MoveDocument::new(1, SOURCE);
+        let hints: Vec<_> = acquires_hints(&doc, None)
+            .into_iter()
+            .map(|h| (h.position.line, h.position.character, h.label))
+            .collect();
+        assert_eq!(
+            hints,
+            vec![
+                (6, 38, "acquires T".to_string()),
+                (6, 53, "acquires T".to_string()),
+            ]
+        );
+        let line = Range::new(
+            tower_lsp::lsp_types::Position::new(6, 0),
+            tower_lsp::lsp_types::Position::new(6, 40),
+        );
+        assert_eq!(acquires_hints(&doc, Some(line)).len(), 1);
+    }
+
+    #[test]
+    fn test_type_argument() {
+        assert_eq!(type_argument("<T>(a)"), Some("T".to_string()));
+        assert_eq!(type_argument("<Coin<X>>(a)"), Some("Coin<X>".to_string()));
+        assert_eq!(type_argument("(a)"), None);
+        assert_eq!(local_type("Self::Coin<X>"), Some("Coin".to_string()));
+        assert_eq!(local_type("0x1::M::T"), None);
+    }
+}
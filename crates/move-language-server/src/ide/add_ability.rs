@@ -0,0 +1,177 @@
+use crate::{
+    ide::{completion::ABILITIES, locals::node_text},
+    move_document::{rope_text, MoveDocument},
+    tree_sitter_move::kinds,
+};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, Diagnostic, Position, Range, TextEdit, Url, WorkspaceEdit,
+};
+use tree_sitter::Node;
+
+/// Quick fixes of missing ability errors:
+/// add the ability to the struct declaration, or to the constraint of the type parameter.
+pub fn add_ability_fixes(
+    doc: &MoveDocument,
+    uri: &Url,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeAction> {
+    diagnostics
+        .iter()
+        .filter_map(|d| {
+            let (type_name, ability) = parse_missing_ability(&d.message)?;
+            let edit = struct_edit(doc, &type_name, ability)
+                .or_else(|| type_parameter_edit(doc, d.range.start, &type_name, ability))?;
+            Some(CodeAction {
+                title: format!("Add `{}` ability to `{}`", ability, type_name),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![d.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(vec![(uri.clone(), vec![edit])].into_iter().collect()),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// The type and the ability quoted in a missing ability message, like
+/// "The type '0x1::M::S' does not have the ability 'copy'" => `S`, `copy`.
+fn parse_missing_ability(message: &str) -> Option<(String, &'static str)> {
+    if !message.contains("abilit") {
+        return None;
+    }
+    let quoted: Vec<_> = message
+        .split(|c| c == '\'' || c == '`')
+        .skip(1)
+        .step_by(2)
+        .collect();
+    let ability = quoted
+        .iter()
+        .find_map(|q| ABILITIES.iter().map(|(a, _)| *a).find(|a| a == q))?;
+    let ty = quoted
+        .iter()
+        .find(|q| !ABILITIES.iter().any(|(a, _)| a == *q))?;
+    // `0x1::M::S<u64>` => `S`
+    let name = ty.split('<').next()?.rsplit("::").next()?.trim();
+    Some((name.to_string(), ability)).filter(|(n, _)| !n.is_empty())
+}
+
+/// `struct S {` => `struct S has ability {`, or `struct S has copy {` => `struct S has copy, ability {`.
+fn struct_edit(doc: &MoveDocument, name: &str, ability: &str) -> Option<TextEdit> {
+    let root = doc.tree()?.root_node();
+    let definition = find_struct(doc, &root, name)?;
+    let header_end = definition
+        .child_by_field_name("struct_fields")
+        .map_or(definition.end_byte(), |f| f.start_byte());
+    let header = rope_text(doc.doc().rope(), definition.start_byte()..header_end);
+    let header = header.trim_end();
+    let abilities = header.split(" has ").nth(1);
+    if abilities.map_or(false, |a| a.split(',').any(|x| x.trim() == ability)) {
+        return None;
+    }
+    let text = match abilities {
+        Some(_) => format!(", {}", ability),
+        None => format!(" has {}", ability),
+    };
+    let pos = doc
+        .doc()
+        .to_position(definition.start_byte() + header.len())?;
+    Some(TextEdit::new(Range::new(pos, pos), text))
+}
+
+fn find_struct<'a>(doc: &MoveDocument, node: &Node<'a>, name: &str) -> Option<Node<'a>> {
+    if node.kind() == kinds::STRUCT_DEFINITION {
+        let n = node.child_by_field_name("name")?;
+        return Some(*node).filter(|_| node_text(doc, &n) == name);
+    }
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.named_children(&mut cursor).collect();
+    children.iter().find_map(|c| find_struct(doc, c, name))
+}
+
+/// `fun f<T>` => `fun f<T: ability>`, or `fun f<T: drop>` => `fun f<T: drop + ability>`,
+/// for the function at `pos`.
+fn type_parameter_edit(
+    doc: &MoveDocument,
+    pos: Position,
+    name: &str,
+    ability: &str,
+) -> Option<TextEdit> {
+    let function = doc.node_context(pos)?.nearest(kinds::FUNCTION_DEFINITION)?;
+    let params = function.child_by_field_name("type_parameters")?;
+    let text = node_text(doc, &params);
+    let mut offset = params.start_byte();
+    for segment in text.split(|c| c == ',' || c == '<' || c == '>') {
+        let (param, constraint) = match segment.find(':') {
+            Some(i) => (&segment[..i], Some(&segment[i + 1..])),
+            None => (segment, None),
+        };
+        if param.trim() == name {
+            if constraint.map_or(false, |c| c.split('+').any(|a| a.trim() == ability)) {
+                return None;
+            }
+            let (end, text) = match constraint {
+                Some(_) => (segment.trim_end().len(), format!(" + {}", ability)),
+                None => (param.trim_end().len(), format!(": {}", ability)),
+            };
+            let pos = doc.doc().to_position(offset + end)?;
+            return Some(TextEdit::new(Range::new(pos, pos), text));
+        }
+        offset += segment.len() + 1;
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = r"module M {
+    struct S { a: u64 }
+    struct R has drop { a: u64 }
+    fun f<T, U: drop>(t: T, u: U) {}
+}
+";
+
+    fn fix(message: &str, pos: Position) -> Option<TextEdit> {
+        let doc = MoveDocument::new(1, SOURCE);
+        let uri = Url::parse("file:///M.move").unwrap();
+        let diagnostic = Diagnostic {
+            range: Range::new(pos, pos),
+            message: message.to_string(),
+            ..Default::default()
+        };
+        let mut actions = add_ability_fixes(&doc, &uri, &[diagnostic]);
+        let action = actions.pop()?;
+        action.edit?.changes?.remove(&uri)?.pop()
+    }
+
+    #[test]
+    fn test_add_ability_fixes() {
+        assert_eq!(
+            parse_missing_ability("The type '0x1::M::S<u64>' does not have the ability 'copy'"),
+            Some(("S".to_string(), "copy"))
+        );
+        assert!(parse_missing_ability("Unbound variable 'x'").is_none());
+
+        let pos = Position::new(3, 26);
+        let edit = fix("'0x1::M::S' does not have the ability 'copy'", pos).unwrap();
+        assert_eq!(edit.new_text, " has copy");
+        assert_eq!(edit.range.start, Position::new(1, 12));
+
+        let edit = fix("'0x1::M::R' does not have the ability 'copy'", pos).unwrap();
+        assert_eq!(edit.new_text, ", copy");
+        assert_eq!(edit.range.start, Position::new(2, 21));
+        assert!(fix("'0x1::M::R' does not have the ability 'drop'", pos).is_none());
+
+        let edit = fix("'T' does not have the ability 'copy'", pos).unwrap();
+        assert_eq!(edit.new_text, ": copy");
+        assert_eq!(edit.range.start, Position::new(3, 11));
+
+        let edit = fix("'U' does not have the ability 'copy'", pos).unwrap();
+        assert_eq!(edit.new_text, " + copy");
+        assert_eq!(edit.range.start, Position::new(3, 20));
+    }
+}
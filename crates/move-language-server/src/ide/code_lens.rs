@@ -0,0 +1,99 @@
+use crate::{move_document::MoveDocument, tree_sitter_move::kinds};
+use serde_json::json;
+use tower_lsp::lsp_types::{CodeLens, Command, Url};
+use tree_sitter::Node;
+
+pub const COMPILE_COMMAND: &str = "compile";
+pub const RUN_SCRIPT_COMMAND: &str = "runScript";
+/// Runs a test function, listed by the `move/tests` request.
+pub const RUN_TEST_COMMAND: &str = "runTest";
+pub const DISASSEMBLE_COMMAND: &str = "disassemble";
+
+/// "Compile" lenses above modules and scripts, and "Run script" lenses above script functions.
+pub fn code_lens(doc: &MoveDocument, uri: &Url) -> Vec<CodeLens> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let root = tree.root_node();
+
+    let mut lenses = vec![];
+    let mut cursor = root.walk();
+    for node in root.named_children(&mut cursor) {
+        match node.kind() {
+            kinds::ADDRESS_BLOCK => {
+                let mut cursor = node.walk();
+                for module in node
+                    .named_children(&mut cursor)
+                    .filter(|n| n.kind() == kinds::MODULE_DEFINITION)
+                {
+                    lenses.extend(compile_lens(doc, uri, &module));
+                }
+            }
+            kinds::MODULE_DEFINITION => lenses.extend(compile_lens(doc, uri, &node)),
+            kinds::SCRIPT_BLOCK => {
+                lenses.extend(compile_lens(doc, uri, &node));
+                let mut cursor = node.walk();
+                let func = node
+                    .named_children(&mut cursor)
+                    .find(|n| n.kind() == kinds::FUNCTION_DEFINITION);
+                if let Some(func) = func {
+                    lenses.extend(run_script_lens(doc, uri, &func));
+                }
+            }
+            _ => {}
+        }
+    }
+    lenses
+}
+
+fn compile_lens(doc: &MoveDocument, uri: &Url, node: &Node) -> Option<CodeLens> {
+    let range = doc.node_range(&header(node))?;
+    Some(CodeLens {
+        range,
+        command: Some(Command {
+            title: "Compile".to_string(),
+            command: COMPILE_COMMAND.to_string(),
+            arguments: Some(vec![json!({ "file": uri })]),
+        }),
+        data: None,
+    })
+}
+
+fn run_script_lens(doc: &MoveDocument, uri: &Url, func: &Node) -> Option<CodeLens> {
+    let range = doc.node_range(&header(func))?;
+    Some(CodeLens {
+        range,
+        command: Some(Command {
+            title: "Run script".to_string(),
+            command: RUN_SCRIPT_COMMAND.to_string(),
+            arguments: Some(vec![json!({ "file": uri })]),
+        }),
+        data: None,
+    })
+}
+
+/// Lenses are placed on the name of a definition if it has one.
+fn header<'a>(node: &Node<'a>) -> Node<'a> {
+    node.child_by_field_name("name").unwrap_or(*node)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_code_lens() {
+        let source = "address 0x1 {\nmodule A {}\nmodule B {}\n}\nscript {\nfun main() {}\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let uri = Url::parse("file:///test.move").unwrap();
+        let lenses = code_lens(&doc, &uri);
+        let titles: Vec<_> = lenses
+            .iter()
+            .map(|l| l.command.as_ref().unwrap().title.as_str())
+            .collect();
+        assert_eq!(titles, vec!["Compile", "Compile", "Compile", "Run script"]);
+        assert_eq!(lenses[0].range.start.line, 1);
+        assert_eq!(lenses[3].range.start.line, 5);
+    }
+}
@@ -0,0 +1,409 @@
+use crate::{
+    analysis::{Symbol, SymbolKind},
+    ide::{
+        goto_definition::{enclosing_module, find_module, used_module},
+        locals::node_text,
+    },
+    move_document::MoveDocument,
+    salsa::module_index_query::{ModuleDecl, ModuleKey},
+    tree_sitter_move::kinds,
+};
+use move_lang::parser::ast::{Definition, ModuleMember, StructFields};
+use tower_lsp::lsp_types::{CompletionItem, CompletionItemKind, Position};
+use tree_sitter::Node;
+
+/// Abilities, offered after `has` in struct declarations and in type parameter constraints.
+pub(crate) const ABILITIES: &[(&str, &str)] = &[
+    ("copy", "Values of the type can be copied."),
+    ("drop", "Values of the type can be dropped."),
+    (
+        "store",
+        "Values of the type can exist inside a struct in global storage.",
+    ),
+    (
+        "key",
+        "The type can be used as a key for global storage operations.",
+    ),
+];
+
+/// Ability completions, if `pos` is in the abilities of a struct or a type parameter.
+pub fn ability_items(doc: &MoveDocument, pos: Position) -> Option<Vec<CompletionItem>> {
+    let offset = doc.doc().to_offset(pos)?;
+    // the word being typed is not a token of the tree yet, or not a whole one.
+    let line_start = doc.doc().to_offset(Position::new(pos.line, 0))?;
+    let prefix = doc.doc().rope().slice_to_cow(line_start..offset);
+    let word = prefix
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+        .count();
+    if !in_abilities(doc, offset - word) {
+        return None;
+    }
+    let items = ABILITIES
+        .iter()
+        .map(|(ability, doc)| CompletionItem {
+            label: ability.to_string(),
+            kind: Some(CompletionItemKind::Keyword),
+            detail: Some(doc.to_string()),
+            ..Default::default()
+        })
+        .collect();
+    Some(items)
+}
+
+/// Whether `offset` is in the abilities of a struct or in the constraints of a type parameter,
+/// decided on the tokens of the syntax tree before it, so comments and strings don't count.
+pub(crate) fn in_abilities(doc: &MoveDocument, offset: usize) -> bool {
+    let tokens = tokens_before(doc, offset);
+    in_struct_abilities(&tokens) || in_type_constraint(&tokens)
+}
+
+/// Texts of the tokens ending before `offset`, nearest first, without comments.
+/// Stops at the first `{`, `}` or `;`, which no ability list goes past.
+fn tokens_before(doc: &MoveDocument, offset: usize) -> Vec<String> {
+    let root = match doc.tree() {
+        Some(tree) => tree.root_node(),
+        None => return vec![],
+    };
+    let mut leaves = vec![];
+    let mut cursor = root.walk();
+    'walk: loop {
+        let node = cursor.node();
+        if node.start_byte() >= offset {
+            break;
+        }
+        if node.child_count() > 0 {
+            cursor.goto_first_child();
+            continue;
+        }
+        if node.end_byte() <= offset && !node.is_missing() && !node.kind().contains("comment") {
+            leaves.push(node);
+        }
+        while !cursor.goto_next_sibling() {
+            if !cursor.goto_parent() {
+                break 'walk;
+            }
+        }
+    }
+
+    let mut tokens = vec![];
+    for leaf in leaves.iter().rev() {
+        let text = doc.node_text(leaf).to_string();
+        let stop = ["{", "}", ";"].contains(&text.as_str());
+        tokens.push(text);
+        if stop {
+            break;
+        }
+    }
+    tokens
+}
+
+/// `struct S has copy, |`, `tokens` are the ones before the position, nearest first.
+fn in_struct_abilities(tokens: &[String]) -> bool {
+    let has = match tokens.iter().position(|t| t == "has") {
+        Some(i) => i,
+        None => return false,
+    };
+    let is_ability = |t: &String| t == "," || ABILITIES.iter().any(|(a, _)| t == a);
+    tokens[..has].iter().all(is_ability) && tokens[has..].iter().any(|t| t == "struct")
+}
+
+/// `fun f<T: copy + |`, `tokens` are the ones before the position, nearest first.
+fn in_type_constraint(tokens: &[String]) -> bool {
+    let mut depth = 0;
+    // whether the type parameter at the position has a `:`.
+    let mut constrained = false;
+    let mut in_param = true;
+    for t in tokens {
+        match t.as_str() {
+            ">" => depth += 1,
+            "<" if depth == 0 => return constrained,
+            "<" => depth -= 1,
+            ":" if depth == 0 && in_param => constrained = true,
+            "," if depth == 0 => in_param = false,
+            "(" | ")" | "{" | "}" | ";" => return false,
+            _ => {}
+        }
+    }
+    false
+}
+
+/// A module member as written in source, like a struct being packed (`S { .. }`)
+/// or unpacked (`let S { .. } = ..`).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MemberAccess {
+    pub address: Option<String>,
+    pub module: Option<String>,
+    pub name: String,
+}
+
+/// The struct whose field list contains `pos`.
+pub fn struct_at(doc: &MoveDocument, pos: Position) -> Option<MemberAccess> {
+    let offset = doc.doc().to_offset(pos)?;
+    let node = doc
+        .node_context(pos)?
+        .nearest_of(&[kinds::PACK_EXPRESSION, kinds::BIND_UNPACK])?;
+    let access = node.named_child(0)?;
+    // no field completion on the struct name itself.
+    if offset <= access.end_byte() {
+        return None;
+    }
+    let text = doc.node_text(&access);
+    parse_access(text.as_ref())
+}
+
+/// The module access being typed at `pos`, like `M::|` or `0x1::M::f|`,
+/// with the partial member name.
+pub fn module_access_at(doc: &MoveDocument, pos: Position) -> Option<MemberAccess> {
+    let offset = doc.doc().to_offset(pos)?;
+    let line_start = doc.doc().to_offset(Position::new(pos.line, 0))?;
+    let prefix = doc.doc().rope().slice_to_cow(line_start..offset);
+    let start = prefix
+        .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_' || c == ':'))
+        .map_or(0, |i| i + 1);
+    let path = &prefix[start..];
+    let sep = path.rfind("::")?;
+    let mut segments: Vec<_> = path[..sep].split("::").map(|s| s.to_string()).collect();
+    let module = segments.pop().filter(|s| !s.is_empty())?;
+    Some(MemberAccess {
+        address: segments.pop(),
+        module: Some(module),
+        name: path[sep + 2..].to_string(),
+    })
+}
+
+/// The module a script at `pos` refers to as `name`, scripts are not in the module index.
+pub fn script_used_module(doc: &MoveDocument, pos: Position, name: &str) -> Option<ModuleKey> {
+    let script = doc.node_context(pos)?.nearest(kinds::SCRIPT_BLOCK)?;
+    let mut cursor = script.walk();
+    let text = |n: Node| node_text(doc, &n);
+    let found = script
+        .named_children(&mut cursor)
+        .filter(|n| n.kind() == kinds::USE_DECL)
+        .find_map(|u| {
+            let address = u.child_by_field_name("address")?;
+            let module = u.child_by_field_name("module")?;
+            let local = u.child_by_field_name("as").unwrap_or(module);
+            if text(local) == name {
+                Some(ModuleKey::new(&text(address), &text(module)))
+            } else {
+                None
+            }
+        });
+    found
+}
+
+/// Completion items of the functions of `module` callable from elsewhere,
+/// or all of them if `public_only` is false, with their signatures as details.
+/// `symbols` are the ones of the file defining `module`.
+pub fn function_items(symbols: &[Symbol], module: &str, public_only: bool) -> Vec<CompletionItem> {
+    symbols
+        .iter()
+        .filter(|s| s.kind == SymbolKind::Function && s.container.as_deref() == Some(module))
+        .filter(|s| !public_only || s.public)
+        .map(|s| CompletionItem {
+            label: s.name.clone(),
+            kind: Some(CompletionItemKind::Function),
+            detail: s.signature.clone(),
+            ..Default::default()
+        })
+        .collect()
+}
+
+/// `0x1::M::S<T>` => address `0x1`, module `M`, struct `S`.
+pub(crate) fn parse_access(text: &str) -> Option<MemberAccess> {
+    let text = text.split('<').next()?;
+    let mut segments: Vec<_> = text.split("::").map(|s| s.trim().to_string()).collect();
+    let name = segments.pop().filter(|s| !s.is_empty())?;
+    let module = segments.pop();
+    let address = segments.pop();
+    Some(MemberAccess {
+        address,
+        module,
+        name,
+    })
+}
+
+/// The module defining the accessed member, and the member name in that module.
+pub fn resolve_member(
+    access: MemberAccess,
+    file_modules: &[ModuleDecl],
+    pos: Position,
+) -> Option<(ModuleKey, String)> {
+    let MemberAccess {
+        address,
+        module,
+        name,
+    } = access;
+    match (address, module) {
+        (Some(address), Some(module)) => Some((ModuleKey::new(&address, &module), name)),
+        (None, Some(module)) => Some((used_module(file_modules, pos, &module)?, name)),
+        _ => {
+            // `use 0x1::M::{S as T}`, or a member of the enclosing module.
+            let used = file_modules
+                .iter()
+                .flat_map(|m| m.uses.iter())
+                .find(|u| u.member_alias.as_ref().or_else(|| u.member.as_ref()) == Some(&name));
+            match used {
+                Some(u) => Some((u.target.clone(), u.member.clone()?)),
+                None => Some((enclosing_module(file_modules, pos)?.key.clone(), name)),
+            }
+        }
+    }
+}
+
+/// Completion items of the fields of struct `module::name`, with their types as annotations.
+/// `source` is the text `defs` are parsed from.
+pub fn field_items(
+    defs: &[Definition],
+    source: &str,
+    module: &str,
+    name: &str,
+) -> Vec<CompletionItem> {
+    let fields = find_module(defs, module).and_then(|m| {
+        m.members.iter().find_map(|member| match member {
+            ModuleMember::Struct(s) if s.name.0.value == name => match &s.fields {
+                StructFields::Defined(fields) => Some(fields),
+                StructFields::Native(_) => None,
+            },
+            _ => None,
+        })
+    });
+
+    fields
+        .into_iter()
+        .flatten()
+        .map(|(field, ty)| {
+            let span = ty.loc.span();
+            let ty = source.get(span.start().to_usize()..span.end().to_usize());
+            CompletionItem {
+                label: field.0.value.clone(),
+                kind: Some(CompletionItemKind::Field),
+                detail: ty.map(|t| t.to_string()),
+                ..Default::default()
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::file_symbols, salsa::module_index_query::index_modules};
+    use move_lang::{parser::syntax, FileCommentMap};
+
+    const SOURCE: &str = r"address 0x1 {
+module M {
+    struct S { a: u64, b: vector<u8> }
+    fun f(s: S): S {
+        let S { a, b } = s;
+        S { a, b }
+    }
+}
+}
+";
+
+    #[test]
+    fn test_ability_context() {
+        let tokens =
+            |t: &[&str]| -> Vec<String> { t.iter().rev().map(|t| t.to_string()).collect() };
+        assert!(in_struct_abilities(&tokens(&["struct", "S", "has"])));
+        assert!(in_struct_abilities(&tokens(&[
+            "resource", "struct", "S", "<", "T", ">", "has", "copy", ","
+        ])));
+        assert!(!in_struct_abilities(&tokens(&["struct", "S", "hash"])));
+        assert!(!in_struct_abilities(&tokens(&[
+            "struct", "S", "has", "copy", "x"
+        ])));
+        assert!(in_type_constraint(&tokens(&["fun", "f", "<", "T", ":"])));
+        assert!(in_type_constraint(&tokens(&[
+            "struct", "S", "<", "T", ":", "copy", ",", "U", ":", "copy", "+"
+        ])));
+        assert!(!in_type_constraint(&tokens(&["fun", "f", "<", "T", ","])));
+        assert!(!in_type_constraint(&tokens(&[
+            "fun", "f", "<", "T", ":", "copy", ">", "("
+        ])));
+
+        let doc = MoveDocument::new(1, "module M {\n    struct S has \n}\n");
+        let items = ability_items(&doc, Position::new(1, 17)).unwrap();
+        let labels: Vec<_> = items.iter().map(|i| i.label.as_str()).collect();
+        assert_eq!(labels, vec!["copy", "drop", "store", "key"]);
+        assert!(ability_items(&doc, Position::new(0, 5)).is_none());
+        // the word being typed.
+        let doc = MoveDocument::new(1, "module M {\n    struct S has co\n}\n");
+        assert!(ability_items(&doc, Position::new(1, 19)).is_some());
+
+        // neither in comments nor in strings.
+        let doc = MoveDocument::new(1, "module M {\n    // struct S has \n    fun f() {}\n}\n");
+        assert!(ability_items(&doc, Position::new(1, 20)).is_none());
+        let doc = MoveDocument::new(1, "module M {\n    fun f<T: copy + >() {}\n}\n");
+        assert!(ability_items(&doc, Position::new(1, 20)).is_some());
+    }
+
+    #[test]
+    fn test_function_completion() {
+        let script =
+            "script {\n    use 0x1::M as N;\n    fun main() {\n        N::f();\n    }\n}\n";
+        let doc = MoveDocument::new(1, script);
+        let pos = Position::new(3, 11);
+        let access = module_access_at(&doc, pos).unwrap();
+        assert_eq!(access.module.as_deref(), Some("N"));
+        assert!(access.address.is_none());
+        assert_eq!(access.name, "");
+        assert_eq!(
+            script_used_module(&doc, pos, "N"),
+            Some(ModuleKey::new("0x1", "M"))
+        );
+        assert!(script_used_module(&doc, pos, "M").is_none());
+
+        let source = "address 0x1 {\nmodule M {\n    public fun f(a: u64): u64 { a }\n    fun g() {}\n}\n}\n";
+        let (defs, _) = syntax::parse_file_string("test", source, FileCommentMap::new()).unwrap();
+        let symbols = file_symbols(&defs, source);
+        let items = function_items(&symbols, "M", true);
+        let functions: Vec<_> = items
+            .iter()
+            .map(|i| (i.label.as_str(), i.detail.as_deref().unwrap()))
+            .collect();
+        assert_eq!(functions, vec![("f", "public fun f(a: u64): u64")]);
+        assert_eq!(function_items(&symbols, "M", false).len(), 2);
+
+        let access =
+            module_access_at(&MoveDocument::new(1, "    0x1::M::f"), Position::new(0, 13)).unwrap();
+        assert_eq!(access.address.as_deref(), Some("0x1"));
+        assert_eq!(access.name, "f");
+    }
+
+    #[test]
+    fn test_parse_access() {
+        let access = parse_access("0x1::M::S<T>").unwrap();
+        assert_eq!(access.address.as_deref(), Some("0x1"));
+        assert_eq!(access.module.as_deref(), Some("M"));
+        assert_eq!(access.name, "S");
+        let access = parse_access("S").unwrap();
+        assert!(access.module.is_none());
+    }
+
+    #[test]
+    fn test_struct_field_completion() {
+        let doc = MoveDocument::new(1, SOURCE);
+        let modules = index_modules(SOURCE, None);
+        // inside `let S { a, b }`
+        let pos = Position::new(4, 16);
+        let access = struct_at(&doc, pos).unwrap();
+        assert_eq!(access.name, "S");
+        // on the struct name.
+        assert!(struct_at(&doc, Position::new(5, 8)).is_none());
+
+        let (key, name) = resolve_member(access, &modules, pos).unwrap();
+        assert_eq!(key, ModuleKey::new("0x1", "M"));
+
+        let (defs, _) = syntax::parse_file_string("test", SOURCE, FileCommentMap::new()).unwrap();
+        let items = field_items(&defs, SOURCE, &key.name, &name);
+        let fields: Vec<_> = items
+            .iter()
+            .map(|i| (i.label.as_str(), i.detail.as_deref().unwrap()))
+            .collect();
+        assert_eq!(fields, vec![("a", "u64"), ("b", "vector<u8>")]);
+    }
+}
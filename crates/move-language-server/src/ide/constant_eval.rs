@@ -0,0 +1,447 @@
+//! Evaluation of constant declarations, like `const MAX: u64 = 1 << 32;`,
+//! to show their values on hover and report the ones which can't be evaluated,
+//! like `const X: u8 = 255 + 1;`, without waiting for a compilation.
+//!
+//! Expressions the compiler would reject for other reasons, type errors or calls,
+//! are left unevaluated so that they are only reported once.
+
+use move_ir_types::location::Loc;
+use move_lang::{
+    parser::ast::{
+        BinOp_, Constant, Definition, Exp, Exp_, ModuleAccess_, ModuleMember, Type, Type_,
+        UnaryOp_, Value_,
+    },
+    shared::Address,
+};
+use std::fmt;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum IntType {
+    U8,
+    U64,
+    U128,
+}
+
+impl IntType {
+    fn max(self) -> u128 {
+        match self {
+            IntType::U8 => u8::MAX as u128,
+            IntType::U64 => u64::MAX as u128,
+            IntType::U128 => u128::MAX,
+        }
+    }
+
+    fn bits(self) -> u128 {
+        match self {
+            IntType::U8 => 8,
+            IntType::U64 => 64,
+            IntType::U128 => 128,
+        }
+    }
+}
+
+impl fmt::Display for IntType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntType::U8 => write!(f, "u8"),
+            IntType::U64 => write!(f, "u64"),
+            IntType::U128 => write!(f, "u128"),
+        }
+    }
+}
+
+/// Types a constant can be declared with.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum ConstType {
+    Bool,
+    Int(IntType),
+    Address,
+    /// `vector<u8>`
+    Bytes,
+}
+
+impl fmt::Display for ConstType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstType::Bool => write!(f, "bool"),
+            ConstType::Int(t) => write!(f, "{}", t),
+            ConstType::Address => write!(f, "address"),
+            ConstType::Bytes => write!(f, "vector<u8>"),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum ConstValue {
+    Bool(bool),
+    Int(u128),
+    Address(Address),
+    Bytes(Vec<u8>),
+}
+
+impl fmt::Display for ConstValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConstValue::Bool(b) => write!(f, "{}", b),
+            ConstValue::Int(n) => write!(f, "{}", n),
+            ConstValue::Address(a) => write!(f, "{}", a),
+            ConstValue::Bytes(bytes) => {
+                write!(f, "x\"")?;
+                for b in bytes {
+                    write!(f, "{:02x}", b)?;
+                }
+                write!(f, "\"")
+            }
+        }
+    }
+}
+
+/// Why a constant can't be evaluated, at the sub expression which fails.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct EvalError {
+    pub loc: Loc,
+    pub message: String,
+}
+
+/// A constant whose type is supported.
+pub struct EvaluatedConstant<'a> {
+    pub constant: &'a Constant,
+    pub ty: ConstType,
+    /// `None` if the expression is left to the compiler.
+    pub value: Option<Result<ConstValue, EvalError>>,
+}
+
+impl EvaluatedConstant<'_> {
+    pub fn name(&self) -> &str {
+        self.constant.name.0.value.as_str()
+    }
+}
+
+/// Constants of the modules and scripts of `defs`, evaluated.
+/// Only the ones of the module or script enclosing `offset` if given.
+pub fn evaluate_constants(defs: &[Definition], offset: Option<usize>) -> Vec<EvaluatedConstant> {
+    let contains = |loc: &Loc| {
+        offset.map_or(true, |o| {
+            loc.span().start().to_usize() <= o && o <= loc.span().end().to_usize()
+        })
+    };
+    let mut constants: Vec<&Constant> = vec![];
+    for def in defs {
+        let modules = match def {
+            Definition::Module(m) => vec![m],
+            Definition::Address(_, _, modules) => modules.iter().collect(),
+            Definition::Script(s) => {
+                if contains(&s.loc) {
+                    constants.extend(s.constants.iter());
+                }
+                continue;
+            }
+        };
+        for m in modules.into_iter().filter(|m| contains(&m.loc)) {
+            constants.extend(m.members.iter().filter_map(|member| match member {
+                ModuleMember::Constant(c) => Some(c),
+                _ => None,
+            }));
+        }
+    }
+
+    constants
+        .into_iter()
+        .filter_map(|constant| {
+            let ty = const_type(&constant.signature)?;
+            Some(EvaluatedConstant {
+                constant,
+                ty,
+                value: evaluate(constant, ty),
+            })
+        })
+        .collect()
+}
+
+fn const_type(ty: &Type) -> Option<ConstType> {
+    let (access, args) = match &ty.value {
+        Type_::Apply(access, args) => (access, args),
+        _ => return None,
+    };
+    let name = match &access.value {
+        ModuleAccess_::Name(n) => n.value.as_str(),
+        _ => return None,
+    };
+    match (name, args.as_slice()) {
+        ("bool", []) => Some(ConstType::Bool),
+        ("u8", []) => Some(ConstType::Int(IntType::U8)),
+        ("u64", []) => Some(ConstType::Int(IntType::U64)),
+        ("u128", []) => Some(ConstType::Int(IntType::U128)),
+        ("address", []) => Some(ConstType::Address),
+        ("vector", [arg]) if const_type(arg) == Some(ConstType::Int(IntType::U8)) => {
+            Some(ConstType::Bytes)
+        }
+        _ => None,
+    }
+}
+
+/// Values of sub expressions, integers are untyped until an operand or the declaration types them.
+#[derive(Clone, Debug, PartialEq)]
+enum Val {
+    Bool(bool),
+    Int(u128, Option<IntType>),
+    Address(Address),
+    Bytes(Vec<u8>),
+}
+
+enum Error {
+    /// Left to the compiler.
+    Unsupported,
+    Invalid(Loc, String),
+}
+
+fn evaluate(constant: &Constant, ty: ConstType) -> Option<Result<ConstValue, EvalError>> {
+    let expected = match ty {
+        ConstType::Int(t) => Some(t),
+        _ => None,
+    };
+    let loc = constant.value.loc;
+    let value = match eval(&constant.value, expected) {
+        Ok(v) => v,
+        Err(Error::Unsupported) => return None,
+        Err(Error::Invalid(loc, message)) => return Some(Err(EvalError { loc, message })),
+    };
+    let value = match (value, ty) {
+        (Val::Bool(b), ConstType::Bool) => ConstValue::Bool(b),
+        (Val::Int(n, t), ConstType::Int(declared)) if t.map_or(true, |t| t == declared) => {
+            if n > declared.max() {
+                return Some(Err(EvalError {
+                    loc,
+                    message: format!("{} does not fit in {}", n, declared),
+                }));
+            }
+            ConstValue::Int(n)
+        }
+        (Val::Address(a), ConstType::Address) => ConstValue::Address(a),
+        (Val::Bytes(b), ConstType::Bytes) => ConstValue::Bytes(b),
+        _ => return None,
+    };
+    Some(Ok(value))
+}
+
+fn eval(exp: &Exp, expected: Option<IntType>) -> Result<Val, Error> {
+    let loc = exp.loc;
+    match &exp.value {
+        Exp_::Value(v) => Ok(match &v.value {
+            Value_::Address(a) => Val::Address(*a),
+            Value_::U8(n) => Val::Int(*n as u128, Some(IntType::U8)),
+            Value_::U64(n) => Val::Int(*n as u128, Some(IntType::U64)),
+            Value_::U128(n) => Val::Int(*n, Some(IntType::U128)),
+            Value_::Bool(b) => Val::Bool(*b),
+            Value_::Bytearray(b) => Val::Bytes(b.clone()),
+        }),
+        Exp_::InferredNum(n) => Ok(Val::Int(*n, None)),
+        Exp_::Annotate(e, ty) => {
+            let ty = match const_type(ty) {
+                Some(ConstType::Int(t)) => t,
+                _ => return eval(e, expected),
+            };
+            let (n, _) = int(eval(e, Some(ty))?)?;
+            check_fits(n, ty, loc)?;
+            Ok(Val::Int(n, Some(ty)))
+        }
+        Exp_::Cast(e, ty) => {
+            let ty = match const_type(ty) {
+                Some(ConstType::Int(t)) => t,
+                _ => return Err(Error::Unsupported),
+            };
+            let (n, _) = int(eval(e, None)?)?;
+            if n > ty.max() {
+                return Err(Error::Invalid(
+                    loc,
+                    format!("cannot cast {} to {}, it's out of range", n, ty),
+                ));
+            }
+            Ok(Val::Int(n, Some(ty)))
+        }
+        Exp_::UnaryExp(op, e) => match op.value {
+            UnaryOp_::Not => Ok(Val::Bool(!bool(eval(e, None)?)?)),
+        },
+        Exp_::BinopExp(lhs, op, rhs) => binop(loc, lhs, op.value, rhs, expected),
+        _ => Err(Error::Unsupported),
+    }
+}
+
+fn binop(
+    loc: Loc,
+    lhs: &Exp,
+    op: BinOp_,
+    rhs: &Exp,
+    expected: Option<IntType>,
+) -> Result<Val, Error> {
+    use BinOp_::*;
+    match op {
+        And => Ok(Val::Bool(
+            bool(eval(lhs, None)?)? && bool(eval(rhs, None)?)?,
+        )),
+        Or => Ok(Val::Bool(
+            bool(eval(lhs, None)?)? || bool(eval(rhs, None)?)?,
+        )),
+        Eq | Neq => {
+            let (a, b) = (eval(lhs, None)?, eval(rhs, None)?);
+            let equal = match (&a, &b) {
+                (Val::Int(x, tx), Val::Int(y, ty)) => {
+                    unify(*tx, *ty)?;
+                    x == y
+                }
+                (Val::Int(..), _) | (_, Val::Int(..)) => return Err(Error::Unsupported),
+                _ if std::mem::discriminant(&a) == std::mem::discriminant(&b) => a == b,
+                _ => return Err(Error::Unsupported),
+            };
+            Ok(Val::Bool(if op == Eq { equal } else { !equal }))
+        }
+        Lt | Gt | Le | Ge => {
+            let (x, tx) = int(eval(lhs, None)?)?;
+            let (y, ty) = int(eval(rhs, None)?)?;
+            unify(tx, ty)?;
+            Ok(Val::Bool(match op {
+                Lt => x < y,
+                Gt => x > y,
+                Le => x <= y,
+                _ => x >= y,
+            }))
+        }
+        Shl | Shr => {
+            let (x, tx) = int(eval(lhs, expected)?)?;
+            let (y, _) = int(eval(rhs, Some(IntType::U8))?)?;
+            let ty = tx.or(expected).unwrap_or(IntType::U128);
+            if y >= ty.bits() {
+                return Err(Error::Invalid(
+                    loc,
+                    format!("shift by {} is too large for {}", y, ty),
+                ));
+            }
+            let value = if op == Shl {
+                (x << y) & ty.max()
+            } else {
+                x >> y
+            };
+            Ok(Val::Int(value, tx))
+        }
+        Add | Sub | Mul | Mod | Div | BitOr | BitAnd | Xor => {
+            let (x, tx) = int(eval(lhs, expected)?)?;
+            let (y, ty) = int(eval(rhs, expected)?)?;
+            let t = unify(tx, ty)?;
+            let invalid = |message: &str| Error::Invalid(loc, message.to_string());
+            let value = match op {
+                Add => x
+                    .checked_add(y)
+                    .ok_or_else(|| invalid("arithmetic overflow"))?,
+                Sub => x
+                    .checked_sub(y)
+                    .ok_or_else(|| invalid("arithmetic underflow"))?,
+                Mul => x
+                    .checked_mul(y)
+                    .ok_or_else(|| invalid("arithmetic overflow"))?,
+                Mod => x
+                    .checked_rem(y)
+                    .ok_or_else(|| invalid("division by zero"))?,
+                Div => x
+                    .checked_div(y)
+                    .ok_or_else(|| invalid("division by zero"))?,
+                BitOr => x | y,
+                BitAnd => x & y,
+                _ => x ^ y,
+            };
+            if let Some(t) = t.or(expected) {
+                if value > t.max() {
+                    return Err(invalid("arithmetic overflow"));
+                }
+            }
+            Ok(Val::Int(value, t))
+        }
+        _ => Err(Error::Unsupported),
+    }
+}
+
+fn check_fits(n: u128, ty: IntType, loc: Loc) -> Result<(), Error> {
+    if n > ty.max() {
+        return Err(Error::Invalid(loc, format!("{} does not fit in {}", n, ty)));
+    }
+    Ok(())
+}
+
+/// The type of operands typed `a` and `b`, mixing types is a type error.
+fn unify(a: Option<IntType>, b: Option<IntType>) -> Result<Option<IntType>, Error> {
+    match (a, b) {
+        (Some(a), Some(b)) if a != b => Err(Error::Unsupported),
+        _ => Ok(a.or(b)),
+    }
+}
+
+fn int(v: Val) -> Result<(u128, Option<IntType>), Error> {
+    match v {
+        Val::Int(n, t) => Ok((n, t)),
+        _ => Err(Error::Unsupported),
+    }
+}
+
+fn bool(v: Val) -> Result<bool, Error> {
+    match v {
+        Val::Bool(b) => Ok(b),
+        _ => Err(Error::Unsupported),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use move_lang::{parser::syntax, FileCommentMap};
+
+    fn values(source: &str) -> Vec<(String, Option<Result<String, String>>)> {
+        let (defs, _) = syntax::parse_file_string("c.move", source, FileCommentMap::new()).unwrap();
+        evaluate_constants(&defs, None)
+            .into_iter()
+            .map(|c| {
+                (
+                    c.name().to_string(),
+                    c.value
+                        .map(|v| v.map(|v| v.to_string()).map_err(|e| e.message)),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_evaluate_constants() {
+        let source = r#"module M {
+    const A: u64 = 1 << 32;
+    const B: u8 = 255 + 1;
+    const C: u8 = (300 as u8);
+    const D: bool = !(1 < 2) || 3 == 3;
+    const E: vector<u8> = x"0aff";
+    const F: u128 = 10 / 0;
+    const G: u64 = 256;
+    const H: u8 = 256;
+    const I: u64 = 1u8 + 1u64;
+    const J: u64 = 1 - 2;
+}
+"#;
+        let values = values(source);
+        let value = |name: &str| {
+            values
+                .iter()
+                .find(|(n, _)| n == name)
+                .and_then(|(_, v)| v.clone())
+        };
+        assert_eq!(value("A"), Some(Ok("4294967296".to_string())));
+        assert_eq!(value("B"), Some(Err("arithmetic overflow".to_string())));
+        assert_eq!(
+            value("C"),
+            Some(Err("cannot cast 300 to u8, it's out of range".to_string()))
+        );
+        assert_eq!(value("D"), Some(Ok("true".to_string())));
+        assert_eq!(value("E"), Some(Ok("x\"0aff\"".to_string())));
+        assert_eq!(value("F"), Some(Err("division by zero".to_string())));
+        assert_eq!(value("G"), Some(Ok("256".to_string())));
+        assert_eq!(value("H"), Some(Err("256 does not fit in u8".to_string())));
+        // type errors are reported by the compiler.
+        assert_eq!(value("I"), None);
+        assert_eq!(value("J"), Some(Err("arithmetic underflow".to_string())));
+    }
+}
@@ -0,0 +1,248 @@
+//! Small edits from a rewritten text, so that editors keep their cursors and undo history
+//! instead of seeing the whole document replaced.
+//! Lines are diffed first, tokens never span them except in comments, then each changed run
+//! of lines is narrowed to the characters which differ.
+
+use crate::move_document::{rope_text, RopeDoc};
+use std::ops::Range;
+use tower_lsp::lsp_types::{self, TextEdit};
+
+/// Cells of the longest common subsequence table of the changed lines, 4 bytes each.
+const MAX_TABLE: usize = 1 << 22;
+
+/// Edits turning `old` into `new`.
+pub fn diff(old: &str, new: &str) -> Vec<TextEdit> {
+    let doc = RopeDoc::new(0, old);
+    to_edits(&doc, 0, diff_hunks(old, new))
+}
+
+/// `edits` of `doc` split into the parts which change something, edits which don't apply
+/// to `doc` are kept as they are.
+pub fn minimize(doc: &RopeDoc, edits: Vec<TextEdit>) -> Vec<TextEdit> {
+    edits
+        .into_iter()
+        .flat_map(|edit| {
+            let span = doc
+                .to_offset(edit.range.start)
+                .zip(doc.to_offset(edit.range.end))
+                .filter(|(start, end)| start <= end);
+            match span {
+                Some((start, end)) => {
+                    let old = rope_text(doc.rope(), start..end);
+                    to_edits(doc, start, diff_hunks(old.as_ref(), &edit.new_text))
+                }
+                None => vec![edit],
+            }
+        })
+        .collect()
+}
+
+fn to_edits(doc: &RopeDoc, base: usize, hunks: Vec<(Range<usize>, String)>) -> Vec<TextEdit> {
+    hunks
+        .into_iter()
+        .filter_map(|(range, text)| {
+            Some(TextEdit::new(
+                lsp_types::Range::new(
+                    doc.to_position(base + range.start)?,
+                    doc.to_position(base + range.end)?,
+                ),
+                text,
+            ))
+        })
+        .collect()
+}
+
+/// Byte ranges of `old` to replace, in order, with their replacements.
+fn diff_hunks(old: &str, new: &str) -> Vec<(Range<usize>, String)> {
+    let old_lines = lines(old);
+    let new_lines = lines(new);
+    let old_starts = line_starts(&old_lines);
+    let new_starts = line_starts(&new_lines);
+    line_hunks(&old_lines, &new_lines)
+        .into_iter()
+        .filter_map(|(o, n)| {
+            let old_span = old_starts[o.start]..old_starts[o.end];
+            let new_span = new_starts[n.start]..new_starts[n.end];
+            narrow(old, old_span, &new[new_span])
+        })
+        .collect()
+}
+
+/// Lines of `text` with their line endings.
+fn lines(text: &str) -> Vec<&str> {
+    let mut lines = vec![];
+    let mut start = 0;
+    for (i, _) in text.match_indices('\n') {
+        lines.push(&text[start..=i]);
+        start = i + 1;
+    }
+    if start < text.len() {
+        lines.push(&text[start..]);
+    }
+    lines
+}
+
+/// Byte offset of every line, and the end of the text.
+fn line_starts(lines: &[&str]) -> Vec<usize> {
+    let mut starts = vec![0];
+    for line in lines {
+        starts.push(starts.last().unwrap() + line.len());
+    }
+    starts
+}
+
+/// `old_span` of `old` replaced by `new`, without their common prefix and suffix.
+fn narrow(old: &str, old_span: Range<usize>, new: &str) -> Option<(Range<usize>, String)> {
+    let replaced = &old[old_span.clone()];
+    let prefix: usize = replaced
+        .chars()
+        .zip(new.chars())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let (replaced, new) = (&replaced[prefix..], &new[prefix..]);
+    let suffix: usize = replaced
+        .chars()
+        .rev()
+        .zip(new.chars().rev())
+        .take_while(|(a, b)| a == b)
+        .map(|(a, _)| a.len_utf8())
+        .sum();
+    let (replaced, new) = (
+        &replaced[..replaced.len() - suffix],
+        &new[..new.len() - suffix],
+    );
+    if replaced.is_empty() && new.is_empty() {
+        return None;
+    }
+    let start = old_span.start + prefix;
+    Some((start..start + replaced.len(), new.to_string()))
+}
+
+/// Runs of `old` lines replaced by runs of `new` lines.
+fn line_hunks(old: &[&str], new: &[&str]) -> Vec<(Range<usize>, Range<usize>)> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (a, b) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+
+    let mut hunks = vec![];
+    let (mut x, mut y) = (0, 0);
+    for (mx, my) in common_lines(a, b)
+        .into_iter()
+        .chain(Some((a.len(), b.len())))
+    {
+        if x < mx || y < my {
+            hunks.push((prefix + x..prefix + mx, prefix + y..prefix + my));
+        }
+        x = mx + 1;
+        y = my + 1;
+    }
+    hunks
+}
+
+/// Indexes of the lines `a` and `b` have in common, in order, from their longest common
+/// subsequence. Above `MAX_TABLE` cells, nothing is in common and they are replaced at once.
+fn common_lines(a: &[&str], b: &[&str]) -> Vec<(usize, usize)> {
+    let (n, m) = (a.len(), b.len());
+    if n.saturating_mul(m) > MAX_TABLE {
+        return vec![];
+    }
+    // lengths of the common subsequences of `a[i..]` and `b[j..]`.
+    let width = m + 1;
+    let mut lengths = vec![0u32; (n + 1) * width];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            lengths[i * width + j] = if a[i] == b[j] {
+                lengths[(i + 1) * width + j + 1] + 1
+            } else {
+                lengths[(i + 1) * width + j].max(lengths[i * width + j + 1])
+            };
+        }
+    }
+    let mut common = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        if a[i] == b[j] {
+            common.push((i, j));
+            i += 1;
+            j += 1;
+        } else if lengths[(i + 1) * width + j] >= lengths[i * width + j + 1] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    common
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn apply(old: &str, edits: &[TextEdit]) -> String {
+        let mut doc = RopeDoc::new(0, old);
+        for edit in edits.iter().rev() {
+            let start = doc.to_offset(edit.range.start).unwrap();
+            let end = doc.to_offset(edit.range.end).unwrap();
+            doc.edit(xi_rope::Interval::new(start, end), &edit.new_text);
+        }
+        doc.rope().to_string()
+    }
+
+    #[test]
+    fn test_diff() {
+        let old = "module M {\n    fun f() {  }\n    fun g() {}\n}\n";
+        let new = "module M {\n    fun f() {}\n\n    fun g() {}\n}\n";
+        let edits = diff(old, new);
+        assert_eq!(apply(old, &edits), new);
+        assert_eq!(
+            edits,
+            vec![TextEdit::new(
+                lsp_types::Range::new(Position::new(1, 13), Position::new(1, 16)),
+                "}\n".to_string()
+            )]
+        );
+
+        assert!(diff(old, old).is_empty());
+        for (old, new) in vec![
+            ("", "module M {}\n"),
+            ("module M {}\n", ""),
+            ("a\nb\nc\nd", "a\nc\nx\nd\ne"),
+            ("é\nb\n", "ü\nb\n"),
+        ] {
+            assert_eq!(apply(old, &diff(old, new)), new);
+        }
+    }
+
+    #[test]
+    fn test_minimize() {
+        let old = "script {\n    use 0x1::B;\n    use 0x1::A;\n    fun main() {}\n}\n";
+        let doc = RopeDoc::new(0, old);
+        let edit = TextEdit::new(
+            lsp_types::Range::new(Position::new(1, 4), Position::new(2, 15)),
+            "use 0x1::A;\n    use 0x1::B;".to_string(),
+        );
+        let edits = minimize(&doc, vec![edit]);
+        // from the differing module names.
+        assert_eq!(
+            edits,
+            vec![TextEdit::new(
+                lsp_types::Range::new(Position::new(1, 13), Position::new(2, 14)),
+                "A;\n    use 0x1::B".to_string()
+            )]
+        );
+        assert_eq!(
+            apply(old, &edits),
+            "script {\n    use 0x1::A;\n    use 0x1::B;\n    fun main() {}\n}\n"
+        );
+    }
+}
@@ -0,0 +1,271 @@
+use crate::{dialect::Dialect, move_document::MoveDocument, tree_sitter_move::kinds};
+use anyhow::{bail, Result};
+use move_lang::{parser::syntax, shared::Address, strip_comments_and_verify};
+use serde_json::json;
+use std::collections::{BTreeMap, HashMap};
+use tower_lsp::lsp_types::{
+    CodeAction, CodeActionKind, Command, Diagnostic, DiagnosticSeverity, NumberOrString, Range, Url,
+};
+use tree_sitter::Node;
+
+pub(crate) const SENDER_PLACEHOLDER: &str = "{{sender}}";
+/// Code of the errors on modules which need a sender address, when none is configured.
+pub const MISSING_SENDER: &str = "missing-sender";
+/// Client command asking for a sender address and writing it to the workspace settings.
+pub const SET_SENDER_COMMAND: &str = "move.setSenderAddress";
+
+/// The source of `doc` as the compiler checks it:
+/// modules outside of address blocks are placed in an address block of `sender`,
+/// `{{sender}}` placeholders and address literals are replaced by full length addresses.
+pub fn expand_addresses(doc: &MoveDocument, sender: Option<Address>) -> Result<String> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => bail!("fail to parse the document"),
+    };
+    let source = doc.to_string();
+    let sender = sender.map(full_address);
+
+    // (start, end, replacement), none of them overlaps.
+    let mut edits: Vec<(usize, usize, String)> = vec![];
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    for node in root.named_children(&mut cursor) {
+        if node.kind() == kinds::MODULE_DEFINITION {
+            let sender = match sender.as_ref() {
+                Some(s) => s,
+                None => bail!(
+                    "module `{}` is outside of address blocks, but no sender address is configured",
+                    node.child_by_field_name("name")
+                        .and_then(|n| source.get(n.byte_range()))
+                        .unwrap_or_default()
+                ),
+            };
+            edits.push((
+                node.start_byte(),
+                node.start_byte(),
+                format!("address {} {{\n", sender),
+            ));
+            edits.push((node.end_byte(), node.end_byte(), "\n}".to_string()));
+        }
+    }
+    address_literals(&root, &mut |node| {
+        let literal = source.get(node.byte_range()).unwrap_or_default();
+        if let Ok(address) = Address::parse_str(literal) {
+            edits.push((node.start_byte(), node.end_byte(), full_address(address)));
+        }
+    });
+
+    // apply from the end, so that offsets of the remaining edits stay valid.
+    edits.sort_by_key(|(start, end, _)| (*start, *end));
+    let mut expanded = source.clone();
+    for (start, end, text) in edits.into_iter().rev() {
+        expanded.replace_range(start..end, &text);
+    }
+    if let Some(sender) = sender {
+        expanded = expanded.replace(SENDER_PLACEHOLDER, &sender);
+    }
+    Ok(expanded)
+}
+
+/// Parser AST of an expanded source, pretty printed: the definitions the compiler is given
+/// after the preprocessing of `dialect`. `None` if it doesn't parse.
+pub fn expanded_ast(expanded: &str, dialect: &dyn Dialect) -> Option<String> {
+    let source = dialect.preprocess(expanded);
+    let fname = "expanded.move";
+    let (buffer, comment_map, _) = strip_comments_and_verify(fname, &source).ok()?;
+    let (defs, _) = syntax::parse_file_string(fname, &buffer, comment_map).ok()?;
+    Some(format!("{:#?}", defs))
+}
+
+/// Address literals of `doc` which are one of the named `addresses`, with their names.
+/// An address of several names gets all of them, like `Std, Core`.
+pub fn address_names(
+    doc: &MoveDocument,
+    addresses: &BTreeMap<String, String>,
+) -> Vec<(Range, String)> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let mut names: HashMap<Address, Vec<&str>> = HashMap::new();
+    for (name, address) in addresses.iter() {
+        if let Ok(address) = Address::parse_str(address) {
+            names.entry(address).or_default().push(name);
+        }
+    }
+    let mut result = vec![];
+    address_literals(&tree.root_node(), &mut |node| {
+        let named = Address::parse_str(doc.node_text(&node).as_ref())
+            .ok()
+            .and_then(|a| names.get(&a));
+        if let (Some(names), Some(range)) = (named, doc.node_range(&node)) {
+            result.push((range, names.join(", ")));
+        }
+    });
+    result
+}
+
+/// Errors on the names of the modules outside of address blocks,
+/// for a document checked without sender address: the compiler can't place them.
+pub fn missing_sender_diagnostics(doc: &MoveDocument) -> Vec<Diagnostic> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    let modules: Vec<_> = root
+        .named_children(&mut cursor)
+        .filter(|n| n.kind() == kinds::MODULE_DEFINITION)
+        .collect();
+    modules
+        .into_iter()
+        .filter_map(|module| {
+            let name = module.child_by_field_name("name")?;
+            Some(Diagnostic {
+                range: doc.node_range(&name)?,
+                severity: Some(DiagnosticSeverity::Error),
+                code: Some(NumberOrString::String(MISSING_SENDER.to_string())),
+                message: format!(
+                    "module `{}` is outside of address blocks, but no sender address is configured; \
+                     set `sender_address` in the settings, or put the module in an address block",
+                    doc.node_text(&name)
+                ),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+/// Quick fix of missing sender errors: set the sender address of the workspace of `uri`.
+pub fn set_sender_fixes(uri: &Url, diagnostics: &[Diagnostic]) -> Vec<CodeAction> {
+    let code = Some(NumberOrString::String(MISSING_SENDER.to_string()));
+    let fixed: Vec<_> = diagnostics
+        .iter()
+        .filter(|d| d.code == code)
+        .cloned()
+        .collect();
+    if fixed.is_empty() {
+        return vec![];
+    }
+    let title = "Set the sender address".to_string();
+    vec![CodeAction {
+        title: title.clone(),
+        kind: Some(CodeActionKind::QUICKFIX),
+        diagnostics: Some(fixed),
+        command: Some(Command {
+            title,
+            command: SET_SENDER_COMMAND.to_string(),
+            arguments: Some(vec![json!({ "uri": uri })]),
+        }),
+        is_preferred: Some(true),
+        ..Default::default()
+    }]
+}
+
+fn address_literals<'a>(node: &Node<'a>, f: &mut impl FnMut(Node<'a>)) {
+    if node.kind() == kinds::ADDRESS_LITERAL {
+        f(*node);
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        address_literals(&child, f);
+    }
+}
+
+pub(crate) fn full_address(address: Address) -> String {
+    let hex: String = address
+        .to_u8()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect();
+    format!("0x{}", hex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::CoreDialect;
+
+    #[test]
+    fn test_expand_addresses() {
+        let doc = MoveDocument::new(1, "module M {\n    use 0x1::Signer;\n}\n");
+        let sender = Address::parse_str("0x2").ok();
+        let expanded = expand_addresses(&doc, sender).unwrap();
+        let zeros = "0".repeat(31);
+        assert_eq!(
+            expanded,
+            format!(
+                "address 0x{0}2 {{\nmodule M {{\n    use 0x{0}1::Signer;\n}}\n}}\n",
+                zeros
+            )
+        );
+
+        assert!(expand_addresses(&doc, None).is_err());
+
+        let doc = MoveDocument::new(1, "address 0x1 {\nmodule M {}\n}\n");
+        let expanded = expand_addresses(&doc, None).unwrap();
+        assert_eq!(
+            expanded,
+            format!("address 0x{}1 {{\nmodule M {{}}\n}}\n", zeros)
+        );
+    }
+
+    #[test]
+    fn test_expanded_ast() {
+        let doc = MoveDocument::new(1, "module M {\n    fun f() {}\n}\n");
+        let expanded = expand_addresses(&doc, Address::parse_str("0x2").ok()).unwrap();
+        let ast = expanded_ast(&expanded, &CoreDialect).unwrap();
+        assert!(ast.starts_with("[\n    Address("));
+        assert!(ast.contains("\"M\""));
+        assert!(ast.contains("\"f\""));
+
+        assert!(expanded_ast("module {", &CoreDialect).is_none());
+    }
+
+    #[test]
+    fn test_missing_sender() {
+        let doc = MoveDocument::new(
+            1,
+            "module M {}\naddress 0x1 {\nmodule N {}\n}\nscript {\n    fun main() {}\n}\n",
+        );
+        let diagnostics = missing_sender_diagnostics(&doc);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].range.start.line, 0);
+        assert_eq!(diagnostics[0].range.start.character, 7);
+        assert!(diagnostics[0].message.starts_with("module `M`"));
+
+        let uri = Url::parse("file:///M.move").unwrap();
+        let other = Diagnostic {
+            message: "Unused".to_string(),
+            ..Default::default()
+        };
+        assert!(set_sender_fixes(&uri, &[other.clone()]).is_empty());
+        let actions = set_sender_fixes(&uri, &[other, diagnostics[0].clone()]);
+        assert_eq!(actions.len(), 1);
+        assert_eq!(actions[0].diagnostics, Some(diagnostics));
+        let command = actions[0].command.as_ref().unwrap();
+        assert_eq!(command.command, SET_SENDER_COMMAND);
+    }
+
+    #[test]
+    fn test_address_names() {
+        let doc = MoveDocument::new(
+            1,
+            "address 0x1 {\nmodule M {\n    use 0x2::N;\n    use 0x3::O;\n}\n}\n",
+        );
+        let addresses = vec![("Std", "0x1"), ("Core", "0x01"), ("Swap", "0x2")]
+            .into_iter()
+            .map(|(n, a)| (n.to_string(), a.to_string()))
+            .collect();
+        let names: Vec<_> = address_names(&doc, &addresses)
+            .into_iter()
+            .map(|(r, n)| (r.start.line, r.start.character, n))
+            .collect();
+        assert_eq!(
+            names,
+            vec![(0, 8, "Core, Std".to_string()), (2, 8, "Swap".to_string())]
+        );
+    }
+}
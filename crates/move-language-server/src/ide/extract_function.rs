@@ -0,0 +1,234 @@
+use crate::{
+    ide::locals::{ancestor_of_kind, function_locals, node_text, LocalKind},
+    move_document::MoveDocument,
+    tree_sitter_move::kinds,
+};
+use itertools::Itertools;
+use std::collections::HashMap;
+use tower_lsp::lsp_types::{CodeAction, CodeActionKind, Range, TextEdit, Url, WorkspaceEdit};
+use tree_sitter::Node;
+
+const FUNCTION_NAME: &str = "extracted";
+
+/// Move the statements selected by `range` into a new function of the enclosing module,
+/// and call it in their place.
+/// Variables used by the statements become parameters, variables they bind and are used
+/// afterwards are returned. Only offered if the types of all of them are declared,
+/// and the statements neither return nor assign to outer variables.
+pub fn extract_function(doc: &MoveDocument, uri: &Url, range: Range) -> Option<CodeAction> {
+    let start = doc.doc().to_offset(range.start)?;
+    let end = doc.doc().to_offset(range.end)?;
+    if start >= end {
+        return None;
+    }
+    let root = doc.tree()?.root_node();
+    let block = ancestor_of_kind(root.descendant_for_byte_range(start, end)?, kinds::BLOCK)?;
+    let function = ancestor_of_kind(block, kinds::FUNCTION_DEFINITION)?;
+    let items = selected_items(&block, start, end)?;
+    let (first, last) = (items.first()?, items.last()?);
+    let (body_start, body_end) = (first.start_byte(), last.end_byte());
+    if contains_kind(&items, kinds::RETURN_EXPRESSION) {
+        return None;
+    }
+
+    let locals = function_locals(doc, &function);
+    let in_body = |n: &Node| body_start <= n.start_byte() && n.end_byte() <= body_end;
+    let types: HashMap<_, _> = locals
+        .iter()
+        .filter_map(|l| Some((l.name.as_str(), l.ty.as_deref()?)))
+        .collect();
+    let bound: Vec<_> = locals
+        .iter()
+        .filter(|l| l.kind == LocalKind::Let && in_body(&l.node))
+        .map(|l| l.name.as_str())
+        .collect();
+    if locals.iter().any(|l| {
+        l.kind == LocalKind::Assign && in_body(&l.node) && !bound.contains(&l.name.as_str())
+    }) {
+        return None;
+    }
+    let params: Vec<_> = locals
+        .iter()
+        .filter(|l| l.kind == LocalKind::Use && in_body(&l.node))
+        .map(|l| l.name.as_str())
+        .filter(|n| !bound.contains(n))
+        .unique()
+        .collect();
+    let returns: Vec<_> = locals
+        .iter()
+        .filter(|l| l.node.start_byte() >= body_end && l.node.end_byte() <= block.end_byte())
+        .map(|l| l.name.as_str())
+        .filter(|n| bound.contains(n))
+        .unique()
+        .collect();
+    let param_types = params
+        .iter()
+        .map(|p| Some(format!("{}: {}", p, types.get(p)?)))
+        .collect::<Option<Vec<_>>>()?;
+    let return_types = returns
+        .iter()
+        .map(|r| types.get(r).copied())
+        .collect::<Option<Vec<_>>>()?;
+
+    let source = doc.to_string();
+    let name = unique_name(&source);
+    let function_indent = indent_of(&source, function.start_byte());
+    let body_indent = format!("{}    ", function_indent);
+    let body = reindent(
+        &source[line_start(&source, body_start)..body_end],
+        &body_indent,
+    );
+    let (return_type, return_value, call) = match returns.as_slice() {
+        [] => (
+            String::new(),
+            String::new(),
+            format!("{}({});", name, params.join(", ")),
+        ),
+        [r] => (
+            format!(": {}", return_types[0]),
+            format!("\n{}{}", body_indent, r),
+            format!("let {} = {}({});", r, name, params.join(", ")),
+        ),
+        _ => (
+            format!(": ({})", return_types.join(", ")),
+            format!("\n{}({})", body_indent, returns.join(", ")),
+            format!(
+                "let ({}) = {}({});",
+                returns.join(", "),
+                name,
+                params.join(", ")
+            ),
+        ),
+    };
+    let new_function = format!(
+        "\n\n{indent}fun {name}({params}){ret} {{\n{body}{value}\n{indent}}}",
+        indent = function_indent,
+        name = name,
+        params = param_types.join(", "),
+        ret = return_type,
+        body = body,
+        value = return_value,
+    );
+
+    let function_end = doc.doc().to_position(function.end_byte())?;
+    let edits = vec![
+        TextEdit::new(
+            Range::new(
+                doc.doc().to_position(body_start)?,
+                doc.doc().to_position(body_end)?,
+            ),
+            call,
+        ),
+        TextEdit::new(Range::new(function_end, function_end), new_function),
+    ];
+    Some(CodeAction {
+        title: "Extract into function".to_string(),
+        kind: Some(CodeActionKind::REFACTOR_EXTRACT),
+        edit: Some(WorkspaceEdit {
+            changes: Some(vec![(uri.clone(), edits)].into_iter().collect()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Statements of `block` covered by `start..end`, none if one of them is only partly covered.
+fn selected_items<'a>(block: &Node<'a>, start: usize, end: usize) -> Option<Vec<Node<'a>>> {
+    let mut cursor = block.walk();
+    let items: Vec<_> = block
+        .named_children(&mut cursor)
+        .filter(|n| n.kind() == kinds::BLOCK_ITEM)
+        .filter(|n| n.start_byte() < end && start < n.end_byte())
+        .collect();
+    let covered = items
+        .iter()
+        .all(|n| start <= n.start_byte() && n.end_byte() <= end);
+    Some(items).filter(|i| covered && !i.is_empty())
+}
+
+fn contains_kind(nodes: &[Node], kind: &str) -> bool {
+    nodes.iter().any(|n| {
+        if n.kind() == kind {
+            return true;
+        }
+        let mut cursor = n.walk();
+        let children: Vec<_> = n.named_children(&mut cursor).collect();
+        contains_kind(&children, kind)
+    })
+}
+
+fn unique_name(source: &str) -> String {
+    let taken = |name: &str| source.contains(&format!("fun {}(", name));
+    if !taken(FUNCTION_NAME) {
+        return FUNCTION_NAME.to_string();
+    }
+    (1..)
+        .map(|i| format!("{}_{}", FUNCTION_NAME, i))
+        .find(|n| !taken(n))
+        .unwrap()
+}
+
+fn line_start(source: &str, offset: usize) -> usize {
+    source[..offset].rfind('\n').map_or(0, |i| i + 1)
+}
+
+fn indent_of(source: &str, offset: usize) -> String {
+    source[line_start(source, offset)..offset]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect()
+}
+
+/// Replace the common indentation of `lines` with `indent`.
+fn reindent(lines: &str, indent: &str) -> String {
+    let common = lines
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start().len())
+        .min()
+        .unwrap_or(0);
+    lines
+        .lines()
+        .map(|l| {
+            if l.trim().is_empty() {
+                String::new()
+            } else {
+                format!("{}{}", indent, &l[common..])
+            }
+        })
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    #[test]
+    fn test_extract_function() {
+        let source = r"module M {
+    fun f(a: u64): u64 {
+        let b: u64 = a + 1;
+        let c: u64 = b * 2;
+        c
+    }
+}
+";
+        let doc = MoveDocument::new(1, source);
+        let uri = Url::parse("file:///M.move").unwrap();
+        let range = Range::new(Position::new(2, 8), Position::new(3, 27));
+        let action = extract_function(&doc, &uri, range).unwrap();
+        let edits = action.edit.unwrap().changes.unwrap().remove(&uri).unwrap();
+        assert_eq!(edits[0].new_text, "let c = extracted(a);");
+        assert_eq!(
+            edits[1].new_text,
+            "\n\n    fun extracted(a: u64): u64 {\n        let b: u64 = a + 1;\n        let c: u64 = b * 2;\n        c\n    }"
+        );
+
+        // `c` is only partly selected.
+        let range = Range::new(Position::new(2, 8), Position::new(3, 12));
+        assert!(extract_function(&doc, &uri, range).is_none());
+
+        assert_eq!(reindent("    a\n\n      b", "  "), "  a\n\n    b");
+    }
+}
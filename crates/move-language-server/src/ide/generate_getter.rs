@@ -0,0 +1,168 @@
+use crate::{
+    ide::locals::{ancestor_of_kind, node_text},
+    move_document::MoveDocument,
+    tree_sitter_move::kinds,
+};
+use itertools::Itertools;
+use tower_lsp::lsp_types::{CodeAction, CodeActionKind, Range, TextEdit, Url, WorkspaceEdit};
+use tree_sitter::Node;
+
+/// Generate `public fun field(s: &S): &T` accessors for the fields of the struct at `range`,
+/// inserted after the struct.
+/// Fields within `range` are selected, all of them if the range doesn't touch any field.
+/// Fields with a function of the same name already get no accessor.
+pub fn generate_getters(doc: &MoveDocument, uri: &Url, range: Range) -> Option<CodeAction> {
+    let start = doc.doc().to_offset(range.start)?;
+    let end = doc.doc().to_offset(range.end)?;
+    let leaf = doc
+        .tree()?
+        .root_node()
+        .descendant_for_byte_range(start, end)?;
+    let definition = ancestor_of_kind(leaf, kinds::STRUCT_DEFINITION)?;
+    let struct_name = node_text(doc, &definition.child_by_field_name("name")?);
+    let type_params = definition
+        .child_by_field_name("type_parameters")
+        .map(|p| node_text(doc, &p))
+        .unwrap_or_default();
+
+    let source = doc.to_string();
+    let fields = struct_fields(doc, &definition);
+    let touched: Vec<_> = fields
+        .iter()
+        .filter(|(node, _, _)| node.start_byte() <= end && start <= node.end_byte())
+        .collect();
+    let selected: Vec<_> = if touched.is_empty() {
+        fields.iter().collect()
+    } else {
+        touched
+    };
+    let selected: Vec<_> = selected
+        .into_iter()
+        .filter(|(_, name, _)| {
+            !source.contains(&format!("fun {}(", name))
+                && !source.contains(&format!("fun {}<", name))
+        })
+        .collect();
+    if selected.is_empty() {
+        return None;
+    }
+
+    let line_start = source[..definition.start_byte()]
+        .rfind('\n')
+        .map_or(0, |i| i + 1);
+    let indent: String = source[line_start..definition.start_byte()]
+        .chars()
+        .take_while(|c| c.is_whitespace())
+        .collect();
+    let struct_type = format!("{}{}", struct_name, type_arguments(&type_params));
+    let getters: String = selected
+        .iter()
+        .map(|(_, name, ty)| {
+            format!(
+                "\n\n{indent}public fun {name}{params}(s: &{st}): &{ty} {{\n{indent}    &s.{name}\n{indent}}}",
+                indent = indent,
+                name = name,
+                params = type_params,
+                st = struct_type,
+                ty = ty,
+            )
+        })
+        .collect();
+    let struct_end = doc.doc().to_position(definition.end_byte())?;
+    let title = format!(
+        "Generate getters for {}",
+        selected
+            .iter()
+            .map(|(_, name, _)| format!("`{}`", name))
+            .join(", ")
+    );
+    Some(CodeAction {
+        title,
+        kind: Some(CodeActionKind::REFACTOR),
+        edit: Some(WorkspaceEdit {
+            changes: Some(
+                vec![(
+                    uri.clone(),
+                    vec![TextEdit::new(Range::new(struct_end, struct_end), getters)],
+                )]
+                .into_iter()
+                .collect(),
+            ),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Fields of a struct definition: the field node, its name and its type.
+fn struct_fields<'a>(doc: &MoveDocument, definition: &Node<'a>) -> Vec<(Node<'a>, String, String)> {
+    let body = match definition.child_by_field_name("struct_fields") {
+        Some(b) => b,
+        // native structs.
+        None => return vec![],
+    };
+    let mut cursor = body.walk();
+    let fields: Vec<_> = body
+        .named_children(&mut cursor)
+        .filter(|n| n.kind() == kinds::FIELD_ANNOTATION)
+        .filter_map(|n| {
+            let name = node_text(doc, &n.child_by_field_name("field")?);
+            let ty = node_text(doc, &n.child_by_field_name("type")?);
+            Some((n, name, ty))
+        })
+        .collect();
+    fields
+}
+
+/// `<T: copy, U>` => `<T, U>`.
+fn type_arguments(type_params: &str) -> String {
+    let params = type_params
+        .trim()
+        .trim_start_matches('<')
+        .trim_end_matches('>');
+    if params.trim().is_empty() {
+        return String::new();
+    }
+    let names = params
+        .split(',')
+        .map(|p| p.split(':').next().unwrap_or_default().trim())
+        .join(", ");
+    format!("<{}>", names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    const SOURCE: &str = r"module M {
+    struct S<T: copy> { a: u64, b: T }
+}
+";
+
+    fn getters(range: Range) -> Option<String> {
+        let doc = MoveDocument::new(1, SOURCE);
+        let uri = Url::parse("file:///M.move").unwrap();
+        let action = generate_getters(&doc, &uri, range)?;
+        let mut edits = action.edit?.changes?.remove(&uri)?;
+        Some(edits.remove(0).new_text)
+    }
+
+    #[test]
+    fn test_generate_getters() {
+        // on the field `b`.
+        let pos = Position::new(1, 32);
+        assert_eq!(
+            getters(Range::new(pos, pos)).unwrap(),
+            "\n\n    public fun b<T: copy>(s: &S<T>): &T {\n        &s.b\n    }"
+        );
+        // on the struct name, all fields.
+        let pos = Position::new(1, 11);
+        let text = getters(Range::new(pos, pos)).unwrap();
+        assert!(text.contains("public fun a<T: copy>(s: &S<T>): &u64 {"));
+        assert!(text.contains("public fun b<T: copy>"));
+
+        assert_eq!(type_arguments("<T: copy + drop, U>"), "<T, U>");
+        assert_eq!(type_arguments(""), "");
+    }
+}
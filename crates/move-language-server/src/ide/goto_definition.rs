@@ -0,0 +1,207 @@
+use crate::{
+    analysis::{Symbol, SymbolKind},
+    ide::completion::{parse_access, resolve_member},
+    move_document::MoveDocument,
+    salsa::{
+        module_index_query::{ModuleDecl, ModuleKey},
+        FileId,
+    },
+    tree_sitter_move::kinds,
+};
+use move_lang::parser::ast::{Definition, ModuleDefinition};
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::Node;
+
+/// Definition of the module named at `pos`: the file defining it, and the range of its name.
+/// `file_modules` are the modules of `doc`, `project_modules` the modules it can refer to.
+pub fn module_definition(
+    doc: &MoveDocument,
+    pos: Position,
+    file_modules: &[ModuleDecl],
+    project_modules: &[(FileId, ModuleDecl)],
+) -> Option<(FileId, Range)> {
+    let key = module_at(doc, pos, file_modules)?;
+    project_modules
+        .iter()
+        .find(|(_, m)| m.key == key)
+        .map(|(f, m)| (f.clone(), m.name_range))
+}
+
+pub(crate) fn module_at(
+    doc: &MoveDocument,
+    pos: Position,
+    file_modules: &[ModuleDecl],
+) -> Option<ModuleKey> {
+    let node = doc.resolve_to_leaf_node(pos)?;
+    if node.kind() != kinds::MODULE_IDENTIFIER {
+        return None;
+    }
+    let parent = node.parent()?;
+    match parent.kind() {
+        // `use 0x1::M as N;`, both `M` and `N` refer to `0x1::M`.
+        kinds::USE_DECL => {
+            let address = parent.child_by_field_name("address")?;
+            let module = parent.child_by_field_name("module")?;
+            Some(ModuleKey::new(
+                node_text(doc, &address).as_str(),
+                node_text(doc, &module).as_str(),
+            ))
+        }
+        kinds::MODULE_DEFINITION => None,
+        _ => {
+            let name = node_text(doc, &node);
+            // `0x1::M::f`
+            if let Some(address) = node.prev_named_sibling().filter(|s| {
+                parent.kind() == kinds::MODULE_ACCESS && s.kind() == kinds::ADDRESS_LITERAL
+            }) {
+                return Some(ModuleKey::new(
+                    node_text(doc, &address).as_str(),
+                    name.as_str(),
+                ));
+            }
+            // `M::f`
+            used_module(file_modules, pos, name.as_str())
+        }
+    }
+}
+
+/// The function called at `pos`: the module defining it, and its name in that module.
+/// Aliases of used modules and members are resolved.
+pub fn called_function(
+    doc: &MoveDocument,
+    pos: Position,
+    file_modules: &[ModuleDecl],
+) -> Option<(ModuleKey, String)> {
+    let mut node = doc.resolve_to_leaf_node(pos)?;
+    while node.kind() != kinds::MODULE_ACCESS {
+        node = node.parent()?;
+    }
+    if node.parent()?.kind() != kinds::CALL_EXPRESSION {
+        return None;
+    }
+    let access = parse_access(node_text(doc, &node).as_str())?;
+    resolve_member(access, file_modules, pos)
+}
+
+/// Byte range of the definition of function `module::name`, among the `symbols` of a file.
+pub fn function_span(
+    symbols: &[Symbol],
+    module: &str,
+    name: &str,
+) -> Option<std::ops::Range<usize>> {
+    symbols
+        .iter()
+        .find(|s| {
+            s.kind == SymbolKind::Function
+                && s.container.as_deref() == Some(module)
+                && s.name == name
+        })
+        .map(|s| s.definition_span.clone())
+}
+
+pub(crate) fn find_module<'a>(defs: &'a [Definition], name: &str) -> Option<&'a ModuleDefinition> {
+    defs.iter()
+        .flat_map(|d| match d {
+            Definition::Module(m) => vec![m],
+            Definition::Address(_, _, modules) => modules.iter().collect(),
+            Definition::Script(_) => vec![],
+        })
+        .find(|m| m.name.0.value == name)
+}
+
+/// The module referred by `name` at `pos`, brought into scope by a use declaration,
+/// preferably one of the module enclosing `pos`.
+pub(crate) fn used_module(
+    file_modules: &[ModuleDecl],
+    pos: Position,
+    name: &str,
+) -> Option<ModuleKey> {
+    enclosing_module(file_modules, pos)
+        .into_iter()
+        .chain(file_modules.iter())
+        .flat_map(|m| m.uses.iter())
+        .find(|u| u.local_name() == name)
+        .map(|u| u.target.clone())
+}
+
+pub(crate) fn enclosing_module(file_modules: &[ModuleDecl], pos: Position) -> Option<&ModuleDecl> {
+    file_modules.iter().find(|m| contains(&m.range, pos))
+}
+
+fn node_text(doc: &MoveDocument, node: &Node) -> String {
+    doc.node_text(node).into_owned()
+}
+
+fn contains(range: &Range, pos: Position) -> bool {
+    range.start <= pos && pos <= range.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{analysis::file_symbols, salsa::module_index_query::index_modules};
+    use move_lang::{parser::syntax, FileCommentMap};
+    use std::path::PathBuf;
+
+    #[test]
+    fn test_module_definition() {
+        let source = r"address 0x1 {
+module A {
+    use 0x1::B;
+    fun f() { B::g(); 0x1::B::g(); }
+}
+module B {
+    public fun g() {}
+}
+}
+";
+        let modules = index_modules(source, None);
+        let file = PathBuf::from("/test.move");
+        let project: Vec<_> = modules.iter().map(|m| (file.clone(), m.clone())).collect();
+        let doc = MoveDocument::new(1, source);
+
+        let b = (file.clone(), modules[1].name_range);
+        // `B` in the use declaration.
+        let def = module_definition(&doc, Position::new(2, 13), &modules, &project);
+        assert_eq!(def, Some(b.clone()));
+        // `B` in `B::g()`.
+        let def = module_definition(&doc, Position::new(3, 14), &modules, &project);
+        assert_eq!(def, Some(b.clone()));
+        // `B` in `0x1::B::g()`.
+        let def = module_definition(&doc, Position::new(3, 27), &modules, &project);
+        assert_eq!(def, Some(b));
+        // `f` is not a module.
+        let def = module_definition(&doc, Position::new(3, 8), &modules, &project);
+        assert_eq!(def, None);
+    }
+
+    #[test]
+    fn test_called_function() {
+        let source = r"address 0x1 {
+module A {
+    use 0x1::B::{g as h};
+    use 0x1::B as C;
+    fun f() { h(); C::g(); 0x1::B::g(); }
+}
+module B {
+    public fun g() {}
+}
+}
+";
+        let modules = index_modules(source, None);
+        let doc = MoveDocument::new(1, source);
+        let g = Some((ModuleKey::new("0x1", "B"), "g".to_string()));
+        // `h()`, `C::g()` and `0x1::B::g()`.
+        for col in &[14, 22, 35] {
+            assert_eq!(called_function(&doc, Position::new(4, *col), &modules), g);
+        }
+        // the definition of `f`.
+        assert_eq!(called_function(&doc, Position::new(4, 8), &modules), None);
+
+        let (defs, _) = syntax::parse_file_string("test", source, FileCommentMap::new()).unwrap();
+        let symbols = file_symbols(&defs, source);
+        let span = function_span(&symbols, "B", "g").unwrap();
+        assert!(source[span].starts_with("public fun g()"));
+        assert!(function_span(&symbols, "B", "h").is_none());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::{
+    lsp_ext::HighlightToken,
+    move_document::MoveDocument,
+    node_resolver::{query_store, NodeResolver},
+};
+
+/// Highlight tokens of the whole document, for clients without tree-sitter.
+pub fn highlight(doc: &MoveDocument) -> Vec<HighlightToken> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let root = tree.root_node();
+    let captures = match NodeResolver::captures(query_store::HIGHLIGHTS, &root, doc.doc().rope()) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("{}", e);
+            return vec![];
+        }
+    };
+    captures
+        .into_iter()
+        .filter_map(|(kind, node)| {
+            Some(HighlightToken {
+                range: doc.node_range(&node)?,
+                kind,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node_resolver::query_store::QueryStore;
+
+    #[test]
+    fn test_queries_compile() {
+        assert!(QueryStore::global().get(query_store::HIGHLIGHTS).is_some());
+        assert!(QueryStore::global().get(query_store::LOCALS).is_some());
+    }
+
+    #[test]
+    fn test_highlight() {
+        let doc = MoveDocument::new(1, "module M {\n    fun f() {}\n}\n");
+        let tokens: Vec<_> = highlight(&doc)
+            .into_iter()
+            .map(|t| (t.range.start.line, t.range.start.character, t.kind))
+            .collect();
+        assert_eq!(tokens[0], (0, 0, "keyword".to_string()));
+        assert_eq!(tokens[1], (0, 7, "namespace".to_string()));
+        assert!(tokens.contains(&(1, 4, "keyword".to_string())));
+    }
+}
@@ -0,0 +1,440 @@
+use crate::{
+    analysis::definition_signature,
+    dialect::Dialect,
+    ide::{
+        completion::in_abilities,
+        constant_eval,
+        expand::{full_address, SENDER_PLACEHOLDER},
+    },
+    move_document::{rope_text, MoveDocument},
+    tree_sitter_move::kinds,
+};
+use move_ir_types::location::Loc;
+use move_lang::{
+    parser::ast::{
+        Definition, ModuleDefinition, ModuleMember, SpecBlockMember_, SpecBlockTarget_, Type_,
+    },
+    shared::Address,
+};
+use tower_lsp::lsp_types::{Hover, HoverContents, MarkupContent, MarkupKind, Position, Range};
+
+/// Documentation of the built-in type, function or ability at `pos`,
+/// or of a native function added by `dialect`.
+/// Functions are only documented where they are called unqualified, abilities in ability lists,
+/// and no name of a local, field or module member is.
+pub fn builtin_hover(doc: &MoveDocument, pos: Position, dialect: &dyn Dialect) -> Option<Hover> {
+    let context = doc.node_context(pos)?;
+    let leaf = context.leaf;
+    let is_name = [kinds::VARIABLE_IDENTIFIER, kinds::MODULE_IDENTIFIER].contains(&leaf.kind())
+        || leaf
+            .prev_sibling()
+            .map_or(false, |n| ["::", "."].contains(&n.kind()))
+        || leaf.next_sibling().map_or(false, |n| n.kind() == ":");
+    if is_name {
+        return None;
+    }
+    let name = doc.node_text(&leaf);
+    let name = name.as_ref();
+    let (signature, description) = if let Some(builtin) = builtin_type_doc(name) {
+        if leaf.kind() == kinds::FUNCTION_IDENTIFIER {
+            return None;
+        }
+        builtin
+    } else if let Some(ability) = ability_doc(name) {
+        if !in_abilities(doc, context.byte_range.start) {
+            return None;
+        }
+        ability
+    } else {
+        context.nearest(kinds::CALL_EXPRESSION)?;
+        builtin_function_doc(name).or_else(|| dialect.builtin_function(name))?
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value: format!("```move\n{}\n```\n{}", signature, description),
+        }),
+        range: Some(context.range),
+    })
+}
+
+/// The account address the address literal or `{{sender}}` placeholder at `pos` resolves to.
+pub fn address_hover(doc: &MoveDocument, pos: Position, sender: Option<Address>) -> Option<Hover> {
+    let offset = doc.doc().to_offset(pos)?;
+    let (address, range) = match sender_placeholder_at(doc, pos, offset) {
+        Some(range) => (sender, range),
+        None => {
+            let node = doc.node_context(pos)?.nearest(kinds::ADDRESS_LITERAL)?;
+            let literal = doc.node_text(&node);
+            (
+                Some(Address::parse_str(literal.as_ref()).ok()?),
+                doc.node_range(&node)?,
+            )
+        }
+    };
+    let value = match address {
+        Some(address) if Some(address) == sender => {
+            format!(
+                "```move\n{}\n```\nThe sender address.",
+                full_address(address)
+            )
+        }
+        Some(address) => format!("```move\n{}\n```", full_address(address)),
+        None => "No sender address is configured.".to_string(),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(range),
+    })
+}
+
+/// Type and value of the constant named at `pos`, `defs` are the definitions of the document.
+pub fn constant_hover(doc: &MoveDocument, defs: &[Definition], pos: Position) -> Option<Hover> {
+    let context = doc.node_context(pos)?;
+    let name = doc.node_text(&context.leaf);
+    let constants = constant_eval::evaluate_constants(defs, Some(context.byte_range.start));
+    let constant = constants.iter().find(|c| c.name() == name.as_ref())?;
+    let value = match &constant.value {
+        Some(Ok(value)) => format!(
+            "```move\nconst {}: {} = {}\n```",
+            constant.name(),
+            constant.ty,
+            value
+        ),
+        Some(Err(e)) => format!(
+            "```move\nconst {}: {}\n```\nCannot be evaluated: {}.",
+            constant.name(),
+            constant.ty,
+            e.message
+        ),
+        None => format!("```move\nconst {}: {}\n```", constant.name(), constant.ty),
+    };
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(context.range),
+    })
+}
+
+/// The function the spec condition at `pos` is about, with the parameters and results it
+/// refers to. `defs` are the definitions of the document.
+pub fn spec_hover(doc: &MoveDocument, defs: &[Definition], pos: Position) -> Option<Hover> {
+    let offset = doc.doc().to_offset(pos)?;
+    let contains = |loc: &Loc| {
+        let span = loc.span();
+        span.start().to_usize() <= offset && offset < span.end().to_usize()
+    };
+    let text = |loc: &Loc| {
+        let span = loc.span();
+        rope_text(
+            doc.doc().rope(),
+            span.start().to_usize()..span.end().to_usize(),
+        )
+        .into_owned()
+    };
+    let (module, spec, condition) = modules(defs).find_map(|module| {
+        module.members.iter().find_map(|member| match member {
+            ModuleMember::Spec(spec) if contains(&spec.loc) => {
+                let condition = spec.value.members.iter().find(|c| {
+                    contains(&c.loc) && matches!(c.value, SpecBlockMember_::Condition { .. })
+                })?;
+                Some((module, spec, condition))
+            }
+            _ => None,
+        })
+    })?;
+    let name = match &spec.value.target.value {
+        SpecBlockTarget_::Function(name) => &name.0.value,
+        _ => return None,
+    };
+    let function = module.members.iter().find_map(|member| match member {
+        ModuleMember::Function(f) if &f.name.0.value == name => Some(f),
+        _ => None,
+    })?;
+
+    let condition_text = text(&condition.loc);
+    let keyword: String = condition_text
+        .chars()
+        .take_while(|c| c.is_alphanumeric() || *c == '_')
+        .collect();
+    let description = match keyword.as_str() {
+        "requires" => format!("must hold when `{}` is called", name),
+        "ensures" => format!("holds when `{}` returns", name),
+        "aborts_if" => format!("`{}` aborts if it holds", name),
+        "aborts_with" => format!("`{}` aborts with these codes only", name),
+        "succeeds_if" => format!("`{}` doesn't abort if it holds", name),
+        "modifies" => format!("`{}` modifies these resources only", name),
+        "emits" => format!("`{}` emits this event", name),
+        _ => format!("a condition on `{}`", name),
+    };
+    let signature = definition_signature(&text(&function.loc));
+    let mut value = format!(
+        "```move\n{}\n```\n`{}` condition of `{}`: {}.",
+        signature, keyword, name, description
+    );
+
+    let return_type = &function.signature.return_type;
+    let results: Vec<_> = match &return_type.value {
+        Type_::Unit => vec![],
+        Type_::Multiple(types) => types
+            .iter()
+            .enumerate()
+            .map(|(i, ty)| (format!("result_{}", i + 1), text(&ty.loc), "return value"))
+            .collect(),
+        _ => vec![("result".to_string(), text(&return_type.loc), "return value")],
+    };
+    let names: Vec<_> = function
+        .signature
+        .parameters
+        .iter()
+        .map(|(var, ty)| (var.0.value.clone(), text(&ty.loc), "parameter"))
+        .chain(results)
+        .collect();
+    let words: Vec<_> = condition_text[keyword.len()..]
+        .split(|c: char| !c.is_alphanumeric() && c != '_')
+        .collect();
+    let referenced: Vec<_> = names
+        .into_iter()
+        .filter(|(name, _, _)| words.contains(&name.as_str()))
+        .map(|(name, ty, what)| format!("- `{}: {}`, {}", name, ty, what))
+        .collect();
+    if !referenced.is_empty() {
+        value.push_str("\n\nRefers to:\n");
+        value.push_str(&referenced.join("\n"));
+    }
+
+    let span = condition.loc.span();
+    Some(Hover {
+        contents: HoverContents::Markup(MarkupContent {
+            kind: MarkupKind::Markdown,
+            value,
+        }),
+        range: Some(Range::new(
+            doc.doc().to_position(span.start().to_usize())?,
+            doc.doc().to_position(span.end().to_usize())?,
+        )),
+    })
+}
+
+fn modules(defs: &[Definition]) -> impl Iterator<Item = &ModuleDefinition> {
+    defs.iter().flat_map(|def| match def {
+        Definition::Module(m) => vec![m],
+        Definition::Address(_, _, modules) => modules.iter().collect(),
+        Definition::Script(_) => vec![],
+    })
+}
+
+/// Range of the `{{sender}}` placeholder containing `offset`, placeholders don't parse.
+fn sender_placeholder_at(doc: &MoveDocument, pos: Position, offset: usize) -> Option<Range> {
+    let rope = doc.doc().rope();
+    let line_start = doc.doc().to_offset(Position::new(pos.line, 0))?;
+    let line_end = doc
+        .doc()
+        .to_offset(Position::new(pos.line + 1, 0))
+        .unwrap_or_else(|| rope.len());
+    let line = rope.slice_to_cow(line_start..line_end);
+    let (start, end) = line
+        .match_indices(SENDER_PLACEHOLDER)
+        .map(|(i, p)| (line_start + i, line_start + i + p.len()))
+        .find(|(start, end)| *start <= offset && offset < *end)?;
+    Some(Range::new(
+        doc.doc().to_position(start)?,
+        doc.doc().to_position(end)?,
+    ))
+}
+
+/// Signature and doc of a builtin type, ability or function of the core language.
+pub(crate) fn builtin_doc(name: &str) -> Option<(&'static str, &'static str)> {
+    builtin_type_doc(name)
+        .or_else(|| ability_doc(name))
+        .or_else(|| builtin_function_doc(name))
+}
+
+fn builtin_type_doc(name: &str) -> Option<(&'static str, &'static str)> {
+    let doc = match name {
+        "u8" => ("u8", "Unsigned 8-bit integer."),
+        "u64" => ("u64", "Unsigned 64-bit integer."),
+        "u128" => ("u128", "Unsigned 128-bit integer."),
+        "bool" => ("bool", "Boolean, `true` or `false`."),
+        "address" => (
+            "address",
+            "Account address, written as a hex literal like `0x1`.",
+        ),
+        "vector" => (
+            "vector<T>",
+            "Variable length collection of `T`, manipulated with the `Vector` module of stdlib.",
+        ),
+        "signer" => (
+            "signer",
+            "Authority over the resources of an address, only obtainable as a transaction argument.",
+        ),
+        _ => return None,
+    };
+    Some(doc)
+}
+
+fn ability_doc(name: &str) -> Option<(&'static str, &'static str)> {
+    let doc = match name {
+        "copy" => ("copy", "Ability: values of the type can be copied."),
+        "drop" => ("drop", "Ability: values of the type can be dropped."),
+        "store" => (
+            "store",
+            "Ability: values of the type can exist inside a struct in global storage.",
+        ),
+        "key" => (
+            "key",
+            "Ability: the type can be used as a key for global storage operations.",
+        ),
+        _ => return None,
+    };
+    Some(doc)
+}
+
+fn builtin_function_doc(name: &str) -> Option<(&'static str, &'static str)> {
+    let doc = match name {
+        "move_to" => (
+            "move_to<T: key>(account: &signer, value: T)",
+            "Publish `value` under the address of `account`, aborts if a `T` already exists there.",
+        ),
+        "move_from" => (
+            "move_from<T: key>(addr: address): T",
+            "Remove the `T` published under `addr`, aborts if there is none.",
+        ),
+        "borrow_global" => (
+            "borrow_global<T: key>(addr: address): &T",
+            "Immutable reference to the `T` published under `addr`, aborts if there is none.",
+        ),
+        "borrow_global_mut" => (
+            "borrow_global_mut<T: key>(addr: address): &mut T",
+            "Mutable reference to the `T` published under `addr`, aborts if there is none.",
+        ),
+        "exists" => (
+            "exists<T: key>(addr: address): bool",
+            "Whether a `T` is published under `addr`.",
+        ),
+        "freeze" => (
+            "freeze<T>(r: &mut T): &T",
+            "Convert a mutable reference into an immutable one.",
+        ),
+        "assert" => (
+            "assert(cond: bool, code: u64)",
+            "Abort with `code` if `cond` is false.",
+        ),
+        _ => return None,
+    };
+    Some(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dialect::CoreDialect;
+    use move_lang::{parser::syntax, FileCommentMap};
+
+    #[test]
+    fn test_builtin_hover() {
+        let source = "module M {\n    fun f(a: address): bool { exists<u64>(a) }\n}\n";
+        let doc = MoveDocument::new(1, source);
+
+        let hover = builtin_hover(&doc, Position::new(1, 13), &CoreDialect).unwrap();
+        match hover.contents {
+            HoverContents::Markup(m) => assert!(m.value.starts_with("```move\naddress\n```")),
+            _ => panic!("hover should be markdown"),
+        }
+        assert_eq!(hover.range.unwrap().start.character, 13);
+
+        assert!(builtin_hover(&doc, Position::new(1, 30), &CoreDialect).is_some());
+        // user defined function.
+        assert!(builtin_hover(&doc, Position::new(1, 8), &CoreDialect).is_none());
+    }
+
+    #[test]
+    fn test_builtin_hover_names() {
+        let source = "module M {\n    struct S { address: u64 }\n    fun f(s: S): bool { let u8 = 1; copy u8; exists<S>(0x1); M::exists<S>(0x1) }\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let hover =
+            |line, character| builtin_hover(&doc, Position::new(line, character), &CoreDialect);
+
+        // field, and its type.
+        assert!(hover(1, 15).is_none());
+        assert!(hover(1, 24).is_some());
+        // binding, and `copy` out of an ability list.
+        assert!(hover(2, 28).is_none());
+        assert!(hover(2, 36).is_none());
+        // unqualified call, and qualified one.
+        assert!(hover(2, 45).is_some());
+        assert!(hover(2, 64).is_none());
+    }
+
+    #[test]
+    fn test_address_hover() {
+        let source = "script {\n    use 0x1::Signer;\n    fun main() { {{sender}}; }\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let sender = Address::parse_str("0x1").ok();
+        let value = |hover: Option<Hover>| match hover.unwrap().contents {
+            HoverContents::Markup(m) => m.value,
+            _ => panic!("hover should be markdown"),
+        };
+
+        let hover = value(address_hover(&doc, Position::new(1, 9), sender));
+        assert!(hover.contains(&format!("0x{}1", "0".repeat(31))));
+        assert!(hover.ends_with("The sender address."));
+
+        let hover = address_hover(&doc, Position::new(2, 20), sender).unwrap();
+        assert_eq!(hover.range.unwrap().start, Position::new(2, 17));
+        assert!(value(Some(hover)).ends_with("The sender address."));
+        assert_eq!(
+            value(address_hover(&doc, Position::new(2, 20), None)),
+            "No sender address is configured."
+        );
+
+        assert!(address_hover(&doc, Position::new(1, 15), sender).is_none());
+    }
+
+    #[test]
+    fn test_constant_hover() {
+        let source = "module M {\n    const MAX: u64 = 1 << 8;\n    fun f(): u64 { MAX }\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let (defs, _) = syntax::parse_file_string("m.move", source, FileCommentMap::new()).unwrap();
+        let value = |pos| match constant_hover(&doc, &defs, pos).unwrap().contents {
+            HoverContents::Markup(m) => m.value,
+            _ => panic!("hover should be markdown"),
+        };
+        assert_eq!(
+            value(Position::new(1, 11)),
+            "```move\nconst MAX: u64 = 256\n```"
+        );
+        assert_eq!(
+            value(Position::new(2, 20)),
+            "```move\nconst MAX: u64 = 256\n```"
+        );
+        assert!(constant_hover(&doc, &defs, Position::new(2, 8)).is_none());
+    }
+
+    #[test]
+    fn test_spec_hover() {
+        let source = "module M {\n    fun f(a: u64, b: u64): u64 { a + b }\n    spec fun f {\n        ensures result == a + 1;\n        aborts_if false;\n    }\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let (defs, _) = syntax::parse_file_string("m.move", source, FileCommentMap::new()).unwrap();
+        let value = |pos| match spec_hover(&doc, &defs, pos).unwrap().contents {
+            HoverContents::Markup(m) => m.value,
+            _ => panic!("hover should be markdown"),
+        };
+        assert_eq!(
+            value(Position::new(3, 20)),
+            "```move\nfun f(a: u64, b: u64): u64\n```\n`ensures` condition of `f`: holds when `f` returns.\n\n\
+             Refers to:\n- `a: u64`, parameter\n- `result: u64`, return value"
+        );
+        assert_eq!(
+            value(Position::new(4, 10)),
+            "```move\nfun f(a: u64, b: u64): u64\n```\n`aborts_if` condition of `f`: `f` aborts if it holds."
+        );
+        // the function itself.
+        assert!(spec_hover(&doc, &defs, Position::new(1, 8)).is_none());
+    }
+}
@@ -0,0 +1,354 @@
+use crate::{
+    ide::locals::{ancestor_of_kind, contains, function_locals, node_text, Local, LocalKind},
+    move_document::MoveDocument,
+    tree_sitter_move::kinds,
+};
+use tower_lsp::lsp_types::{CodeAction, CodeActionKind, Range, TextEdit, Url, WorkspaceEdit};
+use tree_sitter::Node;
+
+/// Types whose values are copied, not moved, by the uses of a variable.
+const COPYABLE_TYPES: &[&str] = &["u8", "u64", "u128", "bool", "address"];
+/// Operators whose result is a number or a bool, `&` is left out as it's also a borrow.
+const PRIMITIVE_OPERATORS: &[&str] = &[
+    "+", "-", "*", "/", "%", "==", "!=", "<", ">", "<=", ">=", "&&", "||", "!", "<<", ">>", "^",
+    "|",
+];
+
+/// Replace the uses of the variable bound by the `let` statement at `range` with its value,
+/// and remove the statement. Fields packed as `S { x }` become `S { x: value }`.
+/// Only offered when it's safe: the variable is used, and never assigned or borrowed,
+/// the value has no calls which could have side effects,
+/// the variables of the value are not changed before the last use,
+/// and a value used more than once is copied, not moved.
+pub fn inline_variable(doc: &MoveDocument, uri: &Url, range: Range) -> Option<CodeAction> {
+    let leaf = doc.resolve_to_leaf_node(range.start)?;
+    let statement = ancestor_of_kind(leaf, kinds::LET_STATEMENT)?;
+    let function = ancestor_of_kind(statement, kinds::FUNCTION_DEFINITION)?;
+    let item = ancestor_of_kind(statement, kinds::BLOCK_ITEM)?;
+    let value = statement.child_by_field_name("expr")?;
+    let name = node_text(doc, &statement.child_by_field_name("binds")?);
+    if has_call(&value) {
+        return None;
+    }
+
+    let locals = function_locals(doc, &function);
+    // the variable is visible until the end of its block, except after the bindings which
+    // shadow it in their own scope. The value of a shadowing `let` still refers to it.
+    let end = item
+        .parent()
+        .map_or(function.end_byte(), |block| block.end_byte());
+    let shadows: Vec<_> = locals
+        .iter()
+        .filter(|l| l.node.start_byte() >= statement.end_byte() && l.node.end_byte() <= end)
+        .filter(|l| l.name == name && l.kind == LocalKind::Let)
+        .collect();
+    let shadowed = |node: &Node| {
+        shadows.iter().any(|s| {
+            contains(&s.scope, node)
+                && s.statement
+                    .map_or(false, |st| st.end_byte() <= node.start_byte())
+        })
+    };
+    let after: Vec<_> = locals
+        .iter()
+        .filter(|l| l.node.start_byte() >= statement.end_byte() && l.node.end_byte() <= end)
+        .collect();
+    let mut shorthands = vec![];
+    shorthand_fields(doc, function, &name, &mut shorthands);
+    shorthands
+        .retain(|n| n.start_byte() >= statement.end_byte() && n.end_byte() <= end && !shadowed(n));
+    let is_shorthand = |l: &Local| {
+        shorthands
+            .iter()
+            .any(|n| n.byte_range() == l.node.byte_range())
+    };
+    let uses: Vec<_> = after
+        .iter()
+        .filter(|l| l.name == name && l.kind != LocalKind::Let)
+        .filter(|l| !is_shorthand(l) && !shadowed(&l.node))
+        .collect();
+    if uses.is_empty() && shorthands.is_empty() {
+        return None;
+    }
+    if uses.iter().any(|l| l.kind != LocalKind::Use) {
+        return None;
+    }
+    let source = doc.to_string();
+    let borrowed = uses.iter().any(|l| {
+        let before = source[..l.node.start_byte()].trim_end();
+        before.ends_with('&') || before.ends_with("&mut") || before.ends_with("move")
+    });
+    if borrowed {
+        return None;
+    }
+
+    let value_text = node_text(doc, &value);
+    let last_use = uses
+        .iter()
+        .map(|l| l.node.end_byte())
+        .chain(shorthands.iter().map(|n| n.end_byte()))
+        .max()
+        .unwrap_or_else(|| statement.end_byte());
+    let value_vars: Vec<_> = locals
+        .iter()
+        .filter(|l| {
+            value.start_byte() <= l.node.start_byte() && l.node.end_byte() <= value.end_byte()
+        })
+        .map(|l| l.name.as_str())
+        .collect();
+    let changed = after.iter().any(|l| {
+        l.node.start_byte() < last_use
+            && l.kind != LocalKind::Use
+            && value_vars.contains(&l.name.as_str())
+    });
+    if changed || (uses.len() + shorthands.len() > 1 && !is_copyable(doc, &statement, &locals)) {
+        return None;
+    }
+
+    let replacement = if value_text.chars().all(|c| c.is_alphanumeric() || c == '_') {
+        value_text
+    } else {
+        format!("({})", value_text)
+    };
+    let mut edits = vec![TextEdit::new(removal(doc, &source, &item)?, String::new())];
+    for l in uses {
+        edits.push(TextEdit::new(doc.node_range(&l.node)?, replacement.clone()));
+    }
+    for field in shorthands {
+        let text = format!("{}: {}", name, node_text(doc, &value));
+        edits.push(TextEdit::new(doc.node_range(&field)?, text));
+    }
+    Some(CodeAction {
+        title: format!("Inline variable `{}`", name),
+        kind: Some(CodeActionKind::REFACTOR_INLINE),
+        edit: Some(WorkspaceEdit {
+            changes: Some(vec![(uri.clone(), edits)].into_iter().collect()),
+            ..Default::default()
+        }),
+        ..Default::default()
+    })
+}
+
+/// Whether the value bound by `statement` is copied by every use of the variable:
+/// the variable or the value have one of the `COPYABLE_TYPES`, or the value is a literal,
+/// the result of an arithmetic, comparison or logical operator, or a copyable variable.
+fn is_copyable(doc: &MoveDocument, statement: &Node, locals: &[Local]) -> bool {
+    let copyable_type = |ty: &str| COPYABLE_TYPES.contains(&ty);
+    if let Some(ty) = statement.child_by_field_name("type") {
+        return copyable_type(node_text(doc, &ty).as_str());
+    }
+    let value = match statement.child_by_field_name("expr") {
+        Some(v) => v,
+        None => return false,
+    };
+    let text = node_text(doc, &value);
+    let literal = text == "true"
+        || text == "false"
+        || text.starts_with("0x")
+        || text.chars().next().map_or(false, |c| c.is_ascii_digit());
+    let mut cursor = value.walk();
+    let operator = value
+        .children(&mut cursor)
+        .filter(|c| !c.is_named())
+        .any(|c| PRIMITIVE_OPERATORS.contains(&node_text(doc, &c).as_str()));
+    if literal || operator {
+        return true;
+    }
+    // a variable of a declared primitive type.
+    locals
+        .iter()
+        .filter(|l| l.node.end_byte() <= statement.start_byte() && l.name == text)
+        .filter(|l| l.kind == LocalKind::Parameter || l.kind == LocalKind::Let)
+        .last()
+        .and_then(|l| l.ty.as_deref())
+        .map_or(false, copyable_type)
+}
+
+/// Fields of struct packs under `node` written `S { x }` with the variable `name`,
+/// which pack the variable without a separate use of it.
+fn shorthand_fields<'a>(doc: &MoveDocument, node: Node<'a>, name: &str, out: &mut Vec<Node<'a>>) {
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    if node.kind() == kinds::PACK_EXPRESSION {
+        // the first one is the struct name.
+        for field in children.iter().filter(|c| c.is_named()).skip(1) {
+            pack_fields(doc, *field, name, out);
+        }
+    }
+    for child in children {
+        shorthand_fields(doc, child, name, out);
+    }
+}
+
+fn pack_fields<'a>(doc: &MoveDocument, node: Node<'a>, name: &str, out: &mut Vec<Node<'a>>) {
+    if node.named_child_count() == 0 {
+        if node.is_named() && node_text(doc, &node) == name {
+            out.push(node);
+        }
+        return;
+    }
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.children(&mut cursor).collect();
+    // `x: value` fields, whose value is an expression of its own.
+    if children.iter().any(|c| !c.is_named() && c.kind() == ":") {
+        return;
+    }
+    for child in children {
+        pack_fields(doc, child, name, out);
+    }
+}
+
+fn has_call(node: &tree_sitter::Node) -> bool {
+    if node.kind() == kinds::CALL_EXPRESSION {
+        return true;
+    }
+    let mut cursor = node.walk();
+    let children: Vec<_> = node.named_children(&mut cursor).collect();
+    children.iter().any(has_call)
+}
+
+/// Range of `item`, with its whole line if nothing else is on it.
+fn removal(doc: &MoveDocument, source: &str, item: &tree_sitter::Node) -> Option<Range> {
+    let (start, end) = (item.start_byte(), item.end_byte());
+    let line_start = source[..start].rfind('\n').map_or(0, |i| i + 1);
+    let line_end = source[end..]
+        .find('\n')
+        .map_or(source.len(), |i| end + i + 1);
+    let alone =
+        source[line_start..start].trim().is_empty() && source[end..line_end].trim().is_empty();
+    let (start, end) = if alone {
+        (line_start, line_end)
+    } else {
+        (start, end)
+    };
+    Some(Range::new(
+        doc.doc().to_position(start)?,
+        doc.doc().to_position(end)?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    fn inline(source: &str, pos: Position) -> Option<Vec<TextEdit>> {
+        let doc = MoveDocument::new(1, source);
+        let uri = Url::parse("file:///M.move").unwrap();
+        let action = inline_variable(&doc, &uri, Range::new(pos, pos))?;
+        action.edit?.changes?.remove(&uri)
+    }
+
+    #[test]
+    fn test_inline_variable() {
+        let source = r"module M {
+    fun f(a: u64): u64 {
+        let b = a + 1;
+        b * b
+    }
+}
+";
+        let edits = inline(source, Position::new(2, 12)).unwrap();
+        let texts: Vec<_> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(texts, vec!["", "(a + 1)", "(a + 1)"]);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 0), Position::new(3, 0))
+        );
+
+        // `a` changes before `b` is used.
+        let source = r"module M {
+    fun f(a: u64): u64 {
+        let b = a;
+        a = 2;
+        b
+    }
+}
+";
+        assert!(inline(source, Position::new(2, 12)).is_none());
+
+        // the call would run at the use, or not at all.
+        let source = r"module M {
+    fun f(): u64 {
+        let b = g();
+        b
+    }
+    fun g(): u64 { 1 }
+}
+";
+        assert!(inline(source, Position::new(2, 12)).is_none());
+    }
+
+    #[test]
+    fn test_inline_shadowed_variable() {
+        let source = r"module M {
+    fun f(a: u64): u64 {
+        let b = a + 1;
+        { let b = 2; b };
+        b
+    }
+}
+";
+        let edits = inline(source, Position::new(2, 12)).unwrap();
+        assert_eq!(edits.len(), 2);
+        assert_eq!(edits[1].range.start, Position::new(4, 8));
+    }
+
+    #[test]
+    fn test_inline_unused_variable() {
+        let source = r"module M {
+    fun f() {
+        let _x = g();
+        let y = 1;
+    }
+    fun g(): u64 { 1 }
+}
+";
+        assert!(inline(source, Position::new(2, 12)).is_none());
+        assert!(inline(source, Position::new(3, 12)).is_none());
+    }
+
+    #[test]
+    fn test_inline_moved_value() {
+        // `s` would be moved twice.
+        let source = r"module M {
+    struct S { v: u64 }
+    fun f(s: S): (S, S) {
+        let t = s;
+        (t, t)
+    }
+}
+";
+        assert!(inline(source, Position::new(3, 12)).is_none());
+
+        let source = r"module M {
+    fun f(a: u64): (u64, u64) {
+        let b = a;
+        (b, b)
+    }
+}
+";
+        let edits = inline(source, Position::new(2, 12)).unwrap();
+        let texts: Vec<_> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(texts, vec!["", "a", "a"]);
+    }
+
+    #[test]
+    fn test_inline_field_shorthand() {
+        let source = r"module M {
+    struct S { x: u64 }
+    fun f(a: u64): S {
+        let x = a + 1;
+        S { x }
+    }
+}
+";
+        let edits = inline(source, Position::new(3, 12)).unwrap();
+        let texts: Vec<_> = edits.iter().map(|e| e.new_text.as_str()).collect();
+        assert_eq!(texts, vec!["", "x: a + 1"]);
+        assert_eq!(
+            edits[1].range,
+            Range::new(Position::new(4, 12), Position::new(4, 13))
+        );
+    }
+}
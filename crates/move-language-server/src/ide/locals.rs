@@ -0,0 +1,180 @@
+//! Local variables of a function: parameters, let bindings, and their uses.
+
+use crate::{
+    move_document::MoveDocument,
+    node_resolver::{query_store, NodeResolver},
+    tree_sitter_move::kinds,
+};
+use tree_sitter::Node;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum LocalKind {
+    Parameter,
+    /// bound by a `let` statement.
+    Let,
+    /// the left hand side of an assignment.
+    Assign,
+    Use,
+}
+
+#[derive(Clone, Debug)]
+pub struct Local<'a> {
+    pub name: String,
+    pub kind: LocalKind,
+    pub node: Node<'a>,
+    /// the declared type of parameters and single variable lets, like `u64`.
+    pub ty: Option<String>,
+    /// the `let` statement of a binding.
+    pub statement: Option<Node<'a>>,
+    /// the innermost function or block around the occurrence, a binding is only visible in it.
+    pub scope: Node<'a>,
+}
+
+/// Every occurrence of a local variable in `function`, in source order.
+pub fn function_locals<'a>(doc: &MoveDocument, function: &Node<'a>) -> Vec<Local<'a>> {
+    let captures = match NodeResolver::captures(query_store::LOCALS, function, doc.doc().rope()) {
+        Ok(c) => c,
+        Err(e) => {
+            error!("{}", e);
+            return vec![];
+        }
+    };
+    let scopes: Vec<_> = captures
+        .iter()
+        .filter(|(name, _)| name == "local.scope")
+        .map(|(_, node)| *node)
+        .collect();
+    let scope_of = |node: &Node<'a>| {
+        scopes
+            .iter()
+            .filter(|s| contains(s, node))
+            .min_by_key(|s| s.end_byte() - s.start_byte())
+            .copied()
+            .unwrap_or(*function)
+    };
+    let mut locals: Vec<_> = captures
+        .iter()
+        .filter(|(name, _)| name == "local.reference")
+        .map(|(_, node)| classify(doc, *node, scope_of(node)))
+        .collect();
+    locals.sort_by_key(|l| l.node.start_byte());
+    locals
+}
+
+fn classify<'a>(doc: &MoveDocument, node: Node<'a>, scope: Node<'a>) -> Local<'a> {
+    let name = node_text(doc, &node);
+    let mut local = Local {
+        name,
+        kind: LocalKind::Use,
+        node,
+        ty: None,
+        statement: None,
+        scope,
+    };
+    let mut ancestor = node.parent();
+    while let Some(parent) = ancestor {
+        match parent.kind() {
+            kinds::FUNCTION_PARAMETER => {
+                local.kind = LocalKind::Parameter;
+                local.ty = parent
+                    .child_by_field_name("type")
+                    .map(|t| node_text(doc, &t));
+                break;
+            }
+            kinds::LET_STATEMENT => {
+                let binds = parent.child_by_field_name("binds");
+                if binds.map_or(false, |b| contains(&b, &node)) {
+                    local.kind = LocalKind::Let;
+                    local.statement = Some(parent);
+                    // `let (a, b): (u64, u8)` has no type for `a` alone.
+                    if binds.map_or(false, |b| node_text(doc, &b) == local.name) {
+                        local.ty = parent
+                            .child_by_field_name("type")
+                            .map(|t| node_text(doc, &t));
+                    }
+                }
+                break;
+            }
+            kinds::ASSIGN_EXPRESSION => {
+                let lhs = parent.child_by_field_name("lhs");
+                if lhs.map_or(false, |l| node_text(doc, &l) == local.name) {
+                    local.kind = LocalKind::Assign;
+                }
+                break;
+            }
+            kinds::FUNCTION_DEFINITION => break,
+            _ => ancestor = parent.parent(),
+        }
+    }
+    local
+}
+
+pub(crate) fn contains(outer: &Node, inner: &Node) -> bool {
+    outer.start_byte() <= inner.start_byte() && inner.end_byte() <= outer.end_byte()
+}
+
+/// The nearest ancestor of `node` of `kind`, `node` itself included.
+pub(crate) fn ancestor_of_kind<'a>(node: Node<'a>, kind: &str) -> Option<Node<'a>> {
+    let mut node = node;
+    while node.kind() != kind {
+        node = node.parent()?;
+    }
+    Some(node)
+}
+
+pub(crate) fn node_text(doc: &MoveDocument, node: &Node) -> String {
+    doc.node_text(node).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::Position;
+
+    #[test]
+    fn test_function_locals() {
+        let source = r"module M {
+    fun f(a: u64): u64 {
+        let b: u64 = a + 1;
+        let (c, d) = (b, a);
+        c = d;
+        { let e = c; e }
+    }
+}
+";
+        let doc = MoveDocument::new(1, source);
+        let leaf = doc.resolve_to_leaf_node(Position::new(1, 8)).unwrap();
+        let function = ancestor_of_kind(leaf, kinds::FUNCTION_DEFINITION).unwrap();
+        let locals: Vec<_> = function_locals(&doc, &function)
+            .into_iter()
+            .map(|l| (l.name, l.kind, l.ty))
+            .collect();
+        let scopes: Vec<_> = function_locals(&doc, &function)
+            .into_iter()
+            .map(|l| l.scope.start_position().row)
+            .collect();
+        let local =
+            |name: &str, kind, ty: Option<&str>| (name.to_string(), kind, ty.map(String::from));
+        assert_eq!(
+            locals,
+            vec![
+                local("a", LocalKind::Parameter, Some("u64")),
+                local("b", LocalKind::Let, Some("u64")),
+                local("a", LocalKind::Use, None),
+                local("c", LocalKind::Let, None),
+                local("d", LocalKind::Let, None),
+                local("b", LocalKind::Use, None),
+                local("a", LocalKind::Use, None),
+                local("c", LocalKind::Assign, None),
+                local("d", LocalKind::Use, None),
+                local("e", LocalKind::Let, None),
+                local("c", LocalKind::Use, None),
+                local("e", LocalKind::Use, None),
+            ]
+        );
+        // the parameter is scoped to the function, the bindings of the nested block to it.
+        assert_eq!(scopes[0], 1);
+        assert_eq!(scopes[1], 1);
+        assert_eq!(&scopes[10..], &[5, 5, 5]);
+    }
+}
@@ -0,0 +1,21 @@
+//! Editor features computed from open documents.
+
+pub mod acquires;
+pub mod add_ability;
+pub mod code_lens;
+pub mod completion;
+pub mod constant_eval;
+pub mod diff;
+pub mod expand;
+pub mod extract_function;
+pub mod generate_getter;
+pub mod goto_definition;
+pub mod highlight;
+pub mod hover;
+pub mod inline_variable;
+pub mod locals;
+pub mod moniker;
+pub mod on_save;
+pub mod outline;
+pub mod rename;
+pub mod unused_variable;
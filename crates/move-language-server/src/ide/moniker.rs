@@ -0,0 +1,118 @@
+//! Monikers of modules and module members, like `move:0x1::Signer::address_of`,
+//! for indexers to correlate symbols across files and repositories.
+
+use crate::{
+    ide::{
+        completion::{parse_access, resolve_member},
+        goto_definition::enclosing_module,
+        rename::module_name_at,
+    },
+    move_document::MoveDocument,
+    salsa::module_index_query::{ModuleDecl, ModuleKey},
+    tree_sitter_move::kinds,
+};
+use tower_lsp::lsp_types::Position;
+
+/// The symbol named at `pos`: its module, its member name if it's a member,
+/// and whether it's defined in `doc`.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct MonikerSymbol {
+    pub module: ModuleKey,
+    pub member: Option<String>,
+    pub defined_here: bool,
+}
+
+impl MonikerSymbol {
+    /// `<dialect>:<address>::<Module>[::<member>]`.
+    pub fn identifier(&self, dialect: &str) -> String {
+        match &self.member {
+            Some(member) => format!("{}:{}::{}", dialect, self.module, member),
+            None => format!("{}:{}", dialect, self.module),
+        }
+    }
+}
+
+/// The module, or the struct or function named at `pos`.
+/// Module aliases and members brought in by use declarations are resolved.
+pub fn symbol_at(
+    doc: &MoveDocument,
+    pos: Position,
+    file_modules: &[ModuleDecl],
+) -> Option<MonikerSymbol> {
+    let node = doc.resolve_to_leaf_node(pos)?;
+    // locals are not visible outside of their function.
+    if node.kind() == kinds::VARIABLE_IDENTIFIER {
+        return None;
+    }
+    let defined_here = |key: &ModuleKey| file_modules.iter().any(|m| &m.key == key);
+    if node.kind() == kinds::MODULE_IDENTIFIER {
+        let (module, _) = module_name_at(doc, pos, file_modules)?;
+        return Some(MonikerSymbol {
+            defined_here: defined_here(&module),
+            module,
+            member: None,
+        });
+    }
+
+    let parent = node.parent()?;
+    let is_definition_name = (parent.kind() == kinds::FUNCTION_DEFINITION
+        || parent.kind() == kinds::STRUCT_DEFINITION)
+        && parent.child_by_field_name("name").map(|n| n.start_byte()) == Some(node.start_byte());
+    if is_definition_name {
+        return Some(MonikerSymbol {
+            module: enclosing_module(file_modules, pos)?.key.clone(),
+            member: Some(doc.node_text(&node).into_owned()),
+            defined_here: true,
+        });
+    }
+
+    let access = doc.node_context(pos)?.nearest(kinds::MODULE_ACCESS)?;
+    let access = parse_access(doc.node_text(&access).as_ref())?;
+    let (module, member) = resolve_member(access, file_modules, pos)?;
+    Some(MonikerSymbol {
+        defined_here: defined_here(&module),
+        module,
+        member: Some(member),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::module_index_query::index_modules;
+
+    #[test]
+    fn test_symbol_at() {
+        let source = r"address 0x1 {
+module M {
+    use 0x1::Signer as S;
+    struct T {}
+    fun f(s: &signer): address { S::address_of(s) }
+}
+}
+";
+        let modules = index_modules(source, None);
+        let doc = MoveDocument::new(1, source);
+        let identifier = |line, character| {
+            symbol_at(&doc, Position::new(line, character), &modules)
+                .map(|s| (s.identifier("move"), s.defined_here))
+        };
+
+        assert_eq!(identifier(1, 7), Some(("move:0x1::M".to_string(), true)));
+        assert_eq!(
+            identifier(3, 11),
+            Some(("move:0x1::M::T".to_string(), true))
+        );
+        assert_eq!(identifier(4, 8), Some(("move:0x1::M::f".to_string(), true)));
+        assert_eq!(
+            identifier(4, 37),
+            Some(("move:0x1::Signer::address_of".to_string(), false))
+        );
+        assert_eq!(
+            identifier(2, 13),
+            Some(("move:0x1::Signer".to_string(), false))
+        );
+        // a parameter.
+        assert_eq!(identifier(4, 10), None);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::{ide::locals::node_text, move_document::MoveDocument, tree_sitter_move::kinds};
+use tower_lsp::lsp_types::{Position, Range, TextEdit};
+use tree_sitter::Node;
+
+/// Edits removing whitespace at the end of every line.
+pub fn trim_trailing_whitespace(doc: &MoveDocument) -> Vec<TextEdit> {
+    let text = doc.to_string();
+    text.lines()
+        .enumerate()
+        .filter_map(|(line, content)| {
+            let trimmed = content.trim_end();
+            if trimmed.len() == content.len() {
+                return None;
+            }
+            let line = line as u64;
+            let start = trimmed.encode_utf16().count() as u64;
+            let end = content.encode_utf16().count() as u64;
+            Some(TextEdit::new(
+                Range::new(Position::new(line, start), Position::new(line, end)),
+                String::new(),
+            ))
+        })
+        .collect()
+}
+
+/// Edits sorting every run of consecutive `use` declarations, without duplicates.
+/// Documents with syntax errors are left as they are.
+pub fn organize_uses(doc: &MoveDocument) -> Vec<TextEdit> {
+    let root = match doc.tree() {
+        Some(t) if !doc.parse_failed() && !t.root_node().has_error() => t.root_node(),
+        _ => return vec![],
+    };
+    let mut runs = vec![];
+    use_runs(root, &mut runs);
+
+    runs.into_iter()
+        .filter_map(|run| {
+            let (first, last) = (run.first()?, run.last()?);
+            let texts: Vec<_> = run.iter().map(|n| node_text(doc, n)).collect();
+            let mut sorted = texts.clone();
+            sorted.sort();
+            sorted.dedup();
+            if sorted == texts {
+                return None;
+            }
+            // keep the indentation of the first declaration, on lines of their own.
+            let indent = doc.doc().to_position(first.start_byte())?.character as usize;
+            let separator = format!("\n{}", " ".repeat(indent));
+            Some(TextEdit::new(
+                Range::new(
+                    doc.doc().to_position(first.start_byte())?,
+                    doc.doc().to_position(last.end_byte())?,
+                ),
+                sorted.join(&separator),
+            ))
+        })
+        .collect()
+}
+
+/// Runs of `use` declarations which are next to each other, under `node`.
+fn use_runs<'a>(node: Node<'a>, runs: &mut Vec<Vec<Node<'a>>>) {
+    let mut run = vec![];
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        if child.kind() == kinds::USE_DECL {
+            run.push(child);
+            continue;
+        }
+        if !run.is_empty() {
+            runs.push(std::mem::take(&mut run));
+        }
+        use_runs(child, runs);
+    }
+    if !run.is_empty() {
+        runs.push(run);
+    }
+}
+
+/// Whether two ranges share any character.
+pub fn overlaps(a: &Range, b: &Range) -> bool {
+    a.start < b.end && b.start < a.end
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_trim_trailing_whitespace() {
+        let doc = MoveDocument::new(1, "module M {  \n    fun f() {} // ≤ \t\n}\n");
+        let edits = trim_trailing_whitespace(&doc);
+        assert_eq!(edits.len(), 2);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(0, 10), Position::new(0, 12))
+        );
+        assert_eq!(
+            edits[1].range,
+            Range::new(Position::new(1, 19), Position::new(1, 21))
+        );
+        assert!(edits.iter().all(|e| e.new_text.is_empty()));
+    }
+
+    #[test]
+    fn test_organize_uses() {
+        let source = "address 0x1 {\nmodule M {\n    use 0x1::Signer;\n    use 0x1::Event;\n    use 0x1::Signer;\n\n    fun f() {}\n}\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let edits = organize_uses(&doc);
+        assert_eq!(edits.len(), 1);
+        assert_eq!(
+            edits[0].range,
+            Range::new(Position::new(2, 4), Position::new(4, 20))
+        );
+        assert_eq!(edits[0].new_text, "use 0x1::Event;\n    use 0x1::Signer;");
+
+        let sorted = MoveDocument::new(
+            1,
+            "module M {\n    use 0x1::Event;\n    use 0x1::Signer;\n}\n",
+        );
+        assert!(organize_uses(&sorted).is_empty());
+        let broken = MoveDocument::new(
+            1,
+            "module M {\n    use 0x1::B;\n    use 0x1::A;\n    fun f( {}\n}\n",
+        );
+        assert!(organize_uses(&broken).is_empty());
+    }
+}
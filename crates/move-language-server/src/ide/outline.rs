@@ -0,0 +1,319 @@
+//! Outline of a document: its modules and scripts, with the structs and functions they define.
+//!
+//! The outline of a document whose text is in the database comes from its `symbols` query.
+//! Otherwise, like while it doesn't parse, it's walked from the syntax tree.
+//! Outlines are cached by document generation. After edits, only the modules and scripts
+//! on the edited lines are walked again, the others are moved by the lines the edits added
+//! or removed, so that large files stay responsive while they are edited.
+
+use crate::{
+    analysis::{self, Symbol},
+    move_document::MoveDocument,
+    tree_sitter_move::kinds,
+};
+use tower_lsp::lsp_types::{DocumentSymbol, Range, SymbolKind};
+use tree_sitter::{InputEdit, Node};
+
+pub struct Outline {
+    /// generation of the document the outline is up to date with.
+    generation: u64,
+    items: Vec<Item>,
+}
+
+/// The symbol of a module or script, with the bytes and lines it spans.
+#[derive(Clone)]
+struct Item {
+    start_byte: usize,
+    end_byte: usize,
+    start_row: usize,
+    end_row: usize,
+    symbol: DocumentSymbol,
+}
+
+impl Outline {
+    pub fn new(doc: &MoveDocument) -> Self {
+        Self {
+            generation: doc.generation(),
+            items: items(doc, vec![]).0,
+        }
+    }
+
+    /// Bring the outline up to date with `doc`, which it was computed from at an earlier
+    /// generation. Returns the number of modules and scripts walked again.
+    pub fn update(&mut self, doc: &MoveDocument) -> usize {
+        if doc.generation() == self.generation {
+            return 0;
+        }
+        let cached = match doc.edits_since(self.generation) {
+            Some(edits) => edits
+                .iter()
+                .fold(std::mem::take(&mut self.items), |items, edit| {
+                    shift(items, edit)
+                }),
+            None => vec![],
+        };
+        let (items, walked) = items(doc, cached);
+        self.generation = doc.generation();
+        self.items = items;
+        walked
+    }
+
+    pub fn symbols(&self) -> Vec<DocumentSymbol> {
+        self.items.iter().map(|i| i.symbol.clone()).collect()
+    }
+}
+
+/// Items of the top level modules and scripts of `doc`, reusing the `cached` ones
+/// which span the same bytes, with the number of items walked again.
+fn items(doc: &MoveDocument, cached: Vec<Item>) -> (Vec<Item>, usize) {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return (vec![], 0),
+    };
+    let mut nodes = vec![];
+    let root = tree.root_node();
+    let mut cursor = root.walk();
+    for node in root.named_children(&mut cursor) {
+        match node.kind() {
+            kinds::ADDRESS_BLOCK => {
+                let mut cursor = node.walk();
+                nodes.extend(
+                    node.named_children(&mut cursor)
+                        .filter(|n| n.kind() == kinds::MODULE_DEFINITION),
+                );
+            }
+            kinds::MODULE_DEFINITION | kinds::SCRIPT_BLOCK => nodes.push(node),
+            _ => {}
+        }
+    }
+
+    let mut walked = 0;
+    let items = nodes
+        .into_iter()
+        .filter_map(|node| {
+            let reused = cached
+                .iter()
+                .find(|i| i.start_byte == node.start_byte() && i.end_byte == node.end_byte());
+            if let Some(item) = reused {
+                return Some(item.clone());
+            }
+            walked += 1;
+            Some(Item {
+                start_byte: node.start_byte(),
+                end_byte: node.end_byte(),
+                start_row: node.start_position().row,
+                end_row: node.end_position().row,
+                symbol: symbol(doc, &node)?,
+            })
+        })
+        .collect();
+    (items, walked)
+}
+
+/// `items` after `edit`: the ones before it are kept, the ones after it moved,
+/// and the ones on its lines dropped, as their columns may have changed.
+fn shift(items: Vec<Item>, edit: &InputEdit) -> Vec<Item> {
+    let rows = edit.new_end_position.row as i64 - edit.old_end_position.row as i64;
+    let bytes = edit.new_end_byte as i64 - edit.old_end_byte as i64;
+    items
+        .into_iter()
+        .filter_map(|mut item| {
+            if item.end_row < edit.start_position.row {
+                return Some(item);
+            }
+            if item.start_row <= edit.old_end_position.row {
+                return None;
+            }
+            item.start_byte = (item.start_byte as i64 + bytes) as usize;
+            item.end_byte = (item.end_byte as i64 + bytes) as usize;
+            item.start_row = (item.start_row as i64 + rows) as usize;
+            item.end_row = (item.end_row as i64 + rows) as usize;
+            move_lines(&mut item.symbol, rows);
+            Some(item)
+        })
+        .collect()
+}
+
+fn move_lines(symbol: &mut DocumentSymbol, rows: i64) {
+    for range in [&mut symbol.range, &mut symbol.selection_range].iter_mut() {
+        range.start.line = (range.start.line as i64 + rows) as u64;
+        range.end.line = (range.end.line as i64 + rows) as u64;
+    }
+    for child in symbol.children.iter_mut().flatten() {
+        move_lines(child, rows);
+    }
+}
+
+/// A module with its structs and functions, or a script named after its function.
+fn symbol(doc: &MoveDocument, node: &Node) -> Option<DocumentSymbol> {
+    let mut cursor = node.walk();
+    let members: Vec<_> = node.named_children(&mut cursor).collect();
+    if node.kind() == kinds::SCRIPT_BLOCK {
+        let function = members
+            .iter()
+            .find(|m| m.kind() == kinds::FUNCTION_DEFINITION);
+        let name = function.and_then(|f| f.child_by_field_name("name"));
+        return Some(document_symbol(
+            name.map_or_else(|| "script".to_string(), |n| doc.node_text(&n).into_owned()),
+            SymbolKind::Function,
+            doc.node_range(node)?,
+            doc.node_range(name.as_ref().unwrap_or(node))?,
+            None,
+        ));
+    }
+
+    let name = node.child_by_field_name("name")?;
+    let children = members
+        .iter()
+        .filter_map(|member| {
+            let kind = match member.kind() {
+                kinds::STRUCT_DEFINITION => SymbolKind::Struct,
+                kinds::FUNCTION_DEFINITION => SymbolKind::Function,
+                _ => return None,
+            };
+            let name = member.child_by_field_name("name")?;
+            Some(document_symbol(
+                doc.node_text(&name).into_owned(),
+                kind,
+                doc.node_range(member)?,
+                doc.node_range(&name)?,
+                None,
+            ))
+        })
+        .collect();
+    Some(document_symbol(
+        doc.node_text(&name).into_owned(),
+        SymbolKind::Module,
+        doc.node_range(node)?,
+        doc.node_range(&name)?,
+        Some(children),
+    ))
+}
+
+/// Outline of `doc` from the `symbols` of its text.
+pub fn symbols_outline(doc: &MoveDocument, symbols: &[Symbol]) -> Option<Vec<DocumentSymbol>> {
+    let range = |span: &std::ops::Range<usize>| {
+        Some(Range::new(
+            doc.doc().to_position(span.start)?,
+            doc.doc().to_position(span.end)?,
+        ))
+    };
+    let mut outline: Vec<DocumentSymbol> = vec![];
+    for s in symbols {
+        let (kind, children) = match s.kind {
+            analysis::SymbolKind::Module => (SymbolKind::Module, Some(vec![])),
+            analysis::SymbolKind::Script => (SymbolKind::Function, None),
+            analysis::SymbolKind::Struct => (SymbolKind::Struct, None),
+            analysis::SymbolKind::Function => (SymbolKind::Function, None),
+            analysis::SymbolKind::Constant => continue,
+        };
+        let symbol = document_symbol(
+            s.name.clone(),
+            kind,
+            range(&s.definition_span)?,
+            range(&s.span)?,
+            children,
+        );
+        match s.container {
+            // members follow the module defining them.
+            Some(_) => outline.last_mut()?.children.as_mut()?.push(symbol),
+            None => outline.push(symbol),
+        }
+    }
+    Some(outline)
+}
+
+fn document_symbol(
+    name: String,
+    kind: SymbolKind,
+    range: Range,
+    selection_range: Range,
+    children: Option<Vec<DocumentSymbol>>,
+) -> DocumentSymbol {
+    DocumentSymbol {
+        name,
+        detail: None,
+        kind,
+        deprecated: None,
+        range,
+        selection_range,
+        children,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::file_symbols;
+    use move_lang::{parser::syntax, FileCommentMap};
+    use tower_lsp::lsp_types::Position;
+
+    const SOURCE: &str = r"address 0x1 {
+module A {
+    struct T {}
+    fun f() {}
+}
+module B {
+    fun g() {}
+}
+}
+";
+
+    fn names(symbols: &[DocumentSymbol]) -> Vec<String> {
+        symbols
+            .iter()
+            .flat_map(|s| {
+                Some(s.name.clone())
+                    .into_iter()
+                    .chain(names(s.children.as_deref().unwrap_or_default()))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_outline() {
+        let mut doc = MoveDocument::new(1, SOURCE);
+        let mut outline = Outline::new(&doc);
+        assert_eq!(names(&outline.symbols()), vec!["A", "T", "f", "B", "g"]);
+        assert_eq!(outline.update(&doc), 0);
+
+        // only `B` is on the edited line.
+        let g = Range::new(Position::new(6, 8), Position::new(6, 9));
+        doc.edit(g, "h").unwrap();
+        assert_eq!(outline.update(&doc), 1);
+        assert_eq!(names(&outline.symbols()), vec!["A", "T", "f", "B", "h"]);
+
+        // lines added above move every module.
+        let start = Range::new(Position::new(0, 0), Position::new(0, 0));
+        doc.edit(start, "\n\n").unwrap();
+        assert_eq!(outline.update(&doc), 0);
+        assert_eq!(outline.symbols(), Outline::new(&doc).symbols());
+        assert_eq!(
+            outline.symbols()[1].selection_range.start,
+            Position::new(7, 7)
+        );
+
+        doc.reset_with(2, "script {\n    fun main() {}\n}\n");
+        assert_eq!(outline.update(&doc), 1);
+        assert_eq!(names(&outline.symbols()), vec!["main"]);
+    }
+
+    #[test]
+    fn test_symbols_outline() {
+        let doc = MoveDocument::new(1, SOURCE);
+        let (defs, _) = syntax::parse_file_string("test", SOURCE, FileCommentMap::new()).unwrap();
+        let symbols = symbols_outline(&doc, &file_symbols(&defs, SOURCE)).unwrap();
+        assert_eq!(names(&symbols), vec!["A", "T", "f", "B", "g"]);
+        let selections = |symbols: &[DocumentSymbol]| -> Vec<_> {
+            symbols
+                .iter()
+                .flat_map(|s| s.children.iter().flatten().chain(Some(s)))
+                .map(|s| s.selection_range)
+                .collect()
+        };
+        assert_eq!(
+            selections(&symbols),
+            selections(&Outline::new(&doc).symbols())
+        );
+    }
+}
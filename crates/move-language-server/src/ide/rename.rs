@@ -0,0 +1,302 @@
+use crate::{
+    ide::{
+        completion::ABILITIES,
+        goto_definition::module_at,
+        hover::builtin_doc,
+        locals::{contains, function_locals, Local, LocalKind},
+    },
+    move_document::MoveDocument,
+    salsa::module_index_query::{ModuleDecl, ModuleKey},
+    tree_sitter_move::kinds,
+};
+use tower_lsp::lsp_types::{Position, Range};
+use tree_sitter::Node;
+
+/// Reserved words of the language, which can't name anything.
+pub const KEYWORDS: &[&str] = &[
+    "abort", "acquires", "address", "as", "break", "const", "continue", "copy", "copyable", "else",
+    "false", "friend", "fun", "has", "if", "let", "loop", "module", "move", "mut", "native",
+    "public", "resource", "return", "script", "spec", "struct", "true", "use", "while",
+];
+
+/// The module named at `pos`, and the range of the name.
+/// Aliases of modules are not renamed, so they don't count as names of the module.
+pub fn module_name_at(
+    doc: &MoveDocument,
+    pos: Position,
+    file_modules: &[ModuleDecl],
+) -> Option<(ModuleKey, Range)> {
+    let node = doc.resolve_to_leaf_node(pos)?;
+    if node.kind() != kinds::MODULE_IDENTIFIER {
+        return None;
+    }
+    let range = doc.node_range(&node)?;
+    let key = match node.parent()?.kind() {
+        kinds::MODULE_DEFINITION => file_modules
+            .iter()
+            .find(|m| m.name_range == range)?
+            .key
+            .clone(),
+        _ => module_at(doc, pos, file_modules)?,
+    };
+    let name = doc.node_text(&node);
+    if name != key.name.as_str() {
+        return None;
+    }
+    Some((key, range))
+}
+
+/// Ranges of the names of module `key` in `doc`:
+/// its definition, the use declarations and accesses of its members.
+pub fn module_references(
+    doc: &MoveDocument,
+    file_modules: &[ModuleDecl],
+    key: &ModuleKey,
+) -> Vec<Range> {
+    let mut ranges = vec![];
+    if let Some(tree) = doc.tree() {
+        module_identifiers(&tree.root_node(), &mut |node| {
+            let pos = doc.node_range(&node).map(|r| r.start);
+            if let Some((k, range)) = pos.and_then(|p| module_name_at(doc, p, file_modules)) {
+                if &k == key {
+                    ranges.push(range);
+                }
+            }
+        });
+    }
+    ranges
+}
+
+/// The local variable at `pos`, and the ranges of its binding and of all references to it,
+/// up to where it's shadowed or its block ends.
+pub fn local_references(doc: &MoveDocument, pos: Position) -> Option<(String, Vec<Range>)> {
+    let offset = doc.doc().to_offset(pos)?;
+    let function = doc.node_context(pos)?.nearest(kinds::FUNCTION_DEFINITION)?;
+    let locals = function_locals(doc, &function);
+    let at = locals
+        .iter()
+        .position(|l| l.node.start_byte() <= offset && offset <= l.node.end_byte())?;
+    let target = &locals[at];
+    let is_binding = |l: &Local| l.kind == LocalKind::Let || l.kind == LocalKind::Parameter;
+    let bindings: Vec<_> = locals
+        .iter()
+        .filter(|l| l.name == target.name && is_binding(l))
+        .collect();
+    // the innermost binding visible at `l`, the last one in source order of those whose scope
+    // contains it. `let a = a + 1;` binds `a` only after the statement.
+    let binding_of = |l: &Local| {
+        bindings.iter().rev().find(|b| {
+            let scope_start = match b.statement {
+                Some(s) if b.kind == LocalKind::Let => s.end_byte(),
+                _ => b.node.start_byte(),
+            };
+            scope_start <= l.node.start_byte() && contains(&b.scope, &l.node)
+        })
+    };
+    let binding = if is_binding(target) {
+        target
+    } else {
+        *binding_of(target)?
+    };
+
+    let mut ranges = vec![doc.node_range(&binding.node)?];
+    for l in locals
+        .iter()
+        .filter(|l| l.name == target.name && !is_binding(l))
+    {
+        if binding_of(l).map_or(false, |b| b.node.start_byte() == binding.node.start_byte()) {
+            ranges.push(doc.node_range(&l.node)?);
+        }
+    }
+    Some((target.name.clone(), ranges))
+}
+
+/// Ranges of the module alias at `pos`, from its `use ... as Alias` declaration
+/// to every access through it, in the enclosing module or script.
+/// `None` if there is no module alias at `pos`.
+pub fn alias_ranges(doc: &MoveDocument, pos: Position) -> Option<Vec<Range>> {
+    let node = doc.resolve_to_leaf_node(pos)?;
+    if node.kind() != kinds::MODULE_IDENTIFIER {
+        return None;
+    }
+    let name = doc.node_text(&node).into_owned();
+    let scope = doc
+        .node_context(pos)?
+        .nearest_of(&[kinds::MODULE_DEFINITION, kinds::SCRIPT_BLOCK])?;
+    let mut declared = false;
+    let mut ranges = vec![];
+    module_identifiers(&scope, &mut |n| {
+        let parent = match n.parent() {
+            Some(p) if doc.node_text(&n) == name.as_str() => p,
+            _ => return,
+        };
+        let is_alias = parent.kind() == kinds::USE_DECL
+            && parent.child_by_field_name("as").map(|a| a.start_byte()) == Some(n.start_byte());
+        // `0x1::M::f` names the module itself.
+        let is_access = parent.kind() == kinds::MODULE_ACCESS
+            && n.prev_named_sibling()
+                .map_or(true, |s| s.kind() != kinds::ADDRESS_LITERAL);
+        declared |= is_alias;
+        if is_alias || is_access {
+            ranges.extend(doc.node_range(&n));
+        }
+    });
+    let at_pos = ranges.iter().any(|r| r.start <= pos && pos <= r.end);
+    Some(ranges).filter(|_| declared && at_pos)
+}
+
+/// Whether `name` can name a module or a variable.
+pub fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            chars.all(|c| c.is_ascii_alphanumeric() || c == '_') && !KEYWORDS.contains(&name)
+        }
+        _ => false,
+    }
+}
+
+/// Whether the existing name `name` can be renamed: builtin types and functions can't.
+/// Abilities only have a meaning after `has`, so variables may be named after them.
+/// Builtins of dialects are checked by the caller.
+pub fn is_renameable(name: &str) -> bool {
+    let ability = ABILITIES.iter().any(|(a, _)| *a == name);
+    is_identifier(name) && (ability || builtin_doc(name).is_none())
+}
+
+fn module_identifiers<'a>(node: &Node<'a>, f: &mut impl FnMut(Node<'a>)) {
+    if node.kind() == kinds::MODULE_IDENTIFIER {
+        f(*node);
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        module_identifiers(&child, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::module_index_query::index_modules;
+
+    #[test]
+    fn test_module_references() {
+        let source = r"address 0x1 {
+module A {
+    use 0x1::B;
+    use 0x1::B as C;
+    fun f() { B::g(); C::g(); 0x1::B::g(); }
+}
+module B {
+    public fun g() {}
+}
+}
+";
+        let modules = index_modules(source, None);
+        let doc = MoveDocument::new(1, source);
+        let b = ModuleKey::new("0x1", "B");
+
+        let (key, range) = module_name_at(&doc, Position::new(6, 7), &modules).unwrap();
+        assert_eq!(key, b);
+        assert_eq!(range, modules[1].name_range);
+        // the alias `C`.
+        assert!(module_name_at(&doc, Position::new(4, 22), &modules).is_none());
+
+        let starts: Vec<_> = module_references(&doc, &modules, &b)
+            .into_iter()
+            .map(|r| (r.start.line, r.start.character))
+            .collect();
+        assert_eq!(starts, vec![(2, 13), (3, 13), (4, 14), (4, 35), (6, 7)]);
+
+        assert!(is_identifier("Token_2"));
+        assert!(!is_identifier("2Token"));
+        assert!(!is_identifier("To ken"));
+        assert!(!is_identifier("struct"));
+
+        assert!(is_renameable("coin"));
+        assert!(is_renameable("key"));
+        assert!(!is_renameable("borrow_global"));
+        assert!(!is_renameable("u64"));
+    }
+
+    #[test]
+    fn test_alias_ranges() {
+        let source = r"module M {
+    use 0x1::Vector as V;
+    use 0x1::Signer;
+    fun f(s: &signer) { V::empty<u64>(); 0x1::V::g(); Signer::address_of(s); V::h(); }
+}
+";
+        let doc = MoveDocument::new(1, source);
+        let starts = |ranges: Vec<Range>| -> Vec<_> {
+            ranges
+                .into_iter()
+                .map(|r| (r.start.line, r.start.character))
+                .collect()
+        };
+        let expected = vec![(1, 23), (3, 24), (3, 77)];
+        assert_eq!(
+            starts(alias_ranges(&doc, Position::new(1, 23)).unwrap()),
+            expected
+        );
+        assert_eq!(
+            starts(alias_ranges(&doc, Position::new(3, 77)).unwrap()),
+            expected
+        );
+        // not aliased.
+        assert!(alias_ranges(&doc, Position::new(3, 54)).is_none());
+        assert!(alias_ranges(&doc, Position::new(1, 13)).is_none());
+    }
+
+    #[test]
+    fn test_local_references() {
+        let source = r"module M {
+    fun f(a: u64): u64 {
+        let b = a;
+        let a = b + a;
+        a
+    }
+}
+";
+        let doc = MoveDocument::new(1, source);
+        let lines = |ranges: Vec<Range>| -> Vec<_> {
+            ranges
+                .into_iter()
+                .map(|r| (r.start.line, r.start.character))
+                .collect()
+        };
+        // the parameter `a`, from its use in `let b = a`.
+        let (name, ranges) = local_references(&doc, Position::new(2, 16)).unwrap();
+        assert_eq!(name, "a");
+        assert_eq!(lines(ranges), vec![(1, 10), (2, 16), (3, 20)]);
+        // the shadowing `a`.
+        let (_, ranges) = local_references(&doc, Position::new(4, 8)).unwrap();
+        assert_eq!(lines(ranges), vec![(3, 12), (4, 8)]);
+    }
+
+    #[test]
+    fn test_block_shadowing() {
+        let source = r"module M {
+    fun f(): u64 {
+        let x = 1;
+        { let x = 2; x };
+        x
+    }
+}
+";
+        let doc = MoveDocument::new(1, source);
+        let starts = |pos| -> Vec<_> {
+            local_references(&doc, pos)
+                .unwrap()
+                .1
+                .into_iter()
+                .map(|r| (r.start.line, r.start.character))
+                .collect()
+        };
+        // the outer `x` isn't visible in the block once it's shadowed, and is again after it.
+        assert_eq!(starts(Position::new(4, 8)), vec![(2, 12), (4, 8)]);
+        assert_eq!(starts(Position::new(2, 12)), vec![(2, 12), (4, 8)]);
+        assert_eq!(starts(Position::new(3, 21)), vec![(3, 14), (3, 21)]);
+    }
+}
@@ -0,0 +1,71 @@
+use crate::{ide::rename::local_references, move_document::MoveDocument};
+use tower_lsp::lsp_types::{CodeAction, CodeActionKind, Diagnostic, TextEdit, Url, WorkspaceEdit};
+
+/// Quick fixes of unused variable errors: prefix the variable and its references with `_`.
+pub fn prefix_unused_fixes(
+    doc: &MoveDocument,
+    uri: &Url,
+    diagnostics: &[Diagnostic],
+) -> Vec<CodeAction> {
+    diagnostics
+        .iter()
+        .filter(|d| d.message.contains("Unused"))
+        .filter_map(|d| {
+            let (name, ranges) = local_references(doc, d.range.start)?;
+            if name.starts_with('_') {
+                return None;
+            }
+            let new_name = format!("_{}", name);
+            let edits = ranges
+                .into_iter()
+                .map(|r| TextEdit::new(r, new_name.clone()))
+                .collect();
+            Some(CodeAction {
+                title: format!("Rename `{}` to `{}`", name, new_name),
+                kind: Some(CodeActionKind::QUICKFIX),
+                diagnostics: Some(vec![d.clone()]),
+                edit: Some(WorkspaceEdit {
+                    changes: Some(vec![(uri.clone(), edits)].into_iter().collect()),
+                    ..Default::default()
+                }),
+                is_preferred: Some(true),
+                ..Default::default()
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    #[test]
+    fn test_prefix_unused_fixes() {
+        let source = "module M {\n    fun f(a: u64) {\n        let b = a;\n    }\n}\n";
+        let doc = MoveDocument::new(1, source);
+        let uri = Url::parse("file:///M.move").unwrap();
+        let diagnostic = |line, character| Diagnostic {
+            range: Range::new(
+                Position::new(line, character),
+                Position::new(line, character + 1),
+            ),
+            message: "Unused assignment or binding for local 'b'.".to_string(),
+            ..Default::default()
+        };
+
+        let actions = prefix_unused_fixes(&doc, &uri, &[diagnostic(2, 12)]);
+        assert_eq!(actions[0].title, "Rename `b` to `_b`");
+        let edits = actions[0]
+            .edit
+            .clone()
+            .unwrap()
+            .changes
+            .unwrap()
+            .remove(&uri)
+            .unwrap();
+        assert_eq!(edits.len(), 1);
+        assert_eq!(edits[0].range.start, Position::new(2, 12));
+        assert_eq!(edits[0].new_text, "_b");
+    }
+}
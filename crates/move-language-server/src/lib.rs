@@ -1,13 +1,49 @@
 #[macro_use]
-extern crate log;
+extern crate tracing;
 
+pub mod analysis;
+pub mod artifacts;
+#[cfg(feature = "lsp")]
+pub mod dependency_graph;
+#[cfg(feature = "disassemble")]
+pub mod disassemble;
+#[cfg(feature = "lsp")]
+pub mod docgen;
 pub mod error_diagnostic;
+#[cfg(feature = "lsp")]
+pub mod file_watcher;
+pub mod fingerprints;
+#[cfg(feature = "lsp")]
+pub mod host;
+#[cfg(feature = "lsp")]
+mod ide;
+#[cfg(feature = "lsp")]
+pub mod logging;
+#[cfg(feature = "lsp")]
+pub mod lsif;
+pub mod lsp_ext;
+#[cfg(feature = "lsp")]
 pub mod lsp_server;
-mod move_document;
+pub mod move_document;
+#[cfg(feature = "lsp")]
+pub mod published_diagnostics;
+#[cfg(feature = "lsp")]
 pub mod tree_sitter_move;
 pub mod utils;
+pub mod version;
+pub mod virtual_path;
 
 pub mod config;
+pub mod coverage;
+pub mod dialect;
+#[cfg(feature = "lsp")]
 pub mod node_resolver;
-mod salsa;
+pub mod salsa;
+#[cfg(feature = "lsp")]
+pub mod scaffold;
+#[cfg(feature = "runner")]
+pub mod script_runner;
+#[cfg(all(test, feature = "lsp"))]
+mod test_harness;
+#[cfg(feature = "lsp")]
 mod tests;
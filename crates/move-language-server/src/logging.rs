@@ -0,0 +1,499 @@
+//! Tracing subscriber of the server: events are written to stderr, or a log file,
+//! and events of this crate are forwarded to the client through `window/logMessage`
+//! and `$/logTrace`. Records of crates still using `log` are turned into events.
+//!
+//! What gets written to the log is decided by an `EnvFilter`, from `RUST_LOG` at start,
+//! replaceable at runtime with the `move/setLogFilter` request. Events forwarded to the client
+//! have their own filter: the configured level for `window/logMessage`, and the trace option
+//! for `$/logTrace`, so that tracing is verbose even when the log is quiet.
+//!
+//! The trace option is set by `initialize`, then by `$/setTrace` notifications, which are
+//! taken out of the server input by `handle_set_trace`.
+
+use crate::lsp_ext::{LogTrace, LogTraceParams};
+use anyhow::Result;
+use once_cell::sync::OnceCell;
+use parking_lot::RwLock;
+use serde_json::Value;
+use std::{
+    fmt::{Debug, Write as _},
+    fs::{File, OpenOptions},
+    io::{self, Cursor, Write},
+    path::Path,
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, BufReader},
+    sync::mpsc,
+};
+use tower_lsp::{
+    lsp_types::{MessageType, TraceOption},
+    Client,
+};
+use tracing::{
+    field::{Field, Visit},
+    span::{Attributes, Id, Record},
+    subscriber::Interest,
+    Event, Level, Metadata, Subscriber,
+};
+use tracing_subscriber::{
+    filter::{EnvFilter, LevelFilter},
+    layer::{Context, Layer},
+    prelude::*,
+    reload, Registry,
+};
+
+/// Level used for `window/logMessage` when not configured.
+pub const DEFAULT_CLIENT_LOG_LEVEL: LevelFilter = LevelFilter::WARN;
+/// Filter used if `RUST_LOG` is not set.
+pub const DEFAULT_FILTER: &str = "warn,move_language_server=info";
+/// Notification changing the trace option, which tower-lsp drops as an unknown `$/` method.
+pub const SET_TRACE: &str = "$/setTrace";
+
+static CLIENT: OnceCell<ClientSink> = OnceCell::new();
+static FILTER: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
+
+struct ClientSink {
+    client: RwLock<Option<Client>>,
+    level: RwLock<LevelFilter>,
+    trace: RwLock<TraceOption>,
+}
+
+/// Install the subscriber, writing to `log_file` if given, stderr otherwise.
+/// Must be called once, before the server starts.
+pub fn init(log_file: Option<&Path>) -> Result<()> {
+    let writer = match log_file {
+        Some(path) => LogWriter(Some(Arc::new(
+            OpenOptions::new().create(true).append(true).open(path)?,
+        ))),
+        None => LogWriter(None),
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(DEFAULT_FILTER));
+    let (filter, handle) = reload::Layer::new(filter);
+    let _ = FILTER.set(handle);
+    let sink = CLIENT.get_or_init(ClientSink::new);
+
+    let subscriber = Registry::default()
+        .with(LogLayer {
+            filter,
+            log: tracing_subscriber::fmt::layer().with_writer(move || writer.clone()),
+            sink,
+        })
+        .with(ForwardLayer(sink));
+    tracing::subscriber::set_global_default(subscriber)?;
+    tracing_log::LogTracer::init()?;
+    Ok(())
+}
+
+pub fn set_client(client: Client) {
+    if let Some(l) = CLIENT.get() {
+        *l.client.write() = Some(client);
+    }
+}
+
+/// Set the max level of events forwarded to `window/logMessage`, whatever the log filter.
+pub fn set_level(level: Option<&str>) {
+    let level = match level.map(LevelFilter::from_str).transpose() {
+        Ok(l) => l.unwrap_or(DEFAULT_CLIENT_LOG_LEVEL),
+        Err(_) => {
+            warn!("invalid log level {:?}, fallback to default", level);
+            DEFAULT_CLIENT_LOG_LEVEL
+        }
+    };
+    if let Some(l) = CLIENT.get() {
+        *l.level.write() = level;
+    }
+}
+
+/// Set trace option, as the client asked in `initialize` or with `$/setTrace`.
+pub fn set_trace(trace: TraceOption) {
+    if let Some(l) = CLIENT.get() {
+        l.set_trace(trace);
+    }
+}
+
+/// The messages of `input` for the server, less the `$/setTrace` notifications,
+/// which set the trace option.
+pub fn handle_set_trace<R>(input: R) -> impl AsyncRead + Send + Unpin
+where
+    R: AsyncRead + Unpin + Send + 'static,
+{
+    filter_set_trace(input, set_trace)
+}
+
+fn filter_set_trace<R, F>(input: R, mut on_set_trace: F) -> impl AsyncRead + Send + Unpin
+where
+    R: AsyncRead + Unpin + Send + 'static,
+    F: FnMut(TraceOption) + Send + 'static,
+{
+    let (mut sender, receiver) = mpsc::channel(16);
+    tokio::spawn(async move {
+        let mut input = BufReader::new(input);
+        loop {
+            let (frame, body) = match read_frame(&mut input).await {
+                Ok(Some(f)) => f,
+                Ok(None) => break,
+                Err(e) => {
+                    let _ = sender.send(Err(e)).await;
+                    break;
+                }
+            };
+            if let Some(trace) = set_trace_params(&body) {
+                on_set_trace(trace);
+                continue;
+            }
+            if sender.send(Ok(Cursor::new(frame))).await.is_err() {
+                break;
+            }
+        }
+    });
+    tokio::io::stream_reader(receiver)
+}
+
+/// The next message of `input`, headers included, and its body. `None` at the end of input.
+/// Headers without `Content-Length` are returned as is, for the server to report.
+async fn read_frame<R>(input: &mut BufReader<R>) -> io::Result<Option<(Vec<u8>, Vec<u8>)>>
+where
+    R: AsyncRead + Unpin,
+{
+    let mut frame = Vec::new();
+    let mut content_length = None;
+    loop {
+        let mut line = String::new();
+        if input.read_line(&mut line).await? == 0 {
+            return Ok(if frame.is_empty() {
+                None
+            } else {
+                Some((frame, vec![]))
+            });
+        }
+        frame.extend_from_slice(line.as_bytes());
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some(len) = line.strip_prefix("Content-Length:") {
+            content_length = len.trim().parse::<usize>().ok();
+        }
+    }
+    let mut body = vec![0; content_length.unwrap_or_default()];
+    input.read_exact(&mut body).await?;
+    frame.extend_from_slice(&body);
+    Ok(Some((frame, body)))
+}
+
+/// The trace option of a `$/setTrace` notification.
+fn set_trace_params(body: &[u8]) -> Option<TraceOption> {
+    let message: Value = serde_json::from_slice(body).ok()?;
+    if message.get("id").is_some() || message["method"] != SET_TRACE {
+        return None;
+    }
+    serde_json::from_value(message["params"]["value"].clone()).ok()
+}
+
+/// Replace the filter, in the `RUST_LOG` syntax, like `warn,move_language_server::salsa=debug`.
+pub fn set_filter(directives: &str) -> Result<()> {
+    let filter = EnvFilter::try_new(directives)?;
+    match FILTER.get() {
+        Some(handle) => handle.reload(filter)?,
+        None => anyhow::bail!("logging is not initialized"),
+    }
+    Ok(())
+}
+
+/// Writes the events `filter` accepts with `log`, and lets through the events `sink` forwards.
+/// A filter layer would drop events for every layer, the client's included.
+struct LogLayer<L> {
+    filter: reload::Layer<EnvFilter, Registry>,
+    log: L,
+    sink: &'static ClientSink,
+}
+
+impl<L: Layer<Registry>> Layer<Registry> for LogLayer<L> {
+    fn register_callsite(&self, metadata: &'static Metadata<'static>) -> Interest {
+        // the filter can change at runtime, and so can the level and trace option of the sink.
+        self.filter.register_callsite(metadata);
+        self.log.register_callsite(metadata);
+        Interest::sometimes()
+    }
+
+    fn enabled(&self, metadata: &Metadata<'_>, ctx: Context<'_, Registry>) -> bool {
+        self.sink.enabled(metadata) || self.filter.enabled(metadata, ctx)
+    }
+
+    fn new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, Registry>) {
+        self.filter.new_span(attrs, id, ctx.clone());
+        self.log.new_span(attrs, id, ctx);
+    }
+
+    fn on_record(&self, span: &Id, values: &Record<'_>, ctx: Context<'_, Registry>) {
+        self.filter.on_record(span, values, ctx.clone());
+        self.log.on_record(span, values, ctx);
+    }
+
+    fn on_follows_from(&self, span: &Id, follows: &Id, ctx: Context<'_, Registry>) {
+        self.filter.on_follows_from(span, follows, ctx.clone());
+        self.log.on_follows_from(span, follows, ctx);
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, Registry>) {
+        if self.filter.enabled(event.metadata(), ctx.clone()) {
+            self.log.on_event(event, ctx);
+        }
+    }
+
+    fn on_enter(&self, id: &Id, ctx: Context<'_, Registry>) {
+        self.filter.on_enter(id, ctx.clone());
+        self.log.on_enter(id, ctx);
+    }
+
+    fn on_exit(&self, id: &Id, ctx: Context<'_, Registry>) {
+        self.filter.on_exit(id, ctx.clone());
+        self.log.on_exit(id, ctx);
+    }
+
+    fn on_close(&self, id: Id, ctx: Context<'_, Registry>) {
+        self.filter.on_close(id.clone(), ctx.clone());
+        self.log.on_close(id, ctx);
+    }
+}
+
+/// Forwards events to the client sink.
+struct ForwardLayer(&'static ClientSink);
+
+impl<S: Subscriber> Layer<S> for ForwardLayer {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        self.0.forward(event);
+    }
+}
+
+impl ClientSink {
+    fn new() -> Self {
+        ClientSink {
+            client: RwLock::new(None),
+            level: RwLock::new(DEFAULT_CLIENT_LOG_LEVEL),
+            trace: RwLock::new(TraceOption::Off),
+        }
+    }
+
+    fn set_trace(&self, trace: TraceOption) {
+        *self.trace.write() = trace;
+    }
+
+    /// Max level of events forwarded to `$/logTrace`.
+    fn trace_level(&self) -> LevelFilter {
+        match *self.trace.read() {
+            TraceOption::Off => LevelFilter::OFF,
+            TraceOption::Messages => LevelFilter::DEBUG,
+            TraceOption::Verbose => LevelFilter::TRACE,
+        }
+    }
+
+    /// Whether events of `metadata` are forwarded to the client.
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        // events of other crates (tower-lsp included) stay in the log,
+        // so that sending a message never logs recursively into the client.
+        let level = *metadata.level();
+        metadata.is_event()
+            && metadata
+                .target()
+                .starts_with(module_path!().split("::").next().unwrap())
+            && (level <= *self.level.read() || level <= self.trace_level())
+    }
+
+    fn forward(&self, event: &Event) {
+        let metadata = event.metadata();
+        if !self.enabled(metadata) {
+            return;
+        }
+        let client = self.client.read();
+        let client = match client.as_ref() {
+            Some(c) => c,
+            None => return,
+        };
+
+        let mut message = MessageVisitor::default();
+        event.record(&mut message);
+        let level = *metadata.level();
+        if level <= *self.level.read() {
+            client.log_message(to_message_type(level), &message.0);
+        }
+
+        if level > self.trace_level() {
+            return;
+        }
+        let verbose = match *self.trace.read() {
+            TraceOption::Off => return,
+            TraceOption::Messages => None,
+            TraceOption::Verbose => Some(format!(
+                "{} {}:{}",
+                metadata.target(),
+                metadata.file().unwrap_or_default(),
+                metadata.line().unwrap_or_default()
+            )),
+        };
+        client.send_custom_notification::<LogTrace>(LogTraceParams {
+            message: format!("[{}] {}", level, message.0),
+            verbose,
+        });
+    }
+}
+
+/// The message of an event, followed by its other fields.
+#[derive(Default)]
+struct MessageVisitor(String);
+
+impl Visit for MessageVisitor {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.0, "{:?}", value);
+        } else {
+            let _ = write!(self.0, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+/// The log file, or stderr.
+#[derive(Clone)]
+struct LogWriter(Option<Arc<File>>);
+
+impl Write for LogWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match &self.0 {
+            Some(file) => (&**file).write(buf),
+            None => io::stderr().write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match &self.0 {
+            Some(file) => (&**file).flush(),
+            None => io::stderr().flush(),
+        }
+    }
+}
+
+fn to_message_type(level: Level) -> MessageType {
+    match level {
+        Level::ERROR => MessageType::Error,
+        Level::WARN => MessageType::Warning,
+        Level::INFO => MessageType::Info,
+        _ => MessageType::Log,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc as std_mpsc,
+    };
+
+    /// Counts the events it gets.
+    struct Count(Arc<AtomicUsize>);
+
+    impl<S: Subscriber> Layer<S> for Count {
+        fn on_event(&self, _event: &Event<'_>, _ctx: Context<'_, S>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn frame(message: Value) -> String {
+        let body = message.to_string();
+        format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+    }
+
+    #[test]
+    fn test_set_trace() {
+        let sink = ClientSink::new();
+        assert_eq!(*sink.trace.read(), TraceOption::Off);
+        sink.set_trace(TraceOption::Verbose);
+        assert_eq!(*sink.trace.read(), TraceOption::Verbose);
+        sink.set_trace(TraceOption::Messages);
+        assert_eq!(*sink.trace.read(), TraceOption::Messages);
+    }
+
+    #[test]
+    fn test_trace_level() {
+        let sink = ClientSink::new();
+        assert_eq!(sink.trace_level(), LevelFilter::OFF);
+        sink.set_trace(TraceOption::Messages);
+        assert_eq!(sink.trace_level(), LevelFilter::DEBUG);
+        sink.set_trace(TraceOption::Verbose);
+        assert_eq!(sink.trace_level(), LevelFilter::TRACE);
+    }
+
+    #[test]
+    fn test_trace_ignores_log_filter() {
+        let sink: &'static ClientSink = Box::leak(Box::new(ClientSink::new()));
+        let logged = Arc::new(AtomicUsize::new(0));
+        let forwarded = Arc::new(AtomicUsize::new(0));
+        let (filter, _handle) = reload::Layer::new(EnvFilter::new("warn"));
+        let subscriber = Registry::default()
+            .with(LogLayer {
+                filter,
+                log: Count(logged.clone()),
+                sink,
+            })
+            .with(Count(forwarded.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            debug!("dropped");
+            sink.set_trace(TraceOption::Verbose);
+            trace!("traced");
+            warn!("logged and traced");
+        });
+        assert_eq!(logged.load(Ordering::SeqCst), 1);
+        assert_eq!(forwarded.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_set_trace_params() {
+        let params = |message: Value| set_trace_params(message.to_string().as_bytes());
+        assert_eq!(
+            params(
+                json!({"jsonrpc": "2.0", "method": "$/setTrace", "params": {"value": "verbose"}})
+            ),
+            Some(TraceOption::Verbose)
+        );
+        assert_eq!(
+            params(json!({"jsonrpc": "2.0", "method": "$/setTrace", "params": {"value": "bad"}})),
+            None
+        );
+        assert_eq!(
+            params(json!({"jsonrpc": "2.0", "method": "initialized", "params": {}})),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_filter_set_trace() {
+        let initialized = frame(json!({"jsonrpc": "2.0", "method": "initialized", "params": {}}));
+        let shutdown = frame(json!({"jsonrpc": "2.0", "id": 1, "method": "shutdown"}));
+        let input = format!(
+            "{}{}{}",
+            initialized,
+            frame(
+                json!({"jsonrpc": "2.0", "method": "$/setTrace", "params": {"value": "messages"}})
+            ),
+            shutdown
+        );
+
+        let (sender, receiver) = std_mpsc::channel();
+        let mut output = filter_set_trace(Cursor::new(input.into_bytes()), move |trace| {
+            sender.send(trace).unwrap()
+        });
+        let mut forwarded = String::new();
+        output.read_to_string(&mut forwarded).await.unwrap();
+
+        assert_eq!(forwarded, format!("{}{}", initialized, shutdown));
+        assert_eq!(
+            receiver.try_iter().collect::<Vec<_>>(),
+            vec![TraceOption::Messages]
+        );
+    }
+}
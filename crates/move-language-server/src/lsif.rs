@@ -0,0 +1,348 @@
+//! LSIF dump of a project, for code navigation on code hosting platforms without a running server.
+//!
+//! Modules, structs and functions are indexed with the providers of the server:
+//! their definitions, their references and the signatures of their definitions as hovers.
+//! Symbols are correlated across files, and across dumps, by their monikers.
+
+use crate::{
+    analysis::definition_signature,
+    dialect::HasDialect,
+    ide::moniker::symbol_at,
+    move_document::MoveDocument,
+    salsa::{
+        config_query::Config,
+        module_index_query::{ModuleDecl, ModuleIndex},
+        RootDatabase,
+    },
+    tree_sitter_move::kinds,
+};
+use anyhow::Result;
+use serde_json::{json, Value};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Write,
+    path::Path,
+};
+use tower_lsp::lsp_types::{Range, Url};
+use tree_sitter::Node;
+
+pub const LSIF_VERSION: &str = "0.4.3";
+
+/// A module, struct or function named in a file.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Occurrence {
+    pub range: Range,
+    /// moniker identifier of the symbol.
+    pub identifier: String,
+    pub definition: bool,
+    /// signature of the definition.
+    pub hover: Option<String>,
+}
+
+/// Writes vertices and edges as json lines, numbering them in order.
+struct Emitter<W> {
+    out: W,
+    next_id: u64,
+}
+
+impl<W: Write> Emitter<W> {
+    fn emit(&mut self, kind: &str, label: &str, mut fields: Value) -> Result<u64> {
+        self.next_id += 1;
+        let id = self.next_id;
+        if let Value::Object(map) = &mut fields {
+            map.insert("id".to_string(), json!(id));
+            map.insert("type".to_string(), json!(kind));
+            map.insert("label".to_string(), json!(label));
+        }
+        serde_json::to_writer(&mut self.out, &fields)?;
+        self.out.write_all(b"\n")?;
+        Ok(id)
+    }
+
+    fn vertex(&mut self, label: &str, fields: Value) -> Result<u64> {
+        self.emit("vertex", label, fields)
+    }
+
+    fn edge(&mut self, label: &str, out_v: u64, in_v: u64) -> Result<u64> {
+        self.emit("edge", label, json!({ "outV": out_v, "inV": in_v }))
+    }
+}
+
+/// Vertices shared by every occurrence of a symbol.
+struct SymbolIds {
+    result_set: u64,
+    definitions: u64,
+    references: u64,
+}
+
+/// Dump the stdlib, module and script files of `db` as LSIF to `out`.
+/// Only symbols defined in the project are indexed, with the modules they use.
+pub fn dump(db: &RootDatabase, root: &Path, out: impl Write) -> Result<()> {
+    let files: BTreeSet<_> = db
+        .stdlib_files()
+        .into_iter()
+        .chain(db.module_files())
+        .chain(db.script_files())
+        .collect();
+    let dialect = db.dialect().name();
+    let mut occurrences = BTreeMap::new();
+    for file in files {
+        let doc = db.syntax_document(file.clone());
+        let found = file_occurrences(&doc, db.file_modules(file.clone()).as_slice(), dialect);
+        occurrences.insert(file, found);
+    }
+    let defined: BTreeMap<&str, Option<&str>> = occurrences
+        .values()
+        .flatten()
+        .filter(|o| o.definition)
+        .map(|o| (o.identifier.as_str(), o.hover.as_deref()))
+        .collect();
+    // modules of other projects are referred to by their monikers.
+    let module_prefix = format!("{}:", dialect);
+    let indexed = |o: &Occurrence| {
+        defined.contains_key(o.identifier.as_str())
+            || o.identifier[module_prefix.len()..].matches("::").count() == 1
+    };
+
+    let mut emitter = Emitter { out, next_id: 0 };
+    let root_uri = Url::from_directory_path(root)
+        .map_err(|_| anyhow::anyhow!("{} is not an absolute directory path", root.display()))?;
+    emitter.vertex(
+        "metaData",
+        json!({
+            "version": LSIF_VERSION,
+            "projectRoot": root_uri,
+            "positionEncoding": "utf-16",
+            "toolInfo": { "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") },
+        }),
+    )?;
+    let project = emitter.vertex("project", json!({ "kind": "move" }))?;
+
+    let identifiers: BTreeSet<&str> = occurrences
+        .values()
+        .flatten()
+        .filter(|o| indexed(o))
+        .map(|o| o.identifier.as_str())
+        .collect();
+    let mut symbols = BTreeMap::new();
+    for identifier in identifiers {
+        let result_set = emitter.vertex("resultSet", json!({}))?;
+        let moniker = emitter.vertex(
+            "moniker",
+            json!({
+                "scheme": "move",
+                "identifier": identifier,
+                "unique": "global",
+                "kind": if defined.contains_key(identifier) { "export" } else { "import" },
+            }),
+        )?;
+        emitter.edge("moniker", result_set, moniker)?;
+        if let Some(Some(hover)) = defined.get(identifier) {
+            let hover = emitter.vertex(
+                "hoverResult",
+                json!({ "result": { "contents": {
+                    "kind": "markdown",
+                    "value": format!("```move\n{}\n```", hover),
+                } } }),
+            )?;
+            emitter.edge("textDocument/hover", result_set, hover)?;
+        }
+        let definitions = emitter.vertex("definitionResult", json!({}))?;
+        emitter.edge("textDocument/definition", result_set, definitions)?;
+        let references = emitter.vertex("referenceResult", json!({}))?;
+        emitter.edge("textDocument/references", result_set, references)?;
+        symbols.insert(
+            identifier,
+            SymbolIds {
+                result_set,
+                definitions,
+                references,
+            },
+        );
+    }
+
+    // (result, document, property, ranges) of every item edge.
+    let mut items: BTreeMap<(u64, u64, &str), Vec<u64>> = BTreeMap::new();
+    let mut documents = vec![];
+    for (file, found) in occurrences.iter() {
+        let uri = match Url::from_file_path(file) {
+            Ok(uri) => uri,
+            Err(_) => continue,
+        };
+        let document = emitter.vertex("document", json!({ "uri": uri, "languageId": "move" }))?;
+        documents.push(document);
+        let mut ranges = vec![];
+        for o in found.iter().filter(|o| indexed(o)) {
+            let ids = &symbols[o.identifier.as_str()];
+            let range = emitter.vertex(
+                "range",
+                json!({ "start": o.range.start, "end": o.range.end }),
+            )?;
+            emitter.edge("next", range, ids.result_set)?;
+            ranges.push(range);
+            if o.definition {
+                items
+                    .entry((ids.definitions, document, ""))
+                    .or_default()
+                    .push(range);
+            }
+            let property = if o.definition {
+                "definitions"
+            } else {
+                "references"
+            };
+            items
+                .entry((ids.references, document, property))
+                .or_default()
+                .push(range);
+        }
+        if !ranges.is_empty() {
+            emitter.emit(
+                "edge",
+                "contains",
+                json!({ "outV": document, "inVs": ranges }),
+            )?;
+        }
+    }
+    emitter.emit(
+        "edge",
+        "contains",
+        json!({ "outV": project, "inVs": documents }),
+    )?;
+    for ((result, document, property), ranges) in items {
+        let mut edge = json!({ "outV": result, "inVs": ranges, "document": document });
+        if !property.is_empty() {
+            edge["property"] = json!(property);
+        }
+        emitter.emit("edge", "item", edge)?;
+    }
+    emitter.out.flush()?;
+    Ok(())
+}
+
+/// Modules, structs and functions named in `doc`, with `dialect` qualified monikers.
+pub fn file_occurrences(
+    doc: &MoveDocument,
+    file_modules: &[ModuleDecl],
+    dialect: &str,
+) -> Vec<Occurrence> {
+    let tree = match doc.tree() {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let mut occurrences = vec![];
+    leaves(&tree.root_node(), &mut |node| {
+        if node.kind() == kinds::ADDRESS_LITERAL || node.kind() == kinds::VARIABLE_IDENTIFIER {
+            return;
+        }
+        let range = match doc.node_range(&node) {
+            Some(r) => r,
+            None => return,
+        };
+        // the end of a name resolves to it, its start may resolve to a preceding `::`.
+        let symbol = match symbol_at(doc, range.end, file_modules) {
+            Some(s) => s,
+            None => return,
+        };
+        let definition = node.parent().filter(|p| is_definition_name(p, &node));
+        let hover = definition.map(|d| match d.kind() {
+            kinds::MODULE_DEFINITION => format!("module {}", symbol.module),
+            _ => definition_signature(doc.node_text(&d).as_ref()),
+        });
+        occurrences.push(Occurrence {
+            range,
+            identifier: symbol.identifier(dialect),
+            definition: definition.is_some(),
+            hover,
+        });
+    });
+    occurrences
+}
+
+fn is_definition_name(parent: &Node, node: &Node) -> bool {
+    let definitions = [
+        kinds::MODULE_DEFINITION,
+        kinds::STRUCT_DEFINITION,
+        kinds::FUNCTION_DEFINITION,
+    ];
+    definitions.contains(&parent.kind())
+        && parent.child_by_field_name("name").map(|n| n.start_byte()) == Some(node.start_byte())
+}
+
+fn leaves<'a>(node: &Node<'a>, f: &mut impl FnMut(Node<'a>)) {
+    if node.named_child_count() == 0 {
+        f(*node);
+        return;
+    }
+    let mut cursor = node.walk();
+    for child in node.named_children(&mut cursor) {
+        leaves(&child, f);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::module_index_query::index_modules;
+    use std::path::PathBuf;
+    use xi_rope::Rope;
+
+    const SOURCE: &str = r"address 0x1 {
+module M {
+    use 0x1::Signer;
+    struct T {}
+    public fun f(s: &signer): address { Signer::address_of(s) }
+    fun g(): address { f(g_signer()) }
+}
+}
+";
+
+    #[test]
+    fn test_file_occurrences() {
+        let doc = MoveDocument::new(1, SOURCE);
+        let modules = index_modules(SOURCE, None);
+        let occurrences: Vec<_> = file_occurrences(&doc, &modules, "move")
+            .into_iter()
+            .map(|o| (o.range.start.line, o.identifier, o.definition))
+            .collect();
+        assert!(occurrences.contains(&(1, "move:0x1::M".to_string(), true)));
+        assert!(occurrences.contains(&(2, "move:0x1::Signer".to_string(), false)));
+        assert!(occurrences.contains(&(3, "move:0x1::M::T".to_string(), true)));
+        assert!(occurrences.contains(&(4, "move:0x1::M::f".to_string(), true)));
+        assert!(occurrences.contains(&(4, "move:0x1::Signer::address_of".to_string(), false)));
+        assert!(occurrences.contains(&(5, "move:0x1::M::f".to_string(), false)));
+        assert!(!occurrences.iter().any(|(_, i, _)| i.ends_with("::s")));
+    }
+
+    #[test]
+    fn test_dump() {
+        let root = PathBuf::from("/lsif");
+        let file = root.join("M.move");
+        let mut db = RootDatabase::default();
+        db.set_project_files(vec![], vec![file.clone()]);
+        db.update_source(file, Rope::from(SOURCE));
+        let mut out = vec![];
+        dump(&db, &root, &mut out).unwrap();
+
+        let lines: Vec<Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|l| serde_json::from_str(l).unwrap())
+            .collect();
+        assert_eq!(lines[0]["label"], "metaData");
+        let labels = |label: &str| lines.iter().filter(|l| l["label"] == label).count();
+        assert_eq!(labels("document"), 1);
+        // M, T, f and g are defined, Signer is used.
+        assert_eq!(labels("resultSet"), 5);
+        assert_eq!(labels("hoverResult"), 4);
+        let hovers: Vec<_> = lines
+            .iter()
+            .filter_map(|l| l["result"]["contents"]["value"].as_str())
+            .collect();
+        assert!(hovers.contains(&"```move\npublic fun f(s: &signer): address\n```"));
+        // ids are numbered in order.
+        for (i, line) in lines.iter().enumerate() {
+            assert_eq!(line["id"], json!(i + 1));
+        }
+    }
+}
@@ -0,0 +1,456 @@
+//! Notifications and requests which are not part of the lsp spec.
+
+use crate::{
+    artifacts::CompilationManifest, config::ConfigProblem, error_diagnostic::DiagnosticInfo,
+};
+use lsp_types::{
+    notification::Notification, Command, Diagnostic, Location, Position, ProgressToken, Range,
+    ServerCapabilities, TextDocumentIdentifier, Url,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+
+/// Custom requests are served through `workspace/executeCommand` with these command names,
+/// the request params are passed as the single command argument.
+pub const DEPENDENCY_GRAPH: &str = "move/dependencyGraph";
+pub const READ_FILE: &str = "move/readFile";
+pub const HIGHLIGHT: &str = "move/highlight";
+pub const EXPAND_ADDRESSES: &str = "move/expandAddresses";
+pub const DOCUMENT_DIAGNOSTIC: &str = "move/documentDiagnostic";
+pub const SERVER_INFO: &str = "move/serverInfo";
+pub const METRICS: &str = "move/metrics";
+pub const SET_LOG_FILTER: &str = "move/setLogFilter";
+pub const NEW_PROJECT: &str = "move/newProject";
+pub const NEW_MODULE: &str = "move/newModule";
+pub const GENERATE_DOCS: &str = "move/generateDocs";
+pub const TESTS: &str = "move/tests";
+pub const LOAD_COVERAGE: &str = "move/loadCoverage";
+pub const ADDRESS_DECORATIONS: &str = "move/addressDecorations";
+pub const LINKED_EDITING_RANGE: &str = "move/linkedEditingRange";
+pub const MONIKER: &str = "move/moniker";
+pub const UNUSED_PUBLIC_FUNCTIONS: &str = "move/unusedPublicFunctions";
+pub const INLAY_HINTS: &str = "move/inlayHints";
+
+/// Result of the `compile` command: the written artifacts, or the errors failing the compilation.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompileResult {
+    #[serde(flatten)]
+    pub manifest: CompilationManifest,
+    /// empty if the compilation succeeded, sorted by file and position otherwise.
+    pub errors: Vec<DiagnosticInfo>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum GraphFormat {
+    Dot,
+    Json,
+}
+
+impl Default for GraphFormat {
+    fn default() -> Self {
+        GraphFormat::Json
+    }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DependencyGraphParams {
+    #[serde(default)]
+    pub format: GraphFormat,
+    /// a file of the project whose graph it is, in a multi-root workspace.
+    /// The modules of the first project otherwise.
+    #[serde(default)]
+    pub uri: Option<Url>,
+}
+
+/// Content of a `file:` or `move-readonly:` document, for clients which can't read files
+/// outside the workspace.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadFileParams {
+    pub uri: Url,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReadFileResult {
+    pub text: String,
+    /// stdlib files and `move-readonly:` documents should not be edited.
+    pub readonly: bool,
+}
+
+/// Syntax highlights of an open document, computed with the server side tree-sitter queries.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HighlightToken {
+    pub range: Range,
+    /// capture name of `queries/highlight.scm`, like `keyword` or `function`.
+    pub kind: String,
+}
+
+/// Source of an open document as the compiler checks it, with the sender address applied,
+/// and the parser AST generated from it, `null` if it doesn't parse.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ExpandAddressesParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+/// Address literals of an open document which are named in the `addresses` config,
+/// for editors to show hints like `0x1 (Std)`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressDecorationsParams {
+    pub text_document: TextDocumentIdentifier,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AddressDecoration {
+    pub range: Range,
+    /// names of the address, separated by commas.
+    pub name: String,
+}
+
+/// Result of `move/linkedEditingRange`, shaped like `textDocument/linkedEditingRange` of lsp 3.16.
+/// It takes `TextDocumentPositionParams`, and links a module alias with its accesses.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkedEditingRanges {
+    pub ranges: Vec<Range>,
+    #[serde(default)]
+    pub word_pattern: Option<String>,
+}
+
+/// Result of `move/moniker`, shaped like `textDocument/moniker` of lsp 3.16.
+/// It takes `TextDocumentPositionParams`.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Moniker {
+    /// always `move`.
+    pub scheme: String,
+    /// `<dialect>:<address>::<Module>[::<member>]`.
+    pub identifier: String,
+    pub unique: UniquenessLevel,
+    pub kind: Option<MonikerKind>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum UniquenessLevel {
+    Document,
+    Project,
+    Group,
+    Scheme,
+    Global,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum MonikerKind {
+    Import,
+    Export,
+    Local,
+}
+
+/// Inlay hints of an open document, shaped like `textDocument/inlayHint` of lsp 3.17:
+/// the resources acquired by calls to functions of the same module.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHintParams {
+    pub text_document: TextDocumentIdentifier,
+    /// hints of the whole document if not set.
+    #[serde(default)]
+    pub range: Option<Range>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct InlayHint {
+    pub position: Position,
+    pub label: String,
+    #[serde(default)]
+    pub padding_left: bool,
+}
+
+/// Pull diagnostics of an open document, shaped like `textDocument/diagnostic` of lsp 3.17.
+/// Clients declaring the `pullDiagnostics` experimental capability get no pushed diagnostics.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentDiagnosticParams {
+    pub text_document: TextDocumentIdentifier,
+    #[serde(default)]
+    pub previous_result_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum DocumentDiagnosticReport {
+    Full {
+        #[serde(rename = "resultId")]
+        result_id: String,
+        items: Vec<Diagnostic>,
+    },
+    /// the diagnostics of `previous_result_id` are still valid.
+    Unchanged {
+        #[serde(rename = "resultId")]
+        result_id: String,
+    },
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BuildInfo {
+    /// version of the crate.
+    pub version: String,
+    #[serde(default)]
+    pub git_commit: Option<String>,
+    #[serde(default)]
+    pub git_dirty: Option<bool>,
+    #[serde(default)]
+    pub built_time: Option<String>,
+    #[serde(default)]
+    pub target: Option<String>,
+    #[serde(default)]
+    pub rustc_version: Option<String>,
+}
+
+/// Result of `move/serverInfo`, which takes no params.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServerInfoResult {
+    pub name: String,
+    pub build_info: BuildInfo,
+    /// abi version of the bundled tree-sitter grammar.
+    pub grammar_abi_version: usize,
+    pub capabilities: ServerCapabilities,
+}
+
+/// Result of `move/metrics`, which takes no params. Sizes are estimates in bytes.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Metrics {
+    pub stdlib_files: usize,
+    pub module_files: usize,
+    pub script_files: usize,
+    pub open_documents: usize,
+    /// the current salsa revision.
+    pub revision: String,
+    /// text of the open documents.
+    pub documents_size: usize,
+    /// file names interned for the compiler.
+    pub interned_size: usize,
+    /// duration of the last check of the project, in milliseconds.
+    pub last_check_millis: Option<u64>,
+}
+
+/// Replace the filter of server logs at runtime.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetLogFilterParams {
+    /// directives in the `RUST_LOG` syntax, like `warn,move_language_server::salsa=debug`.
+    pub filter: String,
+}
+
+/// Create a new Move project.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewProjectParams {
+    /// folder of the project, it must not exist or be empty.
+    pub uri: Url,
+    /// address of the sample module, it's required.
+    #[serde(default)]
+    pub sender_address: Option<String>,
+    /// named addresses written to the manifest.
+    #[serde(default)]
+    pub addresses: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewProjectResult {
+    /// files created, the client may open them.
+    pub files: Vec<Url>,
+}
+
+/// Create a module file, which is added to the project right away.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NewModuleParams {
+    /// module name, also the file name.
+    pub name: String,
+    /// modules folder to create the file in, the first configured one if not set.
+    #[serde(default)]
+    pub folder: Option<Url>,
+    /// address literal or named address of the module, the sender if not set.
+    #[serde(default)]
+    pub address: Option<String>,
+}
+
+/// Write the Markdown documentation of the modules of the project.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateDocsParams {
+    /// folder to write the docs into, `docs` of the workspace if not set.
+    #[serde(default)]
+    pub out_dir: Option<Url>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GenerateDocsResult {
+    /// a page per module, and the index.
+    pub files: Vec<Url>,
+}
+
+/// List the tests of a document, or of the whole project.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestsParams {
+    #[serde(default)]
+    pub text_document: Option<TextDocumentIdentifier>,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum TestKind {
+    Script,
+    Function,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TestItem {
+    pub label: String,
+    pub kind: TestKind,
+    /// the module defining a test function.
+    #[serde(default)]
+    pub container: Option<String>,
+    /// location of the test name.
+    pub location: Location,
+    /// runs the test, if it can be run on its own.
+    #[serde(default)]
+    pub command: Option<Command>,
+}
+
+pub enum LogTrace {}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LogTraceParams {
+    pub message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub verbose: Option<String>,
+}
+
+impl Notification for LogTrace {
+    type Params = LogTraceParams;
+    const METHOD: &'static str = "$/logTrace";
+}
+
+/// A chunk of a result streamed with the `partialResultToken` of its request.
+/// Shares `$/progress` with work done progress, but carries result items as the value.
+pub enum PartialResult {}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PartialResultParams {
+    pub token: ProgressToken,
+    pub value: Value,
+}
+
+impl Notification for PartialResult {
+    type Params = PartialResultParams;
+    const METHOD: &'static str = "$/progress";
+}
+
+/// Server health, sent whenever the server starts or finishes some work.
+pub enum Status {}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum ServerStatus {
+    Idle,
+    Indexing,
+    Checking,
+    Error,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct StatusParams {
+    pub status: ServerStatus,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub message: Option<String>,
+}
+
+impl Notification for Status {
+    type Params = StatusParams;
+    const METHOD: &'static str = "move/status";
+}
+
+/// Problems of the project folders, sent on every config change.
+/// An empty list clears the problems of the previous config.
+pub enum ConfigStatus {}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConfigStatusParams {
+    pub problems: Vec<ConfigProblem>,
+}
+
+impl Notification for ConfigStatus {
+    type Params = ConfigStatusParams;
+    const METHOD: &'static str = "move/configStatus";
+}
+
+/// Artifacts of a file compiled when it was saved, with `compile_on_save`.
+pub enum Compiled {}
+
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CompiledParams {
+    pub uri: Url,
+    #[serde(flatten)]
+    pub manifest: CompilationManifest,
+}
+
+impl Notification for Compiled {
+    type Params = CompiledParams;
+    const METHOD: &'static str = "move/compiled";
+}
+
+/// Load the coverage of the project from a vm trace, published with `move/coverage`.
+#[derive(Debug, Clone, Default, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LoadCoverageParams {
+    /// trace file, `trace` of the output directory if not set.
+    #[serde(default)]
+    pub trace: Option<Url>,
+}
+
+pub enum Coverage {}
+
+/// Coverage of a source file, sent for every file with compiled functions.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CoverageParams {
+    pub uri: Url,
+    /// ranges of which some instruction was executed.
+    pub covered: Vec<Range>,
+    pub uncovered: Vec<Range>,
+}
+
+impl Notification for Coverage {
+    type Params = CoverageParams;
+    const METHOD: &'static str = "move/coverage";
+}
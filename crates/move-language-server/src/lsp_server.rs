@@ -1,16 +1,53 @@
 use crate::{
-    config::ProjectConfig,
+    analysis::{self, Symbol},
+    artifacts::{self, CompilationManifest},
+    config::{ProjectConfig, CONFIG_SECTION, MANIFEST_FILE},
+    coverage,
+    dependency_graph::DependencyGraph,
+    dialect::{CoreDialect, Dialect, HasDialect},
+    disassemble, docgen,
     error_diagnostic::{to_diagnostics, DiagnosticInfo},
+    file_watcher::FileWatcher,
+    fingerprints::{self, Fingerprints},
+    host::AnalysisSnapshot,
+    ide::{
+        acquires, add_ability,
+        code_lens::{
+            self, COMPILE_COMMAND, DISASSEMBLE_COMMAND, RUN_SCRIPT_COMMAND, RUN_TEST_COMMAND,
+        },
+        constant_eval, diff, expand, extract_function, generate_getter, goto_definition, highlight,
+        hover, inline_variable, moniker, on_save,
+        outline::{self, Outline},
+        rename, unused_variable,
+    },
+    logging,
+    lsp_ext::{
+        self, AddressDecoration, AddressDecorationsParams, BuildInfo, CompileResult, Compiled,
+        CompiledParams, ConfigStatus, ConfigStatusParams, Coverage, CoverageParams,
+        DependencyGraphParams, DocumentDiagnosticParams, DocumentDiagnosticReport,
+        ExpandAddressesParams, GenerateDocsParams, GenerateDocsResult, GraphFormat,
+        HighlightParams, InlayHintParams, LinkedEditingRanges, LoadCoverageParams, Metrics,
+        Moniker, MonikerKind, NewModuleParams, NewProjectParams, NewProjectResult, PartialResult,
+        PartialResultParams, ReadFileParams, ReadFileResult, ServerInfoResult, ServerStatus,
+        SetLogFilterParams, Status, StatusParams, TestItem, TestKind, TestsParams, UniquenessLevel,
+    },
     move_document::{MoveDocument, RopeDoc},
+    published_diagnostics::PublishedDiagnostics,
     salsa::{
         config_query::Config,
+        module_index_query::{self, ModuleIndex, ModuleKey},
         move_ast_query::{Ast, AstInfo},
         text_source_query::{SourceReader, TextSource},
-        RootDatabase,
+        FileId, RootDatabase,
     },
-    utils::find_move_file,
+    scaffold,
+    script_runner::{self, ExecutionResult},
+    tree_sitter_move,
+    utils::{canonicalize, check_move_file, find_move_file, SkipReason},
+    version::{PackageVersion, VersionProvider},
+    virtual_path::{readonly_url, VirtualPath, READONLY_SCHEME},
 };
-use anyhow::{bail, Result};
+use anyhow::{bail, format_err, Result};
 use dashmap::DashMap;
 use futures::lock::Mutex;
 use itertools::Itertools;
@@ -18,51 +55,170 @@ use move_core_types::account_address::AccountAddress;
 use move_lang::{
     errors::{Errors, FilesSourceText},
     shared::Address,
+    MOVE_EXTENSION,
 };
-use serde::{Deserialize, Serialize};
+use salsa::Database;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json as json;
 use serde_json::Value;
 use std::{
+    collections::{HashMap, HashSet},
     convert::TryFrom,
     path::{Path, PathBuf},
-    str::FromStr,
+    sync::Arc,
+    time::{Duration, Instant},
 };
+use tokio::sync::mpsc::{self, UnboundedReceiver};
 use tower_lsp::{
     jsonrpc, lsp_types,
     lsp_types::{
         notification::{Notification, Progress},
-        ConfigurationItem, Diagnostic, DiagnosticRelatedInformation, DiagnosticSeverity,
-        DidChangeConfigurationParams, DidChangeTextDocumentParams,
-        DidChangeWatchedFilesRegistrationOptions, DidCloseTextDocumentParams,
-        DidOpenTextDocumentParams, DidSaveTextDocumentParams, DocumentFormattingParams,
-        ExecuteCommandOptions, ExecuteCommandParams, FormattingOptions, GotoDefinitionParams,
-        GotoDefinitionResponse, InitializeParams, InitializeResult, InitializedParams, Location,
-        ProgressParams, ProgressParamsValue, Registration, SaveOptions, ServerCapabilities,
-        ServerInfo, TextDocumentItem, TextDocumentPositionParams, TextDocumentSyncCapability,
-        TextDocumentSyncKind, TextDocumentSyncOptions, TextEdit, Unregistration, Url,
-        WorkDoneProgress, WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressOptions,
-        WorkDoneProgressParams, WorkspaceCapability, WorkspaceFolderCapability,
+        request::{GotoImplementationParams, GotoImplementationResponse},
+        CodeActionOrCommand, CodeActionParams, CodeActionProviderCapability, CodeActionResponse,
+        CodeLens, CodeLensOptions, CodeLensParams, Command, CompletionItem, CompletionOptions,
+        CompletionParams, CompletionResponse, ConfigurationItem, Diagnostic,
+        DiagnosticRelatedInformation, DiagnosticSeverity, DidChangeConfigurationParams,
+        DidChangeTextDocumentParams, DidChangeWatchedFilesRegistrationOptions,
+        DidChangeWorkspaceFoldersParams, DidCloseTextDocumentParams, DidOpenTextDocumentParams,
+        DidSaveTextDocumentParams, DocumentChanges, DocumentFormattingParams, DocumentSymbol,
+        DocumentSymbolParams, DocumentSymbolResponse, ExecuteCommandOptions, ExecuteCommandParams,
+        FormattingOptions, GotoDefinitionParams, GotoDefinitionResponse, Hover, HoverParams,
+        ImplementationProviderCapability, InitializeParams, InitializeResult, InitializedParams,
+        Location, MessageType, PrepareRenameResponse, ProgressParams, ProgressParamsValue,
+        ProgressToken, ReferenceParams, Registration, RenameOptions, RenameParams,
+        RenameProviderCapability, SaveOptions, ServerCapabilities, ServerInfo, SymbolInformation,
+        TextDocumentEdit, TextDocumentItem, TextDocumentPositionParams, TextDocumentSyncCapability,
+        TextDocumentSyncKind, TextDocumentSyncOptions, TextEdit, TraceOption, Unregistration, Url,
+        VersionedTextDocumentIdentifier, WillSaveTextDocumentParams, WorkDoneProgress,
+        WorkDoneProgressBegin, WorkDoneProgressEnd, WorkDoneProgressOptions,
+        WorkDoneProgressParams, WorkspaceCapability, WorkspaceEdit, WorkspaceFolderCapability,
+        WorkspaceFolderCapabilityChangeNotifications, WorkspaceFoldersChangeEvent,
+        WorkspaceSymbolParams,
     },
     Client, LanguageServer,
 };
+use xi_rope::Rope;
 
 pub const LANGUAGE_ID: &str = "move";
+pub const SERVER_NAME: &str = "move language server";
+/// Workspace symbol searches stop at this many results.
+const MAX_WORKSPACE_SYMBOLS: usize = 1000;
+/// Items per `$/progress` notification when results are streamed.
+const PARTIAL_RESULT_CHUNK: usize = 100;
+/// Linked edits stop when the alias is no longer an identifier.
+const LINKED_WORD_PATTERN: &str = "[A-Za-z_][A-Za-z0-9_]*";
 pub struct MoveLanguageServer {
-    inner: Mutex<Inner>,
+    inner: Arc<Mutex<Inner>>,
+    /// shared with `Inner`, so that text changes don't wait for the server lock.
+    docs: Arc<DashMap<Url, MoveDocument>>,
+    /// shared with `Inner`, changes which overtook the open of their document.
+    pending_changes: Arc<DashMap<Url, Vec<DidChangeTextDocumentParams>>>,
 }
 
 impl MoveLanguageServer {
     pub fn new(client: Client) -> Self {
+        Self::with_version_provider(client, &PackageVersion)
+    }
+
+    /// A server reporting the build metadata of `version`.
+    pub fn with_version_provider(client: Client, version: &dyn VersionProvider) -> Self {
+        Self::with_dialect(client, version, Arc::new(CoreDialect))
+    }
+
+    /// A server for sources written in `dialect`, reporting the build metadata of `version`.
+    pub fn with_dialect(
+        client: Client,
+        version: &dyn VersionProvider,
+        dialect: Arc<dyn Dialect>,
+    ) -> Self {
+        logging::set_client(client.clone());
+        let docs: Arc<DashMap<Url, MoveDocument>> = Default::default();
+        let pending_changes: Arc<DashMap<Url, Vec<DidChangeTextDocumentParams>>> =
+            Default::default();
         let inner = Inner {
-            db: RootDatabase::default(),
+            db: RootDatabase::with_dialect(dialect),
             config: ProjectConfig::default(),
-            docs: Default::default(),
+            docs: docs.clone(),
+            pending_changes: pending_changes.clone(),
+            committed_versions: Default::default(),
+            saved_documents: Default::default(),
+            skipped_files: Default::default(),
+            save_generation: 0,
             client,
             file_watch_registration: Default::default(),
+            file_watcher: None,
             client_capabilities: Default::default(),
+            root_path: None,
+            workspace_folders: vec![],
+            shut_down: false,
+            published_diagnostics: Default::default(),
+            compile_diagnostic_files: Default::default(),
+            pulled_diagnostics: Default::default(),
+            outlines: Default::default(),
+            next_result_id: 0,
+            build_info: version.build_info(),
+            last_check_duration: Default::default(),
         };
         Self {
-            inner: Mutex::new(inner),
+            inner: Arc::new(Mutex::new(inner)),
+            docs,
+            pending_changes,
+        }
+    }
+
+    /// Watch the project folders from the server, for clients which can't watch them.
+    fn start_file_watcher(&self, inner: &mut Inner) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        match FileWatcher::new(sender) {
+            Ok(watcher) => {
+                inner.file_watcher = Some(watcher);
+                tokio::spawn(forward_file_events(self.inner.clone(), receiver));
+            }
+            Err(e) => error!("fail to start file watcher, {}", e),
+        }
+    }
+
+    /// Fetch the config of the workspace folders and apply it, keeping the previous one if the
+    /// client settings are invalid.
+    async fn reload_config(&self, inner: &mut Inner) {
+        let client = inner.client.clone();
+        let config = inner.fetch_config().await;
+
+        match config {
+            Err(e) => {
+                // keep the previous config active, a typo in settings should not kill the server.
+                error!("Fetch client configuration failure: {:?}", e);
+                inner.send_status(
+                    ServerStatus::Error,
+                    Some(format!("invalid configuration, {}", e)),
+                );
+                client.show_message(
+                    MessageType::Error,
+                    format!(
+                        "Invalid move language server configuration, keep using the previous one: {}",
+                        e
+                    ),
+                );
+            }
+            Ok(c) => {
+                inner.handle_config_change(c);
+                self.register_file_watch(inner).await;
+            }
+        }
+    }
+
+    /// Register the file watch of the project folders, falling back to watching them
+    /// from the server when the client refuses the registration.
+    async fn register_file_watch(&self, inner: &mut Inner) {
+        if let Err(e) = inner.register_file_watch().await {
+            error!(
+                "fail to register didChangeWatchedFiles, watch files from the server, {}",
+                e
+            );
+            self.start_file_watcher(inner);
+            if let Err(e) = inner.register_file_watch().await {
+                error!("fail to watch files, {}", e);
+            }
         }
     }
 }
@@ -80,48 +236,51 @@ impl LanguageServer for MoveLanguageServer {
 
     async fn initialized(&self, _: InitializedParams) {
         let mut guard = self.inner.lock().await;
-        guard.register_file_watch().await;
+        if guard.is_shut_down() {
+            return;
+        }
+        if !guard.dynamic_file_watch() {
+            self.start_file_watcher(&mut guard);
+        }
+        self.register_file_watch(&mut guard).await;
         info!("move language server initialized");
     }
 
     async fn shutdown(&self) -> jsonrpc::Result<()> {
+        let mut guard = self.inner.lock().await;
+        guard.ensure_running()?;
+        guard.shutdown();
+        info!("move language server shut down");
         Ok(())
     }
 
     async fn did_change_configuration(&self, _params: DidChangeConfigurationParams) {
         let mut guard = self.inner.lock().await;
-        let client = guard.client.clone();
-        let config = async {
-            let configuration_req = ConfigurationItem {
-                scope_uri: None,
-                section: None,
-            };
-            let config = client.configuration(vec![configuration_req]).await;
-            match config?.pop() {
-                None => bail!("client respond empty config data"),
-                Some(config) => match json::from_value::<ProjectConfig>(config) {
-                    Err(e) => bail!("cannot deserialize config data, {:?}", e),
-                    Ok(c) => Ok(c),
-                },
-            }
+        if guard.is_shut_down() {
+            return;
         }
-        .await;
+        self.reload_config(&mut guard).await;
+    }
 
-        match config {
-            Err(e) => {
-                panic!("Fetch client configuration failure: {:?}", e);
-            }
-            Ok(c) => {
-                guard.handle_config_change(c);
-                guard.register_file_watch().await;
-            }
+    async fn did_change_workspace_folders(&self, params: DidChangeWorkspaceFoldersParams) {
+        let mut guard = self.inner.lock().await;
+        if guard.is_shut_down() {
+            return;
         }
+        guard.change_workspace_folders(params.event);
+        self.reload_config(&mut guard).await;
     }
 
     async fn did_change_watched_files(&self, params: lsp_types::DidChangeWatchedFilesParams) {
         let lsp_types::DidChangeWatchedFilesParams { changes } = params;
         let mut inner = self.inner.lock().await;
-        inner.handle_watched_files_change(changes);
+        if inner.is_shut_down() {
+            return;
+        }
+        let renames = inner.handle_watched_files_change(changes);
+        let client = inner.client.clone();
+        drop(inner);
+        apply_module_renames(&client, renames).await;
     }
 
     async fn execute_command(
@@ -135,13 +294,11 @@ impl LanguageServer for MoveLanguageServer {
         } = params;
 
         let mut guard = self.inner.lock().await;
+        guard.ensure_running()?;
         let client = guard.client.clone();
         match command.as_str() {
-            "compile" => {
-                let arg = arguments.pop().ok_or_else(|| {
-                    jsonrpc::Error::invalid_params("no arguments found for compile command")
-                })?;
-
+            COMPILE_COMMAND => {
+                let args: CompilationArgs = parse_arg(&mut arguments, "compile")?;
                 let sender_opt = match arguments
                     .pop()
                     .as_ref()
@@ -153,18 +310,17 @@ impl LanguageServer for MoveLanguageServer {
                         let err_msg = format!("invalid sender address, {}", e);
                         return Ok(Some(Value::String(err_msg)));
                     }
-                    Ok(sender) => sender.map(|s| Address::try_from(s.as_ref()).unwrap()),
+                    Ok(sender) => sender
+                        .map(|s| Address::try_from(s.as_ref()))
+                        .transpose()
+                        .map_err(|e| {
+                            jsonrpc::Error::invalid_params(format!("invalid sender address, {}", e))
+                        })?,
                 };
 
-                let args: CompilationArgs = serde_json::from_value(arg).map_err(|e| {
-                    jsonrpc::Error::invalid_params(format!(
-                        "fail to parse compile arguments, {}",
-                        e
-                    ))
-                })?;
-                if work_done_token.is_some() {
+                if let Some(token) = &work_done_token {
                     client.send_custom_notification::<Progress>(ProgressParams {
-                        token: work_done_token.clone().unwrap(),
+                        token: token.clone(),
                         value: ProgressParamsValue::WorkDone(WorkDoneProgress::Begin(
                             WorkDoneProgressBegin {
                                 title: "Compiling".to_string(),
@@ -178,9 +334,9 @@ impl LanguageServer for MoveLanguageServer {
 
                 let result = guard.do_compilation(sender_opt, args);
 
-                if work_done_token.is_some() {
+                if let Some(token) = work_done_token {
                     client.send_custom_notification::<Progress>(ProgressParams {
-                        token: work_done_token.unwrap(),
+                        token,
                         value: ProgressParamsValue::WorkDone(WorkDoneProgress::End(
                             WorkDoneProgressEnd {
                                 message: Some("Compile Done".to_string()),
@@ -190,10 +346,219 @@ impl LanguageServer for MoveLanguageServer {
                 }
 
                 match result {
-                    Ok(_) => Ok(None),
+                    Ok(compiled) => Ok(compiled.and_then(|c| serde_json::to_value(c).ok())),
                     Err(e) => Ok(Some(Value::String(e))),
                 }
             }
+            RUN_SCRIPT_COMMAND => {
+                let args: RunScriptArgs = parse_arg(&mut arguments, "runScript")?;
+                match guard.run_script(args) {
+                    Ok(r) => Ok(serde_json::to_value(r).ok()),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            RUN_TEST_COMMAND => {
+                let args: RunTestArgs = parse_arg(&mut arguments, "runTest")?;
+                match guard.run_test(args) {
+                    Ok(r) => Ok(serde_json::to_value(r).ok()),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            DISASSEMBLE_COMMAND => {
+                let args: DisassembleArgs = parse_arg(&mut arguments, "disassemble")?;
+                match guard.disassemble(args) {
+                    Ok(content) => Ok(Some(json::json!({ "content": content }))),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::DEPENDENCY_GRAPH => {
+                let params: DependencyGraphParams =
+                    parse_optional_arg(&mut arguments, "dependencyGraph")?;
+                let root = match params.uri.as_ref().and_then(|u| u.to_file_path().ok()) {
+                    Some(p) => guard.db.project_root_of(canonicalize(&p)),
+                    None => guard.db.project_roots().into_iter().next(),
+                };
+                let graph = DependencyGraph::of_files(&guard.db, guard.root_files(root));
+                match params.format {
+                    GraphFormat::Dot => Ok(Some(Value::String(graph.to_dot()))),
+                    GraphFormat::Json => Ok(Some(graph.to_json())),
+                }
+            }
+            lsp_ext::UNUSED_PUBLIC_FUNCTIONS => {
+                let functions = guard.analysis().unused_public_functions();
+                Ok(serde_json::to_value(guard.symbol_informations(functions)).ok())
+            }
+            lsp_ext::HIGHLIGHT => {
+                let params: HighlightParams = parse_arg(&mut arguments, "highlight")?;
+                let tokens = guard
+                    .docs
+                    .get(&params.text_document.uri)
+                    .map(|doc| highlight::highlight(&doc));
+                match tokens {
+                    Some(tokens) => Ok(serde_json::to_value(tokens).ok()),
+                    None => Ok(Some(Value::String(format!(
+                        "{} is not opened",
+                        params.text_document.uri
+                    )))),
+                }
+            }
+            lsp_ext::EXPAND_ADDRESSES => {
+                let params: ExpandAddressesParams = parse_arg(&mut arguments, "expandAddresses")?;
+                let expanded = guard
+                    .docs
+                    .get(&params.text_document.uri)
+                    .map(|doc| expand::expand_addresses(&doc, guard.db.sender()));
+                match expanded {
+                    Some(Ok(content)) => {
+                        let ast = expand::expanded_ast(&content, guard.db.dialect());
+                        Ok(Some(json::json!({ "content": content, "ast": ast })))
+                    }
+                    Some(Err(e)) => Ok(Some(Value::String(format!("{}", e)))),
+                    None => Ok(Some(Value::String(format!(
+                        "{} is not opened",
+                        params.text_document.uri
+                    )))),
+                }
+            }
+            lsp_ext::ADDRESS_DECORATIONS => {
+                let params: AddressDecorationsParams =
+                    parse_arg(&mut arguments, "addressDecorations")?;
+                let decorations = guard.docs.get(&params.text_document.uri).map(|doc| {
+                    expand::address_names(&doc, &guard.config.addresses)
+                        .into_iter()
+                        .map(|(range, name)| AddressDecoration { range, name })
+                        .collect::<Vec<_>>()
+                });
+                match decorations {
+                    Some(decorations) => Ok(serde_json::to_value(decorations).ok()),
+                    None => Ok(Some(Value::String(format!(
+                        "{} is not opened",
+                        params.text_document.uri
+                    )))),
+                }
+            }
+            lsp_ext::LINKED_EDITING_RANGE => {
+                let params: TextDocumentPositionParams =
+                    parse_arg(&mut arguments, "linkedEditingRange")?;
+                let ranges = guard
+                    .docs
+                    .get(&params.text_document.uri)
+                    .and_then(|doc| rename::alias_ranges(&doc, params.position));
+                Ok(ranges.and_then(|ranges| {
+                    serde_json::to_value(LinkedEditingRanges {
+                        ranges,
+                        word_pattern: Some(LINKED_WORD_PATTERN.to_string()),
+                    })
+                    .ok()
+                }))
+            }
+            lsp_ext::INLAY_HINTS => {
+                let params: InlayHintParams = parse_arg(&mut arguments, "inlayHints")?;
+                let hints = guard
+                    .docs
+                    .get(&params.text_document.uri)
+                    .map(|doc| acquires::acquires_hints(&doc, params.range));
+                Ok(hints.and_then(|hints| serde_json::to_value(hints).ok()))
+            }
+            lsp_ext::MONIKER => {
+                let params: TextDocumentPositionParams = parse_arg(&mut arguments, "moniker")?;
+                let monikers = guard.monikers(&params.text_document.uri, params.position);
+                Ok(monikers.and_then(|m| serde_json::to_value(m).ok()))
+            }
+            lsp_ext::DOCUMENT_DIAGNOSTIC => {
+                let params: DocumentDiagnosticParams =
+                    parse_arg(&mut arguments, "documentDiagnostic")?;
+                match guard.document_diagnostic(params) {
+                    Ok(r) => Ok(serde_json::to_value(r).ok()),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::READ_FILE => {
+                let params: ReadFileParams = parse_arg(&mut arguments, "readFile")?;
+                if !guard.is_known_file(&params.uri) {
+                    return Err(jsonrpc::Error::invalid_params(format!(
+                        "{} is not a stdlib or project file",
+                        params.uri
+                    )));
+                }
+                match guard.read_file(&params.uri) {
+                    Ok(r) => Ok(serde_json::to_value(r).ok()),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::SET_LOG_FILTER => {
+                let params: SetLogFilterParams = parse_arg(&mut arguments, "setLogFilter")?;
+                match logging::set_filter(params.filter.as_str()) {
+                    Ok(()) => Ok(None),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::NEW_PROJECT => {
+                let params: NewProjectParams = parse_arg(&mut arguments, "newProject")?;
+                let root = match params.uri.to_file_path() {
+                    Ok(p) => p,
+                    Err(_) => {
+                        return Ok(Some(Value::String(format!(
+                            "{} is not a file uri",
+                            params.uri
+                        ))))
+                    }
+                };
+                match scaffold::new_project(&root, &params) {
+                    Ok(files) => Ok(serde_json::to_value(NewProjectResult {
+                        files: files
+                            .iter()
+                            .filter_map(|f| Url::from_file_path(f).ok())
+                            .collect(),
+                    })
+                    .ok()),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::NEW_MODULE => {
+                let params: NewModuleParams = parse_arg(&mut arguments, "newModule")?;
+                match guard.new_module(params) {
+                    Ok(path) => Ok(Url::from_file_path(&path)
+                        .ok()
+                        .and_then(|u| serde_json::to_value(u).ok())),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::GENERATE_DOCS => {
+                let params: GenerateDocsParams =
+                    parse_optional_arg(&mut arguments, "generateDocs")?;
+                match guard.generate_docs(params) {
+                    Ok(files) => Ok(serde_json::to_value(GenerateDocsResult {
+                        files: files
+                            .iter()
+                            .filter_map(|f| Url::from_file_path(f).ok())
+                            .collect(),
+                    })
+                    .ok()),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::TESTS => {
+                let params: TestsParams = parse_optional_arg(&mut arguments, "tests")?;
+                let uri = params.text_document.map(|d| d.uri);
+                Ok(serde_json::to_value(guard.tests(uri.as_ref())).ok())
+            }
+            lsp_ext::LOAD_COVERAGE => {
+                let params: LoadCoverageParams =
+                    parse_optional_arg(&mut arguments, "loadCoverage")?;
+                match guard.load_coverage(params) {
+                    Ok(()) => Ok(None),
+                    Err(e) => Ok(Some(Value::String(format!("{}", e)))),
+                }
+            }
+            lsp_ext::METRICS => Ok(serde_json::to_value(guard.metrics()).ok()),
+            lsp_ext::SERVER_INFO => Ok(serde_json::to_value(ServerInfoResult {
+                name: SERVER_NAME.to_string(),
+                build_info: guard.build_info.clone(),
+                grammar_abi_version: tree_sitter_move::abi_version(),
+                capabilities: server_capabilities(),
+            })
+            .ok()),
             _ => Ok(None),
         }
     }
@@ -202,21 +567,65 @@ impl LanguageServer for MoveLanguageServer {
             return;
         }
         let mut guard = self.inner.lock().await;
+        if guard.is_shut_down() {
+            return;
+        }
         guard.handle_file_open(params);
     }
 
     async fn did_change(&self, params: DidChangeTextDocumentParams) {
+        // the edit goes to the document right away, even while the server is busy checking.
+        // Changes queued meanwhile are then committed to the database at once.
+        // The open of the document may still wait for the lock, then it applies the edit.
+        let uri = params.text_document.uri.clone();
+        apply_document_changes(&self.docs, &self.pending_changes, params);
         let mut guard = self.inner.lock().await;
-        guard.handle_file_change(params);
+        if guard.is_shut_down() {
+            return;
+        }
+        guard.commit_document(&uri);
     }
 
     async fn did_save(&self, params: DidSaveTextDocumentParams) {
         let mut guard = self.inner.lock().await;
-        guard.handle_file_save(params);
+        if guard.is_shut_down() {
+            return;
+        }
+        let delay = guard.config.diagnostic_delay_ms;
+        if delay == 0 {
+            guard.handle_file_save(params);
+            return;
+        }
+        debug!("file saved: {:?}", &params);
+        guard.saved_documents.push(params.text_document.uri);
+        guard.save_generation += 1;
+        let generation = guard.save_generation;
+        drop(guard);
+        tokio::spawn(check_saved_documents(
+            self.inner.clone(),
+            generation,
+            Duration::from_millis(delay),
+        ));
+    }
+
+    async fn will_save(&self, params: WillSaveTextDocumentParams) {
+        debug!("file will be saved: {:?}", &params);
+    }
+
+    async fn will_save_wait_until(
+        &self,
+        params: WillSaveTextDocumentParams,
+    ) -> jsonrpc::Result<Option<Vec<TextEdit>>> {
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(inner.will_save_edits(&params.text_document.uri))
     }
 
     async fn did_close(&self, params: DidCloseTextDocumentParams) {
         let mut guard = self.inner.lock().await;
+        if guard.is_shut_down() {
+            return;
+        }
         guard.handle_file_close(params);
     }
     async fn goto_definition(
@@ -226,15 +635,113 @@ impl LanguageServer for MoveLanguageServer {
         let GotoDefinitionParams {
             text_document_position_params:
                 TextDocumentPositionParams {
-                    text_document: _,
-                    position: _,
+                    text_document,
+                    position,
                 },
             work_done_progress_params: _,
             partial_result_params: _,
         } = params;
 
-        error!("Got a textDocument/definition request, but it is not implemented");
-        Err(jsonrpc::Error::method_not_found())
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(inner
+            .goto_definition(&text_document.uri, position)
+            .map(GotoDefinitionResponse::Scalar))
+    }
+
+    async fn code_action(
+        &self,
+        params: CodeActionParams,
+    ) -> jsonrpc::Result<Option<CodeActionResponse>> {
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(Some(inner.code_actions(
+            &params.text_document.uri,
+            params.range,
+            params.context.diagnostics.as_slice(),
+        )))
+    }
+
+    async fn symbol(
+        &self,
+        params: WorkspaceSymbolParams,
+    ) -> jsonrpc::Result<Option<Vec<SymbolInformation>>> {
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        let symbols = inner.workspace_symbols(&params.query);
+        Ok(Some(inner.partial_results(
+            params.partial_result_params.partial_result_token,
+            symbols,
+        )))
+    }
+
+    async fn references(&self, params: ReferenceParams) -> jsonrpc::Result<Option<Vec<Location>>> {
+        let ReferenceParams {
+            text_document_position:
+                TextDocumentPositionParams {
+                    text_document,
+                    position,
+                },
+            context,
+            partial_result_params,
+            ..
+        } = params;
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        let locations = inner.references(&text_document.uri, position, context.include_declaration);
+        Ok(Some(inner.partial_results(
+            partial_result_params.partial_result_token,
+            locations,
+        )))
+    }
+
+    async fn prepare_rename(
+        &self,
+        params: TextDocumentPositionParams,
+    ) -> jsonrpc::Result<Option<PrepareRenameResponse>> {
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(inner
+            .prepare_rename(&params.text_document.uri, params.position)
+            .map(
+                |(range, placeholder)| PrepareRenameResponse::RangeWithPlaceholder {
+                    range,
+                    placeholder,
+                },
+            ))
+    }
+
+    async fn rename(&self, params: RenameParams) -> jsonrpc::Result<Option<WorkspaceEdit>> {
+        let RenameParams {
+            text_document_position:
+                TextDocumentPositionParams {
+                    text_document,
+                    position,
+                },
+            new_name,
+            ..
+        } = params;
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        inner
+            .rename(&text_document.uri, position, new_name.as_str())
+            .map_err(|e| jsonrpc::Error::invalid_params(format!("fail to rename, {}", e)))
+    }
+
+    async fn goto_implementation(
+        &self,
+        params: GotoImplementationParams,
+    ) -> jsonrpc::Result<Option<GotoImplementationResponse>> {
+        let TextDocumentPositionParams {
+            text_document,
+            position,
+        } = params.text_document_position_params;
+
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(inner
+            .goto_implementation(&text_document.uri, position)
+            .map(GotoImplementationResponse::Scalar))
     }
 
     async fn formatting(
@@ -247,22 +754,89 @@ impl LanguageServer for MoveLanguageServer {
             work_done_progress_params: _,
         } = params;
         let inner = self.inner.lock().await;
+        inner.ensure_running()?;
 
-        match inner.format_file(
-            text_document
-                .uri
-                .to_file_path()
-                .expect("url to be a file path")
-                .as_path(),
-            options,
-        ) {
-            Ok(s) => Ok(s.map(|t| vec![t])),
+        let file_id = VirtualPath::from_url(&text_document.uri).file_id();
+        match inner.format_file(file_id.as_path(), options) {
+            Ok(edits) => Ok(Some(edits)),
             Err(e) => {
                 error!("formatting failure: {}", e);
                 Ok(None)
             }
         }
     }
+    async fn completion(
+        &self,
+        params: CompletionParams,
+    ) -> jsonrpc::Result<Option<CompletionResponse>> {
+        let CompletionParams {
+            text_document_position:
+                TextDocumentPositionParams {
+                    text_document,
+                    position,
+                },
+            ..
+        } = params;
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(inner
+            .completion(&text_document.uri, position)
+            .map(CompletionResponse::Array))
+    }
+
+    async fn hover(&self, params: HoverParams) -> jsonrpc::Result<Option<Hover>> {
+        let HoverParams {
+            text_document_position_params:
+                TextDocumentPositionParams {
+                    text_document,
+                    position,
+                },
+            ..
+        } = params;
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        let hover = inner.docs.get(&text_document.uri).and_then(|doc| {
+            hover::address_hover(&doc, position, inner.db.sender())
+                .or_else(|| hover::builtin_hover(&doc, position, inner.db.dialect()))
+                .or_else(|| inner.definitions_hover(&doc, &text_document.uri, position))
+        });
+        Ok(hover)
+    }
+
+    async fn document_symbol(
+        &self,
+        params: DocumentSymbolParams,
+    ) -> jsonrpc::Result<Option<DocumentSymbolResponse>> {
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        Ok(inner
+            .document_symbols(&params.text_document.uri)
+            .map(DocumentSymbolResponse::Nested))
+    }
+
+    async fn code_lens(&self, params: CodeLensParams) -> jsonrpc::Result<Option<Vec<CodeLens>>> {
+        let CodeLensParams { text_document, .. } = params;
+        let inner = self.inner.lock().await;
+        inner.ensure_running()?;
+        let mut lenses = match inner.docs.get(&text_document.uri) {
+            Some(doc) => code_lens::code_lens(&doc, &text_document.uri),
+            None => return Ok(None),
+        };
+        // test scripts already have a "Run script" lens.
+        lenses.extend(
+            inner
+                .tests(Some(&text_document.uri))
+                .into_iter()
+                .filter(|t| t.kind == TestKind::Function && t.command.is_some())
+                .map(|t| CodeLens {
+                    range: t.location.range,
+                    command: t.command,
+                    data: None,
+                }),
+        );
+        Ok(Some(lenses))
+    }
+
     // async fn goto_declaration(
     //     &self,
     //     params: GotoDeclarationParams,
@@ -281,10 +855,46 @@ impl LanguageServer for MoveLanguageServer {
 pub struct Inner {
     db: RootDatabase,
     config: ProjectConfig,
-    docs: DashMap<Url, MoveDocument>,
+    docs: Arc<DashMap<Url, MoveDocument>>,
+    /// changes received before the open of their document, applied when it's handled.
+    pending_changes: Arc<DashMap<Url, Vec<DidChangeTextDocumentParams>>>,
+    /// version of each document whose text is in the database,
+    /// the one diagnostics computed from the database are published for.
+    committed_versions: HashMap<Url, u64>,
+    /// documents saved since the last check, when saves are delayed.
+    saved_documents: Vec<Url>,
+    /// bumped on every delayed save, only the check of the latest one runs.
+    save_generation: u64,
+    /// files left out of the project, as they are too large or not utf-8.
+    skipped_files: HashMap<PathBuf, SkipReason>,
     client: Client,
     file_watch_registration: uuid::Uuid,
+    /// watches the project folders if the client can't.
+    file_watcher: Option<FileWatcher>,
     client_capabilities: lsp_types::ClientCapabilities,
+    root_path: Option<PathBuf>,
+    /// the workspace root if the client doesn't support workspace folders.
+    workspace_folders: Vec<PathBuf>,
+    shut_down: bool,
+    published_diagnostics: PublishedDiagnostics,
+    /// files the errors of the last failed compilation are published on,
+    /// cleared by the next successful one.
+    compile_diagnostic_files: HashSet<Url>,
+    pulled_diagnostics: DashMap<Url, PulledDiagnostics>,
+    /// outlines of open documents, updated from the edits since they were computed.
+    outlines: DashMap<Url, Outline>,
+    next_result_id: u64,
+    build_info: BuildInfo,
+    /// how long the last check of the project took.
+    last_check_duration: parking_lot::Mutex<Option<Duration>>,
+}
+
+/// The last diagnostics a pull client received for a document.
+#[derive(Clone, Debug)]
+struct PulledDiagnostics {
+    revision: salsa::Revision,
+    result_id: String,
+    diagnostics: Vec<Diagnostic>,
 }
 
 fn _assert_object_safe() {
@@ -301,88 +911,139 @@ pub struct ConfChange(pub ProjectConfig);
 
 impl Inner {
     async fn initialize(&mut self, params: InitializeParams) -> Result<InitializeResult> {
+        let span = info_span!("initialize");
+        let _enter = span.enter();
         let InitializeParams {
             initialization_options,
             capabilities,
+            trace,
+            root_uri,
+            workspace_folders,
             ..
         } = params;
         self.client_capabilities = capabilities;
+        self.root_path = root_uri.and_then(|u| u.to_file_path().ok());
+        self.workspace_folders = match workspace_folders {
+            Some(folders) => folders
+                .into_iter()
+                .filter_map(|f| f.uri.to_file_path().ok())
+                .collect(),
+            None => self.root_path.iter().cloned().collect(),
+        };
+        self.db.set_project_roots(self.workspace_folders.clone());
+        logging::set_trace(trace.unwrap_or(TraceOption::Off));
 
-        if let Some(initial_config) = initialization_options {
-            let conf = serde_json::from_value(initial_config)
+        // initialization options are the client settings of every folder.
+        if initialization_options.is_some() || !self.workspace_folders.is_empty() {
+            let settings = initialization_options.unwrap_or_default();
+            let settings = vec![settings; self.folder_scopes().len()];
+            let conf = self
+                .load_config(settings)
                 .map_err(|e| jsonrpc::Error::invalid_params(format!("invalid config, {}", e)))?;
             self.handle_config_change(conf);
         }
 
         Ok(InitializeResult {
             server_info: Some(ServerInfo {
-                name: "move language server".to_string(),
-                version: None,
+                name: SERVER_NAME.to_string(),
+                version: Some(self.build_info.display_version()),
             }),
-            capabilities: ServerCapabilities {
-                text_document_sync: Some(TextDocumentSyncCapability::Options(
-                    TextDocumentSyncOptions {
-                        open_close: Some(true),
-                        change: Some(TextDocumentSyncKind::Incremental),
-                        save: Some(lsp_types::TextDocumentSyncSaveOptions::SaveOptions(
-                            SaveOptions {
-                                include_text: Some(false),
-                            },
-                        )),
-                        ..Default::default()
-                    },
-                )),
-                workspace: Some(WorkspaceCapability {
-                    workspace_folders: Some(WorkspaceFolderCapability {
-                        supported: Some(false),
-                        change_notifications: None,
-                    }),
-                }),
-                execute_command_provider: Some(ExecuteCommandOptions {
-                    commands: vec!["compile".to_string()],
-                    work_done_progress_options: WorkDoneProgressOptions {
-                        work_done_progress: Some(true),
-                    },
-                }),
-                document_formatting_provider: Some(true),
-                ..ServerCapabilities::default()
-            },
+            capabilities: server_capabilities(),
         })
     }
 
-    async fn register_file_watch(&mut self) {
+    fn metrics(&self) -> Metrics {
+        let stdlib_files = self.db.stdlib_files();
+        let module_files = self.db.module_files();
+        let script_files = self.db.script_files();
+        let interned_size = stdlib_files
+            .iter()
+            .chain(module_files.iter())
+            .chain(script_files.iter())
+            .map(|f| f.as_os_str().len())
+            .sum();
+        Metrics {
+            stdlib_files: stdlib_files.len(),
+            module_files: module_files.len(),
+            script_files: script_files.len(),
+            open_documents: self.docs.len(),
+            revision: format!("{:?}", self.db.salsa_runtime().current_revision()),
+            documents_size: self.docs.iter().map(|d| d.doc().rope().len()).sum(),
+            interned_size,
+            last_check_millis: self
+                .last_check_duration
+                .lock()
+                .map(|d| d.as_millis() as u64),
+        }
+    }
+
+    /// Whether the client watches files on behalf of the server.
+    fn dynamic_file_watch(&self) -> bool {
+        self.client_capabilities
+            .workspace
+            .as_ref()
+            .and_then(|w| w.did_change_watched_files.as_ref())
+            .and_then(|c| c.dynamic_registration)
+            .unwrap_or(false)
+    }
+
+    /// Watch the project folders with the server watcher if there is one, or else by
+    /// registering `workspace/didChangeWatchedFiles` if the client supports it.
+    async fn register_file_watch(&mut self) -> Result<()> {
         let inner = self;
+        if let Some(watcher) = inner.file_watcher.as_mut() {
+            let folders = inner
+                .config
+                .stdlib_folder
+                .iter()
+                .chain(inner.config.modules_folders.iter())
+                .chain(inner.config.scripts_folders.iter())
+                .cloned()
+                .collect();
+            watcher.watch(folders);
+            return Ok(());
+        }
+        if !inner.dynamic_file_watch() {
+            return Ok(());
+        }
 
         // unregister old
         if !inner.file_watch_registration.is_nil() {
-            inner
+            let unregistered = inner
                 .client
                 .unregister_capability(vec![Unregistration {
                     id: inner.file_watch_registration.to_string(),
                     method: lsp_types::notification::DidChangeWatchedFiles::METHOD.to_string(),
                 }])
-                .await
-                .expect("should unregister didChangeWatchedFiles");
+                .await;
+            // the registration is replaced below all the same.
+            if let Err(e) = unregistered {
+                warn!("fail to unregister didChangeWatchedFiles, {}", e);
+            }
+            inner.file_watch_registration = uuid::Uuid::nil();
         }
 
         let mut file_watchers = vec![];
-        if let Some(stdlib) = inner.config.stdlib_folder.as_ref() {
-            let w = lsp_types::FileSystemWatcher {
-                glob_pattern: stdlib.join("**/*.move").display().to_string(),
+        for folder in inner
+            .config
+            .stdlib_folder
+            .iter()
+            .chain(inner.config.modules_folders.iter())
+            .chain(inner.config.scripts_folders.iter())
+        {
+            file_watchers.push(lsp_types::FileSystemWatcher {
+                glob_pattern: folder.join("**/*.move").display().to_string(),
                 kind: Some(
                     lsp_types::WatchKind::Create
                         | lsp_types::WatchKind::Delete
                         | lsp_types::WatchKind::Change,
                 ),
-            };
-            file_watchers.push(w);
-        }
-        for module_folder in inner.config.modules_folders.as_slice() {
-            let w = lsp_types::FileSystemWatcher {
-                glob_pattern: module_folder.join("**/*.move").display().to_string(),
+            });
+            // renamed, created and deleted sub folders, their files get no events of their own.
+            file_watchers.push(lsp_types::FileSystemWatcher {
+                glob_pattern: folder.join("**").display().to_string(),
                 kind: Some(lsp_types::WatchKind::Create | lsp_types::WatchKind::Delete),
-            };
-            file_watchers.push(w);
+            });
         }
 
         let registration_options = DidChangeWatchedFilesRegistrationOptions {
@@ -393,7 +1054,6 @@ impl Inner {
             &uuid::Uuid::NAMESPACE_URL,
             lsp_types::notification::DidChangeWatchedFiles::METHOD.as_bytes(),
         );
-        inner.file_watch_registration = new_registration_id;
         inner
             .client
             .register_capability(vec![Registration {
@@ -401,92 +1061,435 @@ impl Inner {
                 method: lsp_types::notification::DidChangeWatchedFiles::METHOD.to_string(),
                 register_options: serde_json::to_value(registration_options).ok(),
             }])
-            .await
-            .expect("should register didChangeWatchedFiles");
+            .await?;
+        inner.file_watch_registration = new_registration_id;
+        Ok(())
     }
 
-    fn handle_config_change(&mut self, new_config: ProjectConfig) {
-        debug!("server config change to: {:?}", &new_config);
-
-        self.config = new_config.clone();
+    /// Every handler runs with the lock held, so once this returns no work is in flight,
+    /// and the flag stops any further work from starting.
+    fn shutdown(&mut self) {
+        self.shut_down = true;
+        self.file_watcher = None;
+        for doc in self.docs.iter() {
+            self.client
+                .publish_diagnostics(doc.key().clone(), vec![], None);
+        }
+        self.docs.clear();
+        self.published_diagnostics.clear();
+        self.pulled_diagnostics.clear();
+        self.outlines.clear();
+        self.send_status(ServerStatus::Idle, None);
+    }
 
-        let stdlib_files = new_config
-            .stdlib_folder
-            .map(find_move_file)
-            .unwrap_or_default();
-        let module_files: Vec<_> = new_config
-            .modules_folders
-            .into_iter()
-            .flat_map(find_move_file)
-            .collect();
+    fn is_shut_down(&self) -> bool {
+        self.shut_down
+    }
 
-        self.db
-            .set_stdlib_files_with_durability(stdlib_files.clone(), salsa::Durability::HIGH);
-        self.db
-            .set_module_files_with_durability(module_files.clone(), salsa::Durability::HIGH);
-        self.db
-            .set_sender_with_durability(new_config.sender_address, salsa::Durability::HIGH);
+    /// Requests after `shutdown` are invalid.
+    fn ensure_running(&self) -> jsonrpc::Result<()> {
+        if self.shut_down {
+            Err(jsonrpc::Error::invalid_request())
+        } else {
+            Ok(())
+        }
     }
 
-    fn handle_watched_files_change(&mut self, changes: Vec<lsp_types::FileEvent>) {
-        let inner = self;
-        for lsp_types::FileEvent { uri, typ } in changes {
-            let fp = uri.to_file_path().expect("uri to be a file path");
+    /// Config scopes: every workspace folder, or the whole workspace if there is no folder.
+    fn folder_scopes(&self) -> Vec<Option<&Path>> {
+        if self.workspace_folders.is_empty() {
+            vec![None]
+        } else {
+            self.workspace_folders
+                .iter()
+                .map(|f| Some(f.as_path()))
+                .collect()
+        }
+    }
 
-            let is_stdlib_file = inner
-                .config
-                .stdlib_folder
-                .as_ref()
-                .filter(|stdlib_folder| fp.starts_with(stdlib_folder))
-                .is_some();
+    /// Fetch the `move` settings of every workspace folder from the client,
+    /// and merge them with the folder manifests.
+    async fn fetch_config(&self) -> Result<ProjectConfig> {
+        let scopes = self.folder_scopes();
+        let items: Vec<_> = scopes
+            .iter()
+            .map(|folder| ConfigurationItem {
+                scope_uri: folder.and_then(|f| Url::from_file_path(f).ok()),
+                section: Some(CONFIG_SECTION.to_string()),
+            })
+            .collect();
+        let settings = self.client.configuration(items).await?;
+        if settings.len() != scopes.len() {
+            bail!(
+                "client respond {} config items, expect {}",
+                settings.len(),
+                scopes.len()
+            );
+        }
+        self.load_config(settings)
+    }
 
-            if matches!(typ, lsp_types::FileChangeType::Changed) {
-                inner.db.did_change(fp.as_path());
-                return;
+    /// Config of the workspace from the client settings of every folder scope, in order.
+    /// Folders whose `Move.toml` is broken are reported, and use the client settings only.
+    fn load_config(&self, settings: Vec<Value>) -> Result<ProjectConfig> {
+        let mut configs = vec![];
+        for (folder, s) in self.folder_scopes().into_iter().zip(settings) {
+            let (config, manifest_error) = ProjectConfig::load_lenient(folder, s)?;
+            if let Some(e) = manifest_error {
+                warn!("{}", e);
+                self.client.show_message(
+                    MessageType::Warning,
+                    format!("{} is ignored: {}", MANIFEST_FILE, e),
+                );
             }
+            configs.push(config);
+        }
+        Ok(ProjectConfig::combine(configs))
+    }
 
-            let mut files = if is_stdlib_file {
-                inner.db.stdlib_files()
-            } else {
-                inner.db.module_files()
-            };
-            let mut files_updated = false;
-            match typ {
-                lsp_types::FileChangeType::Created => {
-                    if !files.contains(&fp) {
-                        files.push(fp);
-                        files_updated = true;
-                    }
-                }
-                lsp_types::FileChangeType::Deleted => {
-                    if let Some(idx) = files.iter().position(|x| x == &fp) {
-                        files.remove(idx);
-                        files_updated = true;
-                    }
+    /// Replace the workspace folders, the config is reloaded by the caller.
+    fn change_workspace_folders(&mut self, event: WorkspaceFoldersChangeEvent) {
+        let removed: Vec<PathBuf> = event
+            .removed
+            .into_iter()
+            .filter_map(|f| f.uri.to_file_path().ok())
+            .collect();
+        self.workspace_folders.retain(|f| !removed.contains(f));
+        for folder in event.added {
+            if let Ok(path) = folder.uri.to_file_path() {
+                if !self.workspace_folders.contains(&path) {
+                    self.workspace_folders.push(path);
                 }
-                lsp_types::FileChangeType::Changed => {}
-            }
-
-            if !files_updated {
-                return;
             }
-
-            if is_stdlib_file {
-                inner
-                    .db
-                    .set_stdlib_files_with_durability(files, salsa::Durability::HIGH);
-            } else {
-                inner
-                    .db
-                    .set_module_files_with_durability(files, salsa::Durability::HIGH);
-            }
-            inner.diagnose_with_optional_file(None);
         }
+        self.db.set_project_roots(self.workspace_folders.clone());
     }
 
-    fn handle_file_open(&mut self, param: DidOpenTextDocumentParams) {
-        debug!("file opened: {:?}", &param);
-        let DidOpenTextDocumentParams {
+    fn send_status(&self, status: ServerStatus, message: Option<String>) {
+        self.client
+            .send_custom_notification::<Status>(StatusParams { status, message });
+    }
+
+    fn handle_config_change(&mut self, new_config: ProjectConfig) {
+        debug!("server config change to: {:?}", &new_config);
+        self.send_status(ServerStatus::Indexing, None);
+        logging::set_level(new_config.log_level.as_deref());
+
+        let problems = new_config.problems();
+        if !problems.is_empty() {
+            let problems: Vec<_> = problems.iter().map(|p| p.to_string()).collect();
+            warn!("config problems: {}", problems.join("; "));
+            self.client.show_message(
+                MessageType::Warning,
+                format!("Move project files may be missing: {}", problems.join("; ")),
+            );
+        }
+        self.client
+            .send_custom_notification::<ConfigStatus>(ConfigStatusParams { problems });
+
+        self.skipped_files = analysis::apply_config(&mut self.db, &new_config)
+            .into_iter()
+            .collect();
+        self.config = new_config;
+        // index the modules of the new files now, instead of in the next request.
+        self.db.project_modules();
+        if self.pull_diagnostics() {
+            self.send_status(ServerStatus::Idle, None);
+        } else {
+            // reports `Idle` once the open documents are checked.
+            self.diagnose_open_documents();
+        }
+    }
+
+    /// Create a module file from the template, and add it to the project
+    /// without waiting for the file watcher.
+    fn new_module(&mut self, params: NewModuleParams) -> Result<PathBuf> {
+        let folder = match params.folder {
+            Some(uri) => match uri.to_file_path() {
+                Ok(p) => p,
+                Err(_) => bail!("{} is not a file uri", uri),
+            },
+            None => match self.config.modules_folders.first() {
+                Some(f) => f.clone(),
+                None => bail!("no modules folder is configured"),
+            },
+        };
+        let address = scaffold::module_address(
+            params.address.as_deref(),
+            self.config.sender_address,
+            &self.config.addresses,
+        )?;
+        let path = scaffold::new_module(&folder, params.name.as_str(), address.as_str())?;
+
+        let fp = canonicalize(&path);
+        let mut module_files = self.db.module_files();
+        if !module_files.contains(&fp) {
+            module_files.push(fp);
+            self.db
+                .set_module_files_with_durability(module_files, salsa::Durability::HIGH);
+        }
+        self.diagnose_open_documents();
+        Ok(path)
+    }
+
+    /// Write the docs of the modules of module files, stdlib modules are only linked.
+    fn generate_docs(&self, params: GenerateDocsParams) -> Result<Vec<PathBuf>> {
+        let out_dir = match params.out_dir {
+            Some(uri) => match uri.to_file_path() {
+                Ok(p) => p,
+                Err(_) => bail!("{} is not a file uri", uri),
+            },
+            None => match &self.root_path {
+                Some(root) => root.join(docgen::DOCS_DIR),
+                None => bail!("no workspace folder to write docs into"),
+            },
+        };
+        let module_files = self.db.module_files();
+        let project_modules = self.db.project_modules();
+        let modules: Vec<_> = project_modules
+            .iter()
+            .filter(|(f, _)| module_files.contains(f))
+            .collect();
+
+        let mut files = HashMap::new();
+        for (file, _) in modules.iter() {
+            if files.contains_key(file) {
+                continue;
+            }
+            match self.db.ast(file.clone()) {
+                Ok(ast) => {
+                    files.insert(file, (ast, self.db.source_text(file.clone())));
+                }
+                Err(_) => warn!("no docs of {}, it doesn't parse", file.display()),
+            }
+        }
+        let sources: Vec<_> = modules
+            .iter()
+            .filter_map(|(file, decl)| {
+                let (ast, source) = files.get(file)?;
+                Some(docgen::ModuleSource {
+                    decl,
+                    module: goto_definition::find_module(&ast.defs, decl.key.name.as_str())?,
+                    source: source.as_str(),
+                    doc_comments: &ast.doc_comments,
+                })
+            })
+            .collect();
+        let documented = sources.iter().map(|s| s.decl.key.clone()).collect();
+
+        std::fs::create_dir_all(&out_dir)?;
+        let mut written = vec![];
+        for source in sources.iter() {
+            let path = out_dir.join(docgen::doc_file_name(&source.decl.key));
+            std::fs::write(&path, docgen::module_markdown(source, &documented))?;
+            written.push(path);
+        }
+        let index = out_dir.join(docgen::INDEX_FILE);
+        std::fs::write(&index, docgen::index_markdown(&sources))?;
+        written.push(index);
+        Ok(written)
+    }
+
+    /// Apply a batch of file events, the project is checked once at the end if anything changed.
+    /// A renamed file or folder is deleted at its old path and created at the new one,
+    /// the files of a created folder are added, those under a deleted one removed.
+    /// Returns the renames of the modules named after the renamed files, for the client to apply.
+    fn handle_watched_files_change(
+        &mut self,
+        changes: Vec<lsp_types::FileEvent>,
+    ) -> Option<WorkspaceEdit> {
+        let inner = self;
+        let stdlib_folder = inner.config.stdlib_folder.as_deref().map(canonicalize);
+        let scripts_folders: Vec<_> = inner
+            .config
+            .scripts_folders
+            .iter()
+            .map(|f| canonicalize(f))
+            .collect();
+        let folder_stdlibs: Vec<_> = inner
+            .config
+            .folder_stdlibs
+            .values()
+            .map(|f| canonicalize(f))
+            .collect();
+        let mut stdlib_files = inner.db.stdlib_files();
+        let mut module_files = inner.db.module_files();
+        let mut script_files = inner.db.script_files();
+        let (mut stdlib_updated, mut modules_updated, mut scripts_updated) = (false, false, false);
+        let mut content_changed = false;
+        let mut folder_stdlibs_updated = false;
+
+        let changes = coalesce_file_events(changes);
+        let renamed = renamed_files(&changes);
+        for (fp, typ) in changes {
+            if folder_stdlibs.iter().any(|f| fp.starts_with(f)) {
+                match typ {
+                    lsp_types::FileChangeType::Changed if inner.db.has_source(&fp) => {}
+                    // the stdlib files are collected again when one is skipped or not anymore.
+                    lsp_types::FileChangeType::Changed
+                        if check_move_file(&fp, inner.config.max_file_size).is_err()
+                            != inner.skipped_files.contains_key(&fp) =>
+                    {
+                        folder_stdlibs_updated = true
+                    }
+                    lsp_types::FileChangeType::Changed => {
+                        inner.db.did_change(fp.as_path());
+                        content_changed = true;
+                    }
+                    _ => folder_stdlibs_updated = true,
+                }
+                continue;
+            }
+            let is_stdlib_file = stdlib_folder
+                .as_ref()
+                .filter(|stdlib_folder| fp.starts_with(stdlib_folder))
+                .is_some();
+            let is_script_file = scripts_folders.iter().any(|f| fp.starts_with(f));
+            let (files, updated) = if is_stdlib_file {
+                (&mut stdlib_files, &mut stdlib_updated)
+            } else if is_script_file {
+                (&mut script_files, &mut scripts_updated)
+            } else {
+                (&mut module_files, &mut modules_updated)
+            };
+            match typ {
+                // open documents keep their in-memory content, their size is checked on commit.
+                lsp_types::FileChangeType::Changed if inner.db.has_source(&fp) => {}
+                lsp_types::FileChangeType::Changed => {
+                    // the file may have grown over `max_file_size`, or shrunk below it.
+                    match check_move_file(&fp, inner.config.max_file_size) {
+                        Err(reason) => {
+                            warn!("{}: {}", fp.display(), reason);
+                            let count = files.len();
+                            files.retain(|f| *f != fp);
+                            if files.len() != count {
+                                *updated = true;
+                            }
+                            inner.skipped_files.insert(fp, reason);
+                        }
+                        Ok(()) => {
+                            if inner.skipped_files.remove(&fp).is_some() && !files.contains(&fp) {
+                                files.push(fp.clone());
+                                *updated = true;
+                            }
+                            inner.db.did_change(fp.as_path());
+                            content_changed = true;
+                        }
+                    }
+                }
+                // deleted and created again, e.g. by a git checkout.
+                lsp_types::FileChangeType::Created if files.contains(&fp) => {
+                    inner.db.did_change(fp.as_path());
+                    content_changed = true;
+                }
+                lsp_types::FileChangeType::Created => {
+                    let created = if fp.is_dir() {
+                        find_move_file(&fp, inner.config.respect_gitignore)
+                            .iter()
+                            .map(|f| canonicalize(f))
+                            .filter(|f| !files.contains(f))
+                            .collect()
+                    } else if fp.extension().map_or(false, |e| e == MOVE_EXTENSION) {
+                        vec![fp]
+                    } else {
+                        vec![]
+                    };
+                    for fp in created {
+                        if let Err(reason) = check_move_file(&fp, inner.config.max_file_size) {
+                            warn!("{}: {}", fp.display(), reason);
+                            inner.skipped_files.insert(fp, reason);
+                            continue;
+                        }
+                        files.push(fp);
+                        *updated = true;
+                    }
+                }
+                lsp_types::FileChangeType::Deleted => {
+                    inner.skipped_files.retain(|f, _| !f.starts_with(&fp));
+                    let count = files.len();
+                    files.retain(|f| !f.starts_with(&fp));
+                    if files.len() != count {
+                        *updated = true;
+                    }
+                }
+            }
+        }
+
+        if stdlib_updated {
+            inner
+                .db
+                .set_stdlib_files_with_durability(stdlib_files, salsa::Durability::HIGH);
+        }
+        if modules_updated {
+            inner
+                .db
+                .set_module_files_with_durability(module_files, salsa::Durability::HIGH);
+        }
+        if scripts_updated {
+            inner
+                .db
+                .set_script_files_with_durability(script_files, salsa::Durability::HIGH);
+        }
+        if folder_stdlibs_updated {
+            // the files of other stdlib versions are collected again.
+            let config = inner.config.clone();
+            inner.handle_config_change(config);
+        }
+        // the config change checks the open documents itself.
+        if !folder_stdlibs_updated
+            && (stdlib_updated || modules_updated || scripts_updated || content_changed)
+        {
+            inner.diagnose_open_documents();
+        }
+        inner.module_renames(renamed)
+    }
+
+    /// A module named after its file follows the file when it's renamed:
+    /// module `M` of `M.move` renamed to `N.move` is renamed to `N`, along with its references.
+    fn module_renames(&self, renamed: Vec<(PathBuf, PathBuf)>) -> Option<WorkspaceEdit> {
+        let mut changes = vec![];
+        for (old, new) in renamed {
+            let (old_name, new_name) = match (file_stem(&old), file_stem(&new)) {
+                (Some(o), Some(n)) if o != n && rename::is_identifier(n) => (o, n),
+                _ => continue,
+            };
+            let key = match self
+                .db
+                .file_modules(new.clone())
+                .iter()
+                .find(|m| m.key.name == old_name)
+            {
+                Some(m) => m.key.clone(),
+                None => continue,
+            };
+            let uri = match Url::from_file_path(&new) {
+                Ok(u) => u,
+                Err(_) => continue,
+            };
+            match self.rename_module(&uri, key, new_name) {
+                Ok(Some(WorkspaceEdit {
+                    document_changes: Some(DocumentChanges::Edits(edits)),
+                    ..
+                })) => changes.extend(edits),
+                Ok(_) => {}
+                Err(e) => warn!("module {} is not renamed after its file, {}", old_name, e),
+            }
+        }
+        if changes.is_empty() {
+            None
+        } else {
+            Some(WorkspaceEdit {
+                document_changes: Some(DocumentChanges::Edits(changes)),
+                ..Default::default()
+            })
+        }
+    }
+
+    fn handle_file_open(&mut self, param: DidOpenTextDocumentParams) {
+        let span = info_span!("did_open", uri = %param.text_document.uri);
+        let _enter = span.enter();
+        debug!("file opened: {:?}", &param);
+        let DidOpenTextDocumentParams {
             text_document:
                 TextDocumentItem {
                     language_id: _,
@@ -496,36 +1499,91 @@ impl Inner {
                 },
         } = param;
         let doc = MoveDocument::new(version as u64, text.as_str());
-        if let Ok(p) = uri.to_file_path() {
-            self.db.update_source(p.clone(), doc.doc().rope().clone());
-            self.diagnose_with_optional_file(Some(p));
+        open_document(&self.docs, &self.pending_changes, uri.clone(), doc);
+        self.committed_versions.remove(&uri);
+        self.commit_document(&uri);
+    }
+
+    /// Put the latest text of `uri` into the database and check it,
+    /// unless that version is committed already.
+    fn commit_document(&mut self, uri: &Url) {
+        let span = info_span!("commit_document", uri = %uri);
+        let _enter = span.enter();
+        let (version, rope) = match self.docs.get(uri) {
+            Some(d) => (d.doc().version(), d.doc().rope().clone()),
+            None => return,
+        };
+        if self.committed_versions.get(uri) == Some(&version) {
+            debug!("version {} is committed already", version);
+            return;
+        }
+        self.committed_versions.insert(uri.clone(), version);
+        let path = VirtualPath::from_url(uri);
+        if let Some(reason) = self.skipped_reason(&rope) {
+            // the file on disk may fit, but the buffer is the text which is checked.
+            if let VirtualPath::File(p) = &path {
+                self.skipped_files.insert(canonicalize(p), reason.clone());
+            }
+            // the parser may hang on it, only tell why it's not checked.
+            if !self.pull_diagnostics() {
+                let revision = self.db.salsa_runtime().current_revision();
+                let diags = vec![skipped_file_diagnostic(&reason)];
+                self.publish_document_diagnostics(revision, uri.clone(), diags, version);
+            }
+            return;
+        }
+        if let VirtualPath::File(p) = &path {
+            let fp = canonicalize(p);
+            if self.skipped_files.contains_key(&fp) {
+                self.restore_skipped_file(fp);
+            }
         }
-        self.docs.insert(uri.clone(), doc);
+        self.db.update_source(path.file_id(), rope);
+        self.diagnose_document(uri, &path);
     }
 
-    fn handle_file_change(&mut self, param: DidChangeTextDocumentParams) {
-        debug!("file changed: {:?}", &param);
-        let DidChangeTextDocumentParams {
-            text_document,
-            content_changes,
-        } = param;
+    /// Why a document with content `rope` is not checked, if it isn't.
+    /// The content of the editor is utf-8 text, only its size matters.
+    fn skipped_reason(&self, rope: &Rope) -> Option<SkipReason> {
+        let limit = self.config.max_file_size;
+        if limit != 0 && rope.len() as u64 > limit {
+            return Some(SkipReason::TooLarge {
+                size: rope.len() as u64,
+                limit,
+            });
+        }
+        None
+    }
 
-        if let Some(mut doc) = self.docs.get_mut(&text_document.uri) {
-            // incremental edit
-            let changes = content_changes
-                .into_iter()
-                .map(|change| (change.range.unwrap(), change.text));
-            doc.edit_many(text_document.version.unwrap() as u64, changes);
+    /// Check the skipped file `fp` again with the files of its folder,
+    /// its open document fits in `max_file_size` although the file on disk may not.
+    fn restore_skipped_file(&mut self, fp: PathBuf) {
+        let in_folder = |folder: &PathBuf| fp.starts_with(canonicalize(folder));
+        // the stdlib versions are collected from disk, the file stays skipped until it fits.
+        if self.config.folder_stdlibs.values().any(in_folder) {
+            return;
         }
-        if let Some(rope) = self
-            .docs
-            .get(&text_document.uri)
-            .map(|d| d.doc().rope().clone())
-        {
-            if let Ok(p) = text_document.uri.to_file_path() {
-                self.db.update_source(p.clone(), rope);
-                // recheck diagnostics
-                self.diagnose_with_optional_file(Some(p));
+        self.skipped_files.remove(&fp);
+        if self.config.stdlib_folder.iter().any(in_folder) {
+            let mut files = self.db.stdlib_files();
+            if !files.contains(&fp) {
+                files.push(fp);
+                self.db
+                    .set_stdlib_files_with_durability(files, salsa::Durability::HIGH);
+            }
+        } else if self.config.scripts_folders.iter().any(in_folder) {
+            let mut files = self.db.script_files();
+            if !files.contains(&fp) {
+                files.push(fp);
+                self.db
+                    .set_script_files_with_durability(files, salsa::Durability::HIGH);
+            }
+        } else if self.config.modules_folders.iter().any(in_folder) {
+            let mut files = self.db.module_files();
+            if !files.contains(&fp) {
+                files.push(fp);
+                self.db
+                    .set_module_files_with_durability(files, salsa::Durability::HIGH);
             }
         }
     }
@@ -534,100 +1592,1334 @@ impl Inner {
         debug!("file closed: {:?}", &param);
         let DidCloseTextDocumentParams { text_document } = param;
         self.docs.remove(&text_document.uri);
-        if let Ok(p) = text_document.uri.to_file_path() {
-            self.db.close_source(p);
+        self.pending_changes.remove(&text_document.uri);
+        self.committed_versions.remove(&text_document.uri);
+        self.published_diagnostics.remove(&text_document.uri);
+        self.pulled_diagnostics.remove(&text_document.uri);
+        self.outlines.remove(&text_document.uri);
+        let path = VirtualPath::from_url(&text_document.uri);
+        self.db.close_source(path.file_id());
+        if path.is_virtual() {
+            self.client
+                .publish_diagnostics(text_document.uri, vec![], None);
         }
     }
 
     fn handle_file_save(&mut self, param: DidSaveTextDocumentParams) {
+        let span = info_span!("did_save", uri = %param.text_document.uri);
+        let _enter = span.enter();
         debug!("file saved: {:?}", &param);
         let DidSaveTextDocumentParams { text_document } = param;
-        let source_path = text_document.uri.to_file_path().unwrap();
+        let path = VirtualPath::from_url(&text_document.uri);
 
-        self.diagnose_with_optional_file(Some(source_path));
+        self.diagnose_document(&text_document.uri, &path);
+        self.compile_on_save(&text_document.uri);
     }
 
-    fn diagnose_with_optional_file(&self, additional: Option<PathBuf>) {
-        let (sources, result) = match additional {
-            None => self.db.check_all(None),
-            Some(fp) => self.db.check_file(None, fp),
+    /// Compile the saved module or script file `uri` if `compile_on_save` is set, and send
+    /// the artifacts to the client. Files whose sources are unchanged since their last
+    /// compilation are not compiled again, files whose diagnostics have errors aren't compiled.
+    fn compile_on_save(&mut self, uri: &Url) {
+        if !self.config.compile_on_save {
+            return;
+        }
+        let file = match VirtualPath::from_url(uri) {
+            VirtualPath::File(p) => canonicalize(&p),
+            VirtualPath::Virtual(_) => return,
         };
+        if !self.db.module_files().contains(&file) && !self.db.script_files().contains(&file) {
+            return;
+        }
+        // the unit was just checked, its errors are the ones of the diagnostics.
+        let (_, result) = self.db.check_unit(file.clone());
         let errors = result.err().unwrap_or_default();
-        self.publish_diagnostics(sources, errors);
+        // error files are named as they were loaded, `file` is canonical.
+        let has_errors = errors.iter().any(|e| {
+            e.first().map_or(false, |(loc, _)| {
+                canonicalize(Path::new(loc.file())) == file
+            })
+        });
+        if has_errors {
+            debug!("{} has errors, it's not compiled", file.display());
+            return;
+        }
+        let args = CompilationArgs {
+            file: uri.clone(),
+            out_dir: None,
+            force: false,
+        };
+        match self.do_compilation(None, args) {
+            Ok(Some(result)) if result.errors.is_empty() => {
+                self.client
+                    .send_custom_notification::<Compiled>(CompiledParams {
+                        uri: uri.clone(),
+                        manifest: result.manifest,
+                    });
+            }
+            Ok(_) => {}
+            Err(e) => error!("fail to compile {} on save, {}", file.display(), e),
+        }
     }
 
-    fn publish_diagnostics(&self, sources: FilesSourceText, errs: Errors) {
-        let mut diags = to_diagnostics(sources, errs);
+    /// Check the documents saved during the delay.
+    /// Several real files are checked together by a single `diagnose_open_documents`.
+    fn diagnose_saved_documents(&mut self) {
+        let saved: Vec<_> = self.saved_documents.drain(..).unique().collect();
+        let span = info_span!("did_save", documents = saved.len());
+        let _enter = span.enter();
+        let (files, virtuals): (Vec<_>, Vec<_>) = saved
+            .into_iter()
+            .map(|uri| {
+                let path = VirtualPath::from_url(&uri);
+                (uri, path)
+            })
+            .partition(|(_, path)| matches!(path, VirtualPath::File(_)));
+        for (uri, path) in virtuals {
+            self.diagnose_document(&uri, &path);
+        }
+        match files.as_slice() {
+            [] => {}
+            [(uri, path)] => self.diagnose_document(uri, path),
+            _ => self.diagnose_open_documents(),
+        }
+        for (uri, _) in files {
+            self.compile_on_save(&uri);
+        }
+    }
 
-        for f in self.docs.iter() {
-            let (doc, version) = (f.key(), f.doc().version());
-
-            debug!("publish diagnostic for {}", doc.path());
-
-            let diag = if let Some(diag) = diags.remove(doc.path()) {
-                // let file_url = Url::from_file_path(PathBuf::from_str(fname).unwrap()).unwrap();
-                diag.into_iter()
-                    .map(|d| {
-                        let DiagnosticInfo {
-                            primary_label,
-                            secondary_labels,
-                        } = d;
-                        let related_infos: Vec<_> = secondary_labels
-                            .into_iter()
-                            .map(|l| {
-                                let url = Url::from_file_path(PathBuf::from_str(l.file).unwrap())
-                                    .unwrap();
-                                DiagnosticRelatedInformation {
-                                    location: Location::new(url, l.range),
-                                    message: l.msg,
-                                }
-                            })
-                            .collect();
-                        Diagnostic {
-                            range: primary_label.range,
-                            severity: Some(DiagnosticSeverity::Error),
-                            message: primary_label.msg,
-                            related_information: Some(related_infos),
-                            ..Default::default()
-                        }
+    /// Edits to apply before `uri` is saved, as configured.
+    fn will_save_edits(&self, uri: &Url) -> Option<Vec<TextEdit>> {
+        let doc = self.docs.get(uri)?;
+        let mut edits = vec![];
+        if self.config.organize_uses_on_save {
+            edits.extend(diff::minimize(doc.doc(), on_save::organize_uses(&doc)));
+        }
+        if self.config.trim_trailing_whitespace_on_save {
+            // lines of sorted `use` declarations are trimmed already.
+            let trims: Vec<_> = on_save::trim_trailing_whitespace(&doc)
+                .into_iter()
+                .filter(|t| !edits.iter().any(|e| on_save::overlaps(&e.range, &t.range)))
+                .collect();
+            edits.extend(trims);
+        }
+        Some(edits).filter(|e| !e.is_empty())
+    }
+
+    /// Check a single document.
+    /// Real files go through the compiler, virtual ones only get syntax diagnostics.
+    fn diagnose_document(&self, uri: &Url, path: &VirtualPath) {
+        if self.pull_diagnostics() {
+            return;
+        }
+        match path {
+            VirtualPath::File(p) if self.skipped_files.contains_key(&canonicalize(p)) => {}
+            VirtualPath::File(_) => self.diagnose_open_documents(),
+            VirtualPath::Virtual(_) => self.diagnose_syntax(uri, path.file_id()),
+        }
+    }
+
+    fn diagnose_syntax(&self, uri: &Url, file_id: FileId) {
+        let revision = self.db.salsa_runtime().current_revision();
+        let version = match self.committed_versions.get(uri) {
+            Some(v) => *v,
+            None => return,
+        };
+        let diags = self.syntax_diagnostics(uri, file_id);
+        self.publish_document_diagnostics(revision, uri.clone(), diags, version);
+    }
+
+    fn syntax_diagnostics(&self, uri: &Url, file_id: FileId) -> Vec<Diagnostic> {
+        let max = self.config.max_diagnostics_per_file.unwrap_or(usize::MAX);
+        let errors = self.db.ast(file_id.clone()).err().unwrap_or_default();
+        let mut sources = FilesSourceText::new();
+        let fname = self.db.leak_str(file_id.clone());
+        sources.insert(fname, self.db.source_text(file_id));
+        to_diagnostics(sources, errors)
+            .remove(fname)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|d| Diagnostic {
+                range: d.primary_label.range,
+                severity: Some(DiagnosticSeverity::Error),
+                message: d.primary_label.msg,
+                ..Default::default()
+            })
+            .chain(
+                self.docs
+                    .get(uri)
+                    .and_then(|doc| parse_failure_diagnostic(&doc)),
+            )
+            .take(max)
+            .collect()
+    }
+
+    /// Check every open file as its own unit, only the units depending on what changed
+    /// since the last check are checked again.
+    fn diagnose_open_documents(&self) {
+        if self.pull_diagnostics() {
+            return;
+        }
+        self.send_status(ServerStatus::Checking, None);
+        let revision = self.db.salsa_runtime().current_revision();
+        let start = Instant::now();
+        let files: Vec<_> = self
+            .docs
+            .iter()
+            .filter_map(|d| match VirtualPath::from_url(d.key()) {
+                VirtualPath::File(p) => Some(canonicalize(&p)),
+                VirtualPath::Virtual(_) => None,
+            })
+            .filter(|fp| !self.skipped_files.contains_key(fp))
+            .filter(|fp| self.config.stdlib_diagnostics || !self.is_stdlib_file(fp))
+            .collect();
+        let mut sources = FilesSourceText::new();
+        let mut errors = Errors::new();
+        for fp in files {
+            let (unit_sources, result) = self.db.check_unit(fp);
+            sources.extend(unit_sources);
+            // errors in the interfaces of shared dependencies are found by each unit.
+            for error in result.err().unwrap_or_default() {
+                if !errors.contains(&error) {
+                    errors.push(error);
+                }
+            }
+        }
+        *self.last_check_duration.lock() = Some(start.elapsed());
+        self.publish_diagnostics(revision, sources, errors);
+        self.send_status(ServerStatus::Idle, None);
+    }
+
+    /// Publish diagnostics of document `version` computed at salsa `revision`.
+    /// The batch is dropped if the inputs changed since, if another version of the document
+    /// is committed, or if diagnostics of a newer version were published,
+    /// so stale results never show up under the version of a newer document.
+    /// Edits not committed yet don't matter, the diagnostics are the ones of `version`.
+    /// Diagnostics the client already has are not sent again.
+    fn publish_document_diagnostics(
+        &self,
+        revision: salsa::Revision,
+        uri: Url,
+        diags: Vec<Diagnostic>,
+        version: u64,
+    ) {
+        if self.db.salsa_runtime().current_revision() != revision {
+            debug!(
+                "drop diagnostics of {} computed at stale {:?}",
+                uri, revision
+            );
+            return;
+        }
+        if let Some(&committed) = self.committed_versions.get(&uri) {
+            if committed != version {
+                debug!(
+                    "drop diagnostics of {} version {}, version {} is committed",
+                    uri, version, committed
+                );
+                return;
+            }
+        }
+        if let Some(diags) = self.published_diagnostics.update(&uri, version, diags) {
+            self.client
+                .publish_diagnostics(uri, diags, Some(version as i64));
+        }
+    }
+
+    /// Errors on every definition of a module defined more than once,
+    /// found by the module index without waiting for the compiler.
+    /// Projects of a multi-root workspace are checked apart.
+    fn duplicate_module_diagnostics(&self) -> HashMap<PathBuf, Vec<Diagnostic>> {
+        let roots = self.db.project_roots();
+        let shards: Vec<_> = if roots.is_empty() {
+            vec![self.db.project_modules()]
+        } else {
+            roots
+                .into_iter()
+                .map(|r| self.db.root_modules(Some(r)))
+                .collect()
+        };
+        let mut diagnostics: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        let duplicates = shards
+            .iter()
+            .flat_map(|modules| module_index_query::duplicate_modules(modules.iter()));
+        for defs in duplicates {
+            for (idx, (file, decl)) in defs.iter().enumerate() {
+                let file_diagnostics = diagnostics.entry(canonicalize(file)).or_default();
+                // modules of stdlib files and of files outside every root are in every shard.
+                if file_diagnostics.iter().any(|d| d.range == decl.name_range) {
+                    continue;
+                }
+                let related_information = defs
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != idx)
+                    .filter_map(|(_, (other, other_decl))| {
+                        Some(DiagnosticRelatedInformation {
+                            location: Location::new(
+                                Url::from_file_path(other).ok()?,
+                                other_decl.name_range,
+                            ),
+                            message: format!("`{}` is also defined here", other_decl.key),
+                        })
                     })
-                    .collect()
+                    .collect();
+                file_diagnostics.push(Diagnostic {
+                    range: decl.name_range,
+                    severity: Some(DiagnosticSeverity::Error),
+                    message: format!(
+                        "duplicate definition of module `{}`, it's defined {} times",
+                        decl.key,
+                        defs.len()
+                    ),
+                    related_information: Some(related_information),
+                    ..Default::default()
+                });
+            }
+        }
+        diagnostics
+    }
+
+    /// Errors of the constants of `file` which can't be evaluated, like overflows.
+    fn constant_diagnostics(&self, doc: &MoveDocument, file: &Path) -> Vec<Diagnostic> {
+        let ast = match self.db.ast(file.to_path_buf()) {
+            Ok(ast) => ast,
+            Err(_) => return vec![],
+        };
+        constant_eval::evaluate_constants(ast.defs.as_slice(), None)
+            .into_iter()
+            .filter_map(|c| {
+                let e = c.value?.err()?;
+                let span = e.loc.span();
+                Some(Diagnostic {
+                    range: lsp_types::Range::new(
+                        doc.doc().to_position(span.start().to_usize())?,
+                        doc.doc().to_position(span.end().to_usize())?,
+                    ),
+                    severity: Some(DiagnosticSeverity::Error),
+                    message: format!("invalid value of constant `{}`, {}", c.name(), e.message),
+                    ..Default::default()
+                })
+            })
+            .collect()
+    }
+
+    /// Hover of a constant or of a spec condition, from the parsed definitions of `doc`.
+    fn definitions_hover(
+        &self,
+        doc: &MoveDocument,
+        uri: &Url,
+        pos: lsp_types::Position,
+    ) -> Option<Hover> {
+        let file_id = canonicalize(&VirtualPath::from_url(uri).file_id());
+        let ast = self.db.ast(file_id).ok()?;
+        let defs = ast.defs.as_slice();
+        hover::constant_hover(doc, defs, pos).or_else(|| hover::spec_hover(doc, defs, pos))
+    }
+
+    /// Stdlib and module files of the project at `root`, every one for `None`.
+    fn root_files(&self, root: Option<PathBuf>) -> Vec<PathBuf> {
+        self.db
+            .stdlib_files()
+            .into_iter()
+            .chain(self.db.root_module_files(root))
+            .collect()
+    }
+
+    /// Warnings on `use` declarations which take part in a dependency cycle.
+    /// Projects of a multi-root workspace are checked apart.
+    fn dependency_cycle_diagnostics(&self) -> HashMap<PathBuf, Vec<Diagnostic>> {
+        let roots: Vec<_> = match self.db.project_roots() {
+            roots if roots.is_empty() => vec![None],
+            roots => roots.into_iter().map(Some).collect(),
+        };
+        let graphs: Vec<_> = roots
+            .into_iter()
+            .map(|root| DependencyGraph::of_files(&self.db, self.root_files(root)))
+            .collect();
+        let mut diagnostics: HashMap<PathBuf, Vec<Diagnostic>> = HashMap::new();
+        for cycle in graphs.iter().flat_map(|g| g.cycles()) {
+            let members = cycle.iter().map(|e| e.from.to_string()).unique().join(", ");
+            for (idx, edge) in cycle.iter().enumerate() {
+                let related_information = cycle
+                    .iter()
+                    .enumerate()
+                    .filter(|(i, _)| *i != idx)
+                    .filter_map(|(_, other)| {
+                        Some(DiagnosticRelatedInformation {
+                            location: Location::new(
+                                Url::from_file_path(&other.file).ok()?,
+                                other.range,
+                            ),
+                            message: format!("`{}` uses `{}`", other.from, other.to),
+                        })
+                    })
+                    .collect();
+                let file_diagnostics = diagnostics.entry(edge.file.clone()).or_default();
+                // cycles of stdlib files and of files in no project root are in every project.
+                if file_diagnostics.iter().any(|d| d.range == edge.range) {
+                    continue;
+                }
+                file_diagnostics.push(Diagnostic {
+                    range: edge.range,
+                    severity: Some(DiagnosticSeverity::Warning),
+                    message: format!(
+                        "`{}` uses `{}`, which forms a dependency cycle between {}",
+                        edge.from, edge.to, members
+                    ),
+                    related_information: Some(related_information),
+                    ..Default::default()
+                });
+            }
+        }
+        diagnostics
+    }
+
+    fn publish_diagnostics(
+        &self,
+        revision: salsa::Revision,
+        sources: FilesSourceText,
+        errs: Errors,
+    ) {
+        for (uri, version, diags) in self.document_diagnostics(sources, errs) {
+            self.publish_document_diagnostics(revision, uri, diags, version);
+        }
+    }
+
+    /// The diagnostic of a compiler error, secondary labels are its related information.
+    fn error_diagnostic(&self, error: DiagnosticInfo) -> Diagnostic {
+        let DiagnosticInfo {
+            primary_label,
+            secondary_labels,
+        } = error;
+        let related_infos: Vec<_> = secondary_labels
+            .into_iter()
+            .filter_map(|l| {
+                Some(DiagnosticRelatedInformation {
+                    location: Location::new(Url::from_file_path(l.file).ok()?, l.range),
+                    message: l.msg,
+                })
+            })
+            .collect();
+        Diagnostic {
+            range: primary_label.range,
+            severity: Some(DiagnosticSeverity::Error),
+            message: primary_label.msg,
+            related_information: Some(related_infos).filter(|_| self.config.related_information),
+            ..Default::default()
+        }
+    }
+
+    /// Publish the errors of a failed compilation on the files they are in,
+    /// and clear them from the files of the previous failure which have none anymore.
+    /// Open documents get the diagnostics of the next check again.
+    fn publish_compile_diagnostics(&mut self, errors: HashMap<&str, Vec<DiagnosticInfo>>) {
+        if self.pull_diagnostics() {
+            return;
+        }
+        let mut files = HashSet::new();
+        for (file, errors) in errors {
+            let uri = match Url::from_file_path(file) {
+                Ok(u) => u,
+                Err(_) => continue,
+            };
+            let diags = errors
+                .into_iter()
+                .map(|e| self.error_diagnostic(e))
+                .collect();
+            // the next check publishes its diagnostics, even if they didn't change.
+            self.published_diagnostics.remove(&uri);
+            self.client.publish_diagnostics(uri.clone(), diags, None);
+            files.insert(uri);
+        }
+        let fixed: Vec<_> = self
+            .compile_diagnostic_files
+            .difference(&files)
+            .cloned()
+            .collect();
+        self.compile_diagnostic_files = files;
+        self.clear_compile_diagnostics_of(fixed);
+    }
+
+    /// Clear the errors of the last failed compilation, once a compilation succeeds.
+    fn clear_compile_diagnostics(&mut self) {
+        let files = std::mem::take(&mut self.compile_diagnostic_files);
+        self.clear_compile_diagnostics_of(files.into_iter().collect());
+    }
+
+    fn clear_compile_diagnostics_of(&mut self, files: Vec<Url>) {
+        let mut open_documents = false;
+        for uri in files {
+            if self.docs.contains_key(&uri) {
+                self.published_diagnostics.remove(&uri);
+                open_documents = true;
             } else {
-                vec![]
+                self.client.publish_diagnostics(uri, vec![], None);
+            }
+        }
+        if open_documents {
+            self.diagnose_open_documents();
+        }
+    }
+
+    /// Diagnostics of every open file, with their document versions.
+    fn document_diagnostics(
+        &self,
+        sources: FilesSourceText,
+        errs: Errors,
+    ) -> Vec<(Url, u64, Vec<Diagnostic>)> {
+        let mut result = vec![];
+        let mut diags = to_diagnostics(sources, errs);
+        let mut cycle_diags = self.dependency_cycle_diagnostics();
+        let mut duplicate_diags = self.duplicate_module_diagnostics();
+
+        for f in self.docs.iter() {
+            // the errors are the ones of the text in the database.
+            let (doc, version) = match self.committed_versions.get(f.key()) {
+                Some(v) => (f.key(), *v),
+                None => continue,
+            };
+            let fp = match VirtualPath::from_url(doc) {
+                VirtualPath::File(p) => canonicalize(&p),
+                VirtualPath::Virtual(_) => continue,
             };
 
-            self.client
-                .publish_diagnostics(doc.clone(), diag, Some(version as i64));
+            debug!("publish diagnostic for {}", fp.display());
+
+            let errors = diags.remove(fp.to_string_lossy().as_ref());
+            // still publish an empty list, to clear what's reported before the config changed.
+            let errors =
+                errors.filter(|_| self.config.stdlib_diagnostics || !self.is_stdlib_file(&fp));
+            let mut diag: Vec<Diagnostic> = errors
+                .unwrap_or_default()
+                .into_iter()
+                .map(|d| self.error_diagnostic(d))
+                .collect();
+            if self.db.sender_of(&fp).is_none() {
+                // they replace the less helpful compiler errors on the same modules.
+                let sender_diags = expand::missing_sender_diagnostics(f.value());
+                diag.retain(|d| {
+                    !sender_diags
+                        .iter()
+                        .any(|s| on_save::overlaps(&d.range, &s.range))
+                });
+                diag.extend(sender_diags);
+            }
+            // the compiler may report the same constant, once it's checked.
+            let constant_diags: Vec<_> = self
+                .constant_diagnostics(f.value(), &fp)
+                .into_iter()
+                .filter(|c| !diag.iter().any(|d| on_save::overlaps(&d.range, &c.range)))
+                .collect();
+            diag.extend(constant_diags);
+            // the compiler reports the same acquires, once the module type checks.
+            let acquires_diags: Vec<_> = acquires::acquires_diagnostics(f.value())
+                .into_iter()
+                .filter(|a| !diag.iter().any(|d| on_save::overlaps(&d.range, &a.range)))
+                .collect();
+            diag.extend(acquires_diags);
+            diag.extend(duplicate_diags.remove(&fp).unwrap_or_default());
+            diag.extend(cycle_diags.remove(&fp).unwrap_or_default());
+            diag.extend(parse_failure_diagnostic(f.value()));
+            if let Some(reason) = self.skipped_reason(f.doc().rope()) {
+                diag = vec![skipped_file_diagnostic(&reason)];
+            }
+            if let Some(max) = self.config.max_diagnostics_per_file {
+                diag.truncate(max);
+            }
+
+            result.push((doc.clone(), version, diag));
+        }
+        result
+    }
+
+    /// The client pulls diagnostics with `move/documentDiagnostic`, so none are pushed.
+    fn pull_diagnostics(&self) -> bool {
+        self.client_capabilities
+            .experimental
+            .as_ref()
+            .and_then(|e| e.get("pullDiagnostics"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false)
+    }
+
+    /// Diagnostics of an open document for pull clients.
+    /// A check is skipped if nothing changed since the last pull,
+    /// and the result id is kept as long as the diagnostics stay the same.
+    fn document_diagnostic(
+        &mut self,
+        params: DocumentDiagnosticParams,
+    ) -> Result<DocumentDiagnosticReport> {
+        let DocumentDiagnosticParams {
+            text_document,
+            previous_result_id,
+        } = params;
+        let uri = text_document.uri;
+        if !self.docs.contains_key(&uri) {
+            bail!("{} is not opened", uri);
+        }
+        let revision = self.db.salsa_runtime().current_revision();
+        let cached = self.pulled_diagnostics.get(&uri).map(|c| c.clone());
+        let unchanged_since = |result_id: &str| previous_result_id.as_deref() == Some(result_id);
+        if let Some(cached) = cached.as_ref() {
+            if cached.revision == revision && unchanged_since(&cached.result_id) {
+                return Ok(DocumentDiagnosticReport::Unchanged {
+                    result_id: cached.result_id.clone(),
+                });
+            }
+        }
+
+        let path = VirtualPath::from_url(&uri);
+        let diagnostics = match &path {
+            VirtualPath::File(p) => {
+                let (sources, result) = self.db.check_unit(p.clone());
+                let errors = result.err().unwrap_or_default();
+                self.document_diagnostics(sources, errors)
+                    .into_iter()
+                    .find(|(u, _, _)| u == &uri)
+                    .map(|(_, _, d)| d)
+                    .unwrap_or_default()
+            }
+            VirtualPath::Virtual(_) => self.syntax_diagnostics(&uri, path.file_id()),
+        };
+        let result_id = match cached {
+            Some(c) if c.diagnostics == diagnostics => c.result_id,
+            _ => {
+                self.next_result_id += 1;
+                self.next_result_id.to_string()
+            }
+        };
+        self.pulled_diagnostics.insert(
+            uri,
+            PulledDiagnostics {
+                revision,
+                result_id: result_id.clone(),
+                diagnostics: diagnostics.clone(),
+            },
+        );
+        if unchanged_since(&result_id) {
+            Ok(DocumentDiagnosticReport::Unchanged { result_id })
+        } else {
+            Ok(DocumentDiagnosticReport::Full {
+                result_id,
+                items: diagnostics,
+            })
         }
     }
 
+    /// Compile a source file into the output directory, returns the manifest of the artifacts,
+    /// or the compiler errors. Failing to write the artifacts is an error message.
     fn do_compilation(
         &mut self,
         sender: Option<Address>,
         arg: CompilationArgs,
-    ) -> Result<(), String> {
-        let CompilationArgs { file, out_dir } = arg;
+    ) -> Result<Option<CompileResult>, String> {
+        let span = info_span!("compile", file = %arg.file);
+        let _enter = span.enter();
+        let CompilationArgs {
+            file,
+            out_dir,
+            force,
+        } = arg;
 
         if let Ok(p) = file.to_file_path() {
-            match self.db.compile_file(sender, p) {
-                (s, Ok(u)) => move_lang::output_compiled_units(
-                    true,
-                    s,
-                    u,
-                    out_dir.as_path().to_string_lossy().as_ref(),
+            let p = canonicalize(&p);
+            let out_dir = out_dir.unwrap_or_else(|| self.default_out_dir(p.as_path()));
+            let mut fingerprints = Fingerprints::load(&out_dir);
+            let fingerprint = self.compilation_fingerprint(sender, &p);
+            if let Some(artifacts) = fingerprints
+                .fresh_artifacts(&p, &fingerprint)
+                .filter(|_| !force)
+            {
+                debug!("{} is up to date in {}", p.display(), out_dir.display());
+                self.clear_compile_diagnostics();
+                return Ok(Some(CompileResult {
+                    manifest: CompilationManifest {
+                        out_dir,
+                        artifacts: artifacts.to_vec(),
+                    },
+                    errors: vec![],
+                }));
+            }
+            match self.db.compile_file(sender, p.clone()) {
+                (_, Ok(u)) => {
+                    let manifest =
+                        artifacts::write_units(u, &out_dir).map_err(|e| e.to_string())?;
+                    fingerprints.insert(p, fingerprint, manifest.artifacts.clone());
+                    // the artifacts are written, failing to save only costs a recompilation.
+                    if let Err(e) = fingerprints.save(&out_dir) {
+                        warn!("fail to save compilation fingerprints, {}", e);
+                    }
+                    self.clear_compile_diagnostics();
+                    Ok(Some(CompileResult {
+                        manifest,
+                        errors: vec![],
+                    }))
+                }
+                (s, Err(e)) => {
+                    let by_file = to_diagnostics(s, e);
+                    let mut errors: Vec<_> = by_file.values().flatten().cloned().collect();
+                    self.publish_compile_diagnostics(by_file);
+                    errors.sort_by_key(|d| (d.primary_label.file, d.primary_label.range.start));
+                    Ok(Some(CompileResult {
+                        manifest: CompilationManifest {
+                            out_dir,
+                            artifacts: vec![],
+                        },
+                        errors,
+                    }))
+                }
+            }
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Publish the coverage of the project from a vm trace, mapped with the current sources.
+    fn load_coverage(&self, params: LoadCoverageParams) -> Result<()> {
+        let trace_file = match params.trace {
+            Some(uri) => match uri.to_file_path() {
+                Ok(p) => p,
+                Err(_) => bail!("{} is not a file uri", uri),
+            },
+            None => match &self.root_path {
+                Some(root) => self.default_out_dir(root).join(coverage::TRACE_FILE),
+                None => bail!("no workspace folder to find the trace file in"),
+            },
+        };
+        let trace = coverage::parse_trace(&std::fs::read_to_string(&trace_file)?);
+        let units = match self
+            .db
+            .check_all(None)
+            .1
+            .and_then(move_lang::to_bytecode::translate::program)
+        {
+            Ok(units) => units,
+            Err(_) => bail!("the project doesn't compile, the trace can't be mapped to sources"),
+        };
+
+        for (file, file_coverage) in coverage::coverage(&units, &trace) {
+            let path = PathBuf::from(file);
+            let uri = match Url::from_file_path(&path) {
+                Ok(u) => u,
+                Err(_) => continue,
+            };
+            let source = RopeDoc::new(0, self.db.source_text(path).as_str());
+            let ranges = |spans: Vec<std::ops::Range<usize>>| -> Vec<lsp_types::Range> {
+                spans
+                    .into_iter()
+                    .filter_map(|s| {
+                        Some(lsp_types::Range::new(
+                            source.to_position(s.start)?,
+                            source.to_position(s.end)?,
+                        ))
+                    })
+                    .collect()
+            };
+            self.client
+                .send_custom_notification::<Coverage>(CoverageParams {
+                    uri,
+                    covered: ranges(file_coverage.covered),
+                    uncovered: ranges(file_coverage.uncovered),
+                });
+        }
+        Ok(())
+    }
+
+    /// Fingerprint of everything compiling `file` reads: the sender and the project sources.
+    fn compilation_fingerprint(&self, sender: Option<Address>, file: &Path) -> String {
+        let mut files = self.db.stdlib_files_of(file.to_path_buf());
+        files.extend(self.db.module_files());
+        if !files.iter().any(|f| f == file) {
+            files.push(file.to_path_buf());
+        }
+        let sources: Vec<_> = files
+            .into_iter()
+            .map(|f| {
+                let source = self.db.source_text(f.clone());
+                (f, source)
+            })
+            .collect();
+        let sender = sender
+            .or_else(|| self.db.sender_of(file))
+            .map(|s| s.to_string());
+        fingerprints::fingerprint(
+            sender.as_deref(),
+            sources.iter().map(|(f, s)| (f.as_path(), s.as_str())),
+        )
+    }
+
+    fn run_script(&self, args: RunScriptArgs) -> Result<ExecutionResult> {
+        let RunScriptArgs {
+            file,
+            sender,
+            args,
+            type_args,
+        } = args;
+        let fp = match file.to_file_path() {
+            Ok(p) => canonicalize(&p),
+            Err(_) => bail!("{} is not a file", file),
+        };
+        let sender = match sender {
+            Some(s) => Some(script_runner::parse_sender(s.as_str())?),
+            None => self
+                .db
+                .sender_of(&fp)
+                .map(|a| AccountAddress::new(a.to_u8())),
+        };
+
+        let address = sender
+            .map(|s| Address::try_from(s.as_ref()))
+            .transpose()
+            .map_err(|e| format_err!("invalid sender address, {}", e))?;
+        let (sources, units) = self.db.compile_file_with_deps(address, fp.clone());
+        let units = match units {
+            Ok(u) => u,
+            Err(e) => bail!(
+                "{}",
+                String::from_utf8_lossy(
+                    move_lang::errors::report_errors_to_buffer(sources, e).as_slice()
+                )
+            ),
+        };
+        script_runner::run_script(
+            units,
+            self.db.leak_str(fp),
+            sender.into_iter().collect(),
+            args.as_slice(),
+            type_args.as_slice(),
+        )
+    }
+
+    fn run_test(&self, args: RunTestArgs) -> Result<ExecutionResult> {
+        let RunTestArgs {
+            file,
+            module,
+            function,
+        } = args;
+        let fp = match file.to_file_path() {
+            Ok(p) => canonicalize(&p),
+            Err(_) => bail!("{} is not a file", file),
+        };
+        let (sources, units) = self.db.compile_file_with_deps(None, fp.clone());
+        let units = match units {
+            Ok(u) => u,
+            Err(e) => bail!(
+                "{}",
+                String::from_utf8_lossy(
+                    move_lang::errors::report_errors_to_buffer(sources, e).as_slice()
                 )
-                .map_err(|e| format!("{}", e)),
-                (s, Err(e)) => Err(String::from_utf8_lossy(
-                    move_lang::errors::report_errors_to_buffer(s, e).as_slice(),
+            ),
+        };
+        script_runner::run_function(units, self.db.leak_str(fp), &module, &function)
+    }
+
+    /// Disassemble a `.mv` file, or the units compiled from a source file.
+    fn disassemble(&self, args: DisassembleArgs) -> Result<String> {
+        let DisassembleArgs { file } = args;
+        let fp = match file.to_file_path() {
+            Ok(p) => canonicalize(&p),
+            Err(_) => bail!("{} is not a file", file),
+        };
+        if fp.extension().and_then(|e| e.to_str()) == Some("mv") {
+            let bytes = std::fs::read(fp.as_path())?;
+            return disassemble::disassemble_bytes(bytes.as_slice());
+        }
+
+        match self.db.compile_file(None, fp.clone()) {
+            (_, Ok(units)) => disassemble::disassemble_units(units, self.db.leak_str(fp)),
+            (s, Err(e)) => bail!(
+                "{}",
+                String::from_utf8_lossy(
+                    move_lang::errors::report_errors_to_buffer(s, e).as_slice()
                 )
-                .to_string()),
+            ),
+        }
+    }
+
+    /// Analyses of the project as committed to the database.
+    fn analysis(&self) -> AnalysisSnapshot {
+        AnalysisSnapshot::new(&self.db)
+    }
+
+    fn goto_definition(&self, uri: &Url, pos: lsp_types::Position) -> Option<Location> {
+        let file_id = VirtualPath::from_url(uri).file_id();
+        let (file, range) = self.analysis().definition(&file_id, pos)?;
+        Some(Location::new(self.location_url(file.as_path())?, range))
+    }
+
+    /// Quick fixes of `diagnostics`, and refactorings available for the selection `range`.
+    fn code_actions(
+        &self,
+        uri: &Url,
+        range: lsp_types::Range,
+        diagnostics: &[Diagnostic],
+    ) -> Vec<CodeActionOrCommand> {
+        let doc = match self.docs.get(uri) {
+            Some(d) => d,
+            None => return vec![],
+        };
+        add_ability::add_ability_fixes(&doc, uri, diagnostics)
+            .into_iter()
+            .chain(unused_variable::prefix_unused_fixes(&doc, uri, diagnostics))
+            .chain(expand::set_sender_fixes(uri, diagnostics))
+            .chain(extract_function::extract_function(&doc, uri, range))
+            .chain(inline_variable::inline_variable(&doc, uri, range))
+            .chain(generate_getter::generate_getters(&doc, uri, range))
+            .map(|mut action| {
+                let changes = action.edit.as_mut().and_then(|e| e.changes.as_mut());
+                if let Some(edits) = changes.and_then(|c| c.get_mut(uri)) {
+                    *edits = diff::minimize(doc.doc(), std::mem::take(edits));
+                }
+                CodeActionOrCommand::CodeAction(action)
+            })
+            .collect()
+    }
+
+    /// Moniker of the module or member at `pos`, exported if it's defined in the document.
+    fn monikers(&self, uri: &Url, pos: lsp_types::Position) -> Option<Vec<Moniker>> {
+        let doc = self.docs.get(uri)?;
+        let file_id = canonicalize(&VirtualPath::from_url(uri).file_id());
+        let symbol = moniker::symbol_at(&doc, pos, self.db.file_modules(file_id).as_slice())?;
+        Some(vec![Moniker {
+            scheme: LANGUAGE_ID.to_string(),
+            identifier: symbol.identifier(self.db.dialect().name()),
+            unique: UniquenessLevel::Global,
+            kind: Some(if symbol.defined_here {
+                MonikerKind::Export
+            } else {
+                MonikerKind::Import
+            }),
+        }])
+    }
+
+    fn module_name_at(
+        &self,
+        uri: &Url,
+        pos: lsp_types::Position,
+    ) -> Option<(ModuleKey, lsp_types::Range)> {
+        let doc = self.docs.get(uri)?;
+        let file_id = canonicalize(&VirtualPath::from_url(uri).file_id());
+        rename::module_name_at(&doc, pos, self.db.file_modules(file_id).as_slice())
+    }
+
+    /// Rename the module or the local variable at `pos`.
+    fn rename(
+        &self,
+        uri: &Url,
+        pos: lsp_types::Position,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>> {
+        if !rename::is_identifier(new_name) {
+            bail!("`{}` is not a valid name", new_name);
+        }
+        match self.module_name_at(uri, pos) {
+            Some((key, _)) => self.rename_module(uri, key, new_name),
+            None => Ok(self.rename_local(uri, pos, new_name)),
+        }
+    }
+
+    /// Range and current text of the name renamed at `pos`,
+    /// `None` if there is no module or local variable to rename there.
+    fn prepare_rename(
+        &self,
+        uri: &Url,
+        pos: lsp_types::Position,
+    ) -> Option<(lsp_types::Range, String)> {
+        let (range, name) = match self.module_name_at(uri, pos) {
+            Some((key, range)) => (range, key.name),
+            None => {
+                let doc = self.docs.get(uri)?;
+                let (name, ranges) = rename::local_references(&doc, pos)?;
+                let range = ranges
+                    .into_iter()
+                    .find(|r| r.start <= pos && pos <= r.end)?;
+                (range, name)
+            }
+        };
+        let renameable =
+            rename::is_renameable(&name) && self.db.dialect().builtin_function(&name).is_none();
+        Some((range, name)).filter(|_| renameable)
+    }
+
+    fn rename_local(
+        &self,
+        uri: &Url,
+        pos: lsp_types::Position,
+        new_name: &str,
+    ) -> Option<WorkspaceEdit> {
+        let doc = self.docs.get(uri)?;
+        let (_, ranges) = rename::local_references(&doc, pos)?;
+        let edit = TextDocumentEdit {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: Some(doc.doc().version() as i64),
+            },
+            edits: ranges
+                .into_iter()
+                .map(|r| TextEdit::new(r, new_name.to_string()))
+                .collect(),
+        };
+        Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(vec![edit])),
+            ..Default::default()
+        })
+    }
+
+    /// Rename module `key`, in its definition and every module file referring to it.
+    /// Edits of open documents are bound to their current versions.
+    fn rename_module(
+        &self,
+        uri: &Url,
+        key: ModuleKey,
+        new_name: &str,
+    ) -> Result<Option<WorkspaceEdit>> {
+        let current = canonicalize(&VirtualPath::from_url(uri).file_id());
+        let defining_file = self.module_file(
+            &key,
+            current.clone(),
+            self.db.file_modules(current).as_slice(),
+        );
+        if let Some(f) = defining_file.filter(|f| self.is_stdlib_file(f)) {
+            bail!("{} is defined in stdlib file {}", key, f.display());
+        }
+
+        let changes = self
+            .module_references(uri, &key)
+            .into_iter()
+            .map(|(text_document, ranges)| TextDocumentEdit {
+                text_document,
+                edits: ranges
+                    .into_iter()
+                    .map(|r| TextEdit::new(r, new_name.to_string()))
+                    .collect(),
+            })
+            .collect();
+        Ok(Some(WorkspaceEdit {
+            document_changes: Some(DocumentChanges::Edits(changes)),
+            ..Default::default()
+        }))
+    }
+
+    /// Names of module `key` in the module files and the document `uri`,
+    /// open documents are identified with their current versions.
+    fn module_references(
+        &self,
+        uri: &Url,
+        key: &ModuleKey,
+    ) -> Vec<(VersionedTextDocumentIdentifier, Vec<lsp_types::Range>)> {
+        let current = canonicalize(&VirtualPath::from_url(uri).file_id());
+        let open_docs: HashMap<FileId, Url> = self
+            .docs
+            .iter()
+            .map(|d| {
+                (
+                    canonicalize(&VirtualPath::from_url(d.key()).file_id()),
+                    d.key().clone(),
+                )
+            })
+            .collect();
+        let mut files = self.db.module_files();
+        files.extend(self.db.script_files());
+        // modules of other projects of the workspace may have the same name.
+        if let Some(root) = self.db.project_root_of(current.clone()) {
+            files.retain(|f| {
+                self.db
+                    .project_root_of(f.clone())
+                    .map_or(true, |r| r == root)
+            });
+        }
+        if !files.contains(&current) {
+            files.push(current);
+        }
+
+        let mut references = vec![];
+        for file in files {
+            let file_modules = self.db.file_modules(file.clone());
+            let (url, version, ranges) = match open_docs.get(&file) {
+                Some(url) => {
+                    let doc = match self.docs.get(url) {
+                        Some(d) => d,
+                        None => continue,
+                    };
+                    let ranges = rename::module_references(&doc, file_modules.as_slice(), key);
+                    (url.clone(), Some(doc.doc().version() as i64), ranges)
+                }
+                None => {
+                    let url = match Url::from_file_path(&file) {
+                        Ok(u) => u,
+                        Err(_) => continue,
+                    };
+                    let doc = self.db.syntax_document(file);
+                    let ranges = rename::module_references(&doc, file_modules.as_slice(), key);
+                    (url, None, ranges)
+                }
+            };
+            if !ranges.is_empty() {
+                references.push((
+                    VersionedTextDocumentIdentifier { uri: url, version },
+                    ranges,
+                ));
+            }
+        }
+        references
+    }
+
+    /// References of the module or the local variable at `pos`.
+    fn references(
+        &self,
+        uri: &Url,
+        pos: lsp_types::Position,
+        include_declaration: bool,
+    ) -> Vec<Location> {
+        if let Some((key, _)) = self.module_name_at(uri, pos) {
+            let current = canonicalize(&VirtualPath::from_url(uri).file_id());
+            let declaration = self
+                .db
+                .file_modules(current.clone())
+                .iter()
+                .map(|m| (current.clone(), m.clone()))
+                .chain(self.db.visible_modules(current.clone()).iter().cloned())
+                .find(|(_, m)| m.key == key)
+                .map(|(f, m)| (f, m.name_range));
+            let is_declaration = |l: &Location| {
+                let file = canonicalize(&VirtualPath::from_url(&l.uri).file_id());
+                declaration.as_ref() == Some(&(file, l.range))
+            };
+            return self
+                .module_references(uri, &key)
+                .into_iter()
+                .flat_map(|(doc, ranges)| {
+                    ranges
+                        .into_iter()
+                        .map(move |r| Location::new(doc.uri.clone(), r))
+                })
+                .filter(|l| include_declaration || !is_declaration(l))
+                .collect();
+        }
+        let doc = match self.docs.get(uri) {
+            Some(d) => d,
+            None => return vec![],
+        };
+        let ranges = match rename::local_references(&doc, pos) {
+            Some((_, ranges)) => ranges,
+            None => return vec![],
+        };
+        // the binding comes first.
+        let skip = if include_declaration { 0 } else { 1 };
+        ranges
+            .into_iter()
+            .skip(skip)
+            .map(|r| Location::new(uri.clone(), r))
+            .collect()
+    }
+
+    /// Tests of the file of `uri`, or of all module and script files.
+    /// Test scripts come with a `runScript` command, and test functions without parameters
+    /// with a `runTest` command.
+    fn tests(&self, uri: Option<&Url>) -> Vec<TestItem> {
+        let files = match uri {
+            Some(uri) => vec![canonicalize(&VirtualPath::from_url(uri).file_id())],
+            None => self
+                .db
+                .module_files()
+                .into_iter()
+                .chain(self.db.script_files())
+                .collect(),
+        };
+        let analysis = self.analysis();
+        let mut tests = vec![];
+        for file in files {
+            let url = match uri.cloned().or_else(|| Url::from_file_path(&file).ok()) {
+                Some(u) => u,
+                None => continue,
+            };
+            for (symbol, range) in analysis.tests(&file) {
+                let (kind, command) = match symbol.kind {
+                    analysis::SymbolKind::Script => (
+                        TestKind::Script,
+                        Some(Command {
+                            title: "Run test".to_string(),
+                            command: RUN_SCRIPT_COMMAND.to_string(),
+                            arguments: Some(vec![json::json!({ "file": url })]),
+                        }),
+                    ),
+                    _ => {
+                        let runnable = symbol
+                            .signature
+                            .as_deref()
+                            .map_or(false, |s| s.contains(&format!("{}()", symbol.name)));
+                        let command = match &symbol.container {
+                            Some(module) if runnable => Some(Command {
+                                title: "Run test".to_string(),
+                                command: RUN_TEST_COMMAND.to_string(),
+                                arguments: Some(vec![json::json!({
+                                    "file": url,
+                                    "module": module,
+                                    "function": symbol.name,
+                                })]),
+                            }),
+                            _ => None,
+                        };
+                        (TestKind::Function, command)
+                    }
+                };
+                tests.push(TestItem {
+                    label: symbol.name,
+                    kind,
+                    container: symbol.container,
+                    location: Location::new(url.clone(), range),
+                    command,
+                });
+            }
+        }
+        tests
+    }
+
+    /// Project modules, scripts and module members whose name contains `query`, ignoring case.
+    fn workspace_symbols(&self, query: &str) -> Vec<SymbolInformation> {
+        let symbols = self.analysis().symbols(query, MAX_WORKSPACE_SYMBOLS);
+        self.symbol_informations(symbols)
+    }
+
+    /// Outline of an open document, from the symbols query if its text is in the database.
+    /// Otherwise only the parts changed since it was last asked for are computed again.
+    fn document_symbols(&self, uri: &Url) -> Option<Vec<DocumentSymbol>> {
+        let doc = self.docs.get(uri)?;
+        let file_id = VirtualPath::from_url(uri).file_id();
+        let committed = self.committed_versions.get(uri) == Some(&doc.doc().version())
+            && self.skipped_reason(doc.doc().rope()).is_none();
+        if committed && self.db.ast(file_id.clone()).is_ok() {
+            let symbols = self.db.symbols(file_id);
+            if let Some(items) = outline::symbols_outline(&doc, &symbols) {
+                return Some(items);
             }
+        }
+        let mut outline = self
+            .outlines
+            .entry(uri.clone())
+            .or_insert_with(|| Outline::new(&doc));
+        outline.update(&doc);
+        Some(outline.symbols())
+    }
+
+    fn symbol_informations(&self, symbols: Vec<(FileId, Symbol)>) -> Vec<SymbolInformation> {
+        let mut sources: HashMap<FileId, RopeDoc> = HashMap::new();
+        symbols
+            .into_iter()
+            .filter_map(|(file, symbol)| {
+                let url = self.location_url(&file)?;
+                let source = sources
+                    .entry(file.clone())
+                    .or_insert_with(|| RopeDoc::new(0, self.db.source_text(file).as_str()));
+                let range = lsp_types::Range::new(
+                    source.to_position(symbol.span.start)?,
+                    source.to_position(symbol.span.end)?,
+                );
+                Some(SymbolInformation {
+                    name: symbol.name,
+                    kind: symbol_kind(symbol.kind),
+                    deprecated: None,
+                    location: Location::new(url, range),
+                    container_name: symbol.container,
+                })
+            })
+            .collect()
+    }
+
+    /// Stream `items` in chunks if the client asked for partial results,
+    /// the response then carries nothing more.
+    fn partial_results<T: Serialize>(&self, token: Option<ProgressToken>, items: Vec<T>) -> Vec<T> {
+        let token = match token {
+            Some(t) => t,
+            None => return items,
+        };
+        for chunk in items.chunks(PARTIAL_RESULT_CHUNK) {
+            self.client
+                .send_custom_notification::<PartialResult>(PartialResultParams {
+                    token: token.clone(),
+                    value: json::to_value(chunk).unwrap_or_default(),
+                });
+        }
+        vec![]
+    }
+
+    /// Body of the function called at `pos`.
+    fn goto_implementation(&self, uri: &Url, pos: lsp_types::Position) -> Option<Location> {
+        let file_id = VirtualPath::from_url(uri).file_id();
+        let (file, range) = self.analysis().implementation(&file_id, pos)?;
+        Some(Location::new(self.location_url(&file)?, range))
+    }
+
+    /// Abilities, functions of the module accessed at `pos`,
+    /// or field names of the struct packed or unpacked at `pos`.
+    fn completion(&self, uri: &Url, pos: lsp_types::Position) -> Option<Vec<CompletionItem>> {
+        let file_id = VirtualPath::from_url(uri).file_id();
+        self.analysis().completions(&file_id, pos)
+    }
+
+    /// Uri of a definition, stdlib files outside the workspace are read-only if configured so.
+    fn location_url(&self, file: &Path) -> Option<Url> {
+        let in_workspace = self
+            .root_path
+            .as_ref()
+            .filter(|root| file.starts_with(canonicalize(root)))
+            .is_some();
+        if self.config.readonly_stdlib && !in_workspace && self.is_stdlib_file(file) {
+            readonly_url(file)
         } else {
-            Ok(())
+            Url::from_file_path(file).ok()
         }
     }
 
-    fn format_file(&self, fp: &Path, format_opts: FormattingOptions) -> Result<Option<TextEdit>> {
+    fn is_stdlib_file(&self, file: &Path) -> bool {
+        self.db.stdlib_files().iter().any(|f| f == file)
+            || self
+                .db
+                .folder_stdlib_files()
+                .iter()
+                .any(|(_, files)| files.iter().any(|f| f == file))
+    }
+
+    /// Whether `uri` is a stdlib, module or script file of the project,
+    /// the only files `move/readFile` serves.
+    fn is_known_file(&self, uri: &Url) -> bool {
+        let fp = match VirtualPath::from_url(uri).as_file_path() {
+            Some(p) => canonicalize(p),
+            None => return false,
+        };
+        self.is_stdlib_file(fp.as_path())
+            || self.db.module_files().contains(&fp)
+            || self.db.script_files().contains(&fp)
+    }
+
+    fn read_file(&self, uri: &Url) -> Result<ReadFileResult> {
+        let fp = match VirtualPath::from_url(uri).as_file_path() {
+            Some(p) => canonicalize(p),
+            None => bail!("{} is not a file", uri),
+        };
+        if !fp.is_file() {
+            bail!("{} does not exist", fp.display());
+        }
+        Ok(ReadFileResult {
+            readonly: uri.scheme() == READONLY_SCHEME || self.is_stdlib_file(fp.as_path()),
+            text: self.db.source_text(fp),
+        })
+    }
+
+    /// The configured output directory of `file`, `target` under the workspace root otherwise.
+    fn default_out_dir(&self, file: &Path) -> PathBuf {
+        if let Some(out_dir) = self.config.out_dir_of(file) {
+            return out_dir.to_path_buf();
+        }
+        let root = self
+            .root_path
+            .clone()
+            .or_else(|| file.parent().map(|p| p.to_path_buf()))
+            .unwrap_or_default();
+        root.join("target")
+    }
+
+    /// Edits formatting `fp`, only the parts which change are replaced.
+    fn format_file(&self, fp: &Path, format_opts: FormattingOptions) -> Result<Vec<TextEdit>> {
         let source: String = self.db.source_text(fp.to_path_buf());
 
         match self.db.ast(fp.to_path_buf()) {
@@ -656,17 +2948,7 @@ impl Inner {
                     output.push('\n');
                 }
 
-                let rope = RopeDoc::new(0, source.as_str());
-                let end_pos = rope.to_position(source.len());
-                if end_pos.is_none() {
-                    return Ok(None);
-                }
-                let text_edit = TextEdit::new(
-                    lsp_types::Range::new(lsp_types::Position::new(0, 0), end_pos.unwrap()),
-                    output,
-                );
-
-                Ok(Some(text_edit))
+                Ok(diff::diff(source.as_str(), output.as_str()))
             }
             Err(errs) => {
                 let mut files = FilesSourceText::new();
@@ -682,8 +2964,429 @@ impl Inner {
     }
 }
 
+/// Pop the last argument of the `what` command and parse it.
+fn parse_arg<T: DeserializeOwned>(args: &mut Vec<Value>, what: &str) -> jsonrpc::Result<T> {
+    let arg = args.pop().ok_or_else(|| {
+        jsonrpc::Error::invalid_params(format!("no arguments found for {}", what))
+    })?;
+    serde_json::from_value(arg).map_err(|e| {
+        jsonrpc::Error::invalid_params(format!("fail to parse {} arguments, {}", what, e))
+    })
+}
+
+/// Like `parse_arg`, a missing or null argument is the default one.
+fn parse_optional_arg<T: DeserializeOwned + Default>(
+    args: &mut Vec<Value>,
+    what: &str,
+) -> jsonrpc::Result<T> {
+    match args.last() {
+        None | Some(Value::Null) => {
+            args.pop();
+            Ok(T::default())
+        }
+        Some(_) => parse_arg(args, what),
+    }
+}
+
+fn server_capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Options(
+            TextDocumentSyncOptions {
+                open_close: Some(true),
+                change: Some(TextDocumentSyncKind::Incremental),
+                will_save: Some(true),
+                will_save_wait_until: Some(true),
+                save: Some(lsp_types::TextDocumentSyncSaveOptions::SaveOptions(
+                    SaveOptions {
+                        include_text: Some(false),
+                    },
+                )),
+                ..Default::default()
+            },
+        )),
+        workspace: Some(WorkspaceCapability {
+            workspace_folders: Some(WorkspaceFolderCapability {
+                supported: Some(true),
+                change_notifications: Some(WorkspaceFolderCapabilityChangeNotifications::Bool(
+                    true,
+                )),
+            }),
+        }),
+        execute_command_provider: Some(ExecuteCommandOptions {
+            commands: vec![
+                COMPILE_COMMAND.to_string(),
+                RUN_SCRIPT_COMMAND.to_string(),
+                RUN_TEST_COMMAND.to_string(),
+                DISASSEMBLE_COMMAND.to_string(),
+                lsp_ext::DEPENDENCY_GRAPH.to_string(),
+                lsp_ext::READ_FILE.to_string(),
+                lsp_ext::HIGHLIGHT.to_string(),
+                lsp_ext::EXPAND_ADDRESSES.to_string(),
+                lsp_ext::DOCUMENT_DIAGNOSTIC.to_string(),
+                lsp_ext::SERVER_INFO.to_string(),
+                lsp_ext::METRICS.to_string(),
+                lsp_ext::SET_LOG_FILTER.to_string(),
+                lsp_ext::NEW_PROJECT.to_string(),
+                lsp_ext::NEW_MODULE.to_string(),
+                lsp_ext::GENERATE_DOCS.to_string(),
+                lsp_ext::TESTS.to_string(),
+                lsp_ext::LOAD_COVERAGE.to_string(),
+                lsp_ext::ADDRESS_DECORATIONS.to_string(),
+                lsp_ext::LINKED_EDITING_RANGE.to_string(),
+                lsp_ext::MONIKER.to_string(),
+                lsp_ext::UNUSED_PUBLIC_FUNCTIONS.to_string(),
+                lsp_ext::INLAY_HINTS.to_string(),
+            ],
+            work_done_progress_options: WorkDoneProgressOptions {
+                work_done_progress: Some(true),
+            },
+        }),
+        document_formatting_provider: Some(true),
+        definition_provider: Some(true),
+        implementation_provider: Some(ImplementationProviderCapability::Simple(true)),
+        workspace_symbol_provider: Some(true),
+        document_symbol_provider: Some(true),
+        references_provider: Some(true),
+        code_action_provider: Some(CodeActionProviderCapability::Simple(true)),
+        rename_provider: Some(RenameProviderCapability::Options(RenameOptions {
+            prepare_provider: Some(true),
+            work_done_progress_options: Default::default(),
+        })),
+        hover_provider: Some(true),
+        completion_provider: Some(CompletionOptions {
+            resolve_provider: Some(false),
+            trigger_characters: Some(vec!["{".to_string(), ",".to_string()]),
+            work_done_progress_options: Default::default(),
+        }),
+        code_lens_provider: Some(CodeLensOptions {
+            resolve_provider: Some(false),
+        }),
+        experimental: Some(json::json!({
+            "pullDiagnostics": true,
+            "linkedEditingRange": true,
+            "moniker": true,
+            "inlayHints": true,
+        })),
+        ..ServerCapabilities::default()
+    }
+}
+
+/// Candidate renames of a batch of events: every deleted move file with every created one.
+/// Which one was renamed to which is told by the module names, see `Inner::module_renames`.
+fn renamed_files(changes: &[(PathBuf, lsp_types::FileChangeType)]) -> Vec<(PathBuf, PathBuf)> {
+    let is_move_file = |p: &PathBuf| p.extension().map_or(false, |e| e == MOVE_EXTENSION);
+    let of_type = |typ| {
+        changes
+            .iter()
+            .filter(move |(p, t)| *t == typ && is_move_file(p))
+            .map(|(p, _)| p.clone())
+    };
+    of_type(lsp_types::FileChangeType::Deleted)
+        .cartesian_product(of_type(lsp_types::FileChangeType::Created).collect::<Vec<_>>())
+        .collect()
+}
+
+/// File name of `path` without the extension.
+fn file_stem(path: &Path) -> Option<&str> {
+    path.file_stem().and_then(|s| s.to_str())
+}
+
+/// The net change of every file in `changes`, in the order the files first appear.
+/// A file created then changed is still created, otherwise the last event wins.
+fn coalesce_file_events(
+    changes: Vec<lsp_types::FileEvent>,
+) -> Vec<(PathBuf, lsp_types::FileChangeType)> {
+    use lsp_types::FileChangeType::{Changed, Created};
+
+    let mut coalesced: Vec<(PathBuf, lsp_types::FileChangeType)> = vec![];
+    for lsp_types::FileEvent { uri, typ } in changes {
+        let fp = match uri.to_file_path() {
+            Ok(fp) => canonicalize(&fp),
+            Err(_) => {
+                warn!("ignore watched file event of {}", uri);
+                continue;
+            }
+        };
+        match coalesced.iter_mut().find(|(f, _)| f == &fp) {
+            Some((_, last)) => {
+                *last = match (*last, typ) {
+                    (Created, Changed) => Created,
+                    (_, typ) => typ,
+                };
+            }
+            None => coalesced.push((fp, typ)),
+        }
+    }
+    coalesced
+}
+
+/// Apply the changes seen by the server side file watcher, until the server shuts down.
+async fn forward_file_events(
+    inner: Arc<Mutex<Inner>>,
+    mut receiver: UnboundedReceiver<Vec<lsp_types::FileEvent>>,
+) {
+    while let Some(changes) = receiver.recv().await {
+        let mut guard = inner.lock().await;
+        if guard.is_shut_down() {
+            break;
+        }
+        let renames = guard.handle_watched_files_change(changes);
+        let client = guard.client.clone();
+        drop(guard);
+        apply_module_renames(&client, renames).await;
+    }
+}
+
+/// Send the module renames following renamed files to the client,
+/// without holding the lock while it applies them.
+async fn apply_module_renames(client: &Client, renames: Option<WorkspaceEdit>) {
+    let edit = match renames {
+        Some(e) => e,
+        None => return,
+    };
+    match client.apply_edit(edit).await {
+        Ok(r) if !r.applied => warn!("the client doesn't rename the modules of renamed files"),
+        Ok(_) => {}
+        Err(e) => warn!("fail to rename the modules of renamed files, {}", e),
+    }
+}
+
+/// Check the saved documents once `delay` passed without another save.
+async fn check_saved_documents(inner: Arc<Mutex<Inner>>, generation: u64, delay: Duration) {
+    tokio::time::delay_for(delay).await;
+    let mut inner = inner.lock().await;
+    if inner.is_shut_down() || inner.save_generation != generation {
+        return;
+    }
+    inner.diagnose_saved_documents();
+}
+
+/// Apply incremental changes to an open document.
+/// Apply `params` to their document, or queue them until it's open:
+/// a change may overtake the open of its document, which waits for the server lock.
+fn apply_document_changes(
+    docs: &DashMap<Url, MoveDocument>,
+    pending: &DashMap<Url, Vec<DidChangeTextDocumentParams>>,
+    params: DidChangeTextDocumentParams,
+) {
+    debug!("file changed: {:?}", &params);
+    let uri = params.text_document.uri.clone();
+    // the queue stays locked while the document is looked up,
+    // so that an open can't miss a change queued meanwhile.
+    let mut queued = pending.entry(uri.clone()).or_insert_with(Vec::new);
+    match docs.get_mut(&uri) {
+        Some(mut doc) => edit_document(&mut doc, params),
+        None => queued.push(params),
+    }
+}
+
+/// Insert `doc`, just opened, with the changes of newer versions which overtook its open.
+fn open_document(
+    docs: &DashMap<Url, MoveDocument>,
+    pending: &DashMap<Url, Vec<DidChangeTextDocumentParams>>,
+    uri: Url,
+    mut doc: MoveDocument,
+) {
+    let mut queued = pending.entry(uri.clone()).or_insert_with(Vec::new);
+    let mut changes = std::mem::take(&mut *queued);
+    changes.sort_by_key(|c| c.text_document.version);
+    let version = doc.doc().version() as i64;
+    for change in changes
+        .into_iter()
+        .filter(|c| c.text_document.version.map_or(false, |v| v > version))
+    {
+        edit_document(&mut doc, change);
+    }
+    docs.insert(uri, doc);
+}
+
+fn edit_document(doc: &mut MoveDocument, params: DidChangeTextDocumentParams) {
+    let DidChangeTextDocumentParams {
+        text_document,
+        content_changes,
+    } = params;
+    // a change without version still makes a newer document.
+    let version = text_document
+        .version
+        .map_or(doc.doc().version() + 1, |v| v as u64);
+    for change in content_changes {
+        match change.range {
+            Some(range) => doc.edit_many(version, std::iter::once((range, change.text))),
+            // full document sync.
+            None => doc.reset_with(version, change.text),
+        }
+    }
+}
+
+fn skipped_file_diagnostic(reason: &SkipReason) -> Diagnostic {
+    Diagnostic {
+        range: lsp_types::Range::default(),
+        severity: Some(DiagnosticSeverity::Warning),
+        message: reason.to_string(),
+        ..Default::default()
+    }
+}
+
+/// Tells the user that syntax features answer from an outdated tree,
+/// when the document failed to parse.
+fn parse_failure_diagnostic(doc: &MoveDocument) -> Option<Diagnostic> {
+    if !doc.parse_failed() {
+        return None;
+    }
+    Some(Diagnostic {
+        range: lsp_types::Range::default(),
+        severity: Some(DiagnosticSeverity::Warning),
+        message: "fail to parse the document into a syntax tree, \
+                  navigation and symbols may be outdated"
+            .to_string(),
+        ..Default::default()
+    })
+}
+
+fn symbol_kind(kind: analysis::SymbolKind) -> lsp_types::SymbolKind {
+    match kind {
+        analysis::SymbolKind::Module => lsp_types::SymbolKind::Module,
+        analysis::SymbolKind::Struct => lsp_types::SymbolKind::Struct,
+        analysis::SymbolKind::Function | analysis::SymbolKind::Script => {
+            lsp_types::SymbolKind::Function
+        }
+        analysis::SymbolKind::Constant => lsp_types::SymbolKind::Constant,
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DisassembleArgs {
+    /// a compiled `.mv` file, or a move source file.
+    file: Url,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunScriptArgs {
+    file: Url,
+    /// default to the configured sender address.
+    #[serde(default)]
+    sender: Option<String>,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    type_args: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct RunTestArgs {
+    file: Url,
+    /// name of the module defining the test function.
+    module: String,
+    function: String,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CompilationArgs {
     file: Url,
-    out_dir: PathBuf,
+    /// default to `target` under the workspace root.
+    #[serde(default)]
+    out_dir: Option<PathBuf>,
+    /// compile even if the sources are unchanged since the last compilation.
+    #[serde(default)]
+    force: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lsp_types::{
+        FileChangeType::{Changed, Created, Deleted},
+        FileEvent, Position, Range, TextDocumentContentChangeEvent,
+    };
+
+    #[test]
+    fn test_change_before_open() {
+        let docs = DashMap::new();
+        let pending = DashMap::new();
+        let uri = Url::parse("file:///m.move").unwrap();
+        let change = |version: i64, start: u64, end: u64, text: &str| DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier {
+                uri: uri.clone(),
+                version: Some(version),
+            },
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: Some(Range::new(Position::new(0, start), Position::new(0, end))),
+                range_length: None,
+                text: text.to_string(),
+            }],
+        };
+
+        // changes overtake the open, which waits for the server lock.
+        apply_document_changes(&docs, &pending, change(3, 8, 8, "2"));
+        apply_document_changes(&docs, &pending, change(2, 7, 8, "N"));
+        assert!(docs.is_empty());
+        open_document(
+            &docs,
+            &pending,
+            uri.clone(),
+            MoveDocument::new(1, "module M {}\n"),
+        );
+        {
+            let doc = docs.get(&uri).unwrap();
+            assert_eq!(doc.doc().version(), 3);
+            assert_eq!(doc.doc().rope().slice_to_cow(..), "module N2 {}\n");
+        }
+
+        // once open, changes go to the document.
+        apply_document_changes(&docs, &pending, change(4, 7, 9, "P"));
+        let doc = docs.get(&uri).unwrap();
+        assert_eq!(doc.doc().version(), 4);
+        assert_eq!(doc.doc().rope().slice_to_cow(..), "module P {}\n");
+        assert!(pending.get(&uri).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_full_change_without_version() {
+        let mut doc = MoveDocument::new(1, "module M {}\n");
+        edit_document(
+            &mut doc,
+            DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier {
+                    uri: Url::parse("file:///m.move").unwrap(),
+                    version: None,
+                },
+                content_changes: vec![TextDocumentContentChangeEvent {
+                    range: None,
+                    range_length: None,
+                    text: "module N {}\n".to_string(),
+                }],
+            },
+        );
+        assert_eq!(doc.doc().version(), 2);
+        assert_eq!(doc.doc().rope().slice_to_cow(..), "module N {}\n");
+    }
+
+    #[test]
+    fn test_coalesce_file_events() {
+        let path = |name: &str| std::env::temp_dir().join(name);
+        let event = |name: &str, typ| FileEvent::new(Url::from_file_path(path(name)).unwrap(), typ);
+        let changes = vec![
+            // a burst of writes.
+            event("A.move", Changed),
+            event("A.move", Changed),
+            event("A.move", Changed),
+            // written right after it's created.
+            event("B.move", Created),
+            event("B.move", Changed),
+            // a temporary file.
+            event("C.move", Created),
+            event("C.move", Changed),
+            event("C.move", Deleted),
+            // replaced, like by a git checkout.
+            event("D.move", Deleted),
+            event("D.move", Created),
+        ];
+        let expected = vec![
+            (canonicalize(&path("A.move")), Changed),
+            (canonicalize(&path("B.move")), Created),
+            (canonicalize(&path("C.move")), Deleted),
+            (canonicalize(&path("D.move")), Created),
+        ];
+        assert_eq!(coalesce_file_events(changes), expected);
+    }
 }
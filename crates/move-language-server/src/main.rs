@@ -1,15 +1,147 @@
-use move_language_server::lsp_server::MoveLanguageServer;
+use move_language_server::{
+    analysis::{apply_config, Analysis},
+    config::{self, ProjectConfig},
+    logging, lsif,
+    lsp_ext::BuildInfo,
+    lsp_server::MoveLanguageServer,
+    salsa::RootDatabase,
+    tree_sitter_move,
+    utils::canonicalize,
+    version::VersionProvider,
+};
+use std::{
+    fs::File,
+    io::{BufWriter, Read},
+    path::PathBuf,
+};
 use tower_lsp::{LspService, Server};
 
 pub mod built_info {
     include!(concat!(env!("OUT_DIR"), "/built.rs"));
 }
 
+struct BuiltVersion;
+
+impl VersionProvider for BuiltVersion {
+    fn build_info(&self) -> BuildInfo {
+        BuildInfo {
+            version: built_info::PKG_VERSION.to_string(),
+            git_commit: built_info::GIT_COMMIT_HASH.map(|h| h.to_string()),
+            git_dirty: built_info::GIT_DIRTY,
+            built_time: Some(built_info::BUILT_TIME_UTC.to_string()),
+            target: Some(built_info::TARGET.to_string()),
+            rustc_version: Some(built_info::RUSTC_VERSION.to_string()),
+        }
+    }
+}
+
+/// Value of the `--log-file <path>` option.
+fn log_file_arg() -> Option<PathBuf> {
+    let mut args = std::env::args_os().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--log-file" {
+            return args.next().map(PathBuf::from);
+        }
+    }
+    None
+}
+
+/// `move-language-server lsif [<project folder>] [--output <file>]`:
+/// dump the project configured by its `Move.toml` as LSIF, to stdout by default.
+fn lsif_command(args: Vec<String>) -> anyhow::Result<()> {
+    let mut folder = None;
+    let mut output = None;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--output" => output = args.next().map(PathBuf::from),
+            _ => folder = Some(PathBuf::from(arg)),
+        }
+    }
+    let folder = canonicalize(&folder.unwrap_or(std::env::current_dir()?));
+    let config = ProjectConfig::load(Some(&folder), serde_json::Value::Null)?;
+    let mut db = RootDatabase::default();
+    apply_config(&mut db, &config);
+    match output {
+        Some(output) => lsif::dump(&db, &folder, BufWriter::new(File::create(output)?)),
+        None => lsif::dump(&db, &folder, BufWriter::new(std::io::stdout())),
+    }
+}
+
+/// `move-language-server analyze --path <file> [--stdin] [<project folder>]`:
+/// check `file` with the project configured by its `Move.toml`, reading `file` from stdin
+/// with `--stdin`, and print the diagnostics of `file` as json. Fails if there are any,
+/// errors of other project files are left to their own check.
+fn analyze_command(args: Vec<String>) -> anyhow::Result<bool> {
+    let mut folder = None;
+    let mut path = None;
+    let mut stdin = false;
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--path" => path = args.next().map(PathBuf::from),
+            "--stdin" => stdin = true,
+            _ => folder = Some(PathBuf::from(arg)),
+        }
+    }
+    let path = path.ok_or_else(|| anyhow::anyhow!("missing --path <file>"))?;
+    let cwd = std::env::current_dir()?;
+    // with `--stdin`, the file doesn't have to exist.
+    let path = cwd.join(path);
+    let folder = canonicalize(&folder.unwrap_or(cwd));
+    let mut analysis = Analysis::load(&folder)?;
+    if stdin {
+        let mut text = String::new();
+        std::io::stdin().read_to_string(&mut text)?;
+        analysis.set_file_content(&path, &text);
+    }
+    let file = canonicalize(&path);
+    let diagnostics: Vec<_> = analysis
+        .check_file(&path)
+        .into_iter()
+        .filter(|d| d.primary.file == file)
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&diagnostics)?);
+    Ok(diagnostics.is_empty())
+}
+
 #[tokio::main]
 async fn main() {
-    env_logger::init();
+    let args: Vec<_> = std::env::args().skip(1).collect();
+    if args.iter().any(|a| a == "--print-config-schema") {
+        match serde_json::to_string_pretty(&config::config_schema()) {
+            Ok(schema) => println!("{}", schema),
+            Err(e) => {
+                eprintln!("fail to print config schema, {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("lsif") {
+        if let Err(e) = lsif_command(args[1..].to_vec()) {
+            eprintln!("fail to dump lsif, {}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    if args.first().map(String::as_str) == Some("analyze") {
+        match analyze_command(args[1..].to_vec()) {
+            Ok(true) => {}
+            Ok(false) => std::process::exit(1),
+            Err(e) => {
+                eprintln!("fail to analyze, {}", e);
+                std::process::exit(2);
+            }
+        }
+        return;
+    }
+
+    if let Err(e) = logging::init(log_file_arg().as_deref()) {
+        eprintln!("fail to init logging, {}", e);
+    }
 
-    log::info!(
+    tracing::info!(
         "Version {}, built for {} by {} at {}.",
         built_info::PKG_VERSION,
         built_info::TARGET,
@@ -21,14 +153,29 @@ async fn main() {
         built_info::GIT_DIRTY,
         built_info::GIT_COMMIT_HASH,
     ) {
-        log::info!(
+        tracing::info!(
             "git `{}`, commit {}({}).",
             v,
             hash,
             if dirty { "dirty" } else { "clean" }
         );
     }
-    log::info!("Starting language server");
+    if let Err(e) = tree_sitter_move::check_grammar() {
+        tracing::error!("{}", e);
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+    tracing::info!(
+        "Starting language server, grammar abi version {}{}",
+        tree_sitter_move::abi_version(),
+        if tree_sitter_move::is_dynamic() {
+            ", loaded from $TREE_SITTER_MOVE_LIB"
+        } else if tree_sitter_move::EXTERNAL_SCANNER {
+            ", with an external scanner"
+        } else {
+            ""
+        }
+    );
 
     // let mut rt = tokio::runtime::Builder::new()
     //     .threaded_scheduler()
@@ -39,8 +186,9 @@ async fn main() {
     // let rt_handle = rt.handle().clone();
 
     // start server
-    let (service, msg_stream) = LspService::new(|client| MoveLanguageServer::new(client));
-    let stdin = tokio::io::stdin();
+    let (service, msg_stream) =
+        LspService::new(|client| MoveLanguageServer::with_version_provider(client, &BuiltVersion));
+    let stdin = logging::handle_set_trace(tokio::io::stdin());
     let stdout = tokio::io::stdout();
     Server::new(stdin, stdout)
         .interleave(msg_stream)
@@ -1,18 +1,25 @@
 #![allow(unused)]
 
-use super::tree_sitter_move::Parser;
-use crate::{node_resolver::NodeResolver, tree_sitter_move::parser};
+#[cfg(feature = "lsp")]
+use crate::{
+    node_resolver::NodeResolver,
+    tree_sitter_move::{reset_parser, with_parser},
+};
 use anyhow::{bail, ensure, Result};
 use parking_lot::RwLock;
 use serde::export::Formatter;
-use std::cell::Cell;
-use tower_lsp::lsp_types;
+use std::{borrow::Cow, cell::Cell, collections::VecDeque};
+#[cfg(feature = "lsp")]
 use tree_sitter::{InputEdit, Node, Point, Query, Tree};
 use xi_rope::{
     rope::{BaseMetric, Utf16CodeUnitsMetric},
     Cursor, DeltaBuilder, Interval, LinesMetric, Rope, RopeDelta,
 };
 
+/// Edits kept by a document for `MoveDocument::edits_since`.
+#[cfg(feature = "lsp")]
+const MAX_EDIT_LOG: usize = 64;
+
 #[derive(Clone, Debug)]
 pub struct RopeDoc {
     rope: Rope,
@@ -66,32 +73,57 @@ impl RopeDoc {
     }
 }
 
+/// An immutable snapshot of a syntax tree.
+/// Cloning is cheap, and a clone can be moved to another thread.
+#[cfg(feature = "lsp")]
+#[derive(Clone)]
+pub struct SyntaxTree(Tree);
+
+#[cfg(feature = "lsp")]
+impl SyntaxTree {
+    pub fn root_node(&self) -> Node {
+        self.0.root_node()
+    }
+}
+
+/// A rope with its tree-sitter syntax tree, kept in sync on every edit.
+/// Parsing goes through the parser of the current thread, so documents don't own one.
+#[cfg(feature = "lsp")]
 pub struct MoveDocument {
     doc: RopeDoc,
 
-    parser: Parser,
-    tree: Option<Tree>,
+    /// the last good tree, it's stale when `parse_failed`.
+    tree: Option<SyntaxTree>,
+    parse_failed: bool,
+    /// bumped by every change of the text, results computed from the document
+    /// can be kept until it changes.
+    generation: u64,
+    /// edits of the latest generations, oldest first, with the generation each one made.
+    edits: VecDeque<(u64, InputEdit)>,
 }
 
-unsafe impl Send for MoveDocument {}
-unsafe impl Sync for MoveDocument {}
-
+#[cfg(feature = "lsp")]
 impl MoveDocument {
     pub fn new<S: AsRef<str>>(version: u64, s: S) -> Self {
-        let rope = Rope::from(s.as_ref());
-        let rope_doc = RopeDoc::new(version, s);
-
-        let mut parser = parser();
-        let tree = parser.parse_with(&mut |offset, _pos| get_chunk(rope_doc.rope(), offset), None);
-        if tree.is_none() {
-            parser.reset();
-            warn!("Fail to parse input into move syntax tree");
+        let mut doc = Self {
+            doc: RopeDoc::new(version, s),
+            tree: None,
+            parse_failed: false,
+            generation: 0,
+            edits: VecDeque::new(),
         };
+        doc.reparse_tree();
+        doc
+    }
 
+    /// A document whose syntax tree is already parsed from `s`.
+    pub fn with_tree<S: AsRef<str>>(version: u64, s: S, tree: Tree) -> Self {
         Self {
-            doc: rope_doc,
-            parser,
-            tree,
+            doc: RopeDoc::new(version, s),
+            tree: Some(SyntaxTree(tree)),
+            parse_failed: false,
+            generation: 0,
+            edits: VecDeque::new(),
         }
     }
 
@@ -99,6 +131,75 @@ impl MoveDocument {
         &self.doc
     }
 
+    /// The syntax tree, which is the last good one if the latest parse failed.
+    pub fn tree(&self) -> Option<&SyntaxTree> {
+        self.tree.as_ref()
+    }
+
+    /// Whether the latest text failed to parse, even with a fresh parser,
+    /// so that `tree` may not match the text.
+    pub fn parse_failed(&self) -> bool {
+        self.parse_failed
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Edits turning the text of `generation` into the current one, in order.
+    /// `None` if they are not known anymore, e.g. the text was reset since.
+    pub fn edits_since(&self, generation: u64) -> Option<Vec<InputEdit>> {
+        if generation == self.generation {
+            return Some(vec![]);
+        }
+        let first = self.edits.iter().position(|(g, _)| *g > generation)?;
+        // the log must go back to the edit made right after `generation`.
+        if self.edits[first].0 != generation + 1 {
+            return None;
+        }
+        Some(
+            self.edits
+                .iter()
+                .skip(first)
+                .map(|(_, e)| e.clone())
+                .collect(),
+        )
+    }
+
+    /// Text of a node in this document, empty if the node is from an outdated tree
+    /// which doesn't fit the text anymore.
+    pub fn node_text(&self, node: &Node) -> Cow<str> {
+        rope_text(self.doc.rope(), node.byte_range())
+    }
+
+    /// Lsp range of a node in this document.
+    pub fn node_range(&self, node: &Node) -> Option<lsp_types::Range> {
+        Some(lsp_types::Range::new(
+            self.doc.to_position(node.start_byte())?,
+            self.doc.to_position(node.end_byte())?,
+        ))
+    }
+
+    /// The leaf node at `pos`, with its named ancestors and ranges,
+    /// the position resolution every provider starts from.
+    pub fn node_context(&self, pos: lsp_types::Position) -> Option<NodeContext> {
+        let leaf = self.resolve_to_leaf_node(pos)?;
+        let mut ancestors = vec![];
+        let mut node = leaf.parent();
+        while let Some(n) = node {
+            if n.is_named() {
+                ancestors.push(n);
+            }
+            node = n.parent();
+        }
+        Some(NodeContext {
+            leaf,
+            ancestors,
+            byte_range: leaf.start_byte()..leaf.end_byte(),
+            range: self.node_range(&leaf)?,
+        })
+    }
+
     pub fn resolve_to_leaf_node(&self, pos: lsp_types::Position) -> Option<Node> {
         let offset = self.doc.to_offset(pos)?;
         self.tree
@@ -118,8 +219,9 @@ impl MoveDocument {
         edits: impl Iterator<Item = (lsp_types::Range, S)>,
     ) {
         for (range, text) in edits {
-            // TODO: better handle this.
-            let _ = self.edit(range, text);
+            if let Err(e) = self.edit(range, text) {
+                warn!("ignore invalid edit, {}", e);
+            }
         }
         self.doc.incr_version(version);
     }
@@ -127,59 +229,100 @@ impl MoveDocument {
     /// FIXME: As lsp use utf16 for it text position.(see https://github.com/microsoft/language-server-protocol/issues/376)
     /// We need to adjust range to utf8, as rope store text using rust String which is based on utf8.
     /// Once it's solved, we can use incremental doc sync.
-    pub fn edit<S: AsRef<str>>(&mut self, range: lsp_types::Range, text: S) {
+    pub fn edit<S: AsRef<str>>(&mut self, range: lsp_types::Range, text: S) -> Result<()> {
+        let (start, end) = match (
+            self.doc.to_offset(range.start),
+            self.doc.to_offset(range.end),
+        ) {
+            (Some(start), Some(end)) if start <= end => (start, end),
+            _ => bail!("range {:?} out of document", range),
+        };
         let old_doc = self.doc.clone();
 
         // edit rope
-        let iv = Interval {
-            start: self.doc.to_offset(range.start).unwrap(),
-            end: self.doc.to_offset(range.end).unwrap(),
-        };
+        let iv = Interval { start, end };
         let new_end_offset = self.doc.edit(iv, text);
 
         // edit tree if tree exists.
+        let edit = input_edit(&old_doc.rope, iv, &self.doc.rope, new_end_offset);
         if let Some(t) = &mut self.tree {
-            let old_start_point = offset_to_point(&old_doc.rope, iv.start);
-            let old_end_point = offset_to_point(&old_doc.rope, iv.end);
-            let new_end_point = offset_to_point(&self.doc.rope, new_end_offset);
-
-            let edit = InputEdit {
-                start_byte: iv.start,
-                old_end_byte: iv.end,
-                new_end_byte: new_end_offset,
-                start_position: old_end_point,
-                old_end_position: old_end_point,
-                new_end_position: new_end_point,
-            };
-            t.edit(&edit);
+            t.0.edit(&edit);
+        }
+        self.generation += 1;
+        self.edits.push_back((self.generation, edit));
+        if self.edits.len() > MAX_EDIT_LOG {
+            self.edits.pop_front();
         }
 
         self.reparse_tree();
+        Ok(())
     }
 
     pub fn reset_with(&mut self, version: u64, text: impl AsRef<str>) {
         self.doc = RopeDoc::new(version, text);
-        self.parser.reset();
+        self.generation += 1;
+        self.edits.clear();
         self.reparse_tree();
     }
 
     fn reparse_tree(&mut self) {
         let rope = self.doc.rope().clone();
+        let mut input = |offset: usize, _pos: Point| get_chunk(&rope, offset);
 
-        let old_tree = self.tree.clone();
-        let tree = self.parser.parse_with(
-            &mut |offset, _pos| get_chunk(&rope, offset),
-            old_tree.as_ref(),
-        );
+        let old_tree = self.tree.as_ref().map(|t| &t.0);
+        let mut tree = with_parser(|p| p.parse_with(&mut input, old_tree));
         if tree.is_none() {
-            warn!("Fail to parse input into move syntax tree");
-            self.parser.reset();
+            // the parser may be left in a bad state, retry from scratch with a fresh one.
+            warn!("Fail to parse input into move syntax tree, retry with a fresh parser");
+            reset_parser();
+            tree = with_parser(|p| p.parse_with(&mut input, None));
+        }
+
+        match tree {
+            Some(tree) => {
+                self.tree = Some(SyntaxTree(tree));
+                self.parse_failed = false;
+            }
+            None => {
+                warn!("Fail to parse input into move syntax tree, keep the last good one");
+                reset_parser();
+                self.parse_failed = true;
+            }
         }
+    }
+}
+
+/// A node at some position of a document, see `MoveDocument::node_context`.
+#[cfg(feature = "lsp")]
+#[derive(Debug)]
+pub struct NodeContext<'a> {
+    /// The smallest node at the position.
+    pub leaf: Node<'a>,
+    /// Named ancestors of `leaf`, innermost first, up to the root.
+    pub ancestors: Vec<Node<'a>>,
+    /// Byte range of `leaf`.
+    pub byte_range: std::ops::Range<usize>,
+    /// Lsp range of `leaf`, in utf16 code units.
+    pub range: lsp_types::Range,
+}
+
+#[cfg(feature = "lsp")]
+impl<'a> NodeContext<'a> {
+    /// `leaf` if it's of `kind`, or its nearest ancestor of `kind`.
+    pub fn nearest(&self, kind: &str) -> Option<Node<'a>> {
+        self.nearest_of(&[kind])
+    }
 
-        self.tree = tree;
+    /// `leaf` if it's of one of `kinds`, or its nearest ancestor of one of them.
+    pub fn nearest_of(&self, kinds: &[&str]) -> Option<Node<'a>> {
+        std::iter::once(&self.leaf)
+            .chain(self.ancestors.iter())
+            .find(|n| kinds.contains(&n.kind()))
+            .copied()
     }
 }
 
+#[cfg(feature = "lsp")]
 impl std::fmt::Display for MoveDocument {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.doc.rope())
@@ -203,6 +346,9 @@ pub fn position_to_offset(rope: &Rope, pos: lsp_types::Position) -> Option<usize
 }
 
 pub fn offset_to_position(rope: &Rope, offset: usize) -> Option<lsp_types::Position> {
+    if offset > rope.len() || !rope.is_codepoint_boundary(offset) {
+        return None;
+    }
     let line = rope.line_of_offset(offset);
     let offset_of_line_start = rope.count_base_units::<LinesMetric>(line as usize);
     let sub_rope = rope.slice(offset_of_line_start..offset);
@@ -213,6 +359,26 @@ pub fn offset_to_position(rope: &Rope, offset: usize) -> Option<lsp_types::Posit
     })
 }
 
+#[cfg(feature = "lsp")]
+/// The tree-sitter edit which turns `old_rope` into `new_rope`,
+/// where `iv` of `old_rope` is replaced by text ending at `new_end_offset` of `new_rope`.
+pub fn input_edit(
+    old_rope: &Rope,
+    iv: Interval,
+    new_rope: &Rope,
+    new_end_offset: usize,
+) -> InputEdit {
+    InputEdit {
+        start_byte: iv.start,
+        old_end_byte: iv.end,
+        new_end_byte: new_end_offset,
+        start_position: offset_to_point(old_rope, iv.start),
+        old_end_position: offset_to_point(old_rope, iv.end),
+        new_end_position: offset_to_point(new_rope, new_end_offset),
+    }
+}
+
+#[cfg(feature = "lsp")]
 pub fn offset_to_point(rope: &Rope, offset: usize) -> Point {
     let row = rope.line_of_offset(offset);
     let line_offset = rope.offset_of_line(row);
@@ -220,6 +386,20 @@ pub fn offset_to_point(rope: &Rope, offset: usize) -> Point {
     Point { row, column }
 }
 
+/// Text of the byte `range` of `rope`, empty if the range is out of bounds or splits a character.
+/// Ranges of syntax nodes go through this, as the tree may be older than the text.
+pub fn rope_text(rope: &Rope, range: std::ops::Range<usize>) -> Cow<str> {
+    let valid = range.start <= range.end
+        && range.end <= rope.len()
+        && rope.is_codepoint_boundary(range.start)
+        && rope.is_codepoint_boundary(range.end);
+    if valid {
+        rope.slice_to_cow(range)
+    } else {
+        Cow::Borrowed("")
+    }
+}
+
 pub fn get_chunk(rope: &Rope, offset: usize) -> &str {
     let c = Cursor::new(&rope, offset);
     if let Some((node, idx)) = c.get_leaf() {
@@ -229,11 +409,13 @@ pub fn get_chunk(rope: &Rope, offset: usize) -> &str {
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "lsp"))]
 mod tests {
     use super::*;
-    use lsp_types::Range;
-    use tower_lsp::lsp_types::Position;
+    use crate::tree_sitter_move::{kinds, parser};
+    use lsp_types::{Position, Range};
+    use proptest::prelude::*;
+    use std::sync::atomic::AtomicUsize;
 
     #[test]
     fn test_lsp_position_to_offset() {
@@ -289,6 +471,19 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_rope_text() {
+        // `é` takes two bytes, at 3..5.
+        let rope = Rope::from("// é\nM");
+        assert_eq!(rope_text(&rope, 6..7), "M");
+        assert_eq!(rope_text(&rope, 3..5), "é");
+        assert_eq!(rope_text(&rope, 4..7), "");
+        assert_eq!(rope_text(&rope, 6..9), "");
+        assert_eq!(offset_to_position(&rope, 4), None);
+        assert_eq!(offset_to_position(&rope, 9), None);
+        assert_eq!(offset_to_position(&rope, 6), Some(Position::new(1, 0)));
+    }
+
     #[test]
     fn test_position_resolve() {
         let mut doc = MoveDocument::new(1, "module Abc {}");
@@ -308,14 +503,63 @@ mod tests {
         println!("kind: {}, range: {:?}", node.kind(), node.range());
     }
 
+    #[test]
+    fn test_node_context() {
+        let doc = MoveDocument::new(1, "module Abc {\n    fun f() { x }\n}\n");
+        let context = doc.node_context(Position::new(1, 14)).unwrap();
+        assert!(context.leaf.is_named());
+        assert_eq!(
+            context.range,
+            Range::new(Position::new(1, 14), Position::new(1, 15))
+        );
+        let text = doc.to_string();
+        assert_eq!(&text[context.byte_range.clone()], "x");
+        assert!(context.ancestors.iter().all(|n| n.is_named()));
+        assert_eq!(
+            context.ancestors.last().map(|n| n.kind()),
+            Some("source_file")
+        );
+
+        let function = context.nearest(kinds::FUNCTION_DEFINITION).unwrap();
+        assert_eq!(function.start_position().row, 1);
+        assert_eq!(
+            context.nearest_of(&["no_such_kind", kinds::MODULE_DEFINITION]),
+            context
+                .ancestors
+                .iter()
+                .copied()
+                .find(|n| n.kind() == kinds::MODULE_DEFINITION)
+        );
+        assert!(context.nearest("no_such_kind").is_none());
+    }
+
     #[test]
     fn test_edit() {
         let mut doc = MoveDocument::new(1, "");
         let range = Range::new(Position::new(0, 0), Position::new(0, 0));
         let new_text = "address 0x1 {}".to_string();
-        doc.edit(range, new_text.clone());
+        doc.edit(range, new_text.clone()).unwrap();
 
         assert_eq!(format!("{}", &doc), new_text);
+
+        let out_of_doc = Range::new(Position::new(3, 0), Position::new(3, 0));
+        assert!(doc.edit(out_of_doc, "").is_err());
+    }
+
+    #[test]
+    fn test_edits_since() {
+        let mut doc = MoveDocument::new(1, "module A {}");
+        assert_eq!(doc.edits_since(0).map(|e| e.len()), Some(0));
+        let range = Range::new(Position::new(0, 7), Position::new(0, 8));
+        doc.edit(range, "B").unwrap();
+        doc.edit(range, "C").unwrap();
+        assert_eq!(doc.generation(), 2);
+        assert_eq!(doc.edits_since(0).unwrap().len(), 2);
+        assert_eq!(doc.edits_since(1).unwrap().len(), 1);
+
+        doc.reset_with(2, "module D {}");
+        assert!(doc.edits_since(2).is_none());
+        assert_eq!(doc.edits_since(3).map(|e| e.len()), Some(0));
     }
 
     #[test]
@@ -323,12 +567,82 @@ mod tests {
         let mut doc = MoveDocument::new(1, "module Abc {}");
         let range = Range::new(Position::new(0, 8), Position::new(0, 9));
         let new_text = "≤".to_string();
-        doc.edit(range, new_text);
+        doc.edit(range, new_text).unwrap();
 
         assert_eq!(format!("{}", &doc), "module A≤c {}");
         assert!(doc.tree.is_some());
     }
 
+    #[test]
+    fn test_input_edit_multiline() {
+        let old = Rope::from("module A {\n    fun f() {}\n}\n");
+        let mut new = old.clone();
+        // replace `f() {}` on the second line with two lines.
+        let iv = Interval { start: 19, end: 25 };
+        let text = "g() {\n        1;\n    }";
+        new.edit(iv, text);
+
+        let edit = input_edit(&old, iv, &new, iv.start + text.len());
+        assert_eq!(edit.start_position, Point { row: 1, column: 8 });
+        assert_eq!(edit.old_end_position, Point { row: 1, column: 14 });
+        assert_eq!(edit.new_end_position, Point { row: 3, column: 5 });
+    }
+
+    #[test]
+    fn test_recover_from_parse_failure() {
+        static CANCELLED: AtomicUsize = AtomicUsize::new(1);
+        let mut doc = MoveDocument::new(1, "module Abc {}");
+        // every parse of this thread's parser is cancelled, the fresh one takes over.
+        with_parser(|p| unsafe { p.set_cancellation_flag(Some(&CANCELLED)) });
+        let range = Range::new(Position::new(0, 8), Position::new(0, 9));
+        doc.edit(range, "x").unwrap();
+
+        assert!(!doc.parse_failed());
+        assert_same_as_fresh_parse(&doc);
+    }
+
+    fn assert_same_as_fresh_parse(doc: &MoveDocument) {
+        let text = doc.to_string();
+        let fresh = parser().parse(text.as_str(), None).unwrap();
+        let incremental = doc.tree.as_ref().unwrap();
+        assert_eq!(
+            incremental.root_node().to_sexp(),
+            fresh.root_node().to_sexp(),
+            "text: {:?}",
+            text
+        );
+    }
+
+    const PROPTEST_SOURCE: &str = "address 0x1 {\nmodule A {\n    use 0x1::B;\n    struct S { f: u64 }\n    fun f(a: u64): u64 {\n        let b = a + 1; // ≤\n        b\n    }\n}\n}\n";
+
+    proptest! {
+        #[test]
+        fn test_incremental_parse_equals_fresh_parse(
+            edits in prop::collection::vec(
+                (any::<prop::sample::Index>(), 0usize..8, "[a-z0-9 {}();:\n≤𐐀]{0,8}"),
+                1..10,
+            )
+        ) {
+            let mut doc = MoveDocument::new(1, PROPTEST_SOURCE);
+            for (start, len, text) in edits {
+                let current = doc.to_string();
+                let boundaries: Vec<usize> = current
+                    .char_indices()
+                    .map(|(i, _)| i)
+                    .chain(std::iter::once(current.len()))
+                    .collect();
+                let start_idx = start.index(boundaries.len());
+                let end_idx = (start_idx + len).min(boundaries.len() - 1);
+                let range = Range::new(
+                    doc.doc().to_position(boundaries[start_idx]).unwrap(),
+                    doc.doc().to_position(boundaries[end_idx]).unwrap(),
+                );
+                doc.edit(range, text.as_str()).unwrap();
+                assert_same_as_fresh_parse(&doc);
+            }
+        }
+    }
+
     #[test]
     fn test_edit_many() {
         let mut doc = MoveDocument::new(1, "address 0x1 {}");
@@ -1,10 +1,18 @@
 #![allow(unused)]
-use crate::{move_document::RopeDoc, salsa::RootDatabase, tree_sitter_move::language};
-use tree_sitter::{Query, QueryCursor, Range};
+use crate::{
+    move_document::{rope_text, RopeDoc},
+    salsa::RootDatabase,
+    tree_sitter_move::kinds::{self, MODULE_IDENTIFIER},
+};
+use query_store::QueryStore;
+use tree_sitter::{QueryCursor, Range};
 use xi_rope::Rope;
 
 pub const USE_QUERY: &str = include_str!("../queries/use.scm");
+pub const HIGHLIGHT_QUERY: &str = include_str!("../queries/highlight.scm");
+pub const LOCALS_QUERY: &str = include_str!("../queries/locals.scm");
 pub mod module_resolver;
+pub mod query_store;
 
 pub struct NodeResolver {
     rope: Rope,
@@ -26,20 +34,20 @@ pub enum Resolved {
         address: Option<Range>,
     },
 }
-const MODULE_IDENTIFIER: &str = "module_identifier";
-const STRUCT_IDENTIFIER: &str = "struct_identifier";
-const FUNCTION_IDENTIFIER: &str = "function_identifier";
-const VARIABLE_IDENTIFIER: &str = "variable_identifier";
 
 impl NodeResolver {
     pub fn resolve(n: &tree_sitter::Node, root: &tree_sitter::Node) -> Option<Resolved> {
         match n.kind() {
             MODULE_IDENTIFIER => {
                 let range = n.range();
-                let address = if n.parent().map(|p| p.kind() == "module_access").is_some() {
+                let address = if n
+                    .parent()
+                    .map(|p| p.kind() == kinds::MODULE_ACCESS)
+                    .is_some()
+                {
                     let prev_sibling = n.prev_named_sibling();
                     prev_sibling
-                        .filter(|s| s.kind() == "address_literal")
+                        .filter(|s| s.kind() == kinds::ADDRESS_LITERAL)
                         .map(|s| s.range())
                 } else {
                     None
@@ -54,37 +62,147 @@ impl NodeResolver {
         }
     }
 
-    pub fn resolve_use(node: &tree_sitter::Node) -> Vec<UseInfo> {
-        let use_query = Query::new(language(), USE_QUERY).unwrap();
+    /// Captures of the query named `query` in the `QueryStore` under `node`, in document order,
+    /// with their capture names. A node captured by several patterns is only returned with the first one.
+    /// Predicates of the query are matched against the text of the nodes in `rope`.
+    pub fn captures<'a>(
+        query: &str,
+        node: &tree_sitter::Node<'a>,
+        rope: &Rope,
+    ) -> anyhow::Result<Vec<(String, tree_sitter::Node<'a>)>> {
+        let query = QueryStore::global()
+            .get(query)
+            .ok_or_else(|| anyhow::anyhow!("no valid query named `{}`", query))?;
         let mut cursor = QueryCursor::new();
-        let matched = cursor.matches(&use_query, *node, |n| "");
+        let mut captures: Vec<(String, tree_sitter::Node)> = vec![];
+        let mut seen = std::collections::HashSet::new();
+        for (mat, idx) in cursor.captures(query, *node, |n| node_text(rope, &n)) {
+            let cap = &mat.captures[idx];
+            if !seen.insert(cap.node.byte_range()) {
+                continue;
+            }
+            let name = query.capture_names()[cap.index as usize].clone();
+            captures.push((name, cap.node));
+        }
+        Ok(captures)
+    }
+
+    /// `use` declarations under `node`, whose text is in `rope`.
+    /// Captures are looked up by their names in `queries/use.scm`,
+    /// matches without an address or a module are skipped.
+    pub fn resolve_use(node: &tree_sitter::Node, rope: &Rope) -> Vec<UseInfo> {
+        let use_query = match QueryStore::global().get(query_store::USE) {
+            Some(q) => q,
+            None => return vec![],
+        };
+        let names = use_query.capture_names();
+        let mut cursor = QueryCursor::new();
+        let matched = cursor.matches(use_query, *node, |n| node_text(rope, &n));
 
         let mut uses = vec![];
         for mat in matched {
-            let mut use_info = [None; 5];
-            for cap in mat.captures {
-                let idx = cap.index;
-                let node = cap.node;
-                use_info[idx as usize] = Some(node.range());
-            }
-
-            let [addr, module, module_alias, member, member_alias] = use_info;
+            let capture = |name: &str| {
+                mat.captures
+                    .iter()
+                    .find(|c| names[c.index as usize] == name)
+                    .map(|c| UsePart {
+                        range: c.node.range(),
+                        text: node_text(rope, &c.node),
+                    })
+            };
+            let (addr, module) = match (
+                capture(use_captures::ADDRESS),
+                capture(use_captures::MODULE),
+            ) {
+                (Some(addr), Some(module)) => (addr, module),
+                _ => continue,
+            };
             uses.push(UseInfo {
-                addr: addr.unwrap(),
-                module: module.unwrap(),
-                module_alias,
-                member,
-                member_alias,
+                addr,
+                module,
+                module_alias: capture(use_captures::MODULE_ALIAS),
+                member: capture(use_captures::MEMBER),
+                member_alias: capture(use_captures::MEMBER_ALIAS),
             });
         }
         uses
     }
 }
 
+/// Capture names of `queries/use.scm`.
+pub mod use_captures {
+    pub const ADDRESS: &str = "address";
+    pub const MODULE: &str = "module_name";
+    pub const MODULE_ALIAS: &str = "module_alias";
+    pub const MEMBER: &str = "member";
+    pub const MEMBER_ALIAS: &str = "member_alias";
+}
+
+/// Text of `node` for query predicates, owned as the rope may not hold it in one piece.
+fn node_text(rope: &Rope, node: &tree_sitter::Node) -> String {
+    rope_text(rope, node.byte_range()).into_owned()
+}
+
+/// Ranges of the parts of a `use` declaration, like `use 0x1::M::{S as T}`.
+#[derive(Clone, Debug)]
 pub struct UseInfo {
-    addr: Range,
-    module: Range,
-    module_alias: Option<Range>,
-    member: Option<Range>,
-    member_alias: Option<Range>,
+    addr: UsePart,
+    module: UsePart,
+    module_alias: Option<UsePart>,
+    member: Option<UsePart>,
+    member_alias: Option<UsePart>,
+}
+
+/// A part of a `use` declaration, with its source text.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct UsePart {
+    pub range: Range,
+    pub text: String,
+}
+
+impl UseInfo {
+    /// The address literal, like `0x1`.
+    pub fn addr(&self) -> &UsePart {
+        &self.addr
+    }
+
+    pub fn module(&self) -> &UsePart {
+        &self.module
+    }
+
+    /// `T` of `use 0x1::M as T`.
+    pub fn module_alias(&self) -> Option<&UsePart> {
+        self.module_alias.as_ref()
+    }
+
+    /// `S` of `use 0x1::M::S`.
+    pub fn member(&self) -> Option<&UsePart> {
+        self.member.as_ref()
+    }
+
+    /// `T` of `use 0x1::M::{S as T}`.
+    pub fn member_alias(&self) -> Option<&UsePart> {
+        self.member_alias.as_ref()
+    }
+
+    /// Name the module is used as in the file, its alias if any.
+    pub fn module_local_name(&self) -> &str {
+        self.module_alias().unwrap_or(&self.module).text.as_str()
+    }
+
+    /// From the address to the end of the last part.
+    pub fn byte_range(&self) -> std::ops::Range<usize> {
+        let end = [
+            Some(&self.module),
+            self.module_alias(),
+            self.member(),
+            self.member_alias(),
+        ]
+        .iter()
+        .flatten()
+        .map(|p| p.range.end_byte)
+        .max()
+        .unwrap_or(self.module.range.end_byte);
+        self.addr.range.start_byte..end
+    }
 }
@@ -0,0 +1,63 @@
+//! Tree-sitter queries of the server, compiled once on first use and shared by every provider.
+
+use super::{HIGHLIGHT_QUERY, LOCALS_QUERY, USE_QUERY};
+use crate::tree_sitter_move::language;
+use once_cell::sync::Lazy;
+use std::collections::HashMap;
+use tree_sitter::Query;
+
+pub const USE: &str = "use";
+pub const HIGHLIGHTS: &str = "highlights";
+pub const LOCALS: &str = "locals";
+
+static STORE: Lazy<QueryStore> = Lazy::new(QueryStore::new);
+
+/// Compiled queries by name. A query which doesn't compile is logged and left out,
+/// so the providers using it get nothing instead of the server panicking.
+pub struct QueryStore {
+    queries: HashMap<&'static str, Query>,
+}
+
+impl QueryStore {
+    fn new() -> Self {
+        let mut queries = HashMap::new();
+        for (name, source) in [
+            (USE, USE_QUERY),
+            (HIGHLIGHTS, HIGHLIGHT_QUERY),
+            (LOCALS, LOCALS_QUERY),
+        ]
+        .iter()
+        {
+            match Query::new(language(), source) {
+                Ok(query) => {
+                    queries.insert(*name, query);
+                }
+                Err(e) => error!("invalid {} query, {:?}", name, e),
+            }
+        }
+        Self { queries }
+    }
+
+    pub fn global() -> &'static QueryStore {
+        &STORE
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Query> {
+        self.queries.get(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_query_store() {
+        let store = QueryStore::global();
+        for name in [USE, HIGHLIGHTS, LOCALS].iter() {
+            assert!(store.get(name).is_some(), "{} query doesn't compile", name);
+        }
+        assert!(store.get("unknown").is_none());
+        assert!(std::ptr::eq(store, QueryStore::global()));
+    }
+}
@@ -0,0 +1,112 @@
+//! Diagnostics last published to the client.
+//! Overlapping checks report the same errors again and again,
+//! this keeps the client from receiving anything but changes,
+//! and diagnostics of an older document version once a newer one is published.
+
+use dashmap::DashMap;
+use std::collections::HashSet;
+use tower_lsp::lsp_types::{Diagnostic, Url};
+
+#[derive(Debug, Default)]
+pub struct PublishedDiagnostics {
+    /// document version and diagnostics of every file.
+    files: DashMap<Url, (u64, Vec<Diagnostic>)>,
+}
+
+impl PublishedDiagnostics {
+    /// Record `diagnostics` of `uri` at document `version`, deduplicated by range and message.
+    /// Returns what should be published, or `None` if the client already has the same ones,
+    /// or has the diagnostics of a newer version.
+    pub fn update(
+        &self,
+        uri: &Url,
+        version: u64,
+        diagnostics: Vec<Diagnostic>,
+    ) -> Option<Vec<Diagnostic>> {
+        let diagnostics = dedup(diagnostics);
+        if let Some(published) = self.files.get(uri) {
+            let (published_version, published) = &*published;
+            if *published_version > version || *published == diagnostics {
+                return None;
+            }
+        }
+        self.files
+            .insert(uri.clone(), (version, diagnostics.clone()));
+        Some(diagnostics)
+    }
+
+    /// The client dropped the diagnostics of `uri`, e.g. the document is closed.
+    pub fn remove(&self, uri: &Url) {
+        self.files.remove(uri);
+    }
+
+    pub fn clear(&self) {
+        self.files.clear();
+    }
+}
+
+/// Keep the first diagnostic of every range and message, in order.
+fn dedup(diagnostics: Vec<Diagnostic>) -> Vec<Diagnostic> {
+    let mut seen = HashSet::new();
+    diagnostics
+        .into_iter()
+        .filter(|d| {
+            let r = d.range;
+            seen.insert((
+                r.start.line,
+                r.start.character,
+                r.end.line,
+                r.end.character,
+                d.message.clone(),
+            ))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tower_lsp::lsp_types::{Position, Range};
+
+    fn diagnostic(line: u64, message: &str) -> Diagnostic {
+        Diagnostic {
+            range: Range::new(Position::new(line, 0), Position::new(line, 1)),
+            message: message.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_only_changes_are_published() {
+        let published = PublishedDiagnostics::default();
+        let uri = Url::parse("file:///M.move").unwrap();
+
+        let diags = vec![diagnostic(1, "a"), diagnostic(1, "a"), diagnostic(2, "a")];
+        assert_eq!(published.update(&uri, 1, diags.clone()).unwrap().len(), 2);
+        assert!(published.update(&uri, 1, diags).is_none());
+        assert_eq!(
+            published.update(&uri, 1, vec![diagnostic(1, "b")]),
+            Some(vec![diagnostic(1, "b")])
+        );
+        assert_eq!(published.update(&uri, 2, vec![]), Some(vec![]));
+
+        published.remove(&uri);
+        assert_eq!(published.update(&uri, 1, vec![]), Some(vec![]));
+    }
+
+    #[test]
+    fn test_stale_versions_are_dropped() {
+        let published = PublishedDiagnostics::default();
+        let uri = Url::parse("file:///M.move").unwrap();
+
+        assert!(published.update(&uri, 3, vec![]).is_some());
+        // a check of version 2 finishing after the one of version 3.
+        assert!(published
+            .update(&uri, 2, vec![diagnostic(1, "a")])
+            .is_none());
+        assert_eq!(
+            published.update(&uri, 4, vec![diagnostic(1, "a")]),
+            Some(vec![diagnostic(1, "a")])
+        );
+    }
+}
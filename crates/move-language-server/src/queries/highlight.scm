@@ -0,0 +1,30 @@
+; Syntax highlights, capture names follow the tree-sitter-highlight conventions.
+; Earlier patterns take precedence when a node is captured more than once.
+
+(module_identifier) @namespace
+(struct_identifier) @type
+(function_identifier) @function
+(variable_identifier) @variable
+(address_literal) @number
+
+"module" @keyword
+"script" @keyword
+"address" @keyword
+"use" @keyword
+"as" @keyword
+"fun" @keyword
+"public" @keyword
+"native" @keyword
+"struct" @keyword
+"resource" @keyword
+"const" @keyword
+"let" @keyword
+"if" @keyword
+"else" @keyword
+"while" @keyword
+"loop" @keyword
+"return" @keyword
+"abort" @keyword
+"break" @keyword
+"continue" @keyword
+"acquires" @keyword
@@ -0,0 +1,6 @@
+; Scopes, definitions and references of local variables.
+; Parameters are scoped to their function, let bindings to their block.
+
+(function_definition) @local.scope
+(block) @local.scope
+(variable_identifier) @local.reference
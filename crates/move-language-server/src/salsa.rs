@@ -1,8 +1,16 @@
 use crate::{
-    move_document::{get_chunk, position_to_offset},
+    dialect::{CoreDialect, Dialect, HasDialect},
+    utils::canonicalize,
+};
+#[cfg(feature = "lsp")]
+use crate::{
+    move_document::{get_chunk, position_to_offset, MoveDocument},
     node_resolver::NodeResolver,
     tree_sitter_move::parser,
 };
+use itertools::Itertools;
+#[cfg(feature = "lsp")]
+use lsp_types::Location;
 use move_lang::{
     compiled_unit::CompiledUnit,
     errors::{Errors, FilesSourceText},
@@ -10,16 +18,26 @@ use move_lang::{
     shared::Address,
     CommentMap,
 };
-use std::path::{Path, PathBuf};
-use tower_lsp::{lsp_types, lsp_types::Location};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 use xi_rope::Rope;
 
 pub mod config_query;
+pub mod module_graph_query;
+#[cfg(feature = "lsp")]
+pub mod module_index_query;
 pub mod move_ast_query;
 pub mod syntax_tree_query;
 pub mod text_source_query;
+#[cfg(feature = "lsp")]
+pub mod tree_cache;
 
 use config_query::*;
+use module_graph_query::*;
+#[cfg(feature = "lsp")]
+use module_index_query::*;
 use move_ast_query::*;
 use std::{borrow::Cow, collections::HashMap};
 use syntax_tree_query::*;
@@ -27,14 +45,101 @@ use text_source_query::*;
 
 pub type FileId = PathBuf;
 
-#[salsa::database(ConfigStorage, SourceStorage, AstStorage, SyntaxTreeQueryStorage)]
-#[derive(Default)]
+/// Start of the compiler errors on modules which are not defined.
+const UNBOUND_MODULE: &str = "Unbound module";
+
+/// How stdlib files take part in a check.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Deps {
+    /// checked as dependencies.
+    Lib,
+    /// only their interfaces are checked as dependencies,
+    /// errors in function bodies are not reported.
+    Interfaces,
+    /// checked and compiled like module files,
+    /// but interfaces of compiled dependencies are only checked as dependencies.
+    Sources,
+}
+
+// the module index is built from tree-sitter syntax trees, which are not available without `lsp`.
+#[cfg_attr(
+    feature = "lsp",
+    salsa::database(
+        ConfigStorage,
+        SourceStorage,
+        AstStorage,
+        SyntaxTreeQueryStorage,
+        ModuleGraphStorage,
+        ModuleIndexStorage
+    )
+)]
+#[cfg_attr(
+    not(feature = "lsp"),
+    salsa::database(
+        ConfigStorage,
+        SourceStorage,
+        AstStorage,
+        SyntaxTreeQueryStorage,
+        ModuleGraphStorage
+    )
+)]
 pub struct RootDatabase {
     storage: salsa::Storage<Self>,
     sources: HashMap<FileId, Rope>,
+    /// shared with the snapshots, which parse the same files.
+    #[cfg(feature = "lsp")]
+    trees: Arc<tree_cache::TreeCache>,
+    dialect: Arc<dyn Dialect>,
 }
 impl salsa::Database for RootDatabase {}
 
+impl HasDialect for RootDatabase {
+    fn dialect(&self) -> &dyn Dialect {
+        self.dialect.as_ref()
+    }
+}
+
+impl salsa::ParallelDatabase for RootDatabase {
+    /// In-memory sources are copied, the cache of syntax trees is shared.
+    fn snapshot(&self) -> salsa::Snapshot<Self> {
+        salsa::Snapshot::new(Self {
+            storage: self.storage.snapshot(),
+            sources: self.sources.clone(),
+            #[cfg(feature = "lsp")]
+            trees: self.trees.clone(),
+            dialect: self.dialect.clone(),
+        })
+    }
+}
+
+impl Default for RootDatabase {
+    /// A database without project files or sender, so that every input is set.
+    fn default() -> Self {
+        Self::with_dialect(Arc::new(CoreDialect))
+    }
+}
+
+impl RootDatabase {
+    /// A database without project files or sender, for sources of `dialect`.
+    pub fn with_dialect(dialect: Arc<dyn Dialect>) -> Self {
+        let mut db = Self {
+            storage: Default::default(),
+            sources: Default::default(),
+            #[cfg(feature = "lsp")]
+            trees: Default::default(),
+            dialect,
+        };
+        db.set_project_files(vec![], vec![]);
+        db.set_script_files(vec![]);
+        db.set_interface_files_with_durability(vec![], salsa::Durability::HIGH);
+        db.set_sender_with_durability(None, salsa::Durability::HIGH);
+        db.set_folder_stdlib_files_with_durability(vec![], salsa::Durability::HIGH);
+        db.set_project_roots_with_durability(vec![], salsa::Durability::HIGH);
+        db.set_compiler_flags_with_durability(Default::default(), salsa::Durability::HIGH);
+        db
+    }
+}
+
 impl SourceReader for RootDatabase {
     fn read(&self, file_id: FileId) -> Cow<str> {
         match self.sources.get(&file_id) {
@@ -56,25 +161,118 @@ impl SourceReader for RootDatabase {
 
 impl RootDatabase {
     pub fn update_source(&mut self, fileid: FileId, rope: Rope) {
+        let fileid = canonicalize(&fileid);
         self.sources.insert(fileid.clone(), rope);
         self.did_change(fileid.as_path());
     }
 
     pub fn close_source(&mut self, fielid: FileId) {
-        self.sources.remove(&fielid);
+        self.sources.remove(&canonicalize(&fielid));
     }
 
+    /// Whether `file_id` is read from memory instead of the file system.
+    pub fn has_source(&self, file_id: &Path) -> bool {
+        self.sources.contains_key(&canonicalize(file_id))
+    }
+
+    /// `file_id` with its syntax tree, parsed again only if its content changed.
+    #[cfg(feature = "lsp")]
+    pub fn syntax_document(&self, file_id: FileId) -> MoveDocument {
+        let source = self.source_text(file_id);
+        match self.trees.get_or_parse(source.as_str()) {
+            Some(tree) => MoveDocument::with_tree(0, source, tree),
+            None => MoveDocument::new(0, source),
+        }
+    }
+
+    /// Set stdlib and module file lists, canonicalizing every path.
+    pub fn set_project_files(&mut self, stdlib_files: Vec<PathBuf>, module_files: Vec<PathBuf>) {
+        let canonical = |files: Vec<PathBuf>| -> Vec<PathBuf> {
+            files.iter().map(|f| canonicalize(f)).unique().collect()
+        };
+        self.set_stdlib_files_with_durability(canonical(stdlib_files), salsa::Durability::HIGH);
+        self.set_module_files_with_durability(canonical(module_files), salsa::Durability::HIGH);
+    }
+
+    /// Set the stdlib files of folders checked against another stdlib, canonicalizing every path.
+    pub fn set_folder_stdlibs(&mut self, folder_stdlibs: Vec<(PathBuf, Vec<PathBuf>)>) {
+        let folder_stdlibs = folder_stdlibs
+            .into_iter()
+            .map(|(folder, files)| {
+                let files = files.iter().map(|f| canonicalize(f)).unique().collect();
+                (canonicalize(&folder), files)
+            })
+            .collect();
+        self.set_folder_stdlib_files_with_durability(folder_stdlibs, salsa::Durability::HIGH);
+    }
+
+    /// Set the interfaces of compiled dependencies, canonicalizing every path.
+    pub fn set_interfaces(&mut self, interfaces: Vec<PathBuf>) {
+        let interfaces = interfaces
+            .iter()
+            .map(|f| canonicalize(f))
+            .unique()
+            .collect();
+        self.set_interface_files_with_durability(interfaces, salsa::Durability::HIGH);
+    }
+
+    /// Set the roots of the projects of the workspace, canonicalizing every path.
+    pub fn set_project_roots(&mut self, roots: Vec<PathBuf>) {
+        let roots = roots.iter().map(|r| canonicalize(r)).unique().collect();
+        self.set_project_roots_with_durability(roots, salsa::Durability::HIGH);
+    }
+
+    /// Set script files, canonicalizing every path.
+    pub fn set_script_files(&mut self, script_files: Vec<PathBuf>) {
+        let script_files = script_files
+            .iter()
+            .map(|f| canonicalize(f))
+            .unique()
+            .collect();
+        self.set_script_files_with_durability(script_files, salsa::Durability::HIGH);
+    }
+
+    /// Compile `file_path` with the project, units are verified if the compiler flags say so.
     pub fn compile_file(
         &self,
         sender: Option<Address>,
         file_path: PathBuf,
     ) -> (FilesSourceText, Result<Vec<CompiledUnit>, Errors>) {
-        let (sources, cfg_program) = self.check_file(sender, file_path);
-        let compiled_result = cfg_program.and_then(move_lang::to_bytecode::translate::program);
+        let (sources, cfg_program) = self.check_file_reporting_all(sender, file_path);
+        let compiled_result = cfg_program
+            .and_then(move_lang::to_bytecode::translate::program)
+            .and_then(|units| self.verify(units));
+        (sources, compiled_result)
+    }
+
+    /// Like `compile_file`, but stdlib files are compiled too,
+    /// so that the result is self-contained and can be loaded into a vm.
+    /// Interfaces of compiled dependencies stay dependencies, only their bytecode is real.
+    pub fn compile_file_with_deps(
+        &self,
+        sender: Option<Address>,
+        file_path: PathBuf,
+    ) -> (FilesSourceText, Result<Vec<CompiledUnit>, Errors>) {
+        let file_path = canonicalize(&file_path);
+        let sender = sender.or_else(|| self.sender());
+        let stdlib = self.stdlib_files_of(file_path.clone());
+        let root = self.project_root_of(file_path.clone());
+        let source_files = self.source_files(Some(file_path), &stdlib, root);
+        let (sources, parsed_program, dependency_errors) =
+            self.parse_file(source_files, stdlib, Deps::Sources, sender);
+        let checked = move_lang::check_program(parsed_program.map(|(p, _c)| p), sender);
+        let compiled_result = with_dependency_errors(checked, dependency_errors)
+            .and_then(move_lang::to_bytecode::translate::program)
+            .and_then(|units| self.verify(units));
         (sources, compiled_result)
     }
 
     // TODO: refactor this and check_file.
+    /// Files are checked with their own sender: the one of their `sender:` annotation,
+    /// `sender` or the configured one otherwise.
+    /// Files of folders with another stdlib version are checked apart, with the files sharing it,
+    /// and so are the projects of a multi-root workspace.
+    /// The checked program is the one of the files using `stdlib_files` in the first project.
     pub fn check_all(
         &self,
         sender: Option<Address>,
@@ -82,12 +280,53 @@ impl RootDatabase {
         FilesSourceText,
         Result<move_lang::cfgir::ast::Program, Errors>,
     ) {
-        let (sources, parsed_program) = self.parse_file(None);
+        let span = info_span!("check_all");
+        let _enter = span.enter();
         let sender = sender.or_else(|| self.sender());
-        let checked = move_lang::check_program(parsed_program.map(|(p, _c)| p), sender);
-        (sources, checked)
+        let mut stdlibs = vec![self.stdlib_files()];
+        for file in self.module_files().into_iter().chain(self.script_files()) {
+            let stdlib = self.stdlib_files_of(file);
+            if !stdlibs.contains(&stdlib) {
+                stdlibs.push(stdlib);
+            }
+        }
+
+        let roots: Vec<_> = match self.project_roots() {
+            roots if roots.is_empty() => vec![None],
+            roots => roots.into_iter().map(Some).collect(),
+        };
+
+        let mut sources = FilesSourceText::default();
+        let mut program = None;
+        let mut errors = Errors::new();
+        for (idx, stdlib) in stdlibs.into_iter().enumerate() {
+            for root in roots.iter().cloned() {
+                let (stdlib_sources, checked) =
+                    self.check_lazily(None, stdlib.clone(), Deps::Lib, sender, root);
+                sources.extend(stdlib_sources);
+                match checked {
+                    Ok(p) if idx == 0 && program.is_none() => program = Some(p),
+                    Ok(_) => {}
+                    // stdlib files and files in no project root are checked with every project.
+                    Err(e) => {
+                        for error in e {
+                            if !errors.contains(&error) {
+                                errors.push(error);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        match program {
+            Some(p) if errors.is_empty() => (sources, Ok(p)),
+            _ => (sources, self.silence(Err(errors))),
+        }
     }
 
+    /// Check `file_path` and the module files against the interfaces of stdlib files,
+    /// which stay the same while editing, unlike their full definitions.
+    /// Errors silenced by the compiler flags are left out.
     pub fn check_file(
         &self,
         sender: Option<Address>,
@@ -96,47 +335,254 @@ impl RootDatabase {
         FilesSourceText,
         Result<move_lang::cfgir::ast::Program, Errors>,
     ) {
-        let (sources, parsed_program) = self.parse_file(Some(file_path));
+        let (sources, checked) = self.check_file_reporting_all(sender, file_path);
+        (sources, self.silence(checked))
+    }
+
+    fn check_file_reporting_all(
+        &self,
+        sender: Option<Address>,
+        file_path: PathBuf,
+    ) -> (
+        FilesSourceText,
+        Result<move_lang::cfgir::ast::Program, Errors>,
+    ) {
+        let span = info_span!("check_file", file = %file_path.display());
+        let _enter = span.enter();
+        let file_path = canonicalize(&file_path);
         let sender = sender.or_else(|| self.sender());
-        let checked = move_lang::check_program(parsed_program.map(|(p, _c)| p), sender);
+        let stdlib = self.stdlib_files_of(file_path.clone());
+        let root = self.project_root_of(file_path.clone());
+        self.check_lazily(Some(file_path), stdlib, Deps::Interfaces, sender, root)
+    }
+
+    /// Check `file_path` alone against the interfaces of the modules it depends on,
+    /// only a change to those checks it again. Errors silenced by the compiler flags are
+    /// left out, the sources are the ones of the files the errors are in.
+    pub fn check_unit(&self, file_path: PathBuf) -> (FilesSourceText, Result<(), Errors>) {
+        let span = info_span!("check_unit", file = %file_path.display());
+        let _enter = span.enter();
+        let errors = self.unit_errors(canonicalize(&file_path));
+        let mut sources = FilesSourceText::default();
+        for (loc, _) in errors.iter().flatten() {
+            sources
+                .entry(loc.file())
+                .or_insert_with(|| self.source_text(PathBuf::from(loc.file())));
+        }
+        let result = if errors.is_empty() {
+            Ok(())
+        } else {
+            self.silence(Err(errors))
+        };
+        (sources, result)
+    }
+
+    /// Check the project files using `stdlib`, with `file_path` if it's not one of them,
+    /// against the stdlib files they use only. Parsing is cached, but checking every stdlib
+    /// module again on each change is what takes time.
+    /// Modules may be named without `use` declarations, so if a module is unbound the files
+    /// are checked again against every stdlib file.
+    /// `sender` is the one of the files without a `sender:` annotation,
+    /// `root` the one of the project whose files are checked.
+    fn check_lazily(
+        &self,
+        file_path: Option<PathBuf>,
+        stdlib: Vec<PathBuf>,
+        deps_mode: Deps,
+        sender: Option<Address>,
+        root: Option<PathBuf>,
+    ) -> (
+        FilesSourceText,
+        Result<move_lang::cfgir::ast::Program, Errors>,
+    ) {
+        let source_files = self.source_files(file_path, &stdlib, root);
+        let used = self.used_stdlib_files(&stdlib, &source_files);
+        let check = |deps: Vec<PathBuf>| {
+            let (sources, parsed_program, dependency_errors) =
+                self.parse_file(source_files.clone(), deps, deps_mode, sender);
+            let checked = move_lang::check_program(parsed_program.map(|(p, _c)| p), sender);
+            (sources, with_dependency_errors(checked, dependency_errors))
+        };
+        let lazy = used.len() < stdlib.len();
+        let (sources, checked) = check(used);
+        let unbound = match &checked {
+            Err(errors) => errors
+                .iter()
+                .flatten()
+                .any(|(_, msg)| msg.contains(UNBOUND_MODULE)),
+            Ok(_) => false,
+        };
+        if lazy && unbound {
+            debug!("unbound module with the used stdlib files, check with all of them");
+            return check(stdlib);
+        }
         (sources, checked)
     }
 
-    fn parse_file(
+    /// Stdlib files defining the modules `source_files` use, directly or through other
+    /// stdlib files, in the order of `stdlib`. Stdlib files which don't parse are kept,
+    /// for their errors.
+    fn used_stdlib_files(&self, stdlib: &[PathBuf], source_files: &[PathBuf]) -> Vec<PathBuf> {
+        let mut used = vec![false; stdlib.len()];
+        let mut definitions: HashMap<String, Vec<usize>> = HashMap::new();
+        for (idx, file) in stdlib.iter().enumerate() {
+            match self.defined_modules(file.clone()) {
+                Some(names) => {
+                    for name in names {
+                        definitions.entry(name).or_default().push(idx);
+                    }
+                }
+                None => used[idx] = true,
+            }
+        }
+        let mut pending: Vec<String> = source_files
+            .iter()
+            .flat_map(|f| self.used_modules(f.clone()))
+            .collect();
+        while let Some(name) = pending.pop() {
+            for &idx in definitions.get(&name).into_iter().flatten() {
+                if !used[idx] {
+                    used[idx] = true;
+                    pending.extend(self.used_modules(stdlib[idx].clone()));
+                }
+            }
+        }
+        stdlib
+            .iter()
+            .zip(used)
+            .filter(|(_, used)| *used)
+            .map(|(file, _)| file.clone())
+            .collect()
+    }
+
+    /// `result` without the errors silenced by the compiler flags.
+    /// The result stays an error even if every error is silenced.
+    fn silence<T>(&self, result: Result<T, Errors>) -> Result<T, Errors> {
+        let silence = self.compiler_flags().silence;
+        result.map_err(|errors| {
+            errors
+                .into_iter()
+                .filter(|e| {
+                    let message = e.first().map(|(_, msg)| msg.as_str()).unwrap_or_default();
+                    !silence.iter().any(|s| message.contains(s.as_str()))
+                })
+                .collect()
+        })
+    }
+
+    /// Run the bytecode verifier on `units` if the compiler flags say so.
+    fn verify(&self, units: Vec<CompiledUnit>) -> Result<Vec<CompiledUnit>, Errors> {
+        if !self.compiler_flags().verify {
+            return Ok(units);
+        }
+        let (units, errors) = move_lang::compiled_unit::verify_units(units);
+        if errors.is_empty() {
+            Ok(units)
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Sender of `file_path`: the one of its `sender:` annotation if any, the configured one otherwise.
+    pub fn sender_of(&self, file_path: &Path) -> Option<Address> {
+        self.sender_annotation(canonicalize(file_path))
+            .or_else(|| self.sender())
+    }
+
+    /// The files of the project at `root` checked against `stdlib`,
+    /// with `file_path` if it's not one of them.
+    fn source_files(
         &self,
         file_path: Option<PathBuf>,
-    ) -> (FilesSourceText, Result<(ast::Program, CommentMap), Errors>) {
+        stdlib: &[PathBuf],
+        root: Option<PathBuf>,
+    ) -> Vec<PathBuf> {
+        let uses_stdlib = |f: &PathBuf| self.stdlib_files_of(f.clone()) == stdlib;
+        let in_root = |f: &PathBuf| {
+            root.as_ref().map_or(true, |root| {
+                self.project_root_of(f.clone()).map_or(true, |r| r == *root)
+            })
+        };
+        let mut source_files: Vec<PathBuf> = self
+            .root_module_files(root.clone())
+            .into_iter()
+            .filter(uses_stdlib)
+            .collect();
+        match file_path {
+            Some(fp) => {
+                if !source_files.contains(&fp) {
+                    source_files.push(fp);
+                }
+            }
+            // scripts are only checked with the whole project, as nothing depends on them.
+            None => source_files.extend(
+                self.script_files()
+                    .into_iter()
+                    .filter(uses_stdlib)
+                    .filter(in_root),
+            ),
+        }
+        source_files
+    }
+
+    /// Parse `source_files` with the stdlib files `deps`.
+    /// Stdlib files which fail to parse are left out of the program, their errors are returned
+    /// on their own, so that the other files are still checked.
+    /// Files with a `// move-ls: skip` line are left out too.
+    /// Modules outside of address blocks are placed at the sender of their file,
+    /// `sender` if it has no `sender:` annotation.
+    fn parse_file(
+        &self,
+        source_files: Vec<PathBuf>,
+        deps: Vec<PathBuf>,
+        deps_mode: Deps,
+        sender: Option<Address>,
+    ) -> (
+        FilesSourceText,
+        Result<(ast::Program, CommentMap), Errors>,
+        Errors,
+    ) {
+        let mut dependency_errors = Errors::new();
         let mut errors = Errors::new();
 
-        let deps: Vec<PathBuf> = self.stdlib_files();
         let mut lib_definitions = Vec::new();
+        let mut compiled_definitions = Vec::new();
         let mut source_texts = FilesSourceText::default();
+        let interfaces = self.interface_files();
 
         for dep in deps {
+            if self.analysis_disabled(dep.clone()) {
+                continue;
+            }
             let fname = self.leak_str(dep.clone());
             let source_text = self.source_text(dep.clone());
             source_texts.insert(fname, source_text.clone());
-            match self.ast(dep.clone()) {
+            let defs = match deps_mode {
+                Deps::Interfaces => self.interface(dep.clone()),
+                Deps::Lib | Deps::Sources => self.ast(dep.clone()).map(|a| a.defs),
+            };
+            let dep_sender = self.sender_annotation(dep.clone()).or(sender);
+            let defs = defs.map(|defs| at_sender(defs, dep_sender));
+            match defs {
                 Err(mut e) => {
-                    errors.append(&mut e);
+                    dependency_errors.append(&mut e);
                 }
-                Ok(AstInfo { defs, .. }) => {
+                Ok(defs) if deps_mode == Deps::Sources && !interfaces.contains(&dep) => {
+                    compiled_definitions.extend(defs);
+                }
+                Ok(defs) => {
                     lib_definitions.extend(defs);
                     // source_comments.insert(self.leak_str(dep.clone()), comments);
                 }
             }
         }
 
-        let mut module_files: Vec<PathBuf> = self.module_files();
-        if let Some(fp) = file_path {
-            if !module_files.contains(&fp) {
-                module_files.push(fp);
-            }
-        }
-
         let mut source_definitions = Vec::new();
         let mut source_comments = CommentMap::new();
-        for source_file_path in module_files {
+        for source_file_path in source_files {
+            if self.analysis_disabled(source_file_path.clone()) {
+                continue;
+            }
             let fname = self.leak_str(source_file_path.clone());
             let source_text = self.source_text(source_file_path.clone());
             source_texts.insert(fname, source_text.clone());
@@ -147,24 +593,45 @@ impl RootDatabase {
                 Ok(AstInfo {
                     defs, doc_comments, ..
                 }) => {
-                    source_definitions.extend(defs);
+                    let file_sender = self.sender_annotation(source_file_path.clone()).or(sender);
+                    source_definitions.extend(at_sender(defs, file_sender));
                     source_comments.insert(self.leak_str(source_file_path.clone()), doc_comments);
                 }
             }
         }
 
+        source_definitions.extend(compiled_definitions);
         let program = ast::Program {
             lib_definitions,
             source_definitions,
         };
         if errors.is_empty() {
-            (source_texts, Ok((program, source_comments)))
+            (
+                source_texts,
+                Ok((program, source_comments)),
+                dependency_errors,
+            )
         } else {
-            (source_texts, Err(errors))
+            (source_texts, Err(errors), dependency_errors)
         }
     }
 }
 
+/// `result` with the errors of dependencies which failed to parse, these come first.
+fn with_dependency_errors<T>(
+    result: Result<T, Errors>,
+    mut dependency_errors: Errors,
+) -> Result<T, Errors> {
+    if dependency_errors.is_empty() {
+        return result;
+    }
+    if let Err(mut errors) = result {
+        dependency_errors.append(&mut errors);
+    }
+    Err(dependency_errors)
+}
+
+#[cfg(feature = "lsp")]
 #[allow(unused)]
 fn goto_definition(
     db: &dyn TextSource,
@@ -184,6 +651,7 @@ fn goto_definition(
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::CompilerFlags;
     use std::str::FromStr;
 
     pub trait InputChangeNotifier {
@@ -278,6 +746,370 @@ mod tests {
         }
     }
 
+    #[test]
+    pub fn test_check_file_against_interfaces() {
+        let mut db = RootDatabase::default();
+        let lib = PathBuf::from("/lib.move");
+        let path = PathBuf::from("/test.move");
+        db.set_stdlib_files(vec![lib.clone()]);
+        db.set_module_files(vec![]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        // the body of `f` doesn't type check.
+        db.update_source(
+            lib,
+            Rope::from("address 0x1 {\nmodule L {\n    public fun f(): u64 { true }\n}\n}\n"),
+        );
+        db.update_source(
+            path.clone(),
+            Rope::from("script {\n    use 0x1::L;\n    fun main() { L::f(); }\n}\n"),
+        );
+
+        let (_, checked) = db.check_file(None, path);
+        assert!(checked.is_ok());
+        let (_, checked) = db.check_all(None);
+        assert!(checked.is_err());
+    }
+
+    #[test]
+    pub fn test_check_file_with_broken_dependency() {
+        let mut db = RootDatabase::default();
+        let lib = PathBuf::from("/lib.move");
+        let path = PathBuf::from("/test.move");
+        db.set_stdlib_files(vec![lib.clone()]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        db.update_source(lib, Rope::from("address 0x1 {\nmodul L {}\n}\n"));
+        // `f` returns no bool.
+        db.update_source(
+            path.clone(),
+            Rope::from("module M {\n    fun f(): bool { 0 }\n}\n"),
+        );
+
+        let files = |errors: Errors| -> Vec<&'static str> {
+            errors.iter().map(|e| e[0].0.file()).collect()
+        };
+        let (sources, checked) = db.check_file(None, path.clone());
+        assert_eq!(sources.len(), 2);
+        assert_eq!(files(checked.unwrap_err()), vec!["/lib.move", "/test.move"]);
+        let (_, checked) = db.check_all(None);
+        assert_eq!(files(checked.unwrap_err()), vec!["/lib.move", "/test.move"]);
+
+        db.update_source(path.clone(), Rope::from("module M {}\n"));
+        let (_, checked) = db.check_file(None, path);
+        assert_eq!(files(checked.unwrap_err()), vec!["/lib.move"]);
+    }
+
+    #[test]
+    pub fn test_check_unit() {
+        let mut db = RootDatabase::default();
+        let l = PathBuf::from("/lib/L.move");
+        let (a, b) = (PathBuf::from("/m/A.move"), PathBuf::from("/m/B.move"));
+        let script = PathBuf::from("/s/main.move");
+        db.set_project_files(vec![l.clone()], vec![a.clone(), b.clone()]);
+        db.set_script_files(vec![script.clone()]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        db.update_source(
+            l.clone(),
+            Rope::from("address 0x1 {\nmodule L {\n    public fun f(): u64 { 0 }\n}\n}\n"),
+        );
+        db.update_source(
+            a.clone(),
+            Rope::from("module A {\n    use 0x1::L;\n    public fun g(): u64 { L::f() }\n}\n"),
+        );
+        db.update_source(b.clone(), Rope::from("module B {}\n"));
+        db.update_source(
+            script.clone(),
+            Rope::from("script {\n    use 0x1::A;\n    fun main() { let _x: u64 = A::g(); }\n}\n"),
+        );
+        assert_eq!(
+            db.file_module_ids(a.clone()).as_slice(),
+            &[ModuleId {
+                address: Address::parse_str("0x1").unwrap(),
+                name: "A".to_string()
+            }]
+        );
+        assert_eq!(
+            db.dependency_files(script.clone()).as_slice(),
+            &[l.clone(), a.clone()]
+        );
+        assert!(db.check_unit(script.clone()).1.is_ok());
+
+        // `B` is no dependency of the script, which doesn't see its errors.
+        db.update_source(
+            b.clone(),
+            Rope::from("module B {\n    fun h(): u64 { true }\n}\n"),
+        );
+        assert!(db.check_unit(script.clone()).1.is_ok());
+        let (sources, checked) = db.check_unit(b);
+        assert_eq!(sources.len(), 1);
+        assert!(checked.is_err());
+
+        // the interface of `A` changed.
+        db.update_source(
+            a,
+            Rope::from("module A {\n    public fun g(): bool { true }\n}\n"),
+        );
+        assert_eq!(
+            db.dependency_files(script.clone()).as_slice(),
+            &[PathBuf::from("/m/A.move")]
+        );
+        assert!(db.check_unit(script.clone()).1.is_err());
+
+        // without a `use`, modules are looked up in every file.
+        db.update_source(
+            script.clone(),
+            Rope::from("script {\n    fun main() { let _x: u64 = 0x1::L::f(); }\n}\n"),
+        );
+        assert!(db.dependency_files(script.clone()).is_empty());
+        assert!(db.check_unit(script.clone()).1.is_ok());
+
+        // dependencies are checked against at their own sender.
+        db.update_source(
+            b,
+            Rope::from("//! sender: 0x2\nmodule B {\n    public fun h(): u64 { 0 }\n}\n"),
+        );
+        db.update_source(
+            script.clone(),
+            Rope::from("script {\n    use 0x2::B;\n    fun main() { let _x: u64 = B::h(); }\n}\n"),
+        );
+        assert_eq!(db.dependency_files(script.clone()).len(), 1);
+        assert!(db.check_unit(script).1.is_ok());
+    }
+
+    #[test]
+    pub fn test_lazy_stdlib() {
+        let mut db = RootDatabase::default();
+        let (l, u) = (PathBuf::from("/lib/L.move"), PathBuf::from("/lib/U.move"));
+        let m = PathBuf::from("/m.move");
+        db.set_project_files(vec![l.clone(), u.clone()], vec![m.clone()]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        db.update_source(
+            l,
+            Rope::from("address 0x1 {\nmodule L {\n    public fun f() {}\n}\n}\n"),
+        );
+        // the body of `g` doesn't type check.
+        db.update_source(
+            u,
+            Rope::from("address 0x1 {\nmodule U {\n    public fun g(): u64 { true }\n}\n}\n"),
+        );
+        db.update_source(
+            m.clone(),
+            Rope::from("module M {\n    use 0x1::L;\n    fun h() { L::f() }\n}\n"),
+        );
+        // `U` is not used, so it's not checked.
+        let (sources, checked) = db.check_all(None);
+        assert_eq!(sources.len(), 2);
+        assert!(checked.is_ok());
+
+        // without a `use`, `U` is unbound until every stdlib file is checked.
+        db.update_source(
+            m.clone(),
+            Rope::from("module M {\n    fun h(): u64 { 0x1::U::g() }\n}\n"),
+        );
+        let (sources, checked) = db.check_all(None);
+        assert_eq!(sources.len(), 3);
+        let errors = checked.unwrap_err();
+        assert!(errors.iter().all(|e| e[0].0.file() == "/lib/U.move"));
+        assert!(db.check_file(None, m).1.is_ok());
+    }
+
+    #[test]
+    pub fn test_folder_stdlibs() {
+        let mut db = RootDatabase::default();
+        let (old_lib, new_lib) = (PathBuf::from("/old/L.move"), PathBuf::from("/new/L.move"));
+        let (old, new) = (PathBuf::from("/a/old.move"), PathBuf::from("/b/new.move"));
+        db.set_project_files(vec![new_lib.clone()], vec![old.clone(), new.clone()]);
+        db.set_folder_stdlibs(vec![(PathBuf::from("/a"), vec![old_lib.clone()])]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        // `f` is renamed to `g` in the new stdlib.
+        db.update_source(
+            old_lib,
+            Rope::from("address 0x1 {\nmodule L {\n    public fun f() {}\n}\n}\n"),
+        );
+        db.update_source(
+            new_lib,
+            Rope::from("address 0x1 {\nmodule L {\n    public fun g() {}\n}\n}\n"),
+        );
+        db.update_source(
+            old.clone(),
+            Rope::from("module Old {\n    use 0x1::L;\n    fun h() { L::f() }\n}\n"),
+        );
+        db.update_source(
+            new.clone(),
+            Rope::from("module New {\n    use 0x1::L;\n    fun h() { L::g() }\n}\n"),
+        );
+
+        assert!(db.check_file(None, old.clone()).1.is_ok());
+        assert!(db.check_file(None, new).1.is_ok());
+        let (sources, checked) = db.check_all(None);
+        assert_eq!(sources.len(), 4);
+        assert!(checked.is_ok());
+
+        db.set_folder_stdlibs(vec![]);
+        assert!(db.check_file(None, old).1.is_err());
+        assert!(db.check_all(None).1.is_err());
+    }
+
+    #[test]
+    pub fn test_compiler_flags() {
+        let mut db = RootDatabase::default();
+        let path = PathBuf::from("/m.move");
+        db.set_sender(Address::parse_str("0x01").ok());
+        db.update_source(
+            path.clone(),
+            Rope::from("module M {\n    fun f() { let x = 0; }\n}\n"),
+        );
+        assert_eq!(db.check_file(None, path.clone()).1.unwrap_err().len(), 1);
+
+        db.set_compiler_flags(CompilerFlags {
+            silence: vec!["Unused".to_string()],
+            ..Default::default()
+        });
+        assert!(db.check_file(None, path.clone()).1.unwrap_err().is_empty());
+        assert_eq!(db.compile_file(None, path.clone()).1.unwrap_err().len(), 1);
+
+        db.set_compiler_flags(CompilerFlags {
+            verify: true,
+            ..Default::default()
+        });
+        db.update_source(path.clone(), Rope::from("module M {\n    fun f() {}\n}\n"));
+        assert!(db.compile_file(None, path).1.is_ok());
+    }
+
+    #[test]
+    pub fn test_analysis_disabled() {
+        let mut db = RootDatabase::default();
+        let module = PathBuf::from("/m.move");
+        let wip = PathBuf::from("/wip.move");
+        db.set_module_files(vec![module.clone(), wip.clone()]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        db.update_source(module.clone(), Rope::from("module M {}\n"));
+        db.update_source(
+            wip.clone(),
+            Rope::from("// move-ls: skip\nmodule W { fn }\n"),
+        );
+
+        assert!(db.analysis_disabled(wip.clone()));
+        assert!(!db.analysis_disabled(module.clone()));
+        let (sources, checked) = db.check_all(None);
+        assert_eq!(sources.len(), 1);
+        assert!(checked.is_ok());
+        assert!(db.check_file(None, wip.clone()).1.is_ok());
+
+        db.update_source(wip.clone(), Rope::from("module W { fn }\n"));
+        assert!(db.check_all(None).1.is_err());
+    }
+
+    #[test]
+    pub fn test_scripts_are_roots() {
+        let mut db = RootDatabase::default();
+        let module = PathBuf::from("/m.move");
+        let script = PathBuf::from("/s.move");
+        db.set_module_files(vec![module.clone()]);
+        db.set_script_files(vec![script.clone()]);
+        db.set_sender(Address::parse_str("0x01").ok());
+        db.update_source(
+            module.clone(),
+            Rope::from("address 0x1 {\nmodule M {\n    public fun f(): u64 { 0 }\n}\n}\n"),
+        );
+        // `f` returns no bool.
+        db.update_source(
+            script.clone(),
+            Rope::from("script {\n    use 0x1::M;\n    fun main() { if (M::f()) () }\n}\n"),
+        );
+
+        let (sources, checked) = db.check_file(None, module);
+        assert_eq!(sources.len(), 1);
+        assert!(checked.is_ok());
+        assert!(db.check_file(None, script).1.is_err());
+        assert!(db.check_all(None).1.is_err());
+    }
+
+    #[test]
+    pub fn test_sender_annotation() {
+        let mut db = RootDatabase::default();
+        let file = PathBuf::from("/m.move");
+        db.set_module_files(vec![file.clone()]);
+        db.update_source(file.clone(), Rope::from("module M {}\n"));
+        // no sender to publish `M` at.
+        assert!(db.check_file(None, file.clone()).1.is_err());
+
+        db.update_source(file.clone(), Rope::from("//! sender: 0x42\nmodule M {}\n"));
+        assert_eq!(db.sender_of(&file), Address::parse_str("0x42").ok());
+        assert!(db.check_file(None, file.clone()).1.is_ok());
+
+        db.set_sender(Address::parse_str("0x01").ok());
+        assert_eq!(db.sender_of(&file), Address::parse_str("0x42").ok());
+        db.update_source(file.clone(), Rope::from("module M {}\n"));
+        assert_eq!(db.sender_of(&file), Address::parse_str("0x01").ok());
+
+        // only leading comments declare the sender.
+        db.update_source(
+            file.clone(),
+            Rope::from("module M {\n    fun f() {\n        // sender: 0x42\n    }\n}\n"),
+        );
+        assert_eq!(db.sender_of(&file), Address::parse_str("0x01").ok());
+        db.update_source(
+            file.clone(),
+            Rope::from("// A module.\n\n/* sender: 0x42 */\nmodule M {}\n"),
+        );
+        assert_eq!(db.sender_of(&file), Address::parse_str("0x42").ok());
+    }
+
+    #[test]
+    pub fn test_check_mixed_senders() {
+        let mut db = RootDatabase::default();
+        let a = PathBuf::from("/a.move");
+        let b = PathBuf::from("/b.move");
+        db.set_module_files(vec![a.clone(), b.clone()]);
+        db.set_sender(Address::parse_str("0x1").ok());
+        db.update_source(
+            a.clone(),
+            Rope::from("//! sender: 0x2\nmodule A {\n    public fun f(): u64 { 0 }\n}\n"),
+        );
+        db.update_source(
+            b.clone(),
+            Rope::from("module B {\n    use 0x2::A;\n    fun g(): u64 { A::f() }\n}\n"),
+        );
+
+        assert!(db.check_file(None, a).1.is_ok());
+        assert!(db.check_file(None, b.clone()).1.is_ok());
+        assert!(db.check_all(None).1.is_ok());
+        assert!(db.compile_file(None, b).1.is_ok());
+    }
+
+    #[test]
+    pub fn test_check_roots_apart() {
+        let mut db = RootDatabase::default();
+        let (a_m, a_n) = (PathBuf::from("/a/M.move"), PathBuf::from("/a/N.move"));
+        let (b_m, b_n) = (PathBuf::from("/b/M.move"), PathBuf::from("/b/N.move"));
+        db.set_module_files(vec![a_m.clone(), a_n.clone(), b_m.clone(), b_n.clone()]);
+        db.set_project_roots(vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+        db.set_sender(Address::parse_str("0x1").ok());
+        // both projects define `0x1::M`, with another interface.
+        db.update_source(
+            a_m,
+            Rope::from("module M {\n    public fun f(): u64 { 0 }\n}\n"),
+        );
+        db.update_source(
+            a_n.clone(),
+            Rope::from("module N {\n    use 0x1::M;\n    fun g(): u64 { M::f() }\n}\n"),
+        );
+        db.update_source(
+            b_m,
+            Rope::from("module M {\n    public fun f(): bool { true }\n}\n"),
+        );
+        db.update_source(
+            b_n.clone(),
+            Rope::from("module N {\n    use 0x1::M;\n    fun g(): bool { M::f() }\n}\n"),
+        );
+
+        assert!(db.check_unit(a_n.clone()).1.is_ok());
+        assert!(db.check_unit(b_n.clone()).1.is_ok());
+        assert!(db.check_file(None, a_n).1.is_ok());
+        assert!(db.check_file(None, b_n).1.is_ok());
+        assert!(db.check_all(None).1.is_ok());
+    }
+
     #[test]
     pub fn test_ast() {
         let mut db = RootDatabase::default();
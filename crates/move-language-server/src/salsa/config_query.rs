@@ -1,3 +1,4 @@
+use crate::config::CompilerFlags;
 use move_lang::shared::Address;
 use std::path::PathBuf;
 
@@ -9,6 +10,65 @@ pub trait Config {
     #[salsa::input]
     fn module_files(&self) -> Vec<PathBuf>;
 
+    /// Scripts are roots of the project, no other file depends on them.
+    #[salsa::input]
+    fn script_files(&self) -> Vec<PathBuf>;
+
+    /// Interfaces generated from compiled dependencies, among the stdlib files.
+    /// They are checked against but never compiled, their bytecode already exists.
+    #[salsa::input]
+    fn interface_files(&self) -> Vec<PathBuf>;
+
     #[salsa::input]
     fn sender(&self) -> Option<Address>;
+
+    #[salsa::input]
+    fn compiler_flags(&self) -> CompilerFlags;
+
+    /// Stdlib files of folders checked against another stdlib than `stdlib_files`, by folder.
+    #[salsa::input]
+    fn folder_stdlib_files(&self) -> Vec<(PathBuf, Vec<PathBuf>)>;
+
+    /// Stdlib files `file` is checked against:
+    /// those of the innermost folder with its own stdlib, `stdlib_files` otherwise.
+    fn stdlib_files_of(&self, file: PathBuf) -> Vec<PathBuf>;
+
+    /// Roots of the projects of a multi-root workspace, whose modules are indexed apart,
+    /// so that projects can define modules of the same name.
+    #[salsa::input]
+    fn project_roots(&self) -> Vec<PathBuf>;
+
+    /// The innermost project root containing `file`, `None` if no root contains it.
+    fn project_root_of(&self, file: PathBuf) -> Option<PathBuf>;
+
+    /// Module files of the project at `root`, and those in no project root.
+    /// Every module file for `None`.
+    fn root_module_files(&self, root: Option<PathBuf>) -> Vec<PathBuf>;
+}
+
+fn stdlib_files_of(db: &dyn Config, file: PathBuf) -> Vec<PathBuf> {
+    db.folder_stdlib_files()
+        .into_iter()
+        .filter(|(folder, _)| file.starts_with(folder))
+        .max_by_key(|(folder, _)| folder.components().count())
+        .map(|(_, files)| files)
+        .unwrap_or_else(|| db.stdlib_files())
+}
+
+fn project_root_of(db: &dyn Config, file: PathBuf) -> Option<PathBuf> {
+    db.project_roots()
+        .into_iter()
+        .filter(|root| file.starts_with(root))
+        .max_by_key(|root| root.components().count())
+}
+
+fn root_module_files(db: &dyn Config, root: Option<PathBuf>) -> Vec<PathBuf> {
+    let files = db.module_files();
+    match root {
+        Some(root) => files
+            .into_iter()
+            .filter(|f| db.project_root_of(f.clone()).map_or(true, |r| r == root))
+            .collect(),
+        None => files,
+    }
 }
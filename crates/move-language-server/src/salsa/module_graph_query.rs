@@ -0,0 +1,203 @@
+//! Modules identified by their address and name, and checks of a single file against the
+//! interfaces of the modules it depends on.
+//!
+//! A file is checked as its own unit, so that a change only checks again the files which
+//! depend on the changed modules, instead of the whole program.
+
+use super::{
+    config_query::Config,
+    move_ast_query::{at_sender, Ast},
+    UNBOUND_MODULE,
+};
+use move_lang::{
+    errors::Errors,
+    parser::ast::{self, ModuleDefinition, ModuleMember},
+    shared::Address,
+};
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+    sync::Arc,
+};
+
+/// A module by address and name, modules outside of address blocks are at the sender.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ModuleId {
+    pub address: Address,
+    pub name: String,
+}
+
+#[salsa::query_group(ModuleGraphStorage)]
+pub trait ModuleGraph: Ast + Config {
+    /// Modules defined in `file`, empty if it doesn't parse.
+    /// Modules outside of address blocks are left out without a sender.
+    fn file_module_ids(&self, file: PathBuf) -> Arc<Vec<ModuleId>>;
+
+    /// Modules `file` uses with `use` declarations.
+    fn file_dependencies(&self, file: PathBuf) -> Arc<Vec<ModuleId>>;
+
+    /// Files of the modules checked against `stdlib`: the stdlib files and the module files
+    /// of the project at `root` using it, by the modules they define.
+    fn module_id_files(
+        &self,
+        stdlib: Vec<PathBuf>,
+        root: Option<PathBuf>,
+    ) -> Arc<HashMap<ModuleId, PathBuf>>;
+
+    /// Files of the modules `file` depends on, directly or through other modules, sorted.
+    fn dependency_files(&self, file: PathBuf) -> Arc<Vec<PathBuf>>;
+
+    /// Errors of checking `file` against the interfaces of its dependencies.
+    /// Dependencies which don't parse are left out, their errors are the ones of their own unit.
+    /// Modules outside of address blocks are placed at the sender of their own file.
+    fn unit_errors(&self, file: PathBuf) -> Errors;
+}
+
+fn file_module_ids(db: &dyn ModuleGraph, file: PathBuf) -> Arc<Vec<ModuleId>> {
+    let defs = match db.ast(file.clone()) {
+        Ok(info) => info.defs,
+        Err(_) => return Arc::new(vec![]),
+    };
+    let sender = db.sender_annotation(file).or_else(|| db.sender());
+    let id = |address: Option<Address>, m: &ModuleDefinition| {
+        Some(ModuleId {
+            address: address?,
+            name: m.name.0.value.clone(),
+        })
+    };
+    let ids = defs
+        .iter()
+        .flat_map(|def| match def {
+            ast::Definition::Module(m) => vec![id(sender, m)],
+            ast::Definition::Address(_, address, modules) => {
+                modules.iter().map(|m| id(Some(*address), m)).collect()
+            }
+            ast::Definition::Script(_) => vec![],
+        })
+        .flatten()
+        .collect();
+    Arc::new(ids)
+}
+
+fn file_dependencies(db: &dyn ModuleGraph, file: PathBuf) -> Arc<Vec<ModuleId>> {
+    let defs = match db.ast(file) {
+        Ok(info) => info.defs,
+        Err(_) => return Arc::new(vec![]),
+    };
+    let module_uses = |m: &ModuleDefinition| -> Vec<ast::Use> {
+        m.members
+            .iter()
+            .filter_map(|member| match member {
+                ModuleMember::Use(u) => Some(u.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    let ids = defs
+        .iter()
+        .flat_map(|def| match def {
+            ast::Definition::Module(m) => module_uses(m),
+            ast::Definition::Address(_, _, modules) => {
+                modules.iter().flat_map(module_uses).collect()
+            }
+            ast::Definition::Script(s) => s.uses.clone(),
+        })
+        .map(|u| match u {
+            ast::Use::Module(ident, _) | ast::Use::Members(ident, _) => ModuleId {
+                address: ident.0.value.address,
+                name: ident.0.value.name.0.value,
+            },
+        })
+        .collect();
+    Arc::new(ids)
+}
+
+fn module_id_files(
+    db: &dyn ModuleGraph,
+    stdlib: Vec<PathBuf>,
+    root: Option<PathBuf>,
+) -> Arc<HashMap<ModuleId, PathBuf>> {
+    let module_files = db
+        .root_module_files(root)
+        .into_iter()
+        .filter(|f| db.stdlib_files_of(f.clone()) == stdlib);
+    let mut files = HashMap::new();
+    for file in stdlib.iter().cloned().chain(module_files) {
+        for id in db.file_module_ids(file.clone()).iter() {
+            files.insert(id.clone(), file.clone());
+        }
+    }
+    Arc::new(files)
+}
+
+fn dependency_files(db: &dyn ModuleGraph, file: PathBuf) -> Arc<Vec<PathBuf>> {
+    let files = db.module_id_files(
+        db.stdlib_files_of(file.clone()),
+        db.project_root_of(file.clone()),
+    );
+    let mut found = BTreeSet::new();
+    let mut pending: Vec<_> = db.file_dependencies(file.clone()).to_vec();
+    while let Some(id) = pending.pop() {
+        match files.get(&id) {
+            Some(dep) if *dep != file && found.insert(dep.clone()) => {
+                pending.extend(db.file_dependencies(dep.clone()).iter().cloned());
+            }
+            _ => {}
+        }
+    }
+    Arc::new(found.into_iter().collect())
+}
+
+fn unit_errors(db: &dyn ModuleGraph, file: PathBuf) -> Errors {
+    if db.analysis_disabled(file.clone()) {
+        return Errors::new();
+    }
+    let source_definitions = match db.ast(file.clone()) {
+        Ok(info) => info.defs,
+        Err(errors) => return errors,
+    };
+    let sender = db.sender_annotation(file.clone()).or_else(|| db.sender());
+    let check = |deps: &[PathBuf]| {
+        let lib_definitions = deps
+            .iter()
+            .filter(|dep| !db.analysis_disabled((*dep).clone()))
+            .filter_map(|dep| {
+                let interface = db.interface(dep.clone()).ok()?;
+                let sender = db.sender_annotation(dep.clone()).or_else(|| db.sender());
+                Some(at_sender(interface, sender))
+            })
+            .flatten()
+            .collect();
+        let program = ast::Program {
+            lib_definitions,
+            source_definitions: source_definitions.clone(),
+        };
+        move_lang::check_program(Ok(program), sender)
+            .err()
+            .unwrap_or_default()
+    };
+
+    let deps = db.dependency_files(file.clone());
+    let errors = check(deps.as_slice());
+    let unbound = errors
+        .iter()
+        .flatten()
+        .any(|(_, msg)| msg.contains(UNBOUND_MODULE));
+    if !unbound {
+        return errors;
+    }
+    // modules may be named without `use` declarations, check against every module.
+    let all: BTreeSet<_> = db
+        .module_id_files(
+            db.stdlib_files_of(file.clone()),
+            db.project_root_of(file.clone()),
+        )
+        .values()
+        .filter(|f| **f != file)
+        .cloned()
+        .collect();
+    if all.len() == deps.len() {
+        return errors;
+    }
+    check(&all.into_iter().collect::<Vec<_>>())
+}
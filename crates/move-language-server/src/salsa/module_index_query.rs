@@ -0,0 +1,390 @@
+//! Syntax level index of the modules defined in project files, and the modules they use.
+
+use crate::{
+    move_document::offset_to_position,
+    node_resolver::{NodeResolver, UsePart},
+    salsa::{config_query::Config, move_ast_query::Ast, text_source_query::TextSource, FileId},
+    tree_sitter_move::{kinds, parse},
+};
+use lsp_types::Range;
+use move_lang::{
+    parser::ast::{self, ModuleDefinition, ModuleMember},
+    shared::Address,
+};
+use serde::{Serialize, Serializer};
+use std::{collections::BTreeMap, fmt, path::PathBuf, sync::Arc};
+use tree_sitter::Node;
+use xi_rope::Rope;
+
+/// `address::Module`, with address normalized to its shortest hex form.
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct ModuleKey {
+    pub address: String,
+    pub name: String,
+}
+
+impl ModuleKey {
+    pub fn new(address: &str, name: &str) -> Self {
+        Self {
+            address: normalize_address(address),
+            name: name.to_string(),
+        }
+    }
+}
+
+impl Serialize for ModuleKey {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl fmt::Display for ModuleKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}::{}", self.address, self.name)
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModuleUse {
+    pub target: ModuleKey,
+    /// `use 0x1::M as Alias`.
+    pub alias: Option<String>,
+    /// `use 0x1::M::{member as member_alias}`.
+    pub member: Option<String>,
+    pub member_alias: Option<String>,
+    /// range of the whole use declaration.
+    pub range: Range,
+}
+
+impl ModuleUse {
+    /// The name the used module is referred by in the using module.
+    pub fn local_name(&self) -> &str {
+        self.alias.as_deref().unwrap_or(self.target.name.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct ModuleDecl {
+    pub key: ModuleKey,
+    /// range of the whole module definition.
+    pub range: Range,
+    /// range of the module name.
+    pub name_range: Range,
+    pub uses: Vec<ModuleUse>,
+}
+
+#[salsa::query_group(ModuleIndexStorage)]
+pub trait ModuleIndex: TextSource + Config + Ast {
+    fn file_modules(&self, file: FileId) -> Arc<Vec<ModuleDecl>>;
+
+    /// Modules defined in `file` and the modules they use, with the ranges of the used names,
+    /// from the AST of the file. Empty if it doesn't parse.
+    fn module_dependencies(&self, file: FileId) -> Arc<Vec<(ModuleKey, Vec<(ModuleKey, Range)>)>>;
+
+    /// Modules of all stdlib and module files.
+    fn project_modules(&self) -> Arc<Vec<(FileId, ModuleDecl)>>;
+
+    /// Modules of the project at `root`: those of stdlib files, and of module files in no
+    /// other project root. All project modules for `None`.
+    fn root_modules(&self, root: Option<PathBuf>) -> Arc<Vec<(FileId, ModuleDecl)>>;
+
+    /// Modules `file` can refer to, those of the project root it's in.
+    fn visible_modules(&self, file: FileId) -> Arc<Vec<(FileId, ModuleDecl)>>;
+}
+
+fn file_modules(db: &dyn ModuleIndex, file: FileId) -> Arc<Vec<ModuleDecl>> {
+    let source = db.source_text(file);
+    let sender = db.sender();
+    Arc::new(index_modules(source.as_str(), sender))
+}
+
+fn module_dependencies(
+    db: &dyn ModuleIndex,
+    file: FileId,
+) -> Arc<Vec<(ModuleKey, Vec<(ModuleKey, Range)>)>> {
+    let defs = match db.ast(file.clone()) {
+        Ok(info) => info.defs,
+        Err(_) => return Arc::new(vec![]),
+    };
+    let rope = Rope::from(db.source_text(file.clone()).as_str());
+    let default_address = db
+        .sender_annotation(file)
+        .or_else(|| db.sender())
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "_".to_string());
+    let module = |address: &str, m: &ModuleDefinition| {
+        let uses = m
+            .members
+            .iter()
+            .filter_map(|member| match member {
+                ModuleMember::Use(ast::Use::Module(ident, _))
+                | ModuleMember::Use(ast::Use::Members(ident, _)) => {
+                    let span = ident.0.loc.span();
+                    let target = ModuleKey::new(
+                        &ident.0.value.address.to_string(),
+                        &ident.0.value.name.0.value,
+                    );
+                    Some((
+                        target,
+                        to_range(&rope, span.start().to_usize(), span.end().to_usize())?,
+                    ))
+                }
+                _ => None,
+            })
+            .collect();
+        (ModuleKey::new(address, &m.name.0.value), uses)
+    };
+    let modules = defs
+        .iter()
+        .flat_map(|def| match def {
+            ast::Definition::Module(m) => vec![module(default_address.as_str(), m)],
+            ast::Definition::Address(_, address, modules) => {
+                let address = address.to_string();
+                modules
+                    .iter()
+                    .map(|m| module(address.as_str(), m))
+                    .collect()
+            }
+            ast::Definition::Script(_) => vec![],
+        })
+        .collect();
+    Arc::new(modules)
+}
+
+fn project_modules(db: &dyn ModuleIndex) -> Arc<Vec<(FileId, ModuleDecl)>> {
+    let mut modules = vec![];
+    for f in db.stdlib_files().into_iter().chain(db.module_files()) {
+        for m in db.file_modules(f.clone()).iter() {
+            modules.push((f.clone(), m.clone()));
+        }
+    }
+    Arc::new(modules)
+}
+
+fn root_modules(db: &dyn ModuleIndex, root: Option<PathBuf>) -> Arc<Vec<(FileId, ModuleDecl)>> {
+    let root = match root {
+        Some(r) => r,
+        None => return db.project_modules(),
+    };
+    let stdlib_files = db.stdlib_files();
+    let modules = db
+        .project_modules()
+        .iter()
+        .filter(|(f, _)| {
+            stdlib_files.contains(f) || db.project_root_of(f.clone()).map_or(true, |r| r == root)
+        })
+        .cloned()
+        .collect();
+    Arc::new(modules)
+}
+
+fn visible_modules(db: &dyn ModuleIndex, file: FileId) -> Arc<Vec<(FileId, ModuleDecl)>> {
+    db.root_modules(db.project_root_of(file))
+}
+
+/// Groups of definitions of the same module, in more than one place.
+pub fn duplicate_modules<'a>(
+    modules: impl Iterator<Item = &'a (FileId, ModuleDecl)>,
+) -> Vec<Vec<&'a (FileId, ModuleDecl)>> {
+    let mut by_key: BTreeMap<&ModuleKey, Vec<&'a (FileId, ModuleDecl)>> = BTreeMap::new();
+    for m in modules {
+        by_key.entry(&m.1.key).or_default().push(m);
+    }
+    by_key
+        .into_iter()
+        .map(|(_, defs)| defs)
+        .filter(|defs| defs.len() > 1)
+        .collect()
+}
+
+/// Extract modules of a source file. Modules outside of address blocks are placed at `sender`.
+pub fn index_modules(source: &str, sender: Option<Address>) -> Vec<ModuleDecl> {
+    let tree = match parse(source) {
+        Some(t) => t,
+        None => return vec![],
+    };
+    let rope = Rope::from(source);
+    let default_address = sender
+        .map(|s| s.to_string())
+        .unwrap_or_else(|| "_".to_string());
+
+    let root = tree.root_node();
+    let mut modules = vec![];
+    let mut cursor = root.walk();
+    for node in root.named_children(&mut cursor) {
+        match node.kind() {
+            kinds::ADDRESS_BLOCK => {
+                let address = node
+                    .child_by_field_name("address")
+                    .map(|a| node_text(source, &a))
+                    .unwrap_or(default_address.as_str());
+                let mut cursor = node.walk();
+                for m in node
+                    .named_children(&mut cursor)
+                    .filter(|n| n.kind() == kinds::MODULE_DEFINITION)
+                {
+                    modules.extend(index_module(source, &rope, address, &m));
+                }
+            }
+            kinds::MODULE_DEFINITION => {
+                modules.extend(index_module(source, &rope, default_address.as_str(), &node))
+            }
+            _ => {}
+        }
+    }
+    modules
+}
+
+fn index_module(source: &str, rope: &Rope, address: &str, node: &Node) -> Option<ModuleDecl> {
+    let name = node.child_by_field_name("name")?;
+    let uses = NodeResolver::resolve_use(node, rope)
+        .into_iter()
+        .filter_map(|u| {
+            let text = |p: Option<&UsePart>| p.map(|p| p.text.clone());
+            let range = u.byte_range();
+            Some(ModuleUse {
+                target: ModuleKey::new(&u.addr().text, &u.module().text),
+                alias: text(u.module_alias()),
+                member: text(u.member()),
+                member_alias: text(u.member_alias()),
+                range: to_range(rope, range.start, range.end)?,
+            })
+        })
+        .collect();
+
+    Some(ModuleDecl {
+        key: ModuleKey::new(address, node_text(source, &name)),
+        range: to_range(rope, node.start_byte(), node.end_byte())?,
+        name_range: to_range(rope, name.start_byte(), name.end_byte())?,
+        uses,
+    })
+}
+
+fn node_text<'a>(source: &'a str, node: &Node) -> &'a str {
+    source.get(node.byte_range()).unwrap_or_default()
+}
+
+fn to_range(rope: &Rope, start: usize, end: usize) -> Option<Range> {
+    Some(Range::new(
+        offset_to_position(rope, start)?,
+        offset_to_position(rope, end)?,
+    ))
+}
+
+/// `0x0001` and `0x1` are the same address.
+pub fn normalize_address(address: &str) -> String {
+    let address = address.trim();
+    let hex = match address
+        .strip_prefix("0x")
+        .or_else(|| address.strip_prefix("0X"))
+    {
+        Some(h) => h,
+        None => return address.to_string(),
+    };
+    let trimmed = hex.trim_start_matches('0').to_lowercase();
+    if trimmed.is_empty() {
+        "0x0".to_string()
+    } else {
+        format!("0x{}", trimmed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::RootDatabase;
+
+    #[test]
+    fn test_normalize_address() {
+        assert_eq!(normalize_address("0x0001"), "0x1");
+        assert_eq!(normalize_address("0x00"), "0x0");
+        assert_eq!(normalize_address("0xAB"), "0xab");
+        assert_eq!(normalize_address("_"), "_");
+    }
+
+    #[test]
+    fn test_duplicate_modules() {
+        let sender = Address::parse_str("0x1").ok();
+        let modules: Vec<_> = vec![
+            ("a.move", "address 0x1 {\nmodule A {}\nmodule B {}\n}\n"),
+            ("b.move", "module A {}\n"),
+            ("c.move", "address 0x2 {\nmodule A {}\n}\n"),
+        ]
+        .into_iter()
+        .flat_map(|(f, source)| {
+            index_modules(source, sender)
+                .into_iter()
+                .map(move |m| (FileId::from(f), m))
+        })
+        .collect();
+        let duplicates = duplicate_modules(modules.iter());
+        assert_eq!(duplicates.len(), 1);
+        let files: Vec<_> = duplicates[0].iter().map(|(f, _)| f.clone()).collect();
+        assert_eq!(files, vec![FileId::from("a.move"), FileId::from("b.move")]);
+    }
+
+    #[test]
+    fn test_root_modules() {
+        let a = FileId::from("/a/M.move");
+        let b = FileId::from("/b/M.move");
+        let shared = FileId::from("/shared/S.move");
+        let mut db = RootDatabase::default();
+        db.set_project_files(vec![], vec![a.clone(), b.clone(), shared.clone()]);
+        db.set_project_roots(vec![PathBuf::from("/a"), PathBuf::from("/b")]);
+        for (file, source) in vec![
+            (&a, "address 0x1 {\nmodule M {}\n}\n"),
+            (&b, "address 0x1 {\nmodule M {}\n}\n"),
+            (&shared, "address 0x1 {\nmodule S {}\n}\n"),
+        ] {
+            db.update_source(file.clone(), Rope::from(source));
+        }
+
+        let files = |modules: Arc<Vec<(FileId, ModuleDecl)>>| -> Vec<FileId> {
+            modules.iter().map(|(f, _)| f.clone()).collect()
+        };
+        assert_eq!(
+            files(db.visible_modules(a.clone())),
+            vec![a, shared.clone()]
+        );
+        assert_eq!(
+            files(db.visible_modules(b.clone())),
+            vec![b, shared.clone()]
+        );
+        assert_eq!(files(db.visible_modules(shared)).len(), 3);
+        assert_eq!(duplicate_modules(db.project_modules().iter()).len(), 1);
+    }
+
+    #[test]
+    fn test_index_modules() {
+        let source = r"
+address 0x1 {
+module A {
+    use 0x1::B;
+    use 0x2::C::{Self, T};
+}
+module B {}
+}
+module D {
+    use 0x01::A;
+}
+";
+        let modules = index_modules(source, Address::parse_str("0x2").ok());
+        assert_eq!(modules.len(), 3);
+        assert_eq!(modules[0].key, ModuleKey::new("0x1", "A"));
+        let targets: Vec<_> = modules[0]
+            .uses
+            .iter()
+            .map(|u| u.target.to_string())
+            .collect();
+        assert_eq!(targets, vec!["0x1::B", "0x2::C", "0x2::C"]);
+        assert_eq!(modules[0].uses[0].range.start.line, 3);
+        assert_eq!(modules[0].uses[1].member.as_deref(), Some("Self"));
+        assert_eq!(modules[0].uses[2].member.as_deref(), Some("T"));
+        assert_eq!(modules[0].uses[2].local_name(), "C");
+        assert_eq!(modules[0].range.start.line, 2);
+        assert_eq!(modules[0].range.end.line, 5);
+        assert!(modules[1].uses.is_empty());
+        assert_eq!(modules[2].key, ModuleKey::new("0x2", "D"));
+        assert_eq!(modules[2].uses[0].target, ModuleKey::new("0x1", "A"));
+    }
+}
@@ -1,9 +1,17 @@
+use crate::{
+    analysis::{file_symbols, Symbol},
+    dialect::HasDialect,
+};
 use move_lang::{
     errors::Errors,
-    parser::{ast, syntax},
+    parser::{
+        ast::{self, FunctionBody_, ModuleDefinition, ModuleMember},
+        syntax,
+    },
+    shared::Address,
     strip_comments_and_verify, FileCommentMap, MatchedFileCommentMap,
 };
-use std::path::PathBuf;
+use std::{path::PathBuf, sync::Arc};
 
 #[derive(Debug, Clone, Eq, PartialEq)]
 pub struct AstInfo {
@@ -14,15 +22,41 @@ pub struct AstInfo {
 }
 
 #[salsa::query_group(AstStorage)]
-pub trait Ast: super::TextSource {
+pub trait Ast: super::TextSource + HasDialect {
     fn ast(&self, file_name: PathBuf) -> Result<AstInfo, Errors>;
+
+    /// Definitions of `file_name` with native function bodies and no specs,
+    /// enough to check the files depending on it.
+    fn interface(&self, file_name: PathBuf) -> Result<Vec<ast::Definition>, Errors>;
+
+    /// The sender declared by a `sender: 0x..` comment of `file_name`, like `//! sender: 0x42`.
+    /// Only the comments at the start of the file, before any code, declare it.
+    fn sender_annotation(&self, file_name: PathBuf) -> Option<Address>;
+
+    /// Whether `file_name` opts out of checks with a `// move-ls: skip` line,
+    /// it's looked up in the text as the file may not parse.
+    fn analysis_disabled(&self, file_name: PathBuf) -> bool;
+
+    /// Names of the modules defined in `file_name`, `None` if it doesn't parse.
+    fn defined_modules(&self, file_name: PathBuf) -> Option<Vec<String>>;
+
+    /// Names of the modules `file_name` uses with `use` declarations.
+    fn used_modules(&self, file_name: PathBuf) -> Vec<String>;
+
+    /// Modules, scripts and module members defined in `file_name`, with their signatures,
+    /// empty if it doesn't parse.
+    fn symbols(&self, file_name: PathBuf) -> Arc<Vec<Symbol>>;
 }
 
+/// Line comment which leaves a file out of checks and compilation.
+pub const SKIP_ANNOTATION: &str = "move-ls: skip";
+
 fn ast(db: &dyn Ast, file_name: PathBuf) -> Result<AstInfo, Errors> {
-    let source = db.source_text(file_name.clone());
+    let text = db.source_text(file_name.clone());
+    let source = db.dialect().preprocess(text.as_str());
     let fname = db.leak_str(file_name);
     let (no_comments_buffer, comment_map, regular_comment_map) =
-        strip_comments_and_verify(fname, source.as_str())?;
+        strip_comments_and_verify(fname, &source)?;
     let (defs, comments) =
         syntax::parse_file_string(fname, &no_comments_buffer, comment_map.clone())?;
     Ok(AstInfo {
@@ -32,3 +66,136 @@ fn ast(db: &dyn Ast, file_name: PathBuf) -> Result<AstInfo, Errors> {
         regular_comment_map,
     })
 }
+
+fn interface(db: &dyn Ast, file_name: PathBuf) -> Result<Vec<ast::Definition>, Errors> {
+    let mut defs = db.ast(file_name)?.defs;
+    for def in defs.iter_mut() {
+        match def {
+            ast::Definition::Module(m) => strip_module(m),
+            ast::Definition::Address(_, _, modules) => modules.iter_mut().for_each(strip_module),
+            ast::Definition::Script(_) => {}
+        }
+    }
+    Ok(defs)
+}
+
+fn sender_annotation(db: &dyn Ast, file_name: PathBuf) -> Option<Address> {
+    let text = db.source_text(file_name);
+    let dialect = db.dialect();
+    leading_comments(&text).into_iter().find_map(|comment| {
+        let comment = comment
+            .trim_start_matches(|c: char| c == '/' || c == '!' || c == '*' || c.is_whitespace());
+        let address = comment.strip_prefix("sender:")?.trim();
+        dialect.parse_address(address.trim_end_matches("*/").trim())
+    })
+}
+
+/// Comments at the start of `text`, before any code.
+fn leading_comments(text: &str) -> Vec<&str> {
+    let mut comments = vec![];
+    let mut rest = text.trim_start();
+    loop {
+        let end = if rest.starts_with("//") {
+            rest.find('\n').unwrap_or_else(|| rest.len())
+        } else if rest.starts_with("/*") {
+            rest.find("*/").map_or(rest.len(), |end| end + 2)
+        } else {
+            return comments;
+        };
+        comments.push(&rest[..end]);
+        rest = rest[end..].trim_start();
+    }
+}
+
+/// `defs` with the modules outside of address blocks placed in one at `sender`,
+/// so that files with different senders can be checked together.
+/// They are left as they are without a sender, for the compiler to report it.
+pub(crate) fn at_sender(
+    defs: Vec<ast::Definition>,
+    sender: Option<Address>,
+) -> Vec<ast::Definition> {
+    let sender = match sender {
+        Some(s) => s,
+        None => return defs,
+    };
+    defs.into_iter()
+        .map(|def| match def {
+            ast::Definition::Module(m) => ast::Definition::Address(m.loc, sender, vec![m]),
+            def => def,
+        })
+        .collect()
+}
+
+fn analysis_disabled(db: &dyn Ast, file_name: PathBuf) -> bool {
+    db.source_text(file_name).lines().any(|line| {
+        line.trim()
+            .strip_prefix("//")
+            .map(|c| c.trim_start_matches(|c| c == '/' || c == '!').trim())
+            == Some(SKIP_ANNOTATION)
+    })
+}
+
+fn defined_modules(db: &dyn Ast, file_name: PathBuf) -> Option<Vec<String>> {
+    let defs = db.ast(file_name).ok()?.defs;
+    let names = defs
+        .iter()
+        .flat_map(|def| match def {
+            ast::Definition::Module(m) => vec![m.name.0.value.clone()],
+            ast::Definition::Address(_, _, modules) => {
+                modules.iter().map(|m| m.name.0.value.clone()).collect()
+            }
+            ast::Definition::Script(_) => vec![],
+        })
+        .collect();
+    Some(names)
+}
+
+fn used_modules(db: &dyn Ast, file_name: PathBuf) -> Vec<String> {
+    let defs = match db.ast(file_name) {
+        Ok(info) => info.defs,
+        Err(_) => return vec![],
+    };
+    let module_uses = |m: &ModuleDefinition| -> Vec<ast::Use> {
+        m.members
+            .iter()
+            .filter_map(|member| match member {
+                ModuleMember::Use(u) => Some(u.clone()),
+                _ => None,
+            })
+            .collect()
+    };
+    defs.iter()
+        .flat_map(|def| match def {
+            ast::Definition::Module(m) => module_uses(m),
+            ast::Definition::Address(_, _, modules) => {
+                modules.iter().flat_map(module_uses).collect()
+            }
+            ast::Definition::Script(s) => s.uses.clone(),
+        })
+        .map(|u| match u {
+            ast::Use::Module(ident, _) | ast::Use::Members(ident, _) => ident.0.value.name.0.value,
+        })
+        .collect()
+}
+
+fn symbols(db: &dyn Ast, file_name: PathBuf) -> Arc<Vec<Symbol>> {
+    let defs = match db.ast(file_name.clone()) {
+        Ok(info) => info.defs,
+        Err(_) => return Arc::new(vec![]),
+    };
+    let text = db.source_text(file_name);
+    // spans are offsets in the preprocessed source, which keeps the offsets of the text.
+    let source = db.dialect().preprocess(text.as_str());
+    Arc::new(file_symbols(defs.as_slice(), source.as_ref()))
+}
+
+fn strip_module(module: &mut ModuleDefinition) {
+    module
+        .members
+        .retain(|m| !matches!(m, ModuleMember::Spec(_)));
+    for member in module.members.iter_mut() {
+        if let ModuleMember::Function(f) = member {
+            f.body.value = FunctionBody_::Native;
+        }
+    }
+}
@@ -0,0 +1,86 @@
+use crate::tree_sitter_move::parse;
+use parking_lot::Mutex;
+use std::{
+    collections::{hash_map::DefaultHasher, VecDeque},
+    hash::{Hash, Hasher},
+};
+use tree_sitter::Tree;
+
+const DEFAULT_CAPACITY: usize = 64;
+
+/// Syntax trees of files that are not open, keyed by content hash,
+/// so navigating the same dependency files again doesn't parse them again.
+/// The least recently used tree is dropped once `capacity` is reached.
+pub struct TreeCache {
+    capacity: usize,
+    /// most recently used first.
+    entries: Mutex<VecDeque<(u64, Tree)>>,
+}
+
+impl Default for TreeCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl TreeCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::with_capacity(capacity)),
+        }
+    }
+
+    /// The syntax tree of `source`, parsed only if it's not cached.
+    pub fn get_or_parse(&self, source: &str) -> Option<Tree> {
+        let hash = content_hash(source);
+        let mut entries = self.entries.lock();
+        if let Some(idx) = entries.iter().position(|(h, _)| *h == hash) {
+            let entry = entries.remove(idx)?;
+            let tree = entry.1.clone();
+            entries.push_front(entry);
+            return Some(tree);
+        }
+        let tree = parse(source)?;
+        entries.push_front((hash, tree.clone()));
+        entries.truncate(self.capacity);
+        Some(tree)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn content_hash(source: &str) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    source.hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tree_cache() {
+        let cache = TreeCache::new(2);
+        let a = "module A {}";
+        let tree = cache.get_or_parse(a).unwrap();
+        assert!(!tree.root_node().has_error());
+        cache.get_or_parse(a).unwrap();
+        assert_eq!(cache.len(), 1);
+
+        cache.get_or_parse("module B {}").unwrap();
+        // `A` is used again, so `B` is the one evicted.
+        cache.get_or_parse(a).unwrap();
+        cache.get_or_parse("module C {}").unwrap();
+        assert_eq!(cache.len(), 2);
+        let hashes: Vec<_> = cache.entries.lock().iter().map(|(h, _)| *h).collect();
+        assert_eq!(hashes, vec![content_hash("module C {}"), content_hash(a)]);
+    }
+}
@@ -0,0 +1,195 @@
+//! Layout of new Move projects, created by the `move/newProject` command:
+//! a `Move.toml` manifest, a sample module in `modules` and a script testing it in `scripts`.
+//! And the template of modules created by `move/newModule`.
+
+use crate::{config::MANIFEST_FILE, lsp_ext::NewProjectParams};
+use anyhow::{bail, Result};
+use move_lang::shared::Address;
+use serde::Serialize;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+const MODULES_DIR: &str = "modules";
+const SCRIPTS_DIR: &str = "scripts";
+
+/// Manifest of a new project, keys are the ones of `ProjectConfig`.
+#[derive(Serialize)]
+struct Manifest<'a> {
+    sender_address: &'a str,
+    modules_folders: Vec<&'a str>,
+    scripts_folders: Vec<&'a str>,
+    /// tables go last in toml.
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    addresses: BTreeMap<&'a str, &'a str>,
+}
+
+/// Create the project in `root`, which must not exist or be empty.
+/// The sample module is published at the sender, which is required.
+/// Returns the created files.
+pub fn new_project(root: &Path, params: &NewProjectParams) -> Result<Vec<PathBuf>> {
+    if root.is_file() || (root.is_dir() && root.read_dir()?.next().is_some()) {
+        bail!("{} is not an empty folder", root.display());
+    }
+    let sender = match params.sender_address.as_deref() {
+        Some(sender) => sender,
+        None => bail!("no sender address to publish the sample module at"),
+    };
+    if let Err(e) = Address::parse_str(sender) {
+        bail!("invalid sender address {}, {}", sender, e);
+    }
+    for (name, address) in params.addresses.iter() {
+        if let Err(e) = Address::parse_str(address) {
+            bail!("invalid address {} of {}, {}", address, name, e);
+        }
+    }
+
+    let manifest = toml::to_string(&Manifest {
+        sender_address: sender,
+        modules_folders: vec![MODULES_DIR],
+        scripts_folders: vec![SCRIPTS_DIR],
+        addresses: params
+            .addresses
+            .iter()
+            .map(|(name, address)| (name.as_str(), address.as_str()))
+            .collect(),
+    })?;
+    let files = vec![
+        (PathBuf::from(MANIFEST_FILE), manifest),
+        (
+            Path::new(MODULES_DIR).join("Hello.move"),
+            "module Hello {\n    public fun answer(): u64 {\n        42\n    }\n}\n".to_string(),
+        ),
+        (
+            Path::new(SCRIPTS_DIR).join("test_hello.move"),
+            format!(
+                "script {{\n    use {}::Hello;\n\n    fun main() {{\n        assert(Hello::answer() == 42, 1);\n    }}\n}}\n",
+                sender
+            ),
+        ),
+    ];
+
+    let mut created = vec![];
+    for (path, content) in files {
+        let path = root.join(path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, content)?;
+        created.push(path);
+    }
+    Ok(created)
+}
+
+/// The address a new module is placed at: `address` if it's a literal or a named address,
+/// the sender otherwise.
+pub fn module_address(
+    address: Option<&str>,
+    sender: Option<Address>,
+    addresses: &BTreeMap<String, String>,
+) -> Result<String> {
+    match address {
+        Some(a) if a.starts_with("0x") && Address::parse_str(a).is_ok() => Ok(a.to_string()),
+        Some(a) => match addresses.get(a) {
+            Some(address) => Ok(address.clone()),
+            None => bail!("unknown named address {}", a),
+        },
+        None => match sender {
+            Some(sender) => Ok(sender.to_string()),
+            None => bail!("no sender address is configured"),
+        },
+    }
+}
+
+/// Create `<name>.move` in `folder`, defining an empty module `name` at `address`.
+pub fn new_module(folder: &Path, name: &str, address: &str) -> Result<PathBuf> {
+    let valid_name = name
+        .chars()
+        .next()
+        .map_or(false, |c| c.is_ascii_alphabetic())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid_name {
+        bail!("invalid module name {:?}", name);
+    }
+    let path = folder.join(name).with_extension("move");
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    std::fs::create_dir_all(folder)?;
+    std::fs::write(
+        &path,
+        format!("address {} {{\nmodule {} {{\n}}\n}}\n", address, name),
+    )?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ProjectConfig;
+    use serde_json::Value;
+    use tower_lsp::lsp_types::Url;
+
+    #[test]
+    fn test_new_project() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("hello");
+        let params = NewProjectParams {
+            uri: Url::from_file_path(&dir).unwrap(),
+            sender_address: Some("0x42".to_string()),
+            addresses: vec![("Std".to_string(), "0x1".to_string())]
+                .into_iter()
+                .collect(),
+        };
+        let files = new_project(&dir, &params).unwrap();
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|f| f.is_file()));
+
+        let config = ProjectConfig::load(Some(&dir), Value::Null).unwrap();
+        assert_eq!(config.modules_folders, vec![dir.join(MODULES_DIR)]);
+        assert_eq!(config.scripts_folders, vec![dir.join(SCRIPTS_DIR)]);
+        assert_eq!(config.sender_address, Address::parse_str("0x42").ok());
+        let script = std::fs::read_to_string(&files[2]).unwrap();
+        assert!(script.contains("use 0x42::Hello;"));
+
+        // never overwrite an existing project.
+        assert!(new_project(&dir, &params).is_err());
+
+        let params = NewProjectParams {
+            uri: Url::from_file_path(&tmp.path().join("no_sender")).unwrap(),
+            sender_address: None,
+            addresses: BTreeMap::new(),
+        };
+        assert!(new_project(&tmp.path().join("no_sender"), &params).is_err());
+    }
+
+    #[test]
+    fn test_new_module() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().join("modules");
+        let addresses = vec![("Swap".to_string(), "0x3".to_string())]
+            .into_iter()
+            .collect();
+        let sender = Address::parse_str("0x42").ok();
+        assert_eq!(
+            module_address(Some("Swap"), sender, &addresses).unwrap(),
+            "0x3"
+        );
+        assert_eq!(
+            module_address(Some("0x2"), sender, &addresses).unwrap(),
+            "0x2"
+        );
+        assert!(module_address(Some("Std"), sender, &addresses).is_err());
+        assert!(module_address(None, None, &addresses).is_err());
+
+        let path = new_module(&dir, "Token", "0x3").unwrap();
+        assert_eq!(path, dir.join("Token.move"));
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            "address 0x3 {\nmodule Token {\n}\n}\n"
+        );
+        assert!(new_module(&dir, "Token", "0x3").is_err());
+        assert!(new_module(&dir, "1Token", "0x3").is_err());
+    }
+}
@@ -0,0 +1,298 @@
+//! Run a compiled script, or a function of a compiled module, against an in-memory move vm.
+//!
+//! Executions are not metered: the vm charges the zero cost schedule, so no gas is reported.
+
+use anyhow::{bail, format_err, Result};
+use move_core_types::{
+    account_address::AccountAddress,
+    gas_schedule::GasUnits,
+    identifier::Identifier,
+    language_storage::{ModuleId, TypeTag},
+    parser::{parse_transaction_argument, parse_type_tags},
+    transaction_argument::TransactionArgument,
+};
+use move_lang::compiled_unit::CompiledUnit;
+use move_vm_runtime::{data_cache::RemoteCache, move_vm::MoveVM};
+use move_vm_types::{
+    gas_schedule::{zero_cost_schedule, CostStrategy},
+    values::Value,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use vm::errors::{PartialVMResult, VMResult};
+
+/// Gas units the execution may use, nothing is ever charged from them.
+const GAS_LIMIT: u64 = 1_000_000;
+
+/// Storage which only holds the modules compiled along with the script.
+#[derive(Default)]
+struct InMemoryStorage {
+    modules: HashMap<ModuleId, Vec<u8>>,
+}
+
+impl InMemoryStorage {
+    /// Storage of the modules in `units`.
+    fn new(units: &[CompiledUnit]) -> Self {
+        let mut storage = Self::default();
+        for unit in units {
+            if let CompiledUnit::Module { module, .. } = unit {
+                storage.modules.insert(module.self_id(), unit.serialize());
+            }
+        }
+        storage
+    }
+}
+
+impl RemoteCache for InMemoryStorage {
+    fn get_module(&self, module_id: &ModuleId) -> VMResult<Option<Vec<u8>>> {
+        Ok(self.modules.get(module_id).cloned())
+    }
+
+    fn get_resource(
+        &self,
+        _address: &AccountAddress,
+        _tag: &TypeTag,
+    ) -> PartialVMResult<Option<Vec<u8>>> {
+        Ok(None)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutedEvent {
+    pub key: String,
+    pub sequence_number: u64,
+    pub type_tag: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExecutionResult {
+    pub success: bool,
+    pub events: Vec<ExecutedEvent>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Execute the script in `units` whose source is `script_file`.
+/// `args` and `type_args` use the same syntax as the move cli, e.g. `0x1`, `10u64`, `true`.
+pub fn run_script(
+    units: Vec<CompiledUnit>,
+    script_file: &str,
+    senders: Vec<AccountAddress>,
+    args: &[String],
+    type_args: &[String],
+) -> Result<ExecutionResult> {
+    let storage = InMemoryStorage::new(&units);
+    let script = units.iter().find_map(|unit| match unit {
+        CompiledUnit::Script { loc, .. } if loc.file() == script_file => Some(unit.serialize()),
+        _ => None,
+    });
+    let script = match script {
+        Some(s) => s,
+        None => bail!("no script found in {}", script_file),
+    };
+
+    let args = args
+        .iter()
+        .map(|a| parse_transaction_argument(a).map(to_value))
+        .collect::<Result<Vec<_>>>()?;
+    let type_args = type_args
+        .iter()
+        .map(|t| parse_type_tags(t))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .flatten()
+        .collect();
+    Ok(execute(
+        &storage,
+        Entry::Script { script, senders },
+        type_args,
+        args,
+    ))
+}
+
+/// Execute `function`, which takes no arguments, of the module `module` defined in `file`.
+/// The function needn't be public, like a test function.
+pub fn run_function(
+    units: Vec<CompiledUnit>,
+    file: &str,
+    module: &str,
+    function: &str,
+) -> Result<ExecutionResult> {
+    let storage = InMemoryStorage::new(&units);
+    let module_id = units.iter().find_map(|unit| match unit {
+        CompiledUnit::Module {
+            ident, module: m, ..
+        } if ident.loc().file() == file && m.self_id().name().as_str() == module => {
+            Some(m.self_id())
+        }
+        _ => None,
+    });
+    let module = match module_id {
+        Some(id) => id,
+        None => bail!("no module {} found in {}", module, file),
+    };
+    let function = Identifier::new(function)
+        .map_err(|e| format_err!("invalid function name {}, {}", function, e))?;
+    Ok(execute(
+        &storage,
+        Entry::Function { module, function },
+        vec![],
+        vec![],
+    ))
+}
+
+/// What the vm executes.
+enum Entry {
+    Script {
+        script: Vec<u8>,
+        senders: Vec<AccountAddress>,
+    },
+    Function {
+        module: ModuleId,
+        function: Identifier,
+    },
+}
+
+fn execute(
+    storage: &InMemoryStorage,
+    entry: Entry,
+    type_args: Vec<TypeTag>,
+    args: Vec<Value>,
+) -> ExecutionResult {
+    let cost_table = zero_cost_schedule();
+    let mut cost_strategy = CostStrategy::transaction(&cost_table, GasUnits::new(GAS_LIMIT));
+
+    let vm = MoveVM::new();
+    let mut session = vm.new_session(storage);
+    let executed = match entry {
+        Entry::Script { script, senders } => {
+            session.execute_script(script, type_args, args, senders, &mut cost_strategy)
+        }
+        Entry::Function { module, function } => {
+            // the module is the sender, as if it called the function itself.
+            let sender = *module.address();
+            session.execute_function(
+                &module,
+                &function,
+                type_args,
+                args,
+                sender,
+                &mut cost_strategy,
+            )
+        }
+    };
+    match executed.and_then(|_| session.finish()) {
+        Ok(effects) => ExecutionResult {
+            success: true,
+            events: effects
+                .events
+                .into_iter()
+                .map(|(key, seq, ty, _layout, value)| ExecutedEvent {
+                    key: hex(&key),
+                    sequence_number: seq,
+                    type_tag: ty.to_string(),
+                    data: format!("{}", value),
+                })
+                .collect(),
+            error: None,
+        },
+        Err(e) => ExecutionResult {
+            success: false,
+            events: vec![],
+            error: Some(format!("{:?}", e)),
+        },
+    }
+}
+
+pub fn parse_sender(s: &str) -> Result<AccountAddress> {
+    AccountAddress::from_hex_literal(s).map_err(|e| format_err!("invalid sender {}, {}", s, e))
+}
+
+fn to_value(arg: TransactionArgument) -> Value {
+    match arg {
+        TransactionArgument::U8(i) => Value::u8(i),
+        TransactionArgument::U64(i) => Value::u64(i),
+        TransactionArgument::U128(i) => Value::u128(i),
+        TransactionArgument::Bool(b) => Value::bool(b),
+        TransactionArgument::Address(a) => Value::address(a),
+        TransactionArgument::U8Vector(v) => Value::vector_u8(v),
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::salsa::RootDatabase;
+    use move_lang::shared::Address;
+    use std::path::PathBuf;
+    use xi_rope::Rope;
+
+    const SCRIPT: &str = "/script.move";
+
+    fn compile(source: &str) -> Vec<CompiledUnit> {
+        let mut db = RootDatabase::default();
+        db.set_stdlib_files(vec![]);
+        db.set_module_files(vec![]);
+        db.update_source(PathBuf::from(SCRIPT), Rope::from(source));
+        let (_, units) = db.compile_file_with_deps(Address::parse_str("0x1").ok(), SCRIPT.into());
+        units.expect("script doesn't compile")
+    }
+
+    fn run(source: &str, args: &[&str]) -> ExecutionResult {
+        let args: Vec<_> = args.iter().map(|a| a.to_string()).collect();
+        run_script(compile(source), SCRIPT, vec![], &args, &[]).unwrap()
+    }
+
+    #[test]
+    fn test_run_script() {
+        let result = run("script {\nfun main() {}\n}\n", &[]);
+        assert!(result.success);
+        assert!(result.error.is_none());
+
+        let result = run("script {\nfun main() { abort 7 }\n}\n", &[]);
+        assert!(!result.success);
+        assert!(result.error.is_some());
+    }
+
+    #[test]
+    fn test_run_script_args() {
+        let source = "script {\nfun main(x: u64) { assert(x == 1, 2) }\n}\n";
+        assert!(run(source, &["1u64"]).success);
+        assert!(!run(source, &["2u64"]).success);
+        let units = compile(source);
+        assert!(run_script(units, SCRIPT, vec![], &["1x".to_string()], &[]).is_err());
+    }
+
+    #[test]
+    fn test_run_function() {
+        let source = "address 0x1 {\nmodule M {\n    fun test_ok() {}\n    fun test_abort() { abort 1 }\n}\n}\n";
+        assert!(
+            run_function(compile(source), SCRIPT, "M", "test_ok")
+                .unwrap()
+                .success
+        );
+        assert!(
+            !run_function(compile(source), SCRIPT, "M", "test_abort")
+                .unwrap()
+                .success
+        );
+        assert!(run_function(compile(source), SCRIPT, "N", "test_ok").is_err());
+        assert!(run_function(compile(source), "/other.move", "M", "test_ok").is_err());
+    }
+
+    #[test]
+    fn test_run_script_errors() {
+        let units = compile("script {\nfun main() {}\n}\n");
+        assert!(run_script(units, "/other.move", vec![], &[], &[]).is_err());
+        assert!(parse_sender("0xzz").is_err());
+        assert_eq!(
+            parse_sender("0x1").unwrap(),
+            AccountAddress::from_hex_literal("0x1").unwrap()
+        );
+    }
+}
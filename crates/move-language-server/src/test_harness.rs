@@ -0,0 +1,285 @@
+//! Drive a `MoveLanguageServer` over loopback sockets, for black-box tests of the handlers.
+//!
+//! Requests the server sends to the client are answered automatically:
+//! `workspace/configuration` with `settings`, `workspace/applyEdit` as applied,
+//! anything else with `null`.
+
+use crate::lsp_server::MoveLanguageServer;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::VecDeque, time::Duration};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+use tower_lsp::{
+    lsp_types::{PublishDiagnosticsParams, Url},
+    LspService, Server,
+};
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct TestServer {
+    input: TcpStream,
+    output: BufReader<TcpStream>,
+    next_id: u64,
+    /// notifications received while waiting for something else.
+    notifications: VecDeque<(String, Value)>,
+    /// requests of the server, already answered.
+    server_requests: VecDeque<(String, Value)>,
+    /// client settings returned for every `workspace/configuration` item.
+    pub settings: Value,
+}
+
+/// A step of a JSON fixture, one of
+/// `{"request": method, "params": .., "result": ..}` (`result` is optional),
+/// `{"notify": method, "params": ..}`, or
+/// `{"expect": method, "params": ..}`, which waits for a notification whose params contain
+/// the given ones.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum Step {
+    Request {
+        request: String,
+        #[serde(default)]
+        params: Value,
+        #[serde(default)]
+        result: Option<Value>,
+    },
+    Notify {
+        notify: String,
+        #[serde(default)]
+        params: Value,
+    },
+    Expect {
+        expect: String,
+        #[serde(default)]
+        params: Value,
+    },
+}
+
+impl TestServer {
+    /// Spawn a server on the current tokio runtime.
+    pub fn start() -> Self {
+        let (input, server_input) = pipe();
+        let (server_output, output) = pipe();
+        let (service, messages) = LspService::new(|client| MoveLanguageServer::new(client));
+        tokio::spawn(
+            Server::new(server_input, server_output)
+                .interleave(messages)
+                .serve(service),
+        );
+        Self {
+            input,
+            output: BufReader::new(output),
+            next_id: 0,
+            notifications: VecDeque::new(),
+            server_requests: VecDeque::new(),
+            settings: Value::Null,
+        }
+    }
+
+    /// `initialize` then `initialized`, returns the initialize result.
+    pub async fn initialize(&mut self, params: Value) -> Value {
+        let result = self.request("initialize", params).await;
+        self.notify("initialized", json!({})).await;
+        result
+    }
+
+    /// Send a request and wait for its response, returns the result, or the error object.
+    pub async fn request(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(message(Some(id), method, params)).await;
+        loop {
+            let msg = self.recv().await;
+            if msg.get("method").is_none() && msg["id"] == json!(id) {
+                return match msg.get("error") {
+                    Some(e) => e.clone(),
+                    None => msg["result"].clone(),
+                };
+            }
+        }
+    }
+
+    pub async fn notify(&mut self, method: &str, params: Value) {
+        self.send(message(None, method, params)).await;
+    }
+
+    /// Params of the next `method` notification.
+    pub async fn notification(&mut self, method: &str) -> Value {
+        if let Some(idx) = self.notifications.iter().position(|(m, _)| m == method) {
+            return self.notifications.remove(idx).unwrap().1;
+        }
+        loop {
+            let msg = self.recv().await;
+            if msg.get("id").is_none() {
+                if let Some(m) = msg["method"].as_str() {
+                    if m == method {
+                        return msg["params"].clone();
+                    }
+                    self.notifications
+                        .push_back((m.to_string(), msg["params"].clone()));
+                }
+            }
+        }
+    }
+
+    /// Params of the next `method` request of the server.
+    pub async fn server_request(&mut self, method: &str) -> Value {
+        loop {
+            if let Some(idx) = self.server_requests.iter().position(|(m, _)| m == method) {
+                return self.server_requests.remove(idx).unwrap().1;
+            }
+            let msg = self.recv().await;
+            if msg.get("id").is_none() {
+                if let Some(m) = msg["method"].as_str() {
+                    self.notifications
+                        .push_back((m.to_string(), msg["params"].clone()));
+                }
+            }
+        }
+    }
+
+    /// The next diagnostics published for `uri`.
+    pub async fn diagnostics(&mut self, uri: &Url) -> PublishDiagnosticsParams {
+        loop {
+            let params = self.notification("textDocument/publishDiagnostics").await;
+            let params: PublishDiagnosticsParams =
+                serde_json::from_value(params).expect("invalid publishDiagnostics params");
+            if &params.uri == uri {
+                return params;
+            }
+        }
+    }
+
+    /// Replay the steps of a JSON fixture, panics on the first mismatch.
+    pub async fn run_fixture(&mut self, fixture: &str) {
+        let steps: Vec<Step> = serde_json::from_str(fixture).expect("invalid fixture");
+        for step in steps {
+            match step {
+                Step::Request {
+                    request,
+                    params,
+                    result,
+                } => {
+                    let actual = self.request(&request, params).await;
+                    if let Some(expected) = result {
+                        assert!(
+                            json_contains(&actual, &expected),
+                            "{} returns {}, expect {}",
+                            request,
+                            actual,
+                            expected
+                        );
+                    }
+                }
+                Step::Notify { notify, params } => self.notify(&notify, params).await,
+                Step::Expect { expect, params } => loop {
+                    let actual = self.notification(&expect).await;
+                    if json_contains(&actual, &params) {
+                        break;
+                    }
+                },
+            }
+        }
+    }
+
+    async fn send(&mut self, msg: Value) {
+        let body = msg.to_string();
+        let frame = format!("Content-Length: {}\r\n\r\n{}", body.len(), body);
+        self.input
+            .write_all(frame.as_bytes())
+            .await
+            .expect("server input closed");
+    }
+
+    /// Next message from the server, answering the server requests on the way.
+    async fn recv(&mut self) -> Value {
+        loop {
+            let msg = tokio::time::timeout(TIMEOUT, self.read_message())
+                .await
+                .expect("timeout waiting for server message");
+            let (id, method) = match (msg.get("id"), msg["method"].as_str()) {
+                (Some(id), Some(method)) => (id.clone(), method.to_string()),
+                _ => return msg,
+            };
+            let result = match method.as_str() {
+                "workspace/configuration" => {
+                    let items = msg["params"]["items"].as_array().map_or(0, |i| i.len());
+                    Value::Array(vec![self.settings.clone(); items])
+                }
+                "workspace/applyEdit" => json!({ "applied": true }),
+                _ => Value::Null,
+            };
+            self.server_requests
+                .push_back((method, msg["params"].clone()));
+            self.send(json!({ "jsonrpc": "2.0", "id": id, "result": result }))
+                .await;
+        }
+    }
+
+    async fn read_message(&mut self) -> Value {
+        let mut content_length = None;
+        loop {
+            let mut line = String::new();
+            self.output
+                .read_line(&mut line)
+                .await
+                .expect("server output closed");
+            let line = line.trim_end();
+            if line.is_empty() {
+                break;
+            }
+            if let Some(len) = line.strip_prefix("Content-Length:") {
+                content_length = len.trim().parse::<usize>().ok();
+            }
+        }
+        let mut body = vec![0; content_length.expect("message without Content-Length")];
+        self.output
+            .read_exact(&mut body)
+            .await
+            .expect("server output closed");
+        serde_json::from_slice(&body).expect("invalid message from server")
+    }
+}
+
+/// `params` are omitted if null, some methods like `shutdown` take no params.
+fn message(id: Option<u64>, method: &str, params: Value) -> Value {
+    let mut msg = json!({ "jsonrpc": "2.0", "method": method });
+    if let Some(id) = id {
+        msg["id"] = json!(id);
+    }
+    if !params.is_null() {
+        msg["params"] = params;
+    }
+    msg
+}
+
+/// Both ends of a loopback connection, which works on every platform, unlike unix sockets.
+fn pipe() -> (TcpStream, TcpStream) {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").expect("fail to bind loopback");
+    let client = std::net::TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (
+        TcpStream::from_std(client).unwrap(),
+        TcpStream::from_std(server).unwrap(),
+    )
+}
+
+/// Whether every field of `expected` is in `actual`. Arrays must match element-wise.
+pub fn json_contains(actual: &Value, expected: &Value) -> bool {
+    match (actual, expected) {
+        (Value::Object(actual), Value::Object(expected)) => expected
+            .iter()
+            .all(|(k, v)| actual.get(k).map_or(false, |a| json_contains(a, v))),
+        (Value::Array(actual), Value::Array(expected)) => {
+            actual.len() == expected.len()
+                && actual
+                    .iter()
+                    .zip(expected)
+                    .all(|(a, e)| json_contains(a, e))
+        }
+        _ => actual == expected,
+    }
+}
@@ -0,0 +1,10 @@
+/// Système de paiement — 支付
+module Payment {
+    use 0x1::Signer; // signataire ✓
+    /* 🚀 vécteur */ use 0x1::Vector as Vec; // « vecteur »
+    use 0x1::Option::{Self, Option as Opt}; // ünïcödé
+
+    const GREETING: vector<u8> = b"héllo wörld";
+
+    fun f(): u64 { /* ← */ 0 }
+}
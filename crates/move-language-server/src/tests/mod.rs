@@ -1,2 +1,4 @@
 #[cfg(test)]
 mod resolver_test;
+#[cfg(test)]
+mod server_test;
@@ -1,9 +1,49 @@
-use crate::{node_resolver::NodeResolver, tree_sitter_move::parser};
+use crate::{
+    node_resolver::{NodeResolver, UsePart},
+    tree_sitter_move::parser,
+};
+use xi_rope::Rope;
 
 #[test]
 pub fn test_resolve_use() {
     let text = include_str!("cases/use_query.move");
     let tree = parser().parse(text, None).unwrap();
-    let uses = NodeResolver::resolve_use(&tree.root_node());
+    let uses = NodeResolver::resolve_use(&tree.root_node(), &Rope::from(text));
     assert_eq!(uses.len(), 10);
 }
+
+#[test]
+pub fn test_resolve_use_with_unicode() {
+    let text = include_str!("cases/use_query_unicode.move");
+    let rope = Rope::from(text);
+    let tree = parser().parse(text, None).unwrap();
+    let uses = NodeResolver::resolve_use(&tree.root_node(), &rope);
+    let text_of = |p: &UsePart| p.text.clone();
+    let modules: Vec<_> = uses
+        .iter()
+        .map(|u| (text_of(u.addr()), text_of(u.module())))
+        .collect();
+    assert_eq!(
+        modules,
+        vec![
+            ("0x1".to_string(), "Signer".to_string()),
+            ("0x1".to_string(), "Vector".to_string()),
+            ("0x1".to_string(), "Option".to_string()),
+            ("0x1".to_string(), "Option".to_string()),
+        ]
+    );
+    assert_eq!(
+        uses[1].module_alias().map(text_of),
+        Some("Vec".to_string())
+    );
+    assert_eq!(uses[3].member().map(text_of), Some("Option".to_string()));
+    assert_eq!(uses[3].member_alias().map(text_of), Some("Opt".to_string()));
+
+    assert_eq!(uses[0].module_local_name(), "Signer");
+    assert_eq!(uses[1].module_local_name(), "Vec");
+    let alias = uses[1].module_alias().unwrap().range;
+    assert_eq!(
+        rope.slice_to_cow(alias.start_byte..alias.end_byte),
+        "Vec"
+    );
+}
@@ -0,0 +1,381 @@
+use crate::test_harness::TestServer;
+use serde_json::json;
+use tower_lsp::lsp_types::Url;
+
+#[tokio::test]
+async fn test_virtual_document_fixture() {
+    let mut server = TestServer::start();
+    server
+        .run_fixture(include_str!("cases/virtual_document.json"))
+        .await;
+}
+
+#[tokio::test]
+async fn test_syntax_error_diagnostics() {
+    let mut server = TestServer::start();
+    server.initialize(json!({ "capabilities": {} })).await;
+
+    let uri = Url::parse("untitled:Untitled-1").unwrap();
+    server
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "move",
+                    "version": 1,
+                    "text": "module M {\n    fun f( {}\n}\n"
+                }
+            }),
+        )
+        .await;
+    let diags = server.diagnostics(&uri).await;
+    assert_eq!(diags.version, Some(1));
+    assert!(!diags.diagnostics.is_empty());
+    assert_eq!(diags.diagnostics[0].range.start.line, 1);
+}
+
+#[tokio::test]
+async fn test_pull_diagnostics_fixture() {
+    let mut server = TestServer::start();
+    server
+        .run_fixture(include_str!("cases/pull_diagnostics.json"))
+        .await;
+}
+
+#[tokio::test]
+async fn test_compile_errors() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let file = dir.join("M.move");
+    std::fs::write(
+        &file,
+        "address 0x1 {\nmodule M {\n    fun f(): bool { 0 }\n}\n}\n",
+    )
+    .unwrap();
+
+    let uri = Url::from_file_path(&file).unwrap();
+    let compile = json!({
+        "command": "compile",
+        "arguments": [{ "file": uri, "out_dir": dir.join("target") }]
+    });
+
+    let mut server = TestServer::start();
+    server
+        .initialize(json!({
+            "capabilities": {},
+            "initializationOptions": { "modules_folders": [dir] }
+        }))
+        .await;
+    let result = server
+        .request("workspace/executeCommand", compile.clone())
+        .await;
+    assert_eq!(result["artifacts"], json!([]));
+    let errors = result["errors"].as_array().unwrap();
+    assert_eq!(errors.len(), 1);
+    assert_eq!(errors[0]["primaryLabel"]["range"]["start"]["line"], 2);
+    // the errors show up on the file, which isn't open.
+    let diags = server.diagnostics(&uri).await;
+    assert_eq!(diags.diagnostics.len(), 1);
+    assert_eq!(diags.diagnostics[0].range.start.line, 2);
+
+    // and are cleared once it compiles.
+    std::fs::write(
+        &file,
+        "address 0x1 {\nmodule M {\n    fun f(): bool { true }\n}\n}\n",
+    )
+    .unwrap();
+    server
+        .notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": [{ "uri": uri, "type": 2 }] }),
+        )
+        .await;
+    let result = server.request("workspace/executeCommand", compile).await;
+    assert_eq!(result["errors"], json!([]));
+    assert_eq!(server.diagnostics(&uri).await.diagnostics, vec![]);
+}
+
+#[tokio::test]
+async fn test_read_file_outside_project() {
+    let mut server = TestServer::start();
+    server.initialize(json!({ "capabilities": {} })).await;
+    let file = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let result = server
+        .request(
+            "workspace/executeCommand",
+            json!({
+                "command": "move/readFile",
+                "arguments": [{ "uri": Url::from_file_path(&file).unwrap() }]
+            }),
+        )
+        .await;
+    assert_eq!(result["code"], json!(-32602));
+}
+
+#[tokio::test]
+async fn test_run_test_lens() {
+    let tmp = tempfile::tempdir().unwrap();
+    let file = tmp.path().join("M.move");
+    let text = "address 0x1 {\nmodule M {\n    fun test_f() {}\n}\n}\n";
+    std::fs::write(&file, text).unwrap();
+    let uri = Url::from_file_path(&file).unwrap();
+
+    let mut server = TestServer::start();
+    server.initialize(json!({ "capabilities": {} })).await;
+    server
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": uri,
+                    "languageId": "move",
+                    "version": 1,
+                    "text": text
+                }
+            }),
+        )
+        .await;
+    server.diagnostics(&uri).await;
+
+    let lenses = server
+        .request(
+            "textDocument/codeLens",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+    let run = lenses
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|l| l["command"]["command"] == "runTest")
+        .unwrap()
+        .clone();
+    assert_eq!(run["range"]["start"]["line"], 2);
+    let result = server
+        .request(
+            "workspace/executeCommand",
+            json!({
+                "command": "runTest",
+                "arguments": run["command"]["arguments"]
+            }),
+        )
+        .await;
+    assert_eq!(result["success"], json!(true));
+}
+
+#[tokio::test]
+async fn test_file_shrunk_below_max_file_size() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let m = dir.join("M.move");
+    let small = "address 0x1 {\nmodule M {\n    public fun f() {}\n}\n}\n";
+    std::fs::write(&m, format!("{}// {}\n", small, "x".repeat(100))).unwrap();
+
+    let mut server = TestServer::start();
+    server
+        .initialize(json!({
+            "capabilities": {},
+            "initializationOptions": { "modules_folders": [dir], "max_file_size": 100 }
+        }))
+        .await;
+    std::fs::write(&m, small).unwrap();
+    server
+        .notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": [{ "uri": Url::from_file_path(&m).unwrap(), "type": 2 }] }),
+        )
+        .await;
+
+    // M is a dependency again, once it fits.
+    let n = Url::from_file_path(dir.join("N.move")).unwrap();
+    server
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": {
+                    "uri": n,
+                    "languageId": "move",
+                    "version": 1,
+                    "text": "address 0x1 {\nmodule N {\n    use 0x1::M;\n    fun g() { M::f() }\n}\n}\n"
+                }
+            }),
+        )
+        .await;
+    let diags = server.diagnostics(&n).await;
+    assert_eq!(diags.diagnostics, vec![]);
+}
+
+#[tokio::test]
+async fn test_buffer_below_max_file_size() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let m = dir.join("M.move");
+    let text = "address 0x1 {\nmodule M {\n    fun f(): bool { 0 }\n}\n}\n";
+    std::fs::write(&m, format!("{}// {}\n", text, "x".repeat(100))).unwrap();
+
+    let mut server = TestServer::start();
+    server
+        .initialize(json!({
+            "capabilities": {},
+            "initializationOptions": { "modules_folders": [dir], "max_file_size": 100 }
+        }))
+        .await;
+    // the file on disk is too large, the open document is checked.
+    let uri = Url::from_file_path(&m).unwrap();
+    server
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "move", "version": 1, "text": text }
+            }),
+        )
+        .await;
+    let diags = server.diagnostics(&uri).await;
+    assert_eq!(diags.diagnostics.len(), 1);
+    assert_eq!(diags.diagnostics[0].range.start.line, 2);
+}
+
+#[tokio::test]
+async fn test_compile_on_save() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path().to_path_buf();
+    let file = dir.join("M.move");
+    let text = "address 0x1 {\nmodule M {\n    fun f(): bool { 0 }\n}\n}\n";
+    std::fs::write(&file, text).unwrap();
+    let uri = Url::from_file_path(&file).unwrap();
+
+    let mut server = TestServer::start();
+    server
+        .initialize(json!({
+            "capabilities": {},
+            "initializationOptions": { "modules_folders": [dir], "compile_on_save": true }
+        }))
+        .await;
+    server
+        .notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": uri, "languageId": "move", "version": 1, "text": text }
+            }),
+        )
+        .await;
+    let diags = server.diagnostics(&uri).await;
+    assert_eq!(diags.diagnostics.len(), 1);
+
+    // a file with errors isn't compiled, the first artifacts are the ones of the fix.
+    server
+        .notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+
+    let fixed = text.replace("{ 0 }", "{ true }");
+    std::fs::write(&file, &fixed).unwrap();
+    server
+        .notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": uri, "version": 2 },
+                "contentChanges": [{
+                    "range": {
+                        "start": { "line": 2, "character": 20 },
+                        "end": { "line": 2, "character": 21 }
+                    },
+                    "text": "true"
+                }]
+            }),
+        )
+        .await;
+    server
+        .notify(
+            "textDocument/didSave",
+            json!({ "textDocument": { "uri": uri } }),
+        )
+        .await;
+    let compiled = server.notification("move/compiled").await;
+    assert_eq!(compiled["uri"], json!(uri));
+    assert_eq!(compiled["artifacts"].as_array().unwrap().len(), 1);
+    assert_eq!(server.diagnostics(&uri).await.diagnostics, vec![]);
+}
+
+#[tokio::test]
+async fn test_status_after_config_change() {
+    let mut server = TestServer::start();
+    server.settings = json!({});
+    server.initialize(json!({ "capabilities": {} })).await;
+    server
+        .notify(
+            "workspace/didChangeConfiguration",
+            json!({ "settings": {} }),
+        )
+        .await;
+    let mut statuses = vec![];
+    while statuses.last() != Some(&json!("idle")) {
+        statuses.push(server.notification("move/status").await["status"].clone());
+    }
+    // idle once the open documents are checked against the new config, not before.
+    assert_eq!(
+        statuses,
+        vec![json!("indexing"), json!("checking"), json!("idle")]
+    );
+}
+
+#[tokio::test]
+async fn test_module_follows_renamed_file() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = std::fs::canonicalize(tmp.path()).unwrap();
+    let m = dir.join("M.move");
+    let n = dir.join("N.move");
+    std::fs::write(
+        &m,
+        "address 0x1 {\nmodule M {\n    public fun f() {}\n}\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        &n,
+        "address 0x1 {\nmodule N {\n    use 0x1::M;\n    fun g() { M::f() }\n}\n}\n",
+    )
+    .unwrap();
+
+    let mut server = TestServer::start();
+    server
+        .initialize(json!({
+            "capabilities": {},
+            "initializationOptions": { "modules_folders": [dir] }
+        }))
+        .await;
+    let k = dir.join("K.move");
+    std::fs::rename(&m, &k).unwrap();
+    server
+        .notify(
+            "workspace/didChangeWatchedFiles",
+            json!({ "changes": [
+                { "uri": Url::from_file_path(&m).unwrap(), "type": 3 },
+                { "uri": Url::from_file_path(&k).unwrap(), "type": 1 }
+            ] }),
+        )
+        .await;
+
+    // the definition in K.move, the use declaration and the call in N.move.
+    let params = server.server_request("workspace/applyEdit").await;
+    let mut edited: Vec<_> = params["edit"]["documentChanges"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| {
+            let edits = c["edits"].as_array().unwrap();
+            assert!(edits.iter().all(|e| e["newText"] == json!("K")));
+            (c["textDocument"]["uri"].clone(), edits.len())
+        })
+        .collect();
+    edited.sort_by_key(|(uri, _)| uri.to_string());
+    assert_eq!(
+        edited,
+        vec![
+            (json!(Url::from_file_path(&k).unwrap()), 1),
+            (json!(Url::from_file_path(&n).unwrap()), 2),
+        ]
+    );
+}
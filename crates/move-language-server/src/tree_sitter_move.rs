@@ -1,24 +1,218 @@
 pub use tree_sitter::Parser;
 
-use tree_sitter::Language;
+use anyhow::{anyhow, bail, Result};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+use std::cell::RefCell;
+use tree_sitter::{Language, Tree};
 
 extern "C" {
     fn tree_sitter_move() -> Language;
 }
 
 pub fn language() -> Language {
-    unsafe { tree_sitter_move() }
+    match dynamic::LANGUAGE.as_ref() {
+        Some(Ok(language)) => *language,
+        _ => unsafe { tree_sitter_move() },
+    }
+}
+
+/// Whether the grammar is loaded from `$TREE_SITTER_MOVE_LIB` instead of the bundled one.
+pub fn is_dynamic() -> bool {
+    matches!(dynamic::LANGUAGE.as_ref(), Some(Ok(_)))
+}
+
+/// With the `dynamic-grammar` feature, the grammar can be loaded at startup from a shared
+/// library built from the grammar, so that grammar developers only restart the server
+/// to try their changes, instead of rebuilding it.
+#[cfg(feature = "dynamic-grammar")]
+mod dynamic {
+    use super::*;
+    use libloading::{Library, Symbol};
+
+    /// Environment variable with the path of the shared library.
+    pub const GRAMMAR_LIB_VAR: &str = "TREE_SITTER_MOVE_LIB";
+
+    /// The loaded grammar, `None` without the variable.
+    pub static LANGUAGE: Lazy<Option<Result<Language, String>>> = Lazy::new(|| {
+        let path = std::env::var_os(GRAMMAR_LIB_VAR)?;
+        Some(load(path.as_ref()).map_err(|e| {
+            format!(
+                "fail to load grammar from {}, {}",
+                std::path::Path::new(&path).display(),
+                e
+            )
+        }))
+    });
+
+    fn load(path: &std::path::Path) -> Result<Language> {
+        let library = Library::new(path)?;
+        let language = unsafe {
+            let constructor: Symbol<unsafe extern "C" fn() -> Language> =
+                library.get(b"tree_sitter_move")?;
+            constructor()
+        };
+        // trees point into the library for as long as the server runs.
+        std::mem::forget(library);
+        Ok(language)
+    }
+}
+
+#[cfg(not(feature = "dynamic-grammar"))]
+mod dynamic {
+    use super::*;
+
+    pub static LANGUAGE: Lazy<Option<Result<Language, String>>> = Lazy::new(|| None);
+}
+
+/// Whether the grammar has an external scanner, compiled and linked with its parser.
+pub const EXTERNAL_SCANNER: bool = cfg!(external_scanner);
+
+/// ABI version the bundled grammar was generated with.
+pub fn abi_version() -> usize {
+    language().version()
 }
 
 pub fn parser() -> Parser {
-    let language = unsafe { tree_sitter_move() };
     let mut parser = Parser::new();
-    parser.set_language(language).unwrap();
+    parser.set_language(language()).unwrap();
     parser
 }
 
-// // TODO: make it const.
-// pub static NODE_TYPES: Lazy<Vec<DataType>> = Lazy::new(|| {
-//     let node_types = include_str!("../../../tree-sitter-move/src/node-types.json");
-//     serde_json::from_str(node_types).unwrap()
-// });
+thread_local! {
+    /// Parsers can't be shared between threads, so every thread owns one.
+    static PARSER: RefCell<Parser> = RefCell::new(parser());
+}
+
+/// Run `f` with the parser of the current thread.
+pub fn with_parser<R>(f: impl FnOnce(&mut Parser) -> R) -> R {
+    PARSER.with(|p| f(&mut p.borrow_mut()))
+}
+
+/// Replace the parser of the current thread with a fresh one,
+/// as a failed parse may leave it in a bad state.
+pub fn reset_parser() {
+    PARSER.with(|p| *p.borrow_mut() = parser());
+}
+
+/// Parse `source` from scratch with the parser of the current thread.
+pub fn parse(source: &str) -> Option<Tree> {
+    let tree = with_parser(|p| p.parse(source, None));
+    if tree.is_none() {
+        reset_parser();
+    }
+    tree
+}
+
+/// Node kinds of the move grammar used by the server.
+pub mod kinds {
+    pub const ADDRESS_BLOCK: &str = "address_block";
+    pub const MODULE_DEFINITION: &str = "module_definition";
+    pub const SCRIPT_BLOCK: &str = "script_block";
+    pub const FUNCTION_DEFINITION: &str = "function_definition";
+    pub const ADDRESS_LITERAL: &str = "address_literal";
+    pub const MODULE_IDENTIFIER: &str = "module_identifier";
+    pub const MODULE_ACCESS: &str = "module_access";
+    pub const USE_DECL: &str = "use_decl";
+    pub const PACK_EXPRESSION: &str = "pack_expression";
+    pub const BIND_UNPACK: &str = "bind_unpack";
+    pub const STRUCT_DEFINITION: &str = "struct_definition";
+    pub const FIELD_ANNOTATION: &str = "field_annotation";
+    pub const CALL_EXPRESSION: &str = "call_expression";
+    pub const FUNCTION_PARAMETER: &str = "function_parameter";
+    pub const BLOCK: &str = "block";
+    pub const BLOCK_ITEM: &str = "block_item";
+    pub const LET_STATEMENT: &str = "let_statement";
+    pub const ASSIGN_EXPRESSION: &str = "assign_expression";
+    pub const RETURN_EXPRESSION: &str = "return_expression";
+    pub const STRUCT_IDENTIFIER: &str = "struct_identifier";
+    pub const FUNCTION_IDENTIFIER: &str = "function_identifier";
+    pub const VARIABLE_IDENTIFIER: &str = "variable_identifier";
+
+    /// Every kind above, checked against the grammar by `check_grammar`.
+    pub const ALL: &[&str] = &[
+        ADDRESS_BLOCK,
+        MODULE_DEFINITION,
+        SCRIPT_BLOCK,
+        FUNCTION_DEFINITION,
+        ADDRESS_LITERAL,
+        MODULE_IDENTIFIER,
+        MODULE_ACCESS,
+        USE_DECL,
+        PACK_EXPRESSION,
+        BIND_UNPACK,
+        STRUCT_DEFINITION,
+        FIELD_ANNOTATION,
+        CALL_EXPRESSION,
+        FUNCTION_PARAMETER,
+        BLOCK,
+        BLOCK_ITEM,
+        LET_STATEMENT,
+        ASSIGN_EXPRESSION,
+        RETURN_EXPRESSION,
+        STRUCT_IDENTIFIER,
+        FUNCTION_IDENTIFIER,
+        VARIABLE_IDENTIFIER,
+    ];
+}
+
+/// An entry of the `node-types.json` of the grammar, other fields are not needed.
+#[derive(Clone, Debug, Deserialize)]
+pub struct NodeType {
+    #[serde(rename = "type")]
+    pub kind: String,
+    pub named: bool,
+}
+
+/// Node types of the bundled grammar.
+pub static NODE_TYPES: Lazy<Vec<NodeType>> = Lazy::new(|| {
+    let node_types = include_str!(concat!(env!("TREE_SITTER_MOVE_SRC"), "/node-types.json"));
+    serde_json::from_str(node_types).unwrap_or_default()
+});
+
+/// Check that the bundled grammar can be loaded by the tree-sitter runtime,
+/// and that it still has every node kind the server looks for.
+pub fn check_grammar() -> Result<()> {
+    if let Some(Err(e)) = dynamic::LANGUAGE.as_ref() {
+        bail!("{}", e);
+    }
+    Parser::new().set_language(language()).map_err(|e| {
+        anyhow!(
+            "grammar of abi version {} is not supported by tree-sitter, {}",
+            abi_version(),
+            e
+        )
+    })?;
+    let missing: Vec<_> = kinds::ALL.iter().filter(|k| !has_kind(k)).collect();
+    if !missing.is_empty() {
+        bail!(
+            "node kinds {:?} are not in the grammar of abi version {}, \
+             the bundled tree-sitter-move doesn't match the server",
+            missing,
+            abi_version()
+        );
+    }
+    Ok(())
+}
+
+/// Whether `kind` is a named node kind of the grammar. The node types of a loaded grammar
+/// are read from its parse table, as the bundled `node-types.json` is not its own.
+fn has_kind(kind: &str) -> bool {
+    if !is_dynamic() {
+        return NODE_TYPES.iter().any(|t| t.named && t.kind == kind);
+    }
+    let language = language();
+    (0..language.node_kind_count() as u16)
+        .any(|id| language.node_kind_is_named(id) && language.node_kind_for_id(id) == kind)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_grammar() {
+        assert!(abi_version() > 0);
+        check_grammar().unwrap();
+    }
+}
@@ -1,7 +1,13 @@
-use move_lang::MOVE_EXTENSION;
-use std::path::{Path, PathBuf};
+use move_lang::{MOVE_COMPILED_EXTENSION, MOVE_EXTENSION};
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
 
-pub fn find_move_file(path: PathBuf) -> Vec<PathBuf> {
+/// Collect move files under `path`.
+/// When `respect_gitignore` is set, files ignored by `.gitignore`/`.ignore` rules are skipped.
+pub fn find_move_file(path: impl AsRef<Path>, respect_gitignore: bool) -> Vec<PathBuf> {
+    let path = path.as_ref();
     let has_move_extension = |path: &Path| match path.extension().and_then(|s| s.to_str()) {
         Some(extension) => extension == MOVE_EXTENSION,
         None => false,
@@ -16,7 +22,13 @@ pub fn find_move_file(path: PathBuf) -> Vec<PathBuf> {
     if !path.is_dir() {
         // If the filename is specified directly, add it to the list, regardless
         // of whether it has a ".move" extension.
-        result.push(path);
+        result.push(path.to_path_buf());
+    } else if respect_gitignore {
+        result.extend(
+            unignored_files(path)
+                .into_iter()
+                .filter(|p| has_move_extension(p)),
+        );
     } else {
         for entry in walkdir::WalkDir::new(path)
             .into_iter()
@@ -32,3 +44,184 @@ pub fn find_move_file(path: PathBuf) -> Vec<PathBuf> {
     }
     result
 }
+
+/// Files under `path` which aren't ignored by `.gitignore`/`.ignore` rules.
+#[cfg(feature = "fs")]
+fn unignored_files(path: &Path) -> Vec<PathBuf> {
+    ignore::WalkBuilder::new(path)
+        .hidden(false)
+        .build()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().map(|t| t.is_file()).unwrap_or(false))
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Ignore rules are only read with the `fs` feature, without it every file is kept.
+#[cfg(not(feature = "fs"))]
+fn unignored_files(path: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .collect()
+}
+
+/// Why a move file is left out of the project.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum SkipReason {
+    TooLarge { size: u64, limit: u64 },
+    NotUtf8,
+}
+
+impl fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SkipReason::TooLarge { size, limit } => write!(
+                f,
+                "file is skipped, its {} bytes exceed max_file_size of {} bytes",
+                size, limit
+            ),
+            SkipReason::NotUtf8 => write!(f, "file is skipped, it's not utf-8 text"),
+        }
+    }
+}
+
+/// Whether the move file at `path` can be parsed: utf-8 text of at most `max_size` bytes,
+/// 0 for no limit. Unreadable files pass, they are reported when read.
+pub fn check_move_file(path: &Path, max_size: u64) -> Result<(), SkipReason> {
+    let size = match std::fs::metadata(path) {
+        Ok(m) => m.len(),
+        Err(_) => return Ok(()),
+    };
+    if max_size != 0 && size > max_size {
+        return Err(SkipReason::TooLarge {
+            size,
+            limit: max_size,
+        });
+    }
+    match std::fs::read(path) {
+        Ok(content) if std::str::from_utf8(&content).is_err() => Err(SkipReason::NotUtf8),
+        _ => Ok(()),
+    }
+}
+
+/// Collect compiled `.mv` files under `path`.
+pub fn find_bytecode_file(path: &Path) -> Vec<PathBuf> {
+    walkdir::WalkDir::new(path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.into_path())
+        .filter(|p| p.extension().and_then(|s| s.to_str()) == Some(MOVE_COMPILED_EXTENSION))
+        .collect()
+}
+
+/// Resolve symlinks and path case so that one physical file maps to one FileId.
+/// Deleted or not-yet-created files are resolved through their parent directory,
+/// and if that fails too, the path is returned as is.
+pub fn canonicalize(path: &Path) -> PathBuf {
+    if let Ok(p) = canonicalize_existing(path) {
+        return p;
+    }
+    match (path.parent(), path.file_name()) {
+        (Some(parent), Some(name)) if !parent.as_os_str().is_empty() => {
+            match canonicalize_existing(parent) {
+                Ok(p) => p.join(name),
+                Err(_) => path.to_path_buf(),
+            }
+        }
+        _ => path.to_path_buf(),
+    }
+}
+
+#[cfg(feature = "fs")]
+fn canonicalize_existing(path: &Path) -> std::io::Result<PathBuf> {
+    // no `\\?\` prefix on windows, unlike `std::fs::canonicalize`.
+    dunce::canonicalize(path)
+}
+
+#[cfg(not(feature = "fs"))]
+fn canonicalize_existing(path: &Path) -> std::io::Result<PathBuf> {
+    std::fs::canonicalize(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{canonicalize, check_move_file, find_move_file, SkipReason};
+    use std::fs;
+
+    #[test]
+    fn test_find_move_file_respect_gitignore() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        fs::create_dir_all(dir.join("build")).unwrap();
+        // the ignore crate only honors .gitignore inside a git repository.
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(".gitignore"), "build/\n").unwrap();
+        fs::write(dir.join("A.move"), "module A {}").unwrap();
+        fs::write(dir.join("build").join("B.move"), "module B {}").unwrap();
+
+        let files = find_move_file(dir.clone(), true);
+        assert_eq!(files, vec![dir.join("A.move")]);
+
+        let mut files = find_move_file(dir.clone(), false);
+        files.sort();
+        assert_eq!(
+            files,
+            vec![dir.join("A.move"), dir.join("build").join("B.move")]
+        );
+    }
+
+    #[test]
+    fn test_check_move_file() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = tmp.path().to_path_buf();
+        fs::write(dir.join("A.move"), "module A {}").unwrap();
+        fs::write(dir.join("B.move"), [0x6d, 0xff, 0xfe, 0x00]).unwrap();
+
+        assert_eq!(check_move_file(&dir.join("A.move"), 0), Ok(()));
+        assert_eq!(check_move_file(&dir.join("A.move"), 11), Ok(()));
+        assert_eq!(
+            check_move_file(&dir.join("A.move"), 10),
+            Err(SkipReason::TooLarge {
+                size: 11,
+                limit: 10
+            })
+        );
+        assert_eq!(
+            check_move_file(&dir.join("B.move"), 0),
+            Err(SkipReason::NotUtf8)
+        );
+        assert_eq!(check_move_file(&dir.join("C.move"), 0), Ok(()));
+    }
+
+    #[test]
+    fn test_canonicalize() {
+        let tmp = tempfile::tempdir().unwrap();
+        let dir = canonicalize(tmp.path());
+        fs::create_dir_all(dir.join("modules")).unwrap();
+        fs::write(dir.join("modules").join("A.move"), "module A {}").unwrap();
+
+        let indirect = dir
+            .join("modules")
+            .join("..")
+            .join("modules")
+            .join("A.move");
+        assert_eq!(canonicalize(&indirect), dir.join("modules").join("A.move"));
+
+        // file does not exist, resolve through parent.
+        let missing = dir.join("modules").join("..").join("B.move");
+        assert_eq!(canonicalize(&missing), dir.join("B.move"));
+
+        #[cfg(unix)]
+        {
+            std::os::unix::fs::symlink(dir.join("modules"), dir.join("linked")).unwrap();
+            assert_eq!(
+                canonicalize(&dir.join("linked").join("A.move")),
+                dir.join("modules").join("A.move")
+            );
+        }
+    }
+}
@@ -0,0 +1,56 @@
+//! Build metadata of the server, reported in `ServerInfo` and by `move/serverInfo`.
+
+use crate::lsp_ext::BuildInfo;
+
+/// Supplies build metadata only the binary knows, like the git commit it's built from.
+pub trait VersionProvider: Send + Sync {
+    fn build_info(&self) -> BuildInfo;
+}
+
+/// Just the version of this crate, for servers embedded without build metadata.
+pub struct PackageVersion;
+
+impl VersionProvider for PackageVersion {
+    fn build_info(&self) -> BuildInfo {
+        BuildInfo {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            ..Default::default()
+        }
+    }
+}
+
+impl BuildInfo {
+    /// `0.1.0`, followed by the short git commit if known, like `0.1.0 (1a2b3c4, dirty)`.
+    pub fn display_version(&self) -> String {
+        match &self.git_commit {
+            Some(commit) => format!(
+                "{} ({}{})",
+                self.version,
+                commit.get(..7).unwrap_or(commit),
+                if self.git_dirty == Some(true) {
+                    ", dirty"
+                } else {
+                    ""
+                }
+            ),
+            None => self.version.clone(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display_version() {
+        let mut info = PackageVersion.build_info();
+        assert_eq!(info.display_version(), env!("CARGO_PKG_VERSION"));
+        info.git_commit = Some("1a2b3c4d5e6f".to_string());
+        info.git_dirty = Some(true);
+        assert_eq!(
+            info.display_version(),
+            format!("{} (1a2b3c4, dirty)", env!("CARGO_PKG_VERSION"))
+        );
+    }
+}
@@ -0,0 +1,101 @@
+use crate::salsa::FileId;
+use lsp_types::Url;
+use std::path::{Path, PathBuf};
+
+/// Scheme of read-only documents backed by local files, e.g. stdlib files outside the workspace.
+/// Their content is served by the `move/readFile` request.
+pub const READONLY_SCHEME: &str = "move-readonly";
+
+/// Location of a document known to the server.
+/// `file:` and `move-readonly:` uris map to real paths, other schemes (`untitled:`, `git:`, ...)
+/// are virtual: they are parsed and syntax-checked, but never take part in compiler-based checks.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub enum VirtualPath {
+    File(PathBuf),
+    Virtual(Url),
+}
+
+impl VirtualPath {
+    pub fn from_url(url: &Url) -> Self {
+        let path = match url.scheme() {
+            "file" => url.to_file_path().ok(),
+            READONLY_SCHEME => readonly_path(url),
+            _ => None,
+        };
+        match path {
+            Some(p) => VirtualPath::File(p),
+            None => VirtualPath::Virtual(url.clone()),
+        }
+    }
+
+    pub fn as_file_path(&self) -> Option<&Path> {
+        match self {
+            VirtualPath::File(p) => Some(p.as_path()),
+            VirtualPath::Virtual(_) => None,
+        }
+    }
+
+    pub fn is_virtual(&self) -> bool {
+        matches!(self, VirtualPath::Virtual(_))
+    }
+
+    /// The id used to store the document in the database.
+    /// Virtual documents use their uri, which never collides with a real path.
+    pub fn file_id(&self) -> FileId {
+        match self {
+            VirtualPath::File(p) => p.clone(),
+            VirtualPath::Virtual(url) => PathBuf::from(url.as_str()),
+        }
+    }
+}
+
+/// `move-readonly:` uri of a local file.
+pub fn readonly_url(path: &Path) -> Option<Url> {
+    let file_url = Url::from_file_path(path).ok()?;
+    Url::parse(&format!("{}:{}", READONLY_SCHEME, file_url.path())).ok()
+}
+
+fn readonly_path(url: &Url) -> Option<PathBuf> {
+    Url::parse(&format!("file://{}", url.path()))
+        .ok()?
+        .to_file_path()
+        .ok()
+}
+
+impl From<&Url> for VirtualPath {
+    fn from(url: &Url) -> Self {
+        VirtualPath::from_url(url)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{readonly_url, VirtualPath};
+    use lsp_types::Url;
+
+    #[test]
+    fn test_virtual_path() {
+        let untitled = Url::parse("untitled:Untitled-1").unwrap();
+        let vp = VirtualPath::from_url(&untitled);
+        assert!(vp.is_virtual());
+        assert!(vp.as_file_path().is_none());
+        assert_eq!(vp.file_id().to_string_lossy(), "untitled:Untitled-1");
+
+        #[cfg(unix)]
+        {
+            let file = Url::parse("file:///tmp/A.move").unwrap();
+            let vp = VirtualPath::from_url(&file);
+            assert!(!vp.is_virtual());
+            assert_eq!(vp.as_file_path().unwrap().to_string_lossy(), "/tmp/A.move");
+
+            let readonly = readonly_url(std::path::Path::new("/tmp/my lib/A.move")).unwrap();
+            assert_eq!(readonly.as_str(), "move-readonly:/tmp/my%20lib/A.move");
+            let vp = VirtualPath::from_url(&readonly);
+            assert!(!vp.is_virtual());
+            assert_eq!(
+                vp.as_file_path().unwrap().to_string_lossy(),
+                "/tmp/my lib/A.move"
+            );
+        }
+    }
+}
@@ -0,0 +1,43 @@
+use serde_json::Value;
+use std::{path::Path, process::Command};
+
+fn analyze(folder: &Path, path: &str) -> (Option<i32>, Value) {
+    let output = Command::new(env!("CARGO_BIN_EXE_move-language-server"))
+        .current_dir(folder)
+        .args(&["analyze", "--path", path])
+        .output()
+        .unwrap();
+    let diagnostics = serde_json::from_slice(&output.stdout).unwrap();
+    (output.status.code(), diagnostics)
+}
+
+#[test]
+fn test_analyze_reports_the_file_only() {
+    let tmp = tempfile::tempdir().unwrap();
+    let dir = tmp.path();
+    std::fs::create_dir_all(dir.join("modules")).unwrap();
+    std::fs::write(dir.join("Move.toml"), "modules_folders = [\"modules\"]\n").unwrap();
+    std::fs::write(
+        dir.join("modules").join("A.move"),
+        "address 0x1 {\nmodule A {\n    fun f(): u64 { true }\n}\n}\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("modules").join("B.move"),
+        "address 0x1 {\nmodule B {\n    fun g(): u64 { 0 }\n}\n}\n",
+    )
+    .unwrap();
+
+    let (code, diagnostics) = analyze(dir, "modules/B.move");
+    assert_eq!(code, Some(0));
+    assert_eq!(diagnostics, Value::Array(vec![]));
+
+    let (code, diagnostics) = analyze(dir, "modules/A.move");
+    assert_eq!(code, Some(1));
+    let diagnostics = diagnostics.as_array().unwrap();
+    assert_eq!(diagnostics.len(), 1);
+    assert!(diagnostics[0]["primary"]["file"]
+        .as_str()
+        .unwrap()
+        .ends_with("A.move"));
+}
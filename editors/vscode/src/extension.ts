@@ -14,6 +14,18 @@ interface ExtensionConfig {
 	sender: string | undefined
 }
 
+interface CompileLabel {
+	file: string,
+	range: lsp.Range,
+	msg: string
+}
+
+interface CompileResult {
+	outDir: string,
+	artifacts: { path: string }[],
+	errors: { primaryLabel: CompileLabel, secondaryLabels: CompileLabel[] }[]
+}
+
 interface LanguageServerConfig {
 	dialect: string,
 	modules_folders: string[],
@@ -29,6 +41,7 @@ const workspaceClients: Map<vscode.WorkspaceFolder, lsp.LanguageClient> = new Ma
 export async function activate(context: vscode.ExtensionContext) {
 
 	context.subscriptions.push(vscode.commands.registerCommand('move.compile', () => compileCommand().catch(console.error)));
+	context.subscriptions.push(vscode.commands.registerCommand('move.setSenderAddress', (args?: { uri: string }) => setSenderCommand(args).catch(console.error)));
 	const extensionPath = context.extensionPath;
 	const compilationOutputChannel = vscode.window.createOutputChannel('Move Compilation Log');
 
@@ -112,6 +125,30 @@ export async function activate(context: vscode.ExtensionContext) {
 		return compileUsingLSP(sender, document, outdir);
 	}
 
+	/**
+	 * Command: Move: Set sender address
+	 * Asked by the quick fix of modules outside of address blocks, when no sender is configured.
+	 * The address is written to the workspace settings, then the servers are told to reload
+	 * their config, which they pull with the sender as `sender_address`.
+	 */
+	async function setSenderCommand(args?: { uri: string }): Promise<any> {
+		const uri = (args && vscode.Uri.parse(args.uri)) || (vscode.window.activeTextEditor && vscode.window.activeTextEditor.document.uri);
+		const folder = uri && vscode.workspace.getWorkspaceFolder(uri);
+		const sender = await vscode.window.showInputBox({
+			prompt: 'Sender address of the modules outside of address blocks',
+			placeHolder: '0x...',
+			validateInput: (value) => /^0x[0-9a-fA-F]+$/.test(value) ? null : 'an address is 0x followed by hex digits',
+		});
+		if (!sender) {
+			return;
+		}
+		// `move.sender` is a window setting, it can't be set per folder.
+		await vscode.workspace.getConfiguration('move', folder).update('sender', sender, vscode.ConfigurationTarget.Workspace);
+		for (const [clientFolder, client] of workspaceClients) {
+			notifyConfigChange(clientFolder, client);
+		}
+	}
+
 	async function compileUsingLSP(sender: string, document: vscode.TextDocument, outdir: string) {
 		let workspaceFolder = vscode.workspace.getWorkspaceFolder(document.uri);
 		if (!workspaceFolder) {
@@ -137,11 +174,19 @@ export async function activate(context: vscode.ExtensionContext) {
 
 		let response = await client.sendRequest(ExecuteCommandRequest.type, params);
 
-		if (!!response) {
-			compilationOutputChannel.append(`${response}`);
-		} else {
-			compilationOutputChannel.appendLine("Compile Successful");
+		if (!response) {
+			return;
+		}
+		if (typeof response === 'string') {
+			compilationOutputChannel.appendLine(response);
+			return;
+		}
+		const result = response as CompileResult;
+		if (result.errors.length === 0) {
+			compilationOutputChannel.appendLine(`Compile Successful, ${result.artifacts.length} artifacts in ${result.outDir}`);
+			return;
 		}
+		compilationOutputChannel.appendLine(`Compile Failed with ${result.errors.length} errors`);
 	}
 
 	function didOpenTextDocument(document: vscode.TextDocument) {
@@ -204,11 +249,17 @@ export async function activate(context: vscode.ExtensionContext) {
 		workspaceClients.set(folder, client);
 	}
 
+	/**
+	 * Tell the server of `folder` to reload its config, with the settings it would pull.
+	 */
+	function notifyConfigChange(folder: vscode.WorkspaceFolder, client: lsp.LanguageClient) {
+		client.sendNotification('workspace/didChangeConfiguration', { settings: toLanguageServerConfig(loadConfig(folder)) });
+	}
+
 	vscode.workspace.onDidChangeConfiguration(evt => {
 		for (let [folder, client] of workspaceClients) {
 			if (evt.affectsConfiguration("move", folder)) {
-				const moveConfig = vscode.workspace.getConfiguration('move', folder);
-				client.sendNotification('workspace/didChangeConfiguration', { settings: moveConfig });
+				notifyConfigChange(folder, client);
 			}
 		}
 	});